@@ -1,17 +1,633 @@
-use crate::models::{Resolution, SyncMessage, Theme};
-use crate::sync::run_sync;
-use crate::utils::find_usb_drives;
-use crossbeam_channel::{Receiver, Sender, unbounded};
+mod ui;
+
+use crate::diagnostics::{run_benchmarks, summarize_drives, support_report, BenchmarkResults, DriveSummary, SupportInfo};
+use crate::history::{actions_csv, history_dir, list_runs, load_run, HistoryEntry, HistoryRun};
+use crate::models::{ActivityKind, ConflictSuggestion, ConsistencyReport, CrowdedDirectory, DiffLine, DriveUnavailable, Resolution, SyncData, SyncMessage, SyncStats, Theme, UsbDrive};
+use crate::monitor::Monitor;
+use crate::observer::ChannelObserver;
+use crate::session_log::SessionLog;
+use crate::shortcuts;
+use crate::palette::Palette;
+use crate::plan_panel::PlanPanel;
+use crate::report::latest_report;
+use crate::taskbar::{TaskbarProgress, TaskbarState};
+use crate::settings::{mb_per_sec_to_bytes, AnswerRules, DeviceLogVerbosity, LaunchFile, Profile, ProfileOverlap, Settings, LAUNCH_FILE_EXTENSION};
+use crate::sync::{estimate_change_count, find_orphan_files, move_orphans_to_trash, run_sync, OrphanFile};
+use crate::utils::{
+    elide_middle, enclosing_sync_root, ensure_writable, find_usb_drives, folder_totals, format_count, format_size, load_sync_data, normalize_local_folder, probe_folder_permissions, save_sync_data,
+    metadata_path, FolderTotals, PermissionProbe,
+};
+use crossbeam_channel::{Receiver, Sender, TryRecvError, unbounded};
 use eframe::egui;
 use egui::{Color32, RichText};
-use std::path::PathBuf;
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
 const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
+// Shortest run of same-kind log lines that is folded into one row.
+const LOG_GROUP_MIN_LINES: usize = 3;
+// Size difference between the two sides above which the totals are highlighted.
+const LARGE_TRANSFER_HINT_BYTES: u64 = 1024 * 1024 * 1024;
+// Space between a widget and the first-run guide pointing at it.
+const ONBOARDING_GAP: f32 = 36.0;
+// Speed offered when a rate limit is first switched on.
+const DEFAULT_RATE_LIMIT_MB_PER_SEC: f32 = 10.0;
+// Limit offered when the warning about crowded folders is first switched on.
+const DEFAULT_DIRECTORY_ENTRY_SOFT_LIMIT: u32 = 10_000;
+// Messages from the sync thread handled per frame; a larger backlog is worked off over the next frames.
+const MAX_MESSAGES_PER_FRAME: usize = 5000;
+// Time per action assumed for a run copying many small files, until enough of it has run to measure its own pace.
+const ASSUMED_SECONDS_PER_SMALL_FILE: f64 = 0.05;
+// Actions that must be handled after the small file hint before the measured pace is used.
+const SMALL_FILES_CALIBRATION_ACTIONS: usize = 50;
+// Inner size of the window in compact mode.
+const COMPACT_WINDOW_SIZE: egui::Vec2 = egui::vec2(360.0, 120.0);
+// Copying time each speed measurement covers.
+const TRANSFER_RATE_WINDOW: Duration = Duration::from_secs(2);
+
+// A question from the sync thread waiting for an answer, identified by the id it was asked with.
+enum PendingPrompt {
+    Deletion { id: u64, path: PathBuf, position: usize, total: usize },
+    Conflict { id: u64, path: PathBuf, diff: Option<Vec<DiffLine>>, suggestion: ConflictSuggestion },
+}
+
+// Represents a pending remote safety check.
+struct RemoteMissingState {
+    missing: usize,
+    known: usize,
+    examples: Vec<PathBuf>,
+}
+
+// Represents files whose destination path is too long for the USB drive.
+struct LongPathsState {
+    limit: usize,
+    count: usize,
+    examples: Vec<PathBuf>,
+}
 
-// Represents the state of a file conflict.
-struct ConflictState {
+// Represents folders on the USB drive that would hold too many entries.
+struct CrowdedDirectoriesState {
+    directories: Vec<CrowdedDirectory>,
+}
+
+// Represents groups of local files that would share one name on the USB drive.
+struct NameCollisionsState {
+    count: usize,
+    examples: Vec<Vec<PathBuf>>,
+}
+
+// A loaded metadata file with its file paths in display order, or why it could not be read
+type InspectorLoad = Result<(SyncData, Vec<PathBuf>), String>;
+
+// Read-only view of a sync metadata file, loaded on a background thread.
+struct MetadataInspector {
     path: PathBuf,
+    loading: Option<Receiver<InspectorLoad>>,
+    data: Option<SyncData>,
+    // File paths in display order
+    sorted_paths: Vec<PathBuf>,
+    filter: String,
+    applied_filter: Option<String>,
+    filtered: Vec<usize>,
+    // Files whose last write wasn't read back and checked
+    unverified: usize,
+    error: Option<String>,
+}
+
+impl MetadataInspector {
+    fn open(path: PathBuf, ctx: egui::Context) -> Self {
+        let (tx, rx) = unbounded();
+        let load_path = path.clone();
+        thread::spawn(move || {
+            let result = load_sync_data(&load_path)
+                .map(|data| {
+                    let mut sorted_paths: Vec<PathBuf> = data.files.keys().cloned().collect();
+                    sorted_paths.sort();
+                    (data, sorted_paths)
+                })
+                .map_err(|e| e.to_string());
+            tx.send(result).ok();
+            ctx.request_repaint();
+        });
+        Self {
+            path,
+            loading: Some(rx),
+            data: None,
+            sorted_paths: Vec::new(),
+            filter: String::new(),
+            applied_filter: None,
+            filtered: Vec::new(),
+            unverified: 0,
+            error: None,
+        }
+    }
+
+    // Recomputes the visible rows only when the search text changed.
+    fn refresh_filter(&mut self) {
+        if self.applied_filter.as_ref() == Some(&self.filter) {
+            return;
+        }
+        let needle = self.filter.to_lowercase();
+        self.filtered = self
+            .sorted_paths
+            .iter()
+            .enumerate()
+            .filter(|(_, path)| needle.is_empty() || path.to_string_lossy().to_lowercase().contains(&needle))
+            .map(|(index, _)| index)
+            .collect();
+        self.applied_filter = Some(self.filter.clone());
+    }
+
+    fn export_csv(&self, target: &Path) -> std::io::Result<()> {
+        let Some(data) = &self.data else { return Ok(()) };
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(target)?);
+        writeln!(writer, "path,hash,size,modified")?;
+        for path in &self.sorted_paths {
+            if let Some(info) = data.files.get(path) {
+                writeln!(
+                    writer,
+                    "\"{}\",{},{},{}",
+                    path.display().to_string().replace('"', "\"\""),
+                    info.hash,
+                    info.size,
+                    format_time(info.modified)
+                )?;
+            }
+        }
+        writer.flush()
+    }
+}
+
+// The run history window: the past runs, newest first, and the run opened from the list. Both are read on a background thread.
+struct HistoryWindow {
+    loading: Option<Receiver<Vec<HistoryEntry>>>,
+    runs: Vec<HistoryEntry>,
+    opened: Option<HistoryDetail>,
+}
+
+impl HistoryWindow {
+    fn open(ctx: egui::Context) -> Self {
+        let mut window = Self { loading: None, runs: Vec::new(), opened: None };
+        window.reload(ctx);
+        window
+    }
+
+    fn reload(&mut self, ctx: egui::Context) {
+        let (tx, rx) = unbounded();
+        thread::spawn(move || {
+            tx.send(list_runs(&history_dir())).ok();
+            ctx.request_repaint();
+        });
+        self.loading = Some(rx);
+    }
+}
+
+// The actions of one past run, with the rows matching the search.
+struct HistoryDetail {
+    path: PathBuf,
+    loading: Option<Receiver<Result<HistoryRun, String>>>,
+    run: Option<HistoryRun>,
+    filter: String,
+    applied_filter: Option<String>,
+    filtered: Vec<usize>,
+    error: Option<String>,
+}
+
+impl HistoryDetail {
+    fn open(path: PathBuf, ctx: egui::Context) -> Self {
+        let (tx, rx) = unbounded();
+        let load_path = path.clone();
+        thread::spawn(move || {
+            tx.send(load_run(&load_path).map_err(|e| e.to_string())).ok();
+            ctx.request_repaint();
+        });
+        Self { path, loading: Some(rx), run: None, filter: String::new(), applied_filter: None, filtered: Vec::new(), error: None }
+    }
+
+    // Recomputes the visible rows only when the search text changed.
+    fn refresh_filter(&mut self) {
+        let Some(run) = &self.run else { return };
+        if self.applied_filter.as_ref() == Some(&self.filter) {
+            return;
+        }
+        self.filtered = run.search(&self.filter);
+        self.applied_filter = Some(self.filter.clone());
+    }
+
+    // Writes the rows matching the search.
+    fn export_csv(&self, target: &Path) -> std::io::Result<()> {
+        let Some(run) = &self.run else { return Ok(()) };
+        std::fs::write(target, actions_csv(self.filtered.iter().map(|&index| &run.actions[index])))
+    }
+}
+
+// The rules file of the selected folder while its editor is open.
+struct AnswerRulesEditor {
+    // Where the rules are saved; None until a new file is saved for the first time
+    path: Option<PathBuf>,
+    rules: AnswerRules,
+    // Why the file couldn't be read or saved
+    error: Option<String>,
+}
+
+impl AnswerRulesEditor {
+    fn open(path: Option<PathBuf>) -> Self {
+        // Rules that fail the check are still read, so they can be fixed here
+        let (rules, error) = match &path {
+            Some(path) => match AnswerRules::read(path) {
+                Ok(rules) => (rules, None),
+                Err(e) => (AnswerRules::default(), Some(format!("无法读取规则文件: {}", e))),
+            },
+            None => (AnswerRules::default(), None),
+        };
+        Self { path, rules, error }
+    }
+}
+
+// Files on the USB drive that syncing no longer touches, found on a background thread.
+struct OrphanReport {
+    local_folder: PathBuf,
+    usb_drive: PathBuf,
+    loading: Option<Receiver<Result<Vec<OrphanFile>, String>>>,
+    files: Vec<OrphanFile>,
+    error: Option<String>,
+    message: Option<String>,
+    cleaning: Option<OrphanCleanup>,
+}
+
+// Orphaned files being moved to the trash on a background thread. Dropping it stops the move between two files.
+struct OrphanCleanup {
+    result: Receiver<Result<usize, String>>,
+    progress: Receiver<SyncMessage>,
+    stop: Sender<SyncMessage>,
+    status: String,
+}
+
+impl OrphanReport {
+    fn open(local_folder: PathBuf, usb_drive: PathBuf, ctx: egui::Context) -> Self {
+        let mut report = Self { local_folder, usb_drive, loading: None, files: Vec::new(), error: None, message: None, cleaning: None };
+        report.refresh(ctx);
+        report
+    }
+
+    fn clean_up(&mut self, log_verbosity: DeviceLogVerbosity, ctx: egui::Context) {
+        let (result_tx, result) = unbounded();
+        let (progress_tx, progress) = unbounded();
+        let (stop, stop_rx) = unbounded();
+        let (local_folder, usb_drive, files) = (self.local_folder.clone(), self.usb_drive.clone(), self.files.clone());
+        thread::spawn(move || {
+            let observer = ChannelObserver::new(progress_tx, stop_rx);
+            result_tx.send(move_orphans_to_trash(&local_folder, &usb_drive, &files, log_verbosity, &observer).map_err(|e| e.to_string())).ok();
+            ctx.request_repaint();
+        });
+        self.cleaning = Some(OrphanCleanup { result, progress, stop, status: String::new() });
+    }
+
+    fn refresh(&mut self, ctx: egui::Context) {
+        let (tx, rx) = unbounded();
+        let (local_folder, usb_drive) = (self.local_folder.clone(), self.usb_drive.clone());
+        thread::spawn(move || {
+            tx.send(find_orphan_files(&local_folder, &usb_drive).map_err(|e| e.to_string())).ok();
+            ctx.request_repaint();
+        });
+        self.loading = Some(rx);
+        self.files.clear();
+        self.error = None;
+    }
+}
+
+// Where the widgets the first-run guide points at were drawn this frame.
+#[derive(Default)]
+struct OnboardingTargets {
+    folder_button: Option<egui::Rect>,
+    usb_row: Option<egui::Rect>,
+    sync_button: Option<egui::Rect>,
+    // The guide stays hidden while a dialog has the main window disabled
+    main_ui_enabled: bool,
+}
+
+// File count and size of one side of the selected pair, counted on a background thread and cached per folder.
+struct FolderTotalsTracker {
+    cache: HashMap<PathBuf, FolderTotals>,
+    // The folder currently shown
+    path: Option<PathBuf>,
+    counting: Option<Receiver<(PathBuf, FolderTotals)>>,
+    stop: Option<Sender<SyncMessage>>,
+}
+
+impl FolderTotalsTracker {
+    fn new() -> Self {
+        Self { cache: HashMap::new(), path: None, counting: None, stop: None }
+    }
+
+    // Follows the selection: abandons the count for a folder no longer shown and starts one for an uncached folder.
+    fn track(&mut self, path: Option<PathBuf>, ctx: &egui::Context) {
+        if path != self.path {
+            self.cancel();
+            self.path = path;
+        }
+        if let Some(rx) = &self.counting
+            && let Ok((for_path, totals)) = rx.try_recv()
+        {
+            self.cache.insert(for_path, totals);
+            self.counting = None;
+            self.stop = None;
+        }
+        let Some(path) = &self.path else { return };
+        if self.counting.is_none() && !self.cache.contains_key(path) {
+            let (tx_result, rx_result) = unbounded();
+            let (tx_stop, rx_stop) = unbounded();
+            let (path, ctx) = (path.clone(), ctx.clone());
+            thread::spawn(move || {
+                let (tx_progress, _rx_progress) = unbounded();
+                let observer = ChannelObserver::new(tx_progress, rx_stop);
+                if let Some(totals) = folder_totals(&path, &observer) {
+                    tx_result.send((path, totals)).ok();
+                    ctx.request_repaint();
+                }
+            });
+            self.counting = Some(rx_result);
+            self.stop = Some(tx_stop);
+        }
+    }
+
+    fn cancel(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            stop.send(SyncMessage::Stop).ok();
+        }
+        self.counting = None;
+    }
+
+    // Drops the cached count of the shown folder, so the next frame counts it again.
+    fn recount(&mut self) {
+        self.cancel();
+        if let Some(path) = &self.path {
+            self.cache.remove(path);
+        }
+    }
+
+    // Forgets every cached count, e.g. after a sync changed both sides.
+    fn clear(&mut self) {
+        self.cancel();
+        self.cache.clear();
+    }
+
+    fn current(&self) -> Option<FolderTotals> {
+        self.cache.get(self.path.as_ref()?).copied()
+    }
+
+    // Draws "18,204 个文件 · 42.7 GB" with a button that counts again.
+    fn show(&mut self, ui: &mut egui::Ui, highlight: Option<Color32>) {
+        let text = match self.current() {
+            Some(totals) => format!("{} 个文件 · {}", format_count(totals.files), format_size(totals.bytes)),
+            None if self.counting.is_some() => "正在统计...".to_owned(),
+            None => return,
+        };
+        ui.horizontal(|ui| {
+            ui.add_space(30.0);
+            let text = RichText::new(text).small();
+            ui.label(match highlight {
+                Some(color) => text.color(color),
+                None => text.weak(),
+            });
+            if ui.small_button("🔄").on_hover_text("重新统计").clicked() {
+                self.recount();
+            }
+        });
+    }
+}
+
+// Remembers which dialogs were on screen last frame, so each gets its default button focused once when it appears.
+// Enter then answers with the default, and Tab moves between the buttons.
+#[derive(Default)]
+struct DialogFocus {
+    previous: Vec<egui::Id>,
+    current: Vec<egui::Id>,
+}
+
+impl DialogFocus {
+    // Called once per frame before any dialog is drawn.
+    fn begin_frame(&mut self) {
+        self.previous = std::mem::take(&mut self.current);
+    }
+
+    fn default_button(&mut self, dialog: egui::Id, button: &egui::Response) {
+        if !self.previous.contains(&dialog) {
+            button.request_focus();
+        }
+        self.current.push(dialog);
+    }
+}
+
+// Places the movable prompts. Each kind keeps one window id, so egui remembers where the user left it for the
+// next prompt of that kind; the first one starts in the middle, as does any later one a resize left partly off screen.
+#[derive(Default)]
+struct DialogPlacement {
+    // Where each kind of prompt was drawn last frame
+    last_rect: HashMap<&'static str, egui::Rect>,
+}
+
+impl DialogPlacement {
+    fn window<'a>(&self, ctx: &egui::Context, kind: &'static str, window: egui::Window<'a>) -> egui::Window<'a> {
+        let screen = ctx.screen_rect();
+        // Positions are those of the window's center, so centering needs no size
+        let window = window.id(egui::Id::new(kind)).movable(true).pivot(egui::Align2::CENTER_CENTER).default_pos(screen.center());
+        match self.last_rect.get(kind) {
+            // A pixel of slack for rounding at the screen edge, where dragging stops
+            Some(rect) if !screen.expand(1.0).contains_rect(*rect) => window.current_pos(screen.center()),
+            _ => window,
+        }
+    }
+
+    fn shown<R>(&mut self, kind: &'static str, response: Option<&egui::InnerResponse<R>>) {
+        if let Some(response) = response {
+            self.last_rect.insert(kind, response.response.rect);
+        }
+    }
+}
+
+// The strip the window shrinks to while a run goes on, and what to give back when it is left.
+struct CompactMode {
+    // Inner size of the full window, if it was known
+    restore_size: Option<egui::Vec2>,
+    was_maximized: bool,
+    always_on_top: bool,
+}
+
+// Copy speed of the running sync, measured from how far its byte progress moves.
+#[derive(Default)]
+struct TransferRate {
+    // Bytes done when the current measurement started, and when that was
+    window_start: Option<(Instant, u64)>,
+    bytes_per_sec: Option<f64>,
+}
+
+impl TransferRate {
+    fn update(&mut self, done: u64) {
+        let now = Instant::now();
+        let Some((start, start_done)) = self.window_start else {
+            self.window_start = Some((now, done));
+            return;
+        };
+        let elapsed = now.duration_since(start);
+        if elapsed >= TRANSFER_RATE_WINDOW {
+            let rate = done.saturating_sub(start_done) as f64 / elapsed.as_secs_f64();
+            // Smoothed, so a run of small files doesn't swing the estimate
+            self.bytes_per_sec = Some(self.bytes_per_sec.map_or(rate, |previous| 0.7 * previous + 0.3 * rate));
+            self.window_start = Some((now, done));
+        }
+    }
+
+    // Starts a new measurement, e.g. after a pause, which would otherwise count as slow copying.
+    fn restart(&mut self) {
+        self.window_start = None;
+    }
+
+    // e.g. "12.3 MB/s · 剩余约 4 分钟"; None until a measurement is done or while nothing moves.
+    fn text(&self, remaining_bytes: u64) -> Option<String> {
+        let rate = self.bytes_per_sec.filter(|rate| *rate >= 1.0)?;
+        Some(format!("{}/s · 剩余约 {}", format_size(rate as u64), format_rough_duration(remaining_bytes as f64 / rate)))
+    }
+}
+
+// Where a stopped or interrupted run ended, shown in the idle status bar until the next sync.
+struct RunSnapshot {
+    progress: f32,
+    current_file: String,
+    stats: Option<SyncStats>,
+    reason: &'static str,
+}
+
+// The note that the running plan copies many small files to the USB drive, shown until dismissed.
+struct SmallFilesHint {
+    count: usize,
+    since: Instant,
+    // Actions handled before the hint arrived, which don't count towards the measured pace
+    handled_before: usize,
+}
+
+impl SmallFilesHint {
+    // e.g. "本次将向U盘复制 12,000 个小于 64 KB 的文件，逐个写入较慢，剩余约需 8 分钟 (按本次速度估算)"
+    fn text(&self, stats: Option<&SyncStats>) -> String {
+        let handled = stats.map_or(0, |stats| stats.completed + stats.skipped + stats.failed);
+        let remaining = stats.map_or(self.count, |stats| stats.remaining);
+        let measured = handled.saturating_sub(self.handled_before);
+        let (seconds_per_action, basis) = if measured >= SMALL_FILES_CALIBRATION_ACTIONS {
+            (self.since.elapsed().as_secs_f64() / measured as f64, "按本次速度估算")
+        } else {
+            (ASSUMED_SECONDS_PER_SMALL_FILE, "粗略估算")
+        };
+        format!(
+            "本次将向U盘复制 {} 个小于 64 KB 的文件，逐个写入较慢，剩余约需 {} ({})",
+            format_count(self.count as u64),
+            format_rough_duration(remaining as f64 * seconds_per_action),
+            basis
+        )
+    }
+}
+
+// Rounds to minutes or hours, since anything finer would suggest more precision than an estimate has.
+fn format_rough_duration(seconds: f64) -> String {
+    if seconds < 60.0 {
+        "不到 1 分钟".to_owned()
+    } else if seconds < 3600.0 {
+        format!("{:.0} 分钟", seconds / 60.0)
+    } else {
+        format!("{:.1} 小时", seconds / 3600.0)
+    }
+}
+
+impl RunSnapshot {
+    // e.g. "上次同步已停止于 63% · 120/345 项完成"
+    fn summary(&self) -> String {
+        let mut text = format!("上次同步{}于 {:.0}%", self.reason, self.progress * 100.0);
+        if let Some(stats) = &self.stats {
+            let total = stats.completed + stats.skipped + stats.failed + stats.remaining;
+            text.push_str(&format!(" · {}/{} 项完成", stats.completed, total));
+        }
+        text
+    }
+}
+
+// Drive and hashing benchmarks, run on a background thread.
+struct DiagnosticsWindow {
+    progress_rx: Option<Receiver<SyncMessage>>,
+    result_rx: Option<Receiver<Result<Option<BenchmarkResults>, String>>>,
+    stop_tx: Option<Sender<SyncMessage>>,
+    progress: f32,
+    status: String,
+    report: Option<String>,
+    error: Option<String>,
+}
+
+impl DiagnosticsWindow {
+    fn new() -> Self {
+        Self { progress_rx: None, result_rx: None, stop_tx: None, progress: 0.0, status: String::new(), report: None, error: None }
+    }
+
+    fn is_running(&self) -> bool {
+        self.result_rx.is_some()
+    }
+
+    fn start(&mut self, usb_root: PathBuf, local_folder: PathBuf, ctx: egui::Context) {
+        let (tx_progress, rx_progress) = unbounded();
+        let (tx_stop, rx_stop) = unbounded();
+        let (tx_result, rx_result) = unbounded();
+        thread::spawn(move || {
+            let observer = ChannelObserver::new(tx_progress, rx_stop);
+            let result = run_benchmarks(&usb_root, &local_folder, &observer).map_err(|e| e.to_string());
+            tx_result.send(result).ok();
+            ctx.request_repaint();
+        });
+        self.progress_rx = Some(rx_progress);
+        self.result_rx = Some(rx_result);
+        self.stop_tx = Some(tx_stop);
+        self.progress = 0.0;
+        self.status.clear();
+        self.report = None;
+        self.error = None;
+    }
+
+    fn stop(&mut self) {
+        if let Some(stop) = &self.stop_tx {
+            stop.send(SyncMessage::Stop).ok();
+        }
+    }
+
+    // Applies progress updates and returns the outcome once the benchmarks end.
+    fn poll(&mut self) -> Option<Result<Option<BenchmarkResults>, String>> {
+        if let Some(rx) = &self.progress_rx {
+            while let Ok(message) = rx.try_recv() {
+                if let SyncMessage::Progress(progress, status, _) = message {
+                    self.progress = progress;
+                    self.status = status;
+                }
+            }
+        }
+        let result = self.result_rx.as_ref()?.try_recv().ok()?;
+        self.progress_rx = None;
+        self.result_rx = None;
+        self.stop_tx = None;
+        Some(result)
+    }
+}
+
+// The text behind 帮助 → 生成诊断信息, built locally for whoever helps with a problem.
+struct SupportReport {
+    drives: Vec<DriveSummary>,
+    // Drive detection in progress; the text is built once it is done.
+    drives_rx: Option<Receiver<Vec<DriveSummary>>>,
+    redact_paths: bool,
+    text: String,
+    // Outcome of the last copy or save, shown under the buttons.
+    message: Option<String>,
+}
+
+fn format_time(time: std::time::SystemTime) -> String {
+    chrono::DateTime::<chrono::Local>::from(time).format("%Y-%m-%d %H:%M:%S").to_string()
 }
 
 // Represents the application's current synchronization state.
@@ -25,480 +641,1277 @@ enum SyncState {
 // The main application structure.
 pub struct SyncApp {
     local_folder: Option<PathBuf>,
-    usb_drives: Vec<PathBuf>,
+    // Every detected drive, including those that can be selected but not synced to yet
+    usb_drives: Vec<UsbDrive>,
+    // Drive listing in progress; enumerating disks can take seconds while card readers wake up.
+    drives_rx: Option<Receiver<Vec<UsbDrive>>>,
+    // The launch file the app was opened with, handled once the first drive list arrives.
+    pending_launch_file: Option<PathBuf>,
     selected_usb_drive: Option<PathBuf>,
+    // The local folder whose remembered drive was last looked for, so it is selected once per folder
+    drive_chosen_for: Option<PathBuf>,
     sync_log: Vec<RichText>,
     state: SyncState,
-    show_confirmation: bool,
     show_about_window: bool,
-    show_conflict_resolution: bool,
+    show_routing_window: bool,
+    show_options_window: bool,
+    // A picked folder that is partly unreadable, with what couldn't be opened, until the user decides
+    permission_warning: Option<(PathBuf, PermissionProbe)>,
+    // Other profiles overlapping the folder about to be synced, until the user decides
+    overlap_warning: Option<Vec<ProfileOverlap>>,
+    // Extensions compared for line endings as typed in the options, applied to the profile on each edit
+    line_ending_extensions: String,
+    // Whether the desktop shortcut, the startup entry and the launch file association exist,
+    // checked at startup and after each change
+    desktop_shortcut: bool,
+    autostart: bool,
+    file_association: bool,
+    show_in_use_confirmation: bool,
     show_error_dialog: bool,
+    show_clock_warning: bool,
     error_message: String,
-    file_to_delete: Option<PathBuf>,
-    conflict_state: Option<ConflictState>,
+    clock_warning_message: String,
+    file_in_use: Option<PathBuf>,
+    // Backup file newer than its local source, while asking whether to overwrite it
+    newer_destination: Option<PathBuf>,
+    // A copy refused access, with the system's message, while the user decides whether to retry.
+    denied_copy: Option<(PathBuf, String)>,
+    // Deletion and conflict prompts, shown one at a time from the front.
+    pending_prompts: VecDeque<PendingPrompt>,
+    remote_missing_state: Option<RemoteMissingState>,
+    long_paths_state: Option<LongPathsState>,
+    // (count, example paths) of files whose size couldn't be read while planning
+    unreadable_sizes_prompt: Option<(usize, Vec<PathBuf>)>,
+    crowded_directories_state: Option<CrowdedDirectoriesState>,
+    name_collisions_state: Option<NameCollisionsState>,
+    // (old USB folder name, new name) while asking whether to relink a renamed local folder
+    relink_prompt: Option<(String, String)>,
+    // (remaining, total) actions of an interrupted run's plan while asking whether to continue it
+    resume_plan_prompt: Option<(usize, usize)>,
+    // The version that wrote the record, while asking whether to sync without updating it
+    newer_metadata_prompt: Option<String>,
     deletion_choice: Option<bool>, // None: Ask, Some(true): Delete all, Some(false): Keep all
+    conflict_choice: Option<Resolution>, // None: Ask, Some(r): apply r to all conflicts
+    apply_to_all_conflicts: bool,
+    remember_choice: bool,
+    // The deletion dialog's own "记住此选择", so a box ticked in a conflict dialog doesn't carry over
+    remember_deletion_choice: bool,
+    // Store the answer to the conflict on screen as a rule for that file
+    remember_for_path: bool,
     progress: f32,
     current_file: String,
+    stats: Option<SyncStats>,
+    last_run: Option<RunSnapshot>,
     // We need a channel for each sync operation, so we create them on demand.
     tx_to_sync: Option<Sender<SyncMessage>>,
     rx_from_sync: Receiver<SyncMessage>,
     // The handle to the current sync thread.
     sync_thread: Option<JoinHandle<()>>,
     ctx: egui::Context,
+    settings: Settings,
+    // Streams progress to external programs while the user has it turned on
+    monitor: Option<Monitor>,
+    // Warning shown when the destination would nest inside another sync folder, for the pair it was computed for.
+    nested_root_warning: Option<String>,
+    nested_check_for: Option<(PathBuf, PathBuf)>,
+    // Files kept with "不再询问", read from the USB metadata when the options window opens.
+    kept_files: Vec<PathBuf>,
+    session_log: SessionLog,
+    // Background estimate of pending changes for the selected pair, shown on the sync button.
+    change_estimate: Option<usize>,
+    estimate_for: Option<(PathBuf, PathBuf)>,
+    estimate_rx: Option<Receiver<((PathBuf, PathBuf), usize)>>,
+    estimate_stop: Option<Sender<SyncMessage>>,
+    // Contents of the previous session's log while its viewer window is open.
+    previous_session_log: Option<String>,
+    metadata_inspector: Option<MetadataInspector>,
+    history_window: Option<HistoryWindow>,
+    diagnostics: Option<DiagnosticsWindow>,
+    support_report: Option<SupportReport>,
+    orphan_report: Option<OrphanReport>,
+    answer_rules_editor: Option<AnswerRulesEditor>,
+    dialog_focus: DialogFocus,
+    dialog_placement: DialogPlacement,
+    taskbar: TaskbarProgress,
+    // Totals for the local folder and for its sync folder on the selected drive
+    local_totals: FolderTotalsTracker,
+    usb_totals: FolderTotalsTracker,
+    // What the last run left unsynced, shown in a dialog after it completes.
+    completion_summary: Option<String>,
+    // The quick check of both sides after the last completed run, and whether its findings are open
+    consistency: Option<ConsistencyReport>,
+    show_consistency_window: bool,
+    small_files_hint: Option<SmallFilesHint>,
+    show_unsynced_only: bool,
+    // Show only errors and warnings, toggled by the count next to the log heading
+    show_problems_only: bool,
+    // Set when a run finishes with errors, so the log scrolls to the first one on the next frame
+    scroll_to_first_error: bool,
+    // Fold runs of same-kind log lines into expandable rows; the stored log always keeps every line.
+    group_log: bool,
     pub current_theme: Theme,
+    // Colours for this frame, from the theme and the accent in the settings
+    pub palette: Palette,
+    // Copy speed limit of the running sync in MB/s, adjustable from the status bar
+    rate_limit_mb: Option<f32>,
+    // What the running sync reported doing last, which decides whether 跳过此项 is offered
+    activity: ActivityKind,
+    paused: bool,
+    // The stop in progress lets the current action finish first
+    soft_stop: bool,
+    onboarding: OnboardingTargets,
+    // The current or last run's plan, shown beside the main panel
+    plan_panel: PlanPanel,
+    // Set while the window is shrunk to the progress strip
+    compact: Option<CompactMode>,
+    transfer_rate: TransferRate,
 }
 
 impl SyncApp {
-    pub fn new(ctx: egui::Context) -> Self {
+    /// `launch_file` is a `.syncu` file the app was opened with, whose sync pair is selected right away.
+    pub fn new(ctx: egui::Context, launch_file: Option<PathBuf>) -> Self {
         // The main receiver for all sync threads.
         let (_, rx_from_sync) = unbounded();
 
-        let usb_drives = find_usb_drives();
-        let selected_usb_drive = if usb_drives.len() == 1 {
-            Some(usb_drives[0].clone())
-        } else {
-            None
-        };
+        let settings = Settings::load().unwrap_or_default();
+        let palette = Palette::new(&Theme::Light, settings.accent_color);
+        // A port taken by another program leaves the monitor off until the user turns it on again
+        let monitor = settings.monitor_port.and_then(|port| Monitor::start(port).ok());
 
-        Self {
+        let mut app = Self {
             local_folder: None,
-            usb_drives,
-            selected_usb_drive,
-            sync_log: vec![RichText::new("准备就绪").color(Color32::from_rgb(0, 100, 0))],
+            usb_drives: Vec::new(),
+            selected_usb_drive: None,
+            drive_chosen_for: None,
+            drives_rx: None,
+            pending_launch_file: launch_file,
+            sync_log: vec![RichText::new("准备就绪").color(palette.ready)],
             state: SyncState::Idle,
-            show_confirmation: false,
             show_about_window: false,
-            show_conflict_resolution: false,
+            show_routing_window: false,
+            show_options_window: false,
+            permission_warning: None,
+            overlap_warning: None,
+            line_ending_extensions: String::new(),
+            desktop_shortcut: shortcuts::desktop_shortcut_exists(),
+            autostart: shortcuts::autostart_enabled(),
+            file_association: shortcuts::file_association_enabled(),
+            show_in_use_confirmation: false,
             show_error_dialog: false,
+            show_clock_warning: false,
             error_message: "".to_string(),
-            file_to_delete: None,
-            conflict_state: None,
+            clock_warning_message: "".to_string(),
+            file_in_use: None,
+            newer_destination: None,
+            denied_copy: None,
+            pending_prompts: VecDeque::new(),
+            remote_missing_state: None,
+            long_paths_state: None,
+            unreadable_sizes_prompt: None,
+            crowded_directories_state: None,
+            name_collisions_state: None,
+            relink_prompt: None,
+            resume_plan_prompt: None,
+            newer_metadata_prompt: None,
             deletion_choice: None,
+            conflict_choice: None,
+            apply_to_all_conflicts: false,
+            remember_choice: false,
+            remember_deletion_choice: false,
+            remember_for_path: false,
             progress: 0.0,
             current_file: "".to_owned(),
+            last_run: None,
+            stats: None,
             tx_to_sync: None,
             rx_from_sync,
             sync_thread: None,
             ctx,
+            settings,
+            monitor,
+            nested_root_warning: None,
+            nested_check_for: None,
+            kept_files: Vec::new(),
+            session_log: SessionLog::start(),
+            change_estimate: None,
+            estimate_for: None,
+            estimate_rx: None,
+            estimate_stop: None,
+            previous_session_log: None,
+            metadata_inspector: None,
+            history_window: None,
+            diagnostics: None,
+            support_report: None,
+            orphan_report: None,
+            answer_rules_editor: None,
+            dialog_focus: DialogFocus::default(),
+            dialog_placement: DialogPlacement::default(),
+            taskbar: TaskbarProgress::default(),
+            local_totals: FolderTotalsTracker::new(),
+            usb_totals: FolderTotalsTracker::new(),
+            completion_summary: None,
+            consistency: None,
+            small_files_hint: None,
+            show_consistency_window: false,
+            show_unsynced_only: false,
+            show_problems_only: false,
+            scroll_to_first_error: false,
+            group_log: true,
             current_theme: Theme::Light,
-        }
+            palette,
+            rate_limit_mb: None,
+            activity: ActivityKind::Bookkeeping,
+            paused: false,
+            soft_stop: false,
+            onboarding: OnboardingTargets::default(),
+            plan_panel: PlanPanel::default(),
+            compact: None,
+            transfer_rate: TransferRate::default(),
+        };
+        // A launch file names a drive, so it waits for the first list
+        app.refresh_usb_drives();
+        app
     }
 }
 
-impl eframe::App for SyncApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        crate::apply_theme(ctx, &self.current_theme);
+impl SyncApp {
+    // Opens or closes the progress endpoint for external programs and remembers the choice.
+    fn set_monitor(&mut self, port: Option<u16>) {
+        // Close the old port first so the same one can be bound again
+        self.monitor = None;
+        if let Some(port) = port {
+            match Monitor::start(port) {
+                Ok(monitor) => self.monitor = Some(monitor),
+                Err(e) => {
+                    self.error_message = format!("无法打开本机端口 {}: {}", port, e);
+                    self.show_error_dialog = true;
+                    return;
+                }
+            }
+        }
+        self.settings.monitor_port = port;
+        if let Err(e) = self.settings.save() {
+            self.error_message = format!("保存设置失败: {}", e);
+            self.show_error_dialog = true;
+        }
+    }
 
-        // Process all available messages from the sync thread in one go
-        while let Ok(msg) = self.rx_from_sync.try_recv() {
-            match msg {
-                SyncMessage::Log(log) => {
-                    let color = if log.starts_with("错误") {
-                        Color32::from_rgb(210, 90, 90)
-                    } else if log.starts_with("[") {
-                        Color32::from_rgb(100, 180, 100)
-                    } else {
-                        ctx.style().visuals.text_color()
-                    };
-                    self.sync_log.push(RichText::new(log).color(color));
-                }
-                SyncMessage::ConfirmDeletion(path) => {
-                    if let Some(choice) = self.deletion_choice {
-                        if let Some(tx) = &self.tx_to_sync {
-                            tx.send(SyncMessage::DeletionConfirmed(choice)).ok();
-                        }
-                    } else {
-                        self.show_confirmation = true;
-                        self.file_to_delete = Some(path);
+    // Recomputes the nested sync folder warning when the selected pair changes.
+    fn refresh_nested_root_warning(&mut self) {
+        let pair = match (&self.local_folder, &self.selected_usb_drive) {
+            (Some(local), Some(usb)) => Some((local.clone(), usb.clone())),
+            _ => None,
+        };
+        if pair == self.nested_check_for {
+            return;
+        }
+        self.nested_root_warning = pair.as_ref().and_then(|(local, usb)| {
+            let usb_sync_path = usb.join(local.file_name()?);
+            enclosing_sync_root(&usb_sync_path).map(|root| {
+                format!("目标文件夹将位于另一个 SyncU 同步目录 '{}' 内，两者的同步记录可能互相干扰。", root.display())
+            })
+        });
+        self.nested_check_for = pair;
+    }
+
+    // Stops any running estimate and forgets its result.
+    fn cancel_change_estimate(&mut self) {
+        if let Some(stop) = self.estimate_stop.take() {
+            stop.send(SyncMessage::Stop).ok();
+        }
+        self.estimate_rx = None;
+        self.estimate_for = None;
+        self.change_estimate = None;
+    }
+
+    // Starts a new estimate when the selected pair changes and collects finished results.
+    fn refresh_change_estimate(&mut self) {
+        if self.state != SyncState::Idle {
+            return;
+        }
+        let pair = match (&self.local_folder, &self.selected_usb_drive) {
+            (Some(local), Some(usb)) => Some((local.clone(), usb.clone())),
+            _ => None,
+        };
+        if pair != self.estimate_for {
+            self.cancel_change_estimate();
+            if let Some((local, usb)) = pair.clone() {
+                let (tx_result, rx_result) = unbounded();
+                let (tx_stop, rx_stop) = unbounded();
+                let ctx = self.ctx.clone();
+                thread::spawn(move || {
+                    // Nobody watches the progress of an estimate
+                    let (tx_progress, _rx_progress) = unbounded();
+                    let observer = ChannelObserver::new(tx_progress, rx_stop);
+                    if let Ok(Some(count)) = estimate_change_count(&local, &usb, &observer) {
+                        tx_result.send(((local, usb), count)).ok();
+                        ctx.request_repaint();
+                    }
+                });
+                self.estimate_rx = Some(rx_result);
+                self.estimate_stop = Some(tx_stop);
+            }
+            self.estimate_for = pair;
+        }
+        if let Some(rx) = &self.estimate_rx
+            && let Ok((for_pair, count)) = rx.try_recv()
+        {
+            // A result for an older selection is never shown
+            if Some(&for_pair) == self.estimate_for.as_ref() {
+                self.change_estimate = Some(count);
+            }
+        }
+    }
+
+    // Keeps the folder totals in step with the selection; nothing is counted while a sync runs.
+    fn refresh_folder_totals(&mut self) {
+        if self.state != SyncState::Idle {
+            return;
+        }
+        let usb_sync_path = match (&self.local_folder, &self.selected_usb_drive) {
+            (Some(local), Some(usb)) => local.file_name().map(|name| usb.join(name)),
+            _ => None,
+        };
+        self.local_totals.track(self.local_folder.clone(), &self.ctx);
+        self.usb_totals.track(usb_sync_path, &self.ctx);
+    }
+
+    // Whether the two sides differ enough in size that the sync will likely move a lot of data.
+    fn large_transfer_expected(&self) -> bool {
+        match (self.local_totals.current(), self.usb_totals.current()) {
+            (Some(local), Some(usb)) => local.bytes.abs_diff(usb.bytes) >= LARGE_TRANSFER_HINT_BYTES,
+            _ => false,
+        }
+    }
+
+    // Points at the next step of "pick folder → pick drive → sync" until the first sync is started.
+    // Drawn as a foreground layer next to the widgets' rects from this frame, so it follows resizes.
+    fn show_onboarding(&mut self, ctx: &egui::Context) {
+        if self.settings.onboarding_done || self.state != SyncState::Idle || !self.onboarding.main_ui_enabled {
+            return;
+        }
+        let (step, text, target) = if self.local_folder.is_none() {
+            (1, "点击“选择...”，选择要同步的本地文件夹", self.onboarding.folder_button)
+        } else if self.selected_usb_drive.is_none() {
+            (2, "插入U盘后点击“刷新”，或在这里选择要同步到的U盘", self.onboarding.usb_row)
+        } else {
+            (3, "点击“立即同步”开始第一次同步", self.onboarding.sync_button)
+        };
+        let Some(target) = target else { return };
+
+        // Below the widget, unless that would run off the bottom of the window
+        let below = target.bottom() + ONBOARDING_GAP + 90.0 < ctx.screen_rect().bottom();
+        let (anchor, pivot) = if below {
+            (target.center_bottom() + egui::vec2(0.0, ONBOARDING_GAP), egui::Align2::CENTER_TOP)
+        } else {
+            (target.center_top() - egui::vec2(0.0, ONBOARDING_GAP), egui::Align2::CENTER_BOTTOM)
+        };
+        let mut skip = false;
+        let bubble = egui::Area::new(egui::Id::new("onboarding"))
+            .order(egui::Order::Foreground)
+            .fixed_pos(anchor)
+            .pivot(pivot)
+            .constrain(true)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.set_max_width(260.0);
+                    ui.label(RichText::new(format!("入门引导 {}/3", step)).small().weak());
+                    ui.label(text);
+                    skip = ui.small_button("跳过引导").clicked();
+                });
+            })
+            .response
+            .rect;
+
+        let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("onboarding_arrow")));
+        let stroke = egui::Stroke::new(2.0, self.palette.accent);
+        let (from, to) = if below {
+            (bubble.center_top(), target.center_bottom() + egui::vec2(0.0, 4.0))
+        } else {
+            (bubble.center_bottom(), target.center_top() - egui::vec2(0.0, 4.0))
+        };
+        painter.arrow(from, to - from, stroke);
+        painter.rect_stroke(target.expand(3.0), 4.0, stroke, egui::StrokeKind::Outside);
+
+        if skip {
+            self.finish_onboarding();
+        }
+    }
+
+    fn finish_onboarding(&mut self) {
+        if self.settings.onboarding_done {
+            return;
+        }
+        self.settings.onboarding_done = true;
+        if let Err(e) = self.settings.save() {
+            self.error_message = format!("保存设置失败: {}", e);
+            self.show_error_dialog = true;
+        }
+    }
+
+    // Limit checkbox and slider in the status bar; changes reach the running sync right away.
+    fn show_rate_limit_control(&mut self, ui: &mut egui::Ui) {
+        let before = self.rate_limit_mb;
+        ui.horizontal(|ui| {
+            let mut limited = self.rate_limit_mb.is_some();
+            if ui.checkbox(&mut limited, "限速").on_hover_text("限制复制速度，避免占满网络或磁盘；只影响本次同步").changed() {
+                self.rate_limit_mb = limited.then_some(DEFAULT_RATE_LIMIT_MB_PER_SEC);
+            }
+            if let Some(limit) = &mut self.rate_limit_mb {
+                ui.add(egui::Slider::new(limit, 0.5..=100.0).logarithmic(true).suffix(" MB/s"));
+            }
+        });
+        if self.rate_limit_mb != before
+            && let Some(tx) = &self.tx_to_sync
+        {
+            tx.send(SyncMessage::SetRateLimit(self.rate_limit_mb.map(mb_per_sec_to_bytes))).ok();
+        }
+    }
+
+    // Whether the sync thread is blocked on a question shown in a dialog.
+    fn waiting_for_answer(&self) -> bool {
+        !self.pending_prompts.is_empty()
+            || self.show_in_use_confirmation
+            || self.show_clock_warning
+            || self.remote_missing_state.is_some()
+            || self.long_paths_state.is_some()
+            || self.unreadable_sizes_prompt.is_some()
+            || self.crowded_directories_state.is_some()
+            || self.name_collisions_state.is_some()
+            || self.relink_prompt.is_some()
+            || self.resume_plan_prompt.is_some()
+            || self.newer_metadata_prompt.is_some()
+            || self.newer_destination.is_some()
+            || self.denied_copy.is_some()
+    }
+
+    fn send_to_sync(&self, message: SyncMessage) {
+        if let Some(tx) = &self.tx_to_sync {
+            tx.send(message).ok();
+        }
+    }
+
+    // The row shown while a run goes on: skip the copy in progress, pause, and stop now or after the current item.
+    // Every button keeps its place and size when it is disabled or relabelled.
+    fn show_sync_controls(&mut self, ui: &mut egui::Ui) {
+        const HEIGHT: f32 = 40.0;
+        let spacing = ui.spacing().item_spacing.x;
+        let width = 96.0 + 80.0 + 80.0 + 32.0 + 3.0 * spacing;
+        ui.allocate_ui_with_layout(egui::vec2(width, HEIGHT), egui::Layout::left_to_right(egui::Align::Center), |ui| {
+            let button = |text: &str| egui::Button::new(text).corner_radius(egui::CornerRadius::same(6));
+            let skippable = self.activity.skippable() && !self.paused;
+            let skip = ui
+                .add_enabled_ui(skippable, |ui| ui.add_sized([96.0, HEIGHT], button("跳过此项")))
+                .inner
+                .on_hover_text("放弃正在复制的文件，继续下一项")
+                .on_disabled_hover_text("当前步骤无法跳过");
+            if skip.clicked() {
+                self.send_to_sync(SyncMessage::SkipCurrent);
+            }
+
+            // A paused run can't show a question, so pausing waits until the open one is answered
+            let pause_label = if self.paused { "继续" } else { "暂停" };
+            let can_pause = self.paused || !self.waiting_for_answer();
+            if ui.add_enabled_ui(can_pause, |ui| ui.add_sized([80.0, HEIGHT], button(pause_label))).inner.clicked() {
+                self.toggle_pause();
+            }
+
+            let stop = egui::Button::new(RichText::new("停止").color(egui::Color32::WHITE))
+                .corner_radius(egui::CornerRadius::same(6))
+                .fill(self.palette.stop);
+            if ui.add_sized([80.0, HEIGHT], stop).on_hover_text("立即停止同步").clicked() {
+                self.stop_sync(false);
+            }
+            ui.menu_button("⏷", |ui| {
+                if ui.button("立即停止").clicked() {
+                    self.stop_sync(false);
+                    ui.close();
+                }
+                if ui.button("完成当前项后停止").clicked() {
+                    self.stop_sync(true);
+                    ui.close();
+                }
+            });
+        });
+    }
+
+    // Pauses or resumes the run; the speed is measured afresh afterwards.
+    fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.send_to_sync(if self.paused { SyncMessage::Pause } else { SyncMessage::Resume });
+        self.transfer_rate.restart();
+    }
+
+    // The speed and time left of the running sync, once there is a measurement.
+    fn transfer_text(&self) -> Option<String> {
+        let total = self.stats.as_ref()?.total_bytes;
+        let done = (f64::from(self.progress) * total as f64) as u64;
+        self.transfer_rate.text(total.saturating_sub(done))
+    }
+
+    // Shrinks the window to the progress strip, remembering the size to give back.
+    fn enter_compact_mode(&mut self, ctx: &egui::Context) {
+        let (was_maximized, restore_size) = ctx.input(|i| (i.viewport().maximized.unwrap_or(false), i.viewport().inner_rect.map(|rect| rect.size())));
+        if was_maximized {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Maximized(false));
+        }
+        ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(COMPACT_WINDOW_SIZE));
+        ctx.send_viewport_cmd(egui::ViewportCommand::WindowLevel(egui::WindowLevel::AlwaysOnTop));
+        self.compact = Some(CompactMode { restore_size, was_maximized, always_on_top: true });
+    }
+
+    // Gives the window back its size and level from before compact mode.
+    fn leave_compact_mode(&mut self, ctx: &egui::Context) {
+        let Some(compact) = self.compact.take() else { return };
+        ctx.send_viewport_cmd(egui::ViewportCommand::WindowLevel(egui::WindowLevel::Normal));
+        if compact.was_maximized {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Maximized(true));
+        } else if let Some(size) = compact.restore_size {
+            ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(size));
+        }
+    }
+
+    // Results and questions need the whole window, so the strip is left when the run ends or asks something.
+    fn refresh_compact_mode(&mut self, ctx: &egui::Context) {
+        if self.compact.is_some() && (self.state == SyncState::Idle || self.waiting_for_answer() || self.show_error_dialog) {
+            self.leave_compact_mode(ctx);
+        }
+    }
+
+    // A paused run is resumed first so it can notice the stop; a hard stop ends it while paused anyway.
+    fn stop_sync(&mut self, after_current: bool) {
+        self.state = SyncState::Stopping;
+        self.soft_stop = after_current;
+        if after_current {
+            self.send_to_sync(SyncMessage::StopAfterAction);
+            if self.paused {
+                self.send_to_sync(SyncMessage::Resume);
+            }
+        } else {
+            self.send_to_sync(SyncMessage::Stop);
+        }
+        self.paused = false;
+    }
+
+    // What the taskbar button shows: the run's progress, yellow while it waits or stops, red once something failed.
+    fn taskbar_state(&self) -> TaskbarState {
+        let failed = self.stats.as_ref().is_some_and(|stats| stats.failed > 0);
+        match self.state {
+            // A failed run stays red until its summary is dismissed
+            SyncState::Idle if failed && self.completion_summary.is_some() => TaskbarState::Error(1.0),
+            SyncState::Idle => TaskbarState::Hidden,
+            SyncState::Stopping => TaskbarState::Paused(self.progress),
+            SyncState::Syncing if self.paused || self.waiting_for_answer() => TaskbarState::Paused(self.progress),
+            SyncState::Syncing if failed => TaskbarState::Error(self.progress),
+            SyncState::Syncing => TaskbarState::Normal(self.progress),
+        }
+    }
+
+    // Lets the user pick the local folder. One that is largely unreadable is held back until the user decides.
+    fn pick_local_folder(&mut self) {
+        let Some(path) = rfd::FileDialog::new().pick_folder() else { return };
+        self.use_local_folder(&path);
+    }
+
+    // Selects a local folder after the checks a picked one gets; a partly unreadable one is asked about first.
+    fn use_local_folder(&mut self, path: &Path) {
+        match normalize_local_folder(path) {
+            Err(reason) => {
+                self.error_message = reason.to_string();
+                self.show_error_dialog = true;
+            }
+            Ok(path) if self.usb_drives.iter().any(|usb| path.starts_with(&usb.mount_point)) => {
+                self.error_message = "不能选择U盘或其子文件夹作为本地文件夹。".to_string();
+                self.show_error_dialog = true;
+            }
+            Ok(path) => {
+                let probe = probe_folder_permissions(&path);
+                if probe.is_significant() {
+                    self.permission_warning = Some((path, probe));
+                } else {
+                    self.local_folder = Some(path);
+                }
+            }
+        }
+    }
+
+    // Selects the pair named in a launch file, and starts it if the file asks to. Problems go to the error dialog.
+    fn open_launch_file(&mut self, path: &Path) {
+        let launch = match LaunchFile::load(path) {
+            Ok(launch) => launch,
+            Err(e) => {
+                self.error_message = format!("无法打开同步快捷文件 {}: {}", path.display(), e);
+                self.show_error_dialog = true;
+                return;
+            }
+        };
+        self.use_local_folder(&launch.local_folder);
+        if launch.usb_drive.is_some() || launch.usb_volume_id.is_some() {
+            // The volume finds the drive under whatever letter it has now; files without one go by the letter
+            let found = match &launch.usb_volume_id {
+                Some(volume_id) => self.drive_with_volume(volume_id),
+                None => launch.usb_drive.clone().filter(|drive| self.usb_drives.iter().any(|usb| &usb.mount_point == drive)),
+            };
+            let Some(drive) = found else {
+                let name = launch.usb_drive.as_ref().map(|drive| drive.display().to_string()).or(launch.usb_volume_id).unwrap_or_default();
+                self.error_message = format!("同步快捷文件指定的U盘 {} 未插入，请插入后点击刷新并选择。", name);
+                self.show_error_dialog = true;
+                return;
+            };
+            self.selected_usb_drive = Some(drive);
+            self.drive_chosen_for = self.local_folder.clone();
+        }
+        // An error or a warning about the folder comes first; the user starts the sync once it is dealt with
+        if !launch.auto || self.show_error_dialog || self.permission_warning.is_some() {
+            return;
+        }
+        match self.missing_requirement_hint() {
+            None => self.request_sync(),
+            Some(hint) => {
+                self.error_message = format!("无法自动开始同步: {}", hint);
+                self.show_error_dialog = true;
+            }
+        }
+    }
+
+    // Opens the diagnostics window; the text follows once the connected drives have been looked at.
+    fn open_support_report(&mut self) {
+        let drives_rx = Some(self.summarize_drives_in_background());
+        self.support_report = Some(SupportReport { drives: Vec::new(), drives_rx, redact_paths: false, text: String::new(), message: None });
+    }
+
+    // Like `refresh_usb_drives`, off the UI thread since listing drives can stall.
+    fn summarize_drives_in_background(&self) -> Receiver<Vec<DriveSummary>> {
+        let (tx, rx) = unbounded();
+        let ctx = self.ctx.clone();
+        thread::spawn(move || {
+            tx.send(summarize_drives()).ok();
+            ctx.request_repaint();
+        });
+        rx
+    }
+
+    fn support_report_text(&self, drives: &[DriveSummary], redact_paths: bool) -> String {
+        let log: Vec<String> = self.sync_log.iter().map(|line| line.text().to_owned()).collect();
+        // The most recent error in the log, or else the last error dialog
+        let last_error = log
+            .iter()
+            .rev()
+            .find(|line| is_error_log_line(line))
+            .map(String::as_str)
+            .or((!self.error_message.is_empty()).then_some(self.error_message.as_str()));
+        support_report(&SupportInfo { settings: &self.settings, drives, log: &log, last_error, redact_paths })
+    }
+
+    // Writes a launch file for the selected pair, wherever the user wants to double-click it from.
+    fn save_launch_file(&mut self) {
+        let Some(local) = self.local_folder.clone() else { return };
+        let name = local.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_else(|| "SyncU".to_owned());
+        let Some(target) = rfd::FileDialog::new()
+            .add_filter("SyncU", &[LAUNCH_FILE_EXTENSION])
+            .set_file_name(format!("{}.{}", name, LAUNCH_FILE_EXTENSION))
+            .save_file()
+        else {
+            return;
+        };
+        let launch = LaunchFile {
+            local_folder: local,
+            usb_drive: self.selected_usb_drive.clone(),
+            usb_volume_id: self.selected_volume_id(),
+            auto: false,
+        };
+        if let Err(e) = launch.save(&target) {
+            self.error_message = format!("保存同步快捷文件失败: {}", e);
+            self.show_error_dialog = true;
+        }
+    }
+
+    // Starts a run, unless other profiles overlap the selected folder; then the user is asked first.
+    fn request_sync(&mut self) {
+        let overlaps = self.local_folder.as_ref().map(|local| self.settings.overlapping_profiles(local)).unwrap_or_default();
+        if overlaps.is_empty() {
+            self.start_sync();
+        } else {
+            self.overlap_warning = Some(overlaps);
+        }
+    }
+
+    // Starts a run for the selected pair on its own thread.
+    fn start_sync(&mut self) {
+        self.finish_onboarding();
+        self.cancel_change_estimate();
+        self.state = SyncState::Syncing;
+        self.activity = ActivityKind::Bookkeeping;
+        self.paused = false;
+        self.soft_stop = false;
+        self.stats = None;
+        self.transfer_rate = TransferRate::default();
+        self.plan_panel.clear();
+        self.last_run = None;
+        self.show_unsynced_only = false;
+        self.show_problems_only = false;
+        self.scroll_to_first_error = false;
+        self.apply_to_all_conflicts = false;
+        self.remember_choice = false;
+        self.remember_deletion_choice = false;
+        self.remember_for_path = false;
+        self.consistency = None;
+        self.show_consistency_window = false;
+        self.small_files_hint = None;
+        self.sync_log = vec![RichText::new("正在开始同步...").color(self.palette.ready)];
+
+        if let (Some(local), Some(usb)) =
+            (self.local_folder.clone(), self.selected_usb_drive.clone())
+        {
+            // The folder's drive is selected by its volume next time, whatever letter it gets then
+            let volume_id = self.selected_volume_id();
+            if volume_id.is_some() && self.settings.profile_for(&local).usb_volume_id != volume_id {
+                self.remember_in_profile(|profile| profile.usb_volume_id = volume_id);
+            }
+            let profile = self.settings.profile_for(&local);
+            // Start from the folder's saved answers; without them every prompt asks
+            self.deletion_choice = profile.default_deletion_choice;
+            self.conflict_choice = profile.default_conflict_resolution.clone();
+            // Create new channels for this specific sync task.
+            let (tx_to_sync, rx_from_ui) = unbounded();
+            let (tx_from_sync, rx_from_sync) = unbounded();
+            self.tx_to_sync = Some(tx_to_sync);
+            self.rx_from_sync = rx_from_sync;
+
+            let prompt_timeout = profile.prompt_timeout_minutes.map(|minutes| std::time::Duration::from_secs(u64::from(minutes) * 60));
+            self.rate_limit_mb = profile.rate_limit_mb_per_sec;
+            let rate_limit = profile.rate_limit_bytes();
+            let monitor = self.monitor.as_ref().map(Monitor::sender);
+            let history_retention = self.settings.history_retention;
+            let sync_thread = thread::spawn(move || {
+                let observer = ChannelObserver::new(tx_from_sync, rx_from_ui)
+                    .with_prompt_timeout(prompt_timeout)
+                    .with_rate_limit(rate_limit)
+                    .with_monitor(monitor)
+                    .with_history(Some(history_retention));
+                run_sync(Some(local), Some(usb), profile, false, &observer);
+            });
+            self.sync_thread = Some(sync_thread);
+        }
+    }
+
+    // Explains what is still missing before a sync can start, or None if it can.
+    fn missing_requirement_hint(&self) -> Option<&'static str> {
+        if self.local_folder.is_none() {
+            Some("请先选择本地文件夹")
+        } else if self.usb_drives.is_empty() && self.drives_rx.is_some() {
+            Some("正在检测U盘...")
+        } else if self.usb_drives.is_empty() {
+            Some("未检测到U盘，请插入后点击刷新")
+        } else if self.selected_usb_drive.is_none() {
+            Some("请选择目标U盘")
+        } else if self.selected_drive_unavailable().is_some() {
+            Some("所选U盘当前无法使用")
+        } else {
+            None
+        }
+    }
+
+    // Why the selected drive can't be synced to, as of the last refresh.
+    fn selected_drive_unavailable(&self) -> Option<DriveUnavailable> {
+        let selected = self.selected_usb_drive.as_ref()?;
+        self.usb_drives.iter().find(|drive| &drive.mount_point == selected)?.unavailable
+    }
+
+    // The volume of the selected drive, as of the last refresh.
+    fn selected_volume_id(&self) -> Option<String> {
+        let selected = self.selected_usb_drive.as_ref()?;
+        self.usb_drives.iter().find(|drive| &drive.mount_point == selected)?.volume_id.clone()
+    }
+
+    // Where the listed drive holding the volume is mounted now.
+    fn drive_with_volume(&self, volume_id: &str) -> Option<PathBuf> {
+        self.usb_drives.iter().find(|drive| drive.volume_id.as_deref() == Some(volume_id)).map(|drive| drive.mount_point.clone())
+    }
+
+    // Selects the drive a newly chosen folder was last synced to, found by its volume so a changed letter
+    // doesn't matter. Waits for a drive list, then runs once per folder; a drive the user picks afterwards stands.
+    fn select_remembered_drive(&mut self) {
+        if self.usb_drives.is_empty() || self.local_folder == self.drive_chosen_for {
+            return;
+        }
+        self.drive_chosen_for = self.local_folder.clone();
+        let Some(local) = &self.local_folder else { return };
+        let remembered = self.settings.profile_for(local).usb_volume_id;
+        if let Some(drive) = remembered.and_then(|volume_id| self.drive_with_volume(&volume_id)) {
+            self.selected_usb_drive = Some(drive);
+        }
+    }
+
+    // Lists the drives again on a background thread, unless a listing is already under way.
+    fn refresh_usb_drives(&mut self) {
+        if self.drives_rx.is_some() {
+            return;
+        }
+        let (tx, rx) = unbounded();
+        let ctx = self.ctx.clone();
+        thread::spawn(move || {
+            tx.send(find_usb_drives()).ok();
+            ctx.request_repaint();
+        });
+        self.drives_rx = Some(rx);
+    }
+
+    // Takes in a finished drive list. A single drive is selected right away; a drive that is gone is deselected,
+    // unless its volume is back under another letter.
+    fn poll_usb_drives(&mut self) {
+        let Some(rx) = &self.drives_rx else { return };
+        let drives = match rx.try_recv() {
+            Ok(drives) => drives,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => Vec::new(),
+        };
+        let selected_volume = self.selected_volume_id();
+        self.drives_rx = None;
+        self.usb_drives = drives;
+        if let Some(drive) = selected_volume.and_then(|volume_id| self.drive_with_volume(&volume_id)) {
+            self.selected_usb_drive = Some(drive);
+        } else if self.usb_drives.len() == 1 {
+            self.selected_usb_drive = Some(self.usb_drives[0].mount_point.clone());
+        } else if self.selected_usb_drive.as_ref().is_some_and(|selected| !self.usb_drives.iter().any(|drive| &drive.mount_point == selected)) {
+            self.selected_usb_drive = None;
+        }
+        if let Some(path) = self.pending_launch_file.take() {
+            self.open_launch_file(&path);
+        }
+    }
+
+    // Location of the sync metadata for the selected folder and drive.
+    fn metadata_path(&self) -> Option<PathBuf> {
+        let local = self.local_folder.as_ref()?;
+        let usb = self.selected_usb_drive.as_ref()?;
+        Some(metadata_path(&usb.join(local.file_name()?)))
+    }
+
+    // The page written by the last run for the selected folder and drive, if it wrote one.
+    fn latest_report(&self) -> Option<PathBuf> {
+        let local = self.local_folder.as_ref()?;
+        let usb = self.selected_usb_drive.as_ref()?;
+        latest_report(&usb.join(local.file_name()?))
+    }
+
+    fn load_kept_files(&mut self) {
+        self.kept_files = match self.metadata_path().map(|path| load_sync_data(&path)) {
+            Some(Ok(sync_data)) => sync_data.tombstones.into_keys().collect(),
+            Some(Err(e)) => {
+                self.error_message = format!("读取同步记录失败: {}", e);
+                self.show_error_dialog = true;
+                Vec::new()
+            }
+            None => Vec::new(),
+        };
+        self.kept_files.sort();
+    }
+
+    // Removes a tombstone so the next sync asks about the file again.
+    fn forget_kept_file(&mut self, path: &Path) {
+        let Some(metadata_path) = self.metadata_path() else { return };
+        let result = load_sync_data(&metadata_path).and_then(|mut sync_data| {
+            ensure_writable(&sync_data, &metadata_path)?;
+            sync_data.tombstones.remove(path);
+            save_sync_data(&sync_data, &metadata_path)
+        });
+        match result {
+            Ok(()) => self.kept_files.retain(|kept| kept != path),
+            Err(e) => {
+                self.error_message = format!("更新同步记录失败: {}", e);
+                self.show_error_dialog = true;
+            }
+        }
+    }
+
+    // Sends the answer to the prompt at the front of the queue and moves on to the next one.
+    fn answer_front_prompt(&mut self, reply: SyncMessage) {
+        if let Some(tx) = &self.tx_to_sync {
+            tx.send(reply).ok();
+        }
+        self.pending_prompts.pop_front();
+        self.answer_queued_with_defaults();
+    }
+
+    // Answers queued prompts that a "全部…" choice now covers.
+    fn answer_queued_with_defaults(&mut self) {
+        let Some(tx) = &self.tx_to_sync else { return };
+        let deletion_choice = self.deletion_choice;
+        let conflict_choice = self.conflict_choice.clone();
+        self.pending_prompts.retain(|prompt| match (prompt, deletion_choice, &conflict_choice) {
+            (PendingPrompt::Deletion { id, .. }, Some(confirmed), _) => {
+                tx.send(SyncMessage::DeletionConfirmed { id: *id, confirmed }).ok();
+                false
+            }
+            (PendingPrompt::Conflict { id, .. }, _, Some(resolution)) => {
+                tx.send(SyncMessage::ConflictResolved { id: *id, resolution: resolution.clone() }).ok();
+                false
+            }
+            _ => true,
+        });
+    }
+
+    // Writes a dialog answer back to the current folder's profile.
+    fn remember_in_profile(&mut self, update: impl FnOnce(&mut Profile)) {
+        if let Some(local) = self.local_folder.clone() {
+            update(self.settings.profile_mut_for(&local));
+            if let Err(e) = self.settings.save() {
+                self.error_message = format!("保存设置失败: {}", e);
+                self.show_error_dialog = true;
+            }
+        }
+    }
+
+    // Handles the messages from the sync thread, up to a frame's worth.
+    fn handle_sync_messages(&mut self, ctx: &egui::Context) {
+        let batches = drain_messages(&self.rx_from_sync, MAX_MESSAGES_PER_FRAME);
+        let drained = !batches.is_empty();
+        for batch in batches {
+            let msg = match batch {
+                MessageBatch::Logs(lines) => {
+                    for log in lines {
+                        let color = if is_error_log_line(&log) {
+                            self.palette.error
+                        } else if is_warning_log_line(&log) {
+                            self.palette.warning
+                        } else if log.starts_with("[") {
+                            self.palette.success
+                        } else {
+                            ctx.style().visuals.text_color()
+                        };
+                        self.session_log.append(&log);
+                        self.sync_log.push(RichText::new(log).color(color));
                     }
+                    continue;
+                }
+                MessageBatch::Message(msg) => msg,
+            };
+            match msg {
+                SyncMessage::ConfirmDeletion { id, path, position, total } => {
+                    self.pending_prompts.push_back(PendingPrompt::Deletion { id, path, position, total });
+                    self.answer_queued_with_defaults();
+                }
+                SyncMessage::AskForConflictResolution { id, path, diff, suggestion } => {
+                    self.pending_prompts.push_back(PendingPrompt::Conflict { id, path, diff, suggestion });
+                    self.answer_queued_with_defaults();
+                }
+                SyncMessage::ConfirmCopyInUse(path) => {
+                    self.show_in_use_confirmation = true;
+                    self.file_in_use = Some(path);
+                }
+                SyncMessage::PromptExpired { id } => {
+                    self.pending_prompts.retain(|prompt| match prompt {
+                        PendingPrompt::Deletion { id: prompt_id, .. } | PendingPrompt::Conflict { id: prompt_id, .. } => *prompt_id != id,
+                    });
+                }
+                SyncMessage::ConfirmOverwriteNewer(path) => {
+                    self.newer_destination = Some(path);
+                }
+                SyncMessage::ConfirmRetryDenied { path, error } => {
+                    self.denied_copy = Some((path, error));
+                }
+                SyncMessage::ConfirmRemoteMissing { missing, known, examples } => {
+                    self.remote_missing_state = Some(RemoteMissingState { missing, known, examples });
+                }
+                SyncMessage::ConfirmCrowdedDirectories(directories) => {
+                    self.crowded_directories_state = Some(CrowdedDirectoriesState { directories });
+                }
+                SyncMessage::ConfirmLongPaths { limit, count, examples } => {
+                    self.long_paths_state = Some(LongPathsState { limit, count, examples });
+                }
+                SyncMessage::ConfirmUnreadableSizes { count, examples } => {
+                    self.unreadable_sizes_prompt = Some((count, examples));
+                }
+                SyncMessage::ConfirmNameCollisions { count, examples } => {
+                    self.name_collisions_state = Some(NameCollisionsState { count, examples });
+                }
+                SyncMessage::ConfirmRelink { old_name, new_name } => {
+                    self.relink_prompt = Some((old_name, new_name));
                 }
-                SyncMessage::AskForConflictResolution { path } => {
-                    self.show_conflict_resolution = true;
-                    self.conflict_state = Some(ConflictState { path });
+                SyncMessage::ConfirmResumePlan { remaining, total } => {
+                    self.resume_plan_prompt = Some((remaining, total));
                 }
-                SyncMessage::Progress(progress, file) => {
+                SyncMessage::ConfirmNewerMetadata(version) => {
+                    self.newer_metadata_prompt = Some(version);
+                }
+                SyncMessage::AskForClockSkewResolution(description) => {
+                    self.show_clock_warning = true;
+                    self.clock_warning_message = description;
+                }
+                SyncMessage::Progress(progress, file, activity) => {
+                    // Only the planned actions move through the bytes the speed is measured in
+                    if let Some(stats) = self.stats.as_ref().filter(|_| activity != ActivityKind::Bookkeeping) {
+                        self.transfer_rate.update((f64::from(progress) * stats.total_bytes as f64) as u64);
+                    }
                     self.progress = progress;
                     self.current_file = file;
+                    self.activity = activity;
+                }
+                SyncMessage::Stats(stats) => {
+                    self.stats = Some(stats);
+                }
+                SyncMessage::SpaceEstimate(estimate) => {
+                    self.plan_panel.set_space_estimate(estimate);
+                }
+                SyncMessage::Plan(actions) => {
+                    self.plan_panel.set_plan(actions);
+                }
+                SyncMessage::ActionFinished { index, status } => {
+                    self.plan_panel.action_finished(index, status);
+                }
+                SyncMessage::ConsistencyChecked(report) => {
+                    self.consistency = Some(report);
+                }
+                SyncMessage::ManySmallFiles(count) => {
+                    let handled_before = self.stats.as_ref().map_or(0, |stats| stats.completed + stats.skipped + stats.failed);
+                    self.small_files_hint = Some(SmallFilesHint { count, since: Instant::now(), handled_before });
+                }
+                SyncMessage::DeviceRemoved(path) => {
+                    self.last_run = Some(RunSnapshot {
+                        progress: self.progress,
+                        current_file: self.current_file.clone(),
+                        stats: self.stats.clone(),
+                        reason: "因U盘移除中断",
+                    });
+                    self.error_message = format!("U盘已被移除: {}\n请重新插入后点击刷新并再次同步。", path.display());
+                    self.show_error_dialog = true;
+                    self.refresh_usb_drives();
                 }
                 SyncMessage::Complete => {
                     self.state = SyncState::Idle;
-                    self.sync_log
-                        .push(RichText::new("同步完成!").color(Color32::from_rgb(0, 100, 0)));
+                    self.local_totals.clear();
+                    self.usb_totals.clear();
+                    self.pending_prompts.clear();
+                    // Only a run that left nothing behind gets the green message
+                    let unsynced = self.stats.as_ref().and_then(SyncStats::unsynced_summary);
+                    let (text, color) = match &unsynced {
+                        None => ("同步完成!".to_owned(), self.palette.ready),
+                        Some(summary) => (format!("同步完成（{}）", summary), self.palette.warning),
+                    };
+                    self.session_log.append(&text);
+                    self.sync_log.push(RichText::new(text).color(color));
+                    self.completion_summary = unsynced;
+                    self.scroll_to_first_error = self.sync_log.iter().any(|line| is_error_log_line(line.text()));
+                }
+                SyncMessage::CompleteWithoutState(details) => {
+                    self.state = SyncState::Idle;
+                    self.local_totals.clear();
+                    self.usb_totals.clear();
+                    self.pending_prompts.clear();
+                    let text = format!("完成但写入状态失败: {}", details);
+                    self.session_log.append(&text);
+                    self.sync_log.push(RichText::new(text).color(self.palette.error));
+                    self.scroll_to_first_error = true;
+                    // The next run sees the previous metadata, so it may ask about changes this run already made
+                    self.error_message = format!(
+                        "文件已同步，但同步记录未能安全写入U盘:\n{}\n\n请检查U盘后再同步一次，以免下次同步误判变更。",
+                        details
+                    );
+                    self.show_error_dialog = true;
                 }
                 SyncMessage::Stopped => {
+                    self.last_run = Some(RunSnapshot {
+                        progress: self.progress,
+                        current_file: self.current_file.clone(),
+                        stats: self.stats.clone(),
+                        reason: "已停止",
+                    });
                     self.state = SyncState::Idle;
+                    self.local_totals.clear();
+                    self.usb_totals.clear();
+                    self.pending_prompts.clear();
+                    self.session_log.append("同步已停止.");
                     self.sync_log
-                        .push(RichText::new("同步已停止.").color(Color32::from_rgb(210, 210, 90)));
+                        .push(RichText::new("同步已停止.").color(self.palette.stopped));
                 }
                 _ => {}
             }
-            self.ctx.request_repaint();
-        }
-
-        if self.show_error_dialog {
-            egui::Window::new("错误")
-                .collapsible(false)
-                .resizable(false)
-                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
-                .show(ctx, |ui| {
-                    ui.add_space(15.0);
-                    ui.label(&self.error_message);
-                    ui.add_space(10.0);
-                    ui.separator();
-                    ui.vertical_centered(|ui| {
-                        if ui.button("关闭").clicked() {
-                            self.show_error_dialog = false;
-                        }
-                    });
-                });
         }
+        // The sync thread can't wake the UI, so it keeps polling while messages arrive, once per frame;
+        // after a capped drain this also picks up the rest
+        if drained {
+            ctx.request_repaint();
+        }
+    }
+}
 
-        if self.show_confirmation {
-            egui::Window::new("确认删除")
-                .collapsible(false)
-                .resizable(false)
-                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
-                .show(ctx, |ui| {
-                    ui.add_space(15.0);
-                    if let Some(path) = &self.file_to_delete {
-                        let item_type = if path.is_dir() { "目录" } else { "文件" };
-                        ui.label(format!("您确定要删除{item_type}\n'{}'？", path.display()));
-                    }
-                    ui.add_space(10.0);
-                    ui.separator();
-                    ui.vertical(|ui| {
-                        ui.horizontal(|ui| {
-                            if ui.button("确认").clicked() {
-                                if let Some(tx) = &self.tx_to_sync {
-                                    tx.send(SyncMessage::DeletionConfirmed(true)).ok();
-                                }
-                                self.show_confirmation = false;
-                            }
-                            if ui.button("取消").clicked() {
-                                if let Some(tx) = &self.tx_to_sync {
-                                    tx.send(SyncMessage::DeletionConfirmed(false)).ok();
-                                }
-                                self.show_confirmation = false;
-                            }
-                            if ui.button("全部删除").clicked() {
-                                self.deletion_choice = Some(true);
-                                if let Some(tx) = &self.tx_to_sync {
-                                    tx.send(SyncMessage::DeletionConfirmed(true)).ok();
-                                }
-                                self.show_confirmation = false;
-                            }
-                            if ui.button("全部保留").clicked() {
-                                self.deletion_choice = Some(false);
-                                if let Some(tx) = &self.tx_to_sync {
-                                    tx.send(SyncMessage::DeletionConfirmed(false)).ok();
-                                }
-                                self.show_confirmation = false;
-                            }
-                        });
-                    });
-                });
+// A share of the messages from the sync thread: consecutive log lines together, anything else on its own.
+#[derive(Debug)]
+enum MessageBatch {
+    Logs(Vec<String>),
+    Message(SyncMessage),
+}
+
+// Takes at most `limit` waiting messages, in the order they were sent. Log lines are gathered into batches, so a
+// flood of them is added to the log in one go per frame, still before any line a later message adds.
+fn drain_messages(rx: &Receiver<SyncMessage>, limit: usize) -> Vec<MessageBatch> {
+    let mut batches = Vec::new();
+    for msg in rx.try_iter().take(limit) {
+        match msg {
+            SyncMessage::Log(log) => match batches.last_mut() {
+                Some(MessageBatch::Logs(lines)) => lines.push(log),
+                _ => batches.push(MessageBatch::Logs(vec![log])),
+            },
+            msg => batches.push(MessageBatch::Message(msg)),
         }
+    }
+    batches
+}
 
-        if self.show_conflict_resolution {
-            if let Some(conflict) = &self.conflict_state {
-                egui::Window::new(format!("解决冲突: {}", conflict.path.display()))
-                    .collapsible(false)
-                    .resizable(false)
-                    .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
-                    .show(ctx, |ui| {
-                        ui.add_space(15.0);
-                        ui.label("文件在本地和U盘上均被修改。请选择要保留的版本。");
-                        ui.add_space(10.0);
-                        ui.separator();
-                        ui.horizontal(|ui| {
-                            if ui.button("采用本地版本").clicked() {
-                                if let Some(tx) = &self.tx_to_sync {
-                                    tx.send(SyncMessage::ConflictResolved(Resolution::KeepLocal))
-                                        .ok();
-                                }
-                                self.show_conflict_resolution = false;
-                            }
-                            if ui.button("采用U盘版本").clicked() {
-                                if let Some(tx) = &self.tx_to_sync {
-                                    tx.send(SyncMessage::ConflictResolved(Resolution::KeepRemote))
-                                        .ok();
-                                }
-                                self.show_conflict_resolution = false;
-                            }
-                            if ui.button("跳过").clicked() {
-                                if let Some(tx) = &self.tx_to_sync {
-                                    tx.send(SyncMessage::ConflictResolved(Resolution::Skip))
-                                        .ok();
-                                }
-                                self.show_conflict_resolution = false;
-                            }
-                        });
-                    });
-            }
+// Renders a path shortened in the middle to fit the row, with the full text on hover and a copy menu.
+fn elided_path_label(ui: &mut egui::Ui, text: &str, reserved_width: f32, weak: bool) -> egui::Response {
+    let max_width = (ui.available_width() - reserved_width).max(40.0);
+    let font_id = egui::TextStyle::Body.resolve(ui.style());
+    let shown = elide_middle(text, max_width, |candidate| {
+        ui.fonts(|fonts| {
+            fonts
+                .layout_no_wrap(candidate.to_owned(), font_id.clone(), Color32::PLACEHOLDER)
+                .size()
+                .x
+        })
+    });
+    let rich_text = if weak { RichText::new(shown).weak() } else { RichText::new(shown) };
+    let response = ui
+        .add(egui::Label::new(rich_text).sense(egui::Sense::click()))
+        .on_hover_text(text);
+    response.context_menu(|ui| {
+        if ui.button("复制路径").clicked() {
+            ui.ctx().copy_text(text.to_owned());
+            ui.close();
         }
+    });
+    response
+}
 
-        if self.show_about_window {
-            egui::Window::new("关于 SyncU")
-                .collapsible(false)
-                .resizable(false)
-                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
-                .show(ctx, |ui| {
-                    ui.add_space(15.0);
-                    ui.vertical_centered(|ui| {
-                        ui.heading("SyncU");
-                        ui.add_space(10.0);
-                        ui.label(format!("版本: {}", APP_VERSION));
-                        ui.add_space(10.0);
-                        ui.label("作者: Eight_Eggs");
-                        ui.add_space(10.0);
-                        ui.hyperlink_to("访问 GitHub 仓库", "https://github.com/EightEggs/SyncU");
-                    });
-                    ui.add_space(10.0);
-                    ui.separator();
-                    ui.vertical_centered(|ui| {
-                        if ui.button("关闭").clicked() {
-                            self.show_about_window = false;
-                        }
-                    });
-                });
+// A drive as listed in the drive picker: unusable drives are greyed out and tagged with the reason.
+fn drive_text(mount_point: &Path, unavailable: Option<DriveUnavailable>) -> RichText {
+    let path = mount_point.to_string_lossy();
+    match unavailable {
+        None => RichText::new(path),
+        Some(reason) => RichText::new(format!("{}  ({})", path, reason.label())).weak(),
+    }
+}
+
+// Log lines about items a run skipped, declined or failed to sync.
+fn is_unsynced_log_line(line: &str) -> bool {
+    line.starts_with("错误") || ["跳过", "取消删除", "保留且不再询问", "失败"].iter().any(|keyword| line.contains(keyword))
+}
+
+// Log lines shown in the error colour.
+fn is_error_log_line(line: &str) -> bool {
+    line.starts_with("错误") || line.starts_with("完成但写入状态失败")
+}
+
+fn is_warning_log_line(line: &str) -> bool {
+    line.starts_with("警告")
+}
+
+// The action a per-item log line reports, e.g. "本地 -> U盘" for "[12:00:01] 本地 -> U盘: a.txt".
+// Warnings, errors and untimed phase messages have none, so they end a group instead of joining it.
+fn log_item_kind(line: &str) -> Option<&str> {
+    let (_, rest) = line.strip_prefix('[')?.split_once("] ")?;
+    let (kind, _) = rest.split_once(": ")?;
+    Some(kind)
+}
+
+fn deletion_choice_label(choice: Option<bool>) -> &'static str {
+    match choice {
+        None => "每次询问",
+        Some(true) => "全部删除",
+        Some(false) => "全部保留",
+    }
+}
+
+fn conflict_choice_label(choice: &Option<Resolution>) -> &'static str {
+    match choice {
+        None => "每次询问",
+        Some(Resolution::KeepLocal) => "采用本地版本",
+        Some(Resolution::KeepRemote) => "采用U盘版本",
+        Some(Resolution::Skip) => "跳过",
+    }
+}
+
+impl eframe::App for SyncApp {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        self.palette = Palette::new(&self.current_theme, self.settings.accent_color);
+        crate::apply_theme(ctx, &self.current_theme, &self.palette);
+        self.dialog_focus.begin_frame();
+        self.refresh_nested_root_warning();
+        self.poll_usb_drives();
+        self.select_remembered_drive();
+        self.refresh_change_estimate();
+        self.refresh_folder_totals();
+        self.handle_sync_messages(ctx);
+        self.refresh_compact_mode(ctx);
+        self.taskbar.set(frame, self.taskbar_state());
+        // Come back for the pending flush even if nothing else happens
+        if self.session_log.flush_if_due() {
+            ctx.request_repaint_after(std::time::Duration::from_secs(1));
         }
 
-        egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
-            ui.horizontal(|ui| {
-                ui.menu_button("文件", |ui| {
-                    if ui.button("关于").clicked() {
-                        self.show_about_window = true;
-                        ui.close();
-                    }
-                    if ui.button("退出").clicked() {
-                        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
-                    }
-                });
-                ui.separator();
-                ui.menu_button("主题", |ui| {
-                    if ui
-                        .selectable_value(&mut self.current_theme, Theme::Light, "明亮")
-                        .clicked()
-                    {
-                        ui.close();
-                    }
-                    if ui
-                        .selectable_value(&mut self.current_theme, Theme::Dark, "暗黑")
-                        .clicked()
-                    {
-                        ui.close();
-                    }
-                });
-            });
-        });
+        self.show_dialogs(ctx);
+        if self.compact.is_some() {
+            self.show_compact_panel(ctx);
+            return;
+        }
+        self.show_menu_bar(ctx);
+        self.show_status_bar(ctx);
+        self.plan_panel.show(ctx, &self.palette);
+        self.show_main_panel(ctx);
+        self.show_onboarding(ctx);
+    }
+}
 
-        egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
-            ui.add_space(4.0);
-            if self.state != SyncState::Idle {
-                ui.horizontal(|ui| {
-                    if self.state == SyncState::Syncing {
-                        ui.add(egui::Spinner::new());
-                    }
-                    ui.add(egui::ProgressBar::new(self.progress).desired_width(200.0));
-                    ui.label(&self.current_file);
-                });
-            } else {
-                ui.horizontal(|ui| {
-                    ui.label(
-                        self.sync_log
-                            .last()
-                            .cloned()
-                            .unwrap_or_else(|| RichText::new("准备就绪")),
-                    );
-                });
-            }
-            ui.add_space(4.0);
-        });
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        egui::CentralPanel::default().show(ctx, |ui| {
-            // When a dialog is shown, disable the main UI
-            let main_ui_enabled = !self.show_conflict_resolution
-                && !self.show_confirmation
-                && !self.show_about_window
-                && !self.show_error_dialog;
-            ui.add_enabled_ui(main_ui_enabled, |ui| {
-                ui.vertical_centered(|ui| {
-                    ui.add_space(5.0);
-                    ui.heading("SyncU: Sync Your Files with USB");
-                    ui.add_space(5.0);
-                });
+    fn message_count(batch: &MessageBatch) -> usize {
+        match batch {
+            MessageBatch::Logs(lines) => lines.len(),
+            MessageBatch::Message(_) => 1,
+        }
+    }
 
-                ui.add_space(1.0);
-                ui.add_enabled_ui(self.state == SyncState::Idle, |ui| {
-                    ui.vertical_centered(|ui| {
-                        egui::Frame::group(ui.style())
-                            .corner_radius(egui::CornerRadius::same(8))
-                            .inner_margin(egui::Margin::same(12))
-                            .show(ui, |ui| {
-                                // Use vertical layout for rows
-                                ui.vertical(|ui| {
-                                    // First row: Local folder
-                                    ui.horizontal(|ui| {
-                                        ui.label("本地:");
-                                        let local_path_text = self
-                                            .local_folder
-                                            .as_ref()
-                                            .map_or("未选择", |p| p.to_str().unwrap_or(""));
-                                        ui.label(RichText::new(local_path_text).weak());
-
-                                        // Align button to the right
-                                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                                            if ui.button("选择...").clicked() {
-                                                if let Some(path) = rfd::FileDialog::new().pick_folder()
-                                                {
-                                                    let is_usb = self
-                                                        .usb_drives
-                                                        .iter()
-                                                        .any(|usb| path.starts_with(usb));
-                                                    if is_usb {
-                                                        self.error_message =
-                                                            "不能选择U盘或其子文件夹作为本地文件夹。"
-                                                                .to_string();
-                                                        self.show_error_dialog = true;
-                                                    } else {
-                                                        self.local_folder = Some(path);
-                                                    }
-                                                }
-                                            }
-                                        });
-                                    });
-
-                                    ui.add_space(5.0); // spacing between rows
-
-                                    // Second row: USB drive
-                                    ui.horizontal(|ui| {
-                                        ui.label("U盘:");
-                                        if self.usb_drives.len() > 1 {
-                                            egui::ComboBox::from_label("")
-                                                .selected_text(
-                                                    self.selected_usb_drive
-                                                        .as_ref()
-                                                        .map_or("请选择U盘", |p| {
-                                                            p.to_str().unwrap_or("")
-                                                        }),
-                                                )
-                                                .show_ui(ui, |ui| {
-                                                    for drive in &self.usb_drives {
-                                                        ui.selectable_value(
-                                                            &mut self.selected_usb_drive,
-                                                            Some(drive.clone()),
-                                                            drive.to_str().unwrap_or(""),
-                                                        );
-                                                    }
-                                                });
-                                        } else {
-                                            let usb_path_text = self
-                                                .selected_usb_drive
-                                                .as_ref()
-                                                .map_or("未检测到", |p| {
-                                                    p.to_str().unwrap_or("")
-                                                });
-                                            ui.label(RichText::new(usb_path_text).weak());
-                                        }
-
-                                        // Align button to the right
-                                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                                            if ui.button(" 刷新 ").clicked() {
-                                                self.usb_drives = find_usb_drives();
-                                                if self.usb_drives.len() == 1 {
-                                                    self.selected_usb_drive =
-                                                        Some(self.usb_drives[0].clone());
-                                                }
-                                            }
-                                        });
-                                    });
-                                });
-                            });
-                    });
-                });
+    #[test]
+    fn a_flood_of_messages_is_drained_in_capped_frames_in_order() {
+        let sent: Vec<SyncMessage> = (0..12_000)
+            .flat_map(|n| {
+                let log = SyncMessage::Log(format!("line {}", n));
+                let progress = (n % 1_000 == 999).then(|| SyncMessage::Progress(n as f32, format!("f{}", n), ActivityKind::Copy));
+                std::iter::once(log).chain(progress)
+            })
+            .collect();
+        let (tx, rx) = unbounded();
+        for msg in &sent {
+            tx.send(msg.clone()).unwrap();
+        }
 
-                ui.add_space(5.0);
-
-                ui.vertical_centered(|ui| {
-                    match self.state {
-                        SyncState::Idle => {
-                            let enabled =
-                                self.local_folder.is_some() && self.selected_usb_drive.is_some();
-                            let sync_button = egui::Button::new(RichText::new("立即同步"))
-                                .corner_radius(egui::CornerRadius::same(6))
-                                .min_size(egui::vec2(250.0, 40.0));
-                            if ui.add_enabled(enabled, sync_button).clicked() {
-                                self.state = SyncState::Syncing;
-                                self.deletion_choice = None; // Reset deletion choice
-                                self.sync_log = vec![RichText::new("正在开始同步...")
-                                    .color(Color32::from_rgb(0, 100, 0))];
-
-                                if let (Some(local), Some(usb)) =
-                                    (self.local_folder.clone(), self.selected_usb_drive.clone())
-                                {
-                                    // Create new channels for this specific sync task.
-                                    let (tx_to_sync, rx_from_ui) = unbounded();
-                                    let (tx_from_sync, rx_from_sync) = unbounded();
-                                    self.tx_to_sync = Some(tx_to_sync);
-                                    self.rx_from_sync = rx_from_sync;
-
-                                    let sync_thread = thread::spawn(move || {
-                                        run_sync(
-                                            Some(local),
-                                            Some(usb),
-                                            tx_from_sync,
-                                            rx_from_ui,
-                                        );
-                                    });
-                                    self.sync_thread = Some(sync_thread);
-                                }
-                            }
-                        }
-                        SyncState::Syncing => {
-                            let stop_button = egui::Button::new(
-                                RichText::new("停止同步").color(egui::Color32::WHITE),
-                            )
-                            .corner_radius(egui::CornerRadius::same(6))
-                            .min_size(egui::vec2(250.0, 40.0))
-                            .fill(Color32::from_rgb(200, 30, 70));
-                            if ui.add(stop_button).clicked() {
-                                self.state = SyncState::Stopping;
-                                if let Some(tx) = &self.tx_to_sync {
-                                    tx.send(SyncMessage::Stop).ok();
-                                }
-                            }
-                        }
-                        SyncState::Stopping => {
-                            let stop_button = egui::Button::new(
-                                RichText::new("正在停止...").color(egui::Color32::WHITE),
-                            )
-                            .corner_radius(egui::CornerRadius::same(6))
-                            .min_size(egui::vec2(250.0, 40.0))
-                            .fill(Color32::from_rgb(200, 30, 70));
-                            ui.add_enabled(false, stop_button);
-                        }
-                    }
-                });
+        let mut frames = Vec::new();
+        loop {
+            let batches = drain_messages(&rx, MAX_MESSAGES_PER_FRAME);
+            if batches.is_empty() {
+                break;
+            }
+            frames.push(batches);
+        }
 
-                ui.add_space(5.0);
-
-                egui::Frame::group(ui.style())
-                    .corner_radius(egui::CornerRadius::same(8))
-                    .inner_margin(egui::Margin::same(12))
-                    .show(ui, |ui| {
-                        ui.heading(RichText::new("日志").size(16.0));
-                        ui.separator();
-                        egui::ScrollArea::vertical()
-                            .max_height(234.0)
-                            .stick_to_bottom(true)
-                            .auto_shrink([false; 2])
-                            .show(ui, |ui| {
-                                for log in &self.sync_log {
-                                    ui.label(log.clone());
-                                }
-                            });
-                    });
-            });
-        });
+        assert_eq!(frames.len(), sent.len().div_ceil(MAX_MESSAGES_PER_FRAME));
+        let counts: Vec<usize> = frames.iter().map(|batches| batches.iter().map(message_count).sum()).collect();
+        assert!(counts[..counts.len() - 1].iter().all(|&count| count == MAX_MESSAGES_PER_FRAME), "{:?}", counts);
+        // Lines between two other messages go in one batch
+        for batches in &frames {
+            assert!(!batches.windows(2).any(|pair| matches!(pair, [MessageBatch::Logs(_), MessageBatch::Logs(_)])));
+        }
+        let received: Vec<SyncMessage> = frames
+            .into_iter()
+            .flatten()
+            .flat_map(|batch| match batch {
+                MessageBatch::Logs(lines) => lines.into_iter().map(SyncMessage::Log).collect(),
+                MessageBatch::Message(msg) => vec![msg],
+            })
+            .collect();
+        assert_eq!(received, sent);
     }
 }
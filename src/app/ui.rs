@@ -0,0 +1,7 @@
+//! The parts of the main window, each drawn by a method on `SyncApp` and called from `update` in a fixed order.
+
+mod compact;
+mod dialogs;
+mod main_panel;
+mod menu;
+mod status_bar;
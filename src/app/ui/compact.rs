@@ -0,0 +1,56 @@
+//! The strip the window shrinks to while a run goes on: progress, speed and time left, the current file,
+//! and the buttons to pause or stop. Messages from the run are handled as in the full window.
+
+use crate::app::{elided_path_label, SyncApp, SyncState};
+use crate::models::SyncMessage;
+use egui::RichText;
+
+impl SyncApp {
+    pub(in crate::app) fn show_compact_panel(&mut self, ctx: &egui::Context) {
+        let mut leave = false;
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.add(egui::ProgressBar::new(self.progress).show_percentage());
+            elided_path_label(ui, &self.current_file, 0.0, false);
+            ui.horizontal(|ui| {
+                if self.paused {
+                    ui.label(RichText::new("已暂停").small().color(self.palette.warning));
+                } else if self.state == SyncState::Stopping {
+                    ui.label(RichText::new("正在停止...").small().color(self.palette.warning));
+                } else if let Some(transfer) = self.transfer_text() {
+                    ui.label(RichText::new(transfer).small());
+                }
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    leave = ui.small_button("展开").on_hover_text("恢复完整窗口").clicked();
+                    if let Some(compact) = &mut self.compact
+                        && ui.checkbox(&mut compact.always_on_top, "置顶").changed()
+                    {
+                        let level = if compact.always_on_top { egui::WindowLevel::AlwaysOnTop } else { egui::WindowLevel::Normal };
+                        ctx.send_viewport_cmd(egui::ViewportCommand::WindowLevel(level));
+                    }
+                    match self.state {
+                        SyncState::Syncing => {
+                            let stop = egui::Button::new(RichText::new("停止").small().color(egui::Color32::WHITE)).fill(self.palette.stop);
+                            if ui.add(stop).on_hover_text("立即停止同步").clicked() {
+                                self.stop_sync(false);
+                            }
+                            let pause_label = if self.paused { "继续" } else { "暂停" };
+                            let can_pause = self.paused || !self.waiting_for_answer();
+                            if ui.add_enabled(can_pause, egui::Button::new(RichText::new(pause_label).small())).clicked() {
+                                self.toggle_pause();
+                            }
+                        }
+                        // A soft stop can take as long as the copy in progress
+                        SyncState::Stopping if self.soft_stop && ui.small_button("立即停止").clicked() => {
+                            self.send_to_sync(SyncMessage::Stop);
+                            self.soft_stop = false;
+                        }
+                        _ => {}
+                    }
+                });
+            });
+        });
+        if leave {
+            self.leave_compact_mode(ctx);
+        }
+    }
+}
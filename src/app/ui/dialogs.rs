@@ -0,0 +1,1958 @@
+//! Prompts from the running sync, confirmations and the secondary windows, drawn on top of the main window.
+
+use crate::app::{
+    conflict_choice_label, deletion_choice_label, elided_path_label, format_time, AnswerRulesEditor, HistoryDetail, PendingPrompt, SyncApp, SyncState, APP_VERSION, DEFAULT_DIRECTORY_ENTRY_SOFT_LIMIT,
+    DEFAULT_RATE_LIMIT_MB_PER_SEC,
+};
+use crate::history::history_size_text;
+use crate::models::{ClockSkewChoice, CrowdedDirectoryChoice, DiffLine, LongPathChoice, NameCollisionChoice, RemoteMissingChoice, Resolution, SyncMessage};
+use crate::settings::{AnswerRule, ConflictAnswer, DeletionAnswer, DeviceLogVerbosity, HardLinkPolicy, InUsePolicy, LineEndingPolicy, NewerDestinationPolicy, RoutingRule};
+use crate::sync::{OrphanFile, OrphanKind};
+use crate::utils::{format_count, format_size};
+use egui::RichText;
+use std::path::PathBuf;
+
+impl SyncApp {
+    // Later windows are drawn above earlier ones, so the order here is the stacking order.
+    pub(in crate::app) fn show_dialogs(&mut self, ctx: &egui::Context) {
+        self.error_dialog(ctx);
+        self.deletion_dialog(ctx);
+        self.conflict_dialog(ctx);
+        self.clock_warning_dialog(ctx);
+        self.remote_missing_dialog(ctx);
+        self.long_paths_dialog(ctx);
+        self.unreadable_sizes_dialog(ctx);
+        self.crowded_directories_dialog(ctx);
+        self.name_collisions_dialog(ctx);
+        self.relink_dialog(ctx);
+        self.resume_plan_dialog(ctx);
+        self.newer_metadata_dialog(ctx);
+        self.in_use_dialog(ctx);
+        self.newer_destination_dialog(ctx);
+        self.denied_copy_dialog(ctx);
+        self.options_window(ctx);
+        self.metadata_inspector_window(ctx);
+        self.history_window(ctx);
+        self.completion_summary_window(ctx);
+        self.permission_warning_dialog(ctx);
+        self.overlap_warning_dialog(ctx);
+        self.consistency_window(ctx);
+        self.previous_session_log_window(ctx);
+        self.orphan_report_window(ctx);
+        self.diagnostics_window(ctx);
+        self.support_report_window(ctx);
+        self.about_window(ctx);
+        self.routing_window(ctx);
+        self.answer_rules_window(ctx);
+    }
+
+    fn error_dialog(&mut self, ctx: &egui::Context) {
+        if self.show_error_dialog {
+            egui::Window::new("错误")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.add_space(15.0);
+                    ui.label(&self.error_message);
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.vertical_centered(|ui| {
+                        let close = ui.button("关闭");
+                        self.dialog_focus.default_button(egui::Id::new("error_dialog"), &close);
+                        if close.clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                            self.show_error_dialog = false;
+                        }
+                    });
+                });
+        }
+    }
+
+    fn deletion_dialog(&mut self, ctx: &egui::Context) {
+        if let Some(PendingPrompt::Deletion { id, path, position, total }) = self.pending_prompts.front() {
+            let (id, path, position, total) = (*id, path.clone(), *position, *total);
+            let mut reply = None;
+            let title = if total > 1 { format!("确认删除 ({}/{})", position, total) } else { "确认删除".to_owned() };
+            let window = egui::Window::new(title).collapsible(false).resizable(false);
+            // Movable, so the log behind it can be read before answering
+            let response = self
+                .dialog_placement
+                .window(ctx, "confirm_deletion", window)
+                .show(ctx, |ui| {
+                    ui.add_space(15.0);
+                    let is_file = !path.is_dir();
+                    let item_type = if is_file { "文件" } else { "目录" };
+                    ui.label(format!("您确定要删除{item_type}\n'{}'？", path.display()));
+                    if self.pending_prompts.len() > 1 {
+                        ui.label(RichText::new(format!("另有 {} 个待确认项", self.pending_prompts.len() - 1)).weak());
+                    }
+                    if total > 1 {
+                        ui.label(
+                            RichText::new(format!("本次同步还有 {} 个删除操作。\"全部删除\"和\"全部保留\"将应用于全部。", total - position + 1))
+                                .weak(),
+                        );
+                    }
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.vertical(|ui| {
+                        ui.horizontal(|ui| {
+                            if ui.button("确认").clicked() {
+                                reply = Some(SyncMessage::DeletionConfirmed { id, confirmed: true });
+                            }
+                            let cancel = ui.button("取消");
+                            // Keeping the file is the safe answer for both Enter and Esc
+                            self.dialog_focus.default_button(egui::Id::new(("confirm_deletion", id)), &cancel);
+                            if cancel.clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                                reply = Some(SyncMessage::DeletionConfirmed { id, confirmed: false });
+                            }
+                            if ui.button("全部删除").clicked() {
+                                self.deletion_choice = Some(true);
+                                reply = Some(SyncMessage::DeletionConfirmed { id, confirmed: true });
+                            }
+                            if ui.button("全部保留").clicked() {
+                                self.deletion_choice = Some(false);
+                                reply = Some(SyncMessage::DeletionConfirmed { id, confirmed: false });
+                            }
+                            if is_file
+                                && ui
+                                    .button("不再询问此文件")
+                                    .on_hover_text("保留此文件，之后的同步不再提示删除，直到文件内容改变")
+                                    .clicked()
+                            {
+                                reply = Some(SyncMessage::DeletionDeclinedPermanently { id });
+                            }
+                        });
+                        ui.checkbox(&mut self.remember_deletion_choice, "记住此选择 (用于\"全部…\")");
+                    });
+                });
+            self.dialog_placement.shown("confirm_deletion", response.as_ref());
+            if let Some(reply) = reply {
+                self.answer_front_prompt(reply);
+                // A "全部…" answer given with the checkbox ticked becomes the folder's default
+                if self.remember_deletion_choice
+                    && let Some(choice) = self.deletion_choice
+                {
+                    self.remember_in_profile(|profile| profile.default_deletion_choice = Some(choice));
+                }
+                // Each deletion prompt starts unticked
+                self.remember_deletion_choice = false;
+            }
+        }
+    }
+
+    fn conflict_dialog(&mut self, ctx: &egui::Context) {
+        if let Some(PendingPrompt::Conflict { id, path, diff, suggestion }) = self.pending_prompts.front() {
+            let (id, suggestion, conflict_path) = (*id, *suggestion, path.clone());
+            let mut resolution = None;
+            let window = egui::Window::new(format!("解决冲突: {}", path.display())).collapsible(false).resizable(false);
+            let response = self
+                .dialog_placement
+                .window(ctx, "conflict", window)
+                .show(ctx, |ui| {
+                    ui.add_space(15.0);
+                    ui.label("文件在本地和U盘上均被修改。请选择要保留的版本。");
+                    ui.horizontal(|ui| {
+                        ui.label(RichText::new(format!("建议: {}", suggestion.label())).weak());
+                        if let Some(suggested) = suggestion.resolution() {
+                            let follow = ui.small_button("按建议选择");
+                            if follow.clicked() {
+                                resolution = Some(suggested);
+                            }
+                        }
+                    });
+                    if let Some(diff) = diff {
+                        ui.add_space(5.0);
+                        ui.collapsing("差异预览 (- U盘 / + 本地)", |ui| {
+                            egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                                if diff.is_empty() {
+                                    ui.label(RichText::new("内容仅有空白或行尾差异").weak());
+                                }
+                                for line in diff {
+                                    let (text, color) = match line {
+                                        DiffLine::Added(text) => (format!("+ {}", text), self.palette.success),
+                                        DiffLine::Removed(text) => (format!("- {}", text), self.palette.error),
+                                    };
+                                    ui.label(RichText::new(text).monospace().color(color));
+                                }
+                            });
+                        });
+                    }
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        if ui.button("采用本地版本").clicked() {
+                            resolution = Some(Resolution::KeepLocal);
+                        }
+                        if ui.button("采用U盘版本").clicked() {
+                            resolution = Some(Resolution::KeepRemote);
+                        }
+                        let skip = ui.button("跳过");
+                        self.dialog_focus.default_button(egui::Id::new(("conflict", id)), &skip);
+                        if skip.clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                            resolution = Some(Resolution::Skip);
+                        }
+                    });
+                    ui.checkbox(&mut self.apply_to_all_conflicts, "对后续冲突使用相同选择");
+                    ui.add_enabled(
+                        self.apply_to_all_conflicts,
+                        egui::Checkbox::new(&mut self.remember_choice, "记住此选择"),
+                    );
+                    ui.checkbox(&mut self.remember_for_path, "记住对此文件的选择")
+                        .on_hover_text("以后此文件再次冲突时自动采用相同选择；只在冲突时生效，单侧修改照常同步。可在同步选项中删除");
+                });
+            self.dialog_placement.shown("conflict", response.as_ref());
+            if let Some(resolution) = resolution {
+                if self.remember_for_path {
+                    self.remember_for_path = false;
+                    let remembered = resolution.clone();
+                    self.remember_in_profile(|profile| profile.set_conflict_rule(&conflict_path, remembered));
+                }
+                if self.apply_to_all_conflicts {
+                    self.conflict_choice = Some(resolution.clone());
+                    if self.remember_choice {
+                        let remembered = resolution.clone();
+                        self.remember_in_profile(|profile| profile.default_conflict_resolution = Some(remembered));
+                    }
+                }
+                self.answer_front_prompt(SyncMessage::ConflictResolved { id, resolution });
+            }
+        }
+    }
+
+    fn clock_warning_dialog(&mut self, ctx: &egui::Context) {
+        if self.show_clock_warning {
+            egui::Window::new("系统时间异常")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.add_space(15.0);
+                    ui.label(&self.clock_warning_message);
+                    ui.label("系统时间不准确时，按修改时间判断文件是否变化可能出错，导致漏同步或误判冲突。");
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let mut choice = None;
+                        if ui.button("继续同步").clicked() {
+                            choice = Some(ClockSkewChoice::Continue);
+                        }
+                        let full_rehash = ui.button("完整校验所有文件");
+                        self.dialog_focus.default_button(egui::Id::new("clock_warning"), &full_rehash);
+                        if full_rehash.clicked() {
+                            choice = Some(ClockSkewChoice::FullRehash);
+                        }
+                        if ui.button("取消同步").clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                            choice = Some(ClockSkewChoice::Abort);
+                        }
+                        if let Some(choice) = choice {
+                            if let Some(tx) = &self.tx_to_sync {
+                                tx.send(SyncMessage::ClockSkewResolved(choice)).ok();
+                            }
+                            self.show_clock_warning = false;
+                        }
+                    });
+                });
+        }
+    }
+
+    fn remote_missing_dialog(&mut self, ctx: &egui::Context) {
+        if let Some(state) = &self.remote_missing_state {
+            let mut choice = None;
+            egui::Window::new("安全检查")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.add_space(15.0);
+                    ui.label(format!(
+                        "上次同步的 {} 个文件中有 {} 个已不在U盘上，但本地仍然存在。",
+                        state.known, state.missing
+                    ));
+                    ui.label("如果这些文件是在 SyncU 之外被误删的，继续同步会把本地副本也删除。");
+                    ui.add_space(5.0);
+                    ui.collapsing(format!("将被删除的本地文件 (显示前 {} 个)", state.examples.len()), |ui| {
+                        egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                            for path in &state.examples {
+                                ui.label(RichText::new(path.display().to_string()).monospace());
+                            }
+                        });
+                    });
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let recopy = ui.button("重新复制到U盘 (推荐)");
+                        self.dialog_focus.default_button(egui::Id::new("remote_missing"), &recopy);
+                        if recopy.clicked() {
+                            choice = Some(RemoteMissingChoice::Recopy);
+                        }
+                        if ui.button("删除本地文件").clicked() {
+                            choice = Some(RemoteMissingChoice::DeleteLocal);
+                        }
+                        if ui.button("取消同步").clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                            choice = Some(RemoteMissingChoice::Abort);
+                        }
+                    });
+                });
+            if let Some(choice) = choice {
+                if let Some(tx) = &self.tx_to_sync {
+                    tx.send(SyncMessage::RemoteMissingResolved(choice)).ok();
+                }
+                self.remote_missing_state = None;
+            }
+        }
+    }
+
+    fn long_paths_dialog(&mut self, ctx: &egui::Context) {
+        if let Some(state) = &self.long_paths_state {
+            let mut choice = None;
+            egui::Window::new("路径过长")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.add_space(15.0);
+                    ui.label(format!(
+                        "有 {} 个文件复制到U盘后的路径超过 {} 个字符，可能无法写入。",
+                        state.count, state.limit
+                    ));
+                    ui.add_space(5.0);
+                    ui.collapsing(format!("受影响的目标路径 (显示前 {} 个)", state.examples.len()), |ui| {
+                        egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                            for path in &state.examples {
+                                ui.label(RichText::new(path.display().to_string()).monospace());
+                            }
+                        });
+                    });
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let skip = ui.button("跳过这些文件 (推荐)");
+                        self.dialog_focus.default_button(egui::Id::new("long_paths"), &skip);
+                        if skip.clicked() {
+                            choice = Some(LongPathChoice::Skip);
+                        }
+                        if ui.button("仍然尝试").clicked() {
+                            choice = Some(LongPathChoice::Attempt);
+                        }
+                        if ui.button("取消同步").clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                            choice = Some(LongPathChoice::Abort);
+                        }
+                    });
+                });
+            if let Some(choice) = choice {
+                if let Some(tx) = &self.tx_to_sync {
+                    tx.send(SyncMessage::LongPathsResolved(choice)).ok();
+                }
+                self.long_paths_state = None;
+            }
+        }
+    }
+
+    fn unreadable_sizes_dialog(&mut self, ctx: &egui::Context) {
+        if let Some((count, examples)) = &self.unreadable_sizes_prompt {
+            let mut choice = None;
+            egui::Window::new("无法读取文件大小")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.add_space(15.0);
+                    ui.label(format!("无法读取 {} 个文件的大小（可能已被删除或无法访问）。", count));
+                    ui.label("继续同步时，轮到这些文件会再试一次，仍无法读取则跳过，下次同步再处理。");
+                    ui.add_space(5.0);
+                    ui.collapsing(format!("受影响的文件 (显示前 {} 个)", examples.len()), |ui| {
+                        egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                            for path in examples {
+                                ui.label(RichText::new(path.display().to_string()).monospace());
+                            }
+                        });
+                    });
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let proceed = ui.button("继续同步");
+                        self.dialog_focus.default_button(egui::Id::new("unreadable_sizes"), &proceed);
+                        if proceed.clicked() {
+                            choice = Some(true);
+                        }
+                        if ui.button("取消同步").clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                            choice = Some(false);
+                        }
+                    });
+                });
+            if let Some(choice) = choice {
+                if let Some(tx) = &self.tx_to_sync {
+                    tx.send(SyncMessage::UnreadableSizesConfirmed(choice)).ok();
+                }
+                self.unreadable_sizes_prompt = None;
+            }
+        }
+    }
+
+    fn crowded_directories_dialog(&mut self, ctx: &egui::Context) {
+        if let Some(state) = &self.crowded_directories_state {
+            let mut choice = None;
+            egui::Window::new("文件夹项目过多")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.add_space(15.0);
+                    ui.label("同步后U盘上以下文件夹中的项目过多:");
+                    ui.add_space(5.0);
+                    egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                        for directory in &state.directories {
+                            let name = if directory.path.as_os_str().is_empty() { "(同步文件夹)".to_owned() } else { directory.path.display().to_string() };
+                            let limit = if directory.exceeds_file_system {
+                                format!("U盘文件系统最多约 {} 个", format_count(directory.limit as u64))
+                            } else {
+                                format!("提醒上限 {} 个", format_count(directory.limit as u64))
+                            };
+                            ui.label(format!("{}: {} 个项目，{}", name, format_count(directory.entries as u64), limit));
+                        }
+                    });
+                    if state.directories.iter().any(|directory| directory.exceeds_file_system) {
+                        ui.label(RichText::new("FAT 格式的U盘无法在一个文件夹中保存更多项目，复制会中途失败。可以将本地文件整理到子文件夹，或将U盘格式化为 exFAT。").small().color(self.palette.warning));
+                    }
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let skip = ui.button("跳过新增的文件 (推荐)");
+                        self.dialog_focus.default_button(egui::Id::new("crowded_directories"), &skip);
+                        if skip.clicked() {
+                            choice = Some(CrowdedDirectoryChoice::Skip);
+                        }
+                        if ui.button("仍然复制").clicked() {
+                            choice = Some(CrowdedDirectoryChoice::Attempt);
+                        }
+                        if ui.button("取消同步").clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                            choice = Some(CrowdedDirectoryChoice::Abort);
+                        }
+                    });
+                });
+            if let Some(choice) = choice {
+                self.send_to_sync(SyncMessage::CrowdedDirectoriesResolved(choice));
+                self.crowded_directories_state = None;
+            }
+        }
+    }
+
+    fn name_collisions_dialog(&mut self, ctx: &egui::Context) {
+        if let Some(state) = &self.name_collisions_state {
+            let mut choice = None;
+            egui::Window::new("名称冲突")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.add_space(15.0);
+                    ui.label(format!(
+                        "有 {} 组本地文件的名称只差末尾的点或空格。U盘会去掉这些字符，同组文件将互相覆盖。",
+                        state.count
+                    ));
+                    ui.add_space(5.0);
+                    ui.collapsing(format!("冲突的本地文件 (显示前 {} 组)", state.examples.len()), |ui| {
+                        egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                            for group in &state.examples {
+                                for path in group {
+                                    // Quotes make the trailing dots and spaces visible
+                                    ui.label(RichText::new(format!("\"{}\"", path.display())).monospace());
+                                }
+                                ui.separator();
+                            }
+                        });
+                    });
+                    ui.add_space(5.0);
+                    ui.label(RichText::new("每组第一个文件保留原名；改名时其余文件在U盘上以 \"(重名 n)\" 结尾的名称保存").small().weak());
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let rename = ui.button("在U盘上改名保存 (推荐)");
+                        self.dialog_focus.default_button(egui::Id::new("name_collisions"), &rename);
+                        if rename.clicked() {
+                            choice = Some(NameCollisionChoice::Rename);
+                        }
+                        if ui.button("跳过其余文件").clicked() {
+                            choice = Some(NameCollisionChoice::Skip);
+                        }
+                        if ui.button("取消同步").clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                            choice = Some(NameCollisionChoice::Abort);
+                        }
+                    });
+                });
+            if let Some(choice) = choice {
+                if let Some(tx) = &self.tx_to_sync {
+                    tx.send(SyncMessage::NameCollisionsResolved(choice)).ok();
+                }
+                self.name_collisions_state = None;
+            }
+        }
+    }
+
+    fn relink_dialog(&mut self, ctx: &egui::Context) {
+        if let Some((old_name, new_name)) = &self.relink_prompt {
+            let mut choice = None;
+            egui::Window::new("本地文件夹已重命名?")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.add_space(15.0);
+                    ui.label(format!("U盘上找到疑似对应的文件夹 '{}'，是否沿用其同步记录并重命名为 '{}'？", old_name, new_name));
+                    ui.label("选择\"作为新文件夹同步\"会在U盘上创建新的文件夹并重新复制所有文件。");
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let relink = ui.button("沿用并重命名");
+                        self.dialog_focus.default_button(egui::Id::new("relink"), &relink);
+                        if relink.clicked() {
+                            choice = Some(true);
+                        }
+                        // Esc leaves the existing USB folder untouched
+                        if ui.button("作为新文件夹同步").clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                            choice = Some(false);
+                        }
+                    });
+                });
+            if let Some(choice) = choice {
+                if let Some(tx) = &self.tx_to_sync {
+                    tx.send(SyncMessage::RelinkConfirmed(choice)).ok();
+                }
+                self.relink_prompt = None;
+            }
+        }
+    }
+
+    fn resume_plan_dialog(&mut self, ctx: &egui::Context) {
+        if let Some((remaining, total)) = self.resume_plan_prompt {
+            let mut choice = None;
+            egui::Window::new("上次同步未完成")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.add_space(15.0);
+                    ui.label(format!(
+                        "上次同步在完成前被中断，其计划共 {} 项，已完成 {} 项。",
+                        format_count(total as u64),
+                        format_count((total - remaining) as u64)
+                    ));
+                    ui.label("继续时已传输的文件不再重新校验；重新开始会重新分析所有文件。");
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let resume = ui.button(format!("继续上次未完成的同步（还剩 {} 项）", format_count(remaining as u64)));
+                        self.dialog_focus.default_button(egui::Id::new("resume_plan"), &resume);
+                        if resume.clicked() {
+                            choice = Some(true);
+                        }
+                        if ui.button("重新开始").clicked() {
+                            choice = Some(false);
+                        }
+                    });
+                });
+            if let Some(choice) = choice {
+                if let Some(tx) = &self.tx_to_sync {
+                    tx.send(SyncMessage::ResumePlanConfirmed(choice)).ok();
+                }
+                self.resume_plan_prompt = None;
+            }
+        }
+    }
+
+    fn newer_metadata_dialog(&mut self, ctx: &egui::Context) {
+        if let Some(version) = &self.newer_metadata_prompt {
+            let mut choice = None;
+            egui::Window::new("同步记录来自较新版本")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.add_space(15.0);
+                    ui.label(format!("U盘上的同步记录由较新版本的 SyncU ({}) 写入，当前版本为 {}。", version, APP_VERSION));
+                    ui.label("当前版本保存时可能丢失其中的信息。可以继续同步文件而不更新记录，下次同步仍以该记录为准；");
+                    ui.label("也可以停止，改用较新版本同步。");
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let stop = ui.button("停止");
+                        self.dialog_focus.default_button(egui::Id::new("newer_metadata"), &stop);
+                        if stop.clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                            choice = Some(false);
+                        }
+                        if ui.button("继续同步，不更新记录").clicked() {
+                            choice = Some(true);
+                        }
+                    });
+                });
+            if let Some(choice) = choice {
+                if let Some(tx) = &self.tx_to_sync {
+                    tx.send(SyncMessage::NewerMetadataConfirmed(choice)).ok();
+                }
+                self.newer_metadata_prompt = None;
+            }
+        }
+    }
+
+    fn in_use_dialog(&mut self, ctx: &egui::Context) {
+        if self.show_in_use_confirmation {
+            egui::Window::new("文件正在使用")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.add_space(15.0);
+                    if let Some(path) = &self.file_in_use {
+                        ui.label(format!("文件正在被其他程序写入:\n'{}'", path.display()));
+                    }
+                    ui.label("复制正在写入的文件可能得到内容不一致的副本。");
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let mut choice = None;
+                        if ui.button("仍然复制").clicked() {
+                            choice = Some(true);
+                        }
+                        let skip = ui.button("跳过");
+                        self.dialog_focus.default_button(egui::Id::new("copy_in_use"), &skip);
+                        if skip.clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                            choice = Some(false);
+                        }
+                        if let Some(choice) = choice {
+                            if let Some(tx) = &self.tx_to_sync {
+                                tx.send(SyncMessage::CopyInUseConfirmed(choice)).ok();
+                            }
+                            self.show_in_use_confirmation = false;
+                        }
+                    });
+                });
+        }
+    }
+
+    fn newer_destination_dialog(&mut self, ctx: &egui::Context) {
+        if let Some(path) = self.newer_destination.clone() {
+            egui::Window::new("备份文件较新")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.add_space(15.0);
+                    ui.label(format!("备份目标中的文件比本地文件更新:\n'{}'", path.display()));
+                    ui.label("覆盖后，备份中较新的修改将会丢失。");
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let mut choice = None;
+                        if ui.button("仍然覆盖").clicked() {
+                            choice = Some(true);
+                        }
+                        let skip = ui.button("跳过");
+                        self.dialog_focus.default_button(egui::Id::new("overwrite_newer"), &skip);
+                        if skip.clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                            choice = Some(false);
+                        }
+                        if let Some(choice) = choice {
+                            if let Some(tx) = &self.tx_to_sync {
+                                tx.send(SyncMessage::OverwriteNewerConfirmed(choice)).ok();
+                            }
+                            self.newer_destination = None;
+                        }
+                    });
+                });
+        }
+    }
+
+    fn denied_copy_dialog(&mut self, ctx: &egui::Context) {
+        if let Some((path, error)) = self.denied_copy.clone() {
+            egui::Window::new("没有访问权限")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.add_space(15.0);
+                    ui.label(format!("无法复制，系统拒绝访问:\n'{}'", path.display()));
+                    ui.label(RichText::new(error).small().weak());
+                    ui.label("请检查文件是否为只读、被其他程序锁定，或U盘是否处于写保护状态，处理后点击重试。");
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let mut choice = None;
+                        if ui.button("重试").clicked() {
+                            choice = Some(true);
+                        }
+                        let give_up = ui.button("跳过此文件");
+                        self.dialog_focus.default_button(egui::Id::new("retry_denied"), &give_up);
+                        if give_up.clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                            choice = Some(false);
+                        }
+                        if let Some(choice) = choice {
+                            if let Some(tx) = &self.tx_to_sync {
+                                tx.send(SyncMessage::RetryDeniedConfirmed(choice)).ok();
+                            }
+                            self.denied_copy = None;
+                        }
+                    });
+                });
+        }
+    }
+
+    fn options_window(&mut self, ctx: &egui::Context) {
+        if self.show_options_window {
+            let mut open = true;
+            let mut edit_answer_rules = false;
+            egui::Window::new("同步选项")
+                .open(&mut open)
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    let Some(local) = self.local_folder.clone() else {
+                        ui.label("请先选择本地文件夹。");
+                        return;
+                    };
+                    let profile = self.settings.profile_mut_for(&local);
+                    egui::Grid::new("sync_options_grid").show(ui, |ui| {
+                        ui.label("正在使用的文件:");
+                        egui::ComboBox::from_id_salt("in_use_policy")
+                            .selected_text(profile.in_use_policy.label())
+                            .show_ui(ui, |ui| {
+                                for policy in InUsePolicy::ALL {
+                                    ui.selectable_value(&mut profile.in_use_policy, policy, policy.label());
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("删除确认:");
+                        egui::ComboBox::from_id_salt("default_deletion_choice")
+                            .selected_text(deletion_choice_label(profile.default_deletion_choice))
+                            .show_ui(ui, |ui| {
+                                for choice in [None, Some(true), Some(false)] {
+                                    ui.selectable_value(&mut profile.default_deletion_choice, choice, deletion_choice_label(choice));
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("冲突处理:");
+                        egui::ComboBox::from_id_salt("default_conflict_resolution")
+                            .selected_text(conflict_choice_label(&profile.default_conflict_resolution))
+                            .show_ui(ui, |ui| {
+                                for choice in [None, Some(Resolution::KeepLocal), Some(Resolution::KeepRemote), Some(Resolution::Skip)] {
+                                    let label = conflict_choice_label(&choice);
+                                    ui.selectable_value(&mut profile.default_conflict_resolution, choice, label);
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("应答规则:");
+                        ui.horizontal(|ui| {
+                            match &profile.answer_rules_file {
+                                Some(path) => {
+                                    elided_path_label(ui, &path.display().to_string(), 160.0, false);
+                                }
+                                None => {
+                                    ui.label(RichText::new("未使用").weak());
+                                }
+                            }
+                            if ui.button("编辑...").clicked() {
+                                edit_answer_rules = true;
+                            }
+                            if ui.button("选择...").clicked()
+                                && let Some(path) = rfd::FileDialog::new().add_filter("JSON", &["json"]).pick_file()
+                            {
+                                profile.answer_rules_file = Some(path);
+                            }
+                            if profile.answer_rules_file.is_some() && ui.button("清除").clicked() {
+                                profile.answer_rules_file = None;
+                            }
+                        })
+                        .response
+                        .on_hover_text("按路径自动回答删除确认和冲突，适合计划任务等需要可预期结果的同步；没有规则匹配的仍照常询问。同一规则文件可供多个文件夹使用");
+                        ui.end_row();
+
+                        ui.label("仅行尾不同:");
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut profile.resolve_line_ending_conflicts, "自动处理");
+                            ui.add_enabled_ui(profile.resolve_line_ending_conflicts, |ui| {
+                                egui::ComboBox::from_id_salt("line_ending_policy")
+                                    .selected_text(profile.line_ending_policy.label())
+                                    .show_ui(ui, |ui| {
+                                        for policy in LineEndingPolicy::ALL {
+                                            ui.selectable_value(&mut profile.line_ending_policy, policy, policy.label());
+                                        }
+                                    });
+                                ui.label("不超过");
+                                ui.add(egui::DragValue::new(&mut profile.line_ending_max_kb).range(1..=100 * 1024).suffix(" KB"));
+                            });
+                        })
+                        .response
+                        .on_hover_text("冲突的两个文本文件若只有换行符不同（CRLF 与 LF，常由编辑器转换造成），按所选方式保留一侧并原样复制，不再询问；不会修改文件内容。「保留较新版本」无法判断时仍会询问");
+                        ui.end_row();
+
+                        ui.label("");
+                        ui.horizontal(|ui| {
+                            ui.label("文件类型:");
+                            let response = ui.add_enabled(profile.resolve_line_ending_conflicts, egui::TextEdit::singleline(&mut self.line_ending_extensions));
+                            if response.changed() {
+                                profile.line_ending_extensions = self
+                                    .line_ending_extensions
+                                    .split([',', ' '])
+                                    .map(|extension| extension.trim().trim_start_matches('.').to_lowercase())
+                                    .filter(|extension| !extension.is_empty())
+                                    .collect();
+                            }
+                            response.on_hover_text("以逗号分隔的扩展名，只有这些类型的文件会比较行尾");
+                        });
+                        ui.end_row();
+
+                        ui.label("回答超时:");
+                        ui.horizontal(|ui| {
+                            let mut enabled = profile.prompt_timeout_minutes.is_some();
+                            if ui.checkbox(&mut enabled, "删除确认和冲突超过").changed() {
+                                profile.prompt_timeout_minutes = enabled.then_some(30);
+                            }
+                            let mut minutes = profile.prompt_timeout_minutes.unwrap_or(30);
+                            if ui.add_enabled(enabled, egui::DragValue::new(&mut minutes).range(1..=1440).suffix(" 分钟")).changed() {
+                                profile.prompt_timeout_minutes = Some(minutes);
+                            }
+                            ui.label("无人回答时跳过");
+                        })
+                        .response
+                        .on_hover_text("避免一个未注意到的对话框让整个同步停在原地；被跳过的文件会在下次同步时再次询问");
+                        ui.end_row();
+
+                        ui.label("限速:");
+                        ui.horizontal(|ui| {
+                            let mut enabled = profile.rate_limit_mb_per_sec.is_some();
+                            if ui.checkbox(&mut enabled, "复制速度不超过").changed() {
+                                profile.rate_limit_mb_per_sec = enabled.then_some(DEFAULT_RATE_LIMIT_MB_PER_SEC);
+                            }
+                            let mut limit = profile.rate_limit_mb_per_sec.unwrap_or(DEFAULT_RATE_LIMIT_MB_PER_SEC);
+                            if ui.add_enabled(enabled, egui::DragValue::new(&mut limit).range(0.5..=1000.0).speed(0.5).suffix(" MB/s")).changed() {
+                                profile.rate_limit_mb_per_sec = Some(limit);
+                            }
+                        })
+                        .response
+                        .on_hover_text("适用于网络位置（如 NAS）等共享带宽的目标，避免同步时占满网络；同步过程中可在状态栏临时调整");
+                        ui.end_row();
+
+                        ui.label("文件夹项目数:");
+                        ui.horizontal(|ui| {
+                            let mut enabled = profile.directory_entry_soft_limit.is_some();
+                            if ui.checkbox(&mut enabled, "U盘上单个文件夹超过").changed() {
+                                profile.directory_entry_soft_limit = enabled.then_some(DEFAULT_DIRECTORY_ENTRY_SOFT_LIMIT);
+                            }
+                            let mut limit = profile.directory_entry_soft_limit.unwrap_or(DEFAULT_DIRECTORY_ENTRY_SOFT_LIMIT);
+                            if ui.add_enabled(enabled, egui::DragValue::new(&mut limit).range(100..=1_000_000).speed(100)).changed() {
+                                profile.directory_entry_soft_limit = Some(limit);
+                            }
+                            ui.label("个项目时先确认");
+                        })
+                        .response
+                        .on_hover_text("FAT 格式的U盘每个文件夹最多容纳约 65,000 个目录项（长文件名占用多个），超出时总会先确认；此处可另设更低的提醒上限");
+                        ui.end_row();
+
+                        ui.label("安全检查:");
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut profile.safety_check, "U盘文件缺失超过");
+                            ui.add_enabled(
+                                profile.safety_check,
+                                egui::DragValue::new(&mut profile.safety_threshold_percent)
+                                    .range(0..=100)
+                                    .suffix("%"),
+                            );
+                            ui.label("时先确认");
+                        });
+                        ui.end_row();
+
+                        ui.label("扫描:");
+                        ui.checkbox(&mut profile.dedupe_scan, "去重加速扫描")
+                            .on_hover_text("大小相同的文件先比较首尾内容，完全相同的文件复用已计算的校验值；适合含大量重复文件（如照片导出）的文件夹。会改变磁盘读取方式，在机械硬盘上可能更慢");
+                        ui.end_row();
+
+                        ui.label("扩展属性:");
+                        ui.checkbox(&mut profile.copy_extended_attributes, "复制扩展属性")
+                            .on_hover_text("随文件一起复制 Windows 的备用数据流（如下载文件的来源标记）或 Linux/macOS 的用户扩展属性；复制失败只记录警告。FAT32、exFAT 格式的U盘无法保存扩展属性，同步到这类U盘时自动关闭");
+                        ui.end_row();
+
+                        ui.label("空文件:");
+                        ui.checkbox(&mut profile.repair_truncated_files, "自动修复疑似截断的文件")
+                            .on_hover_text("一侧文件变为 0 字节（如复制中断）而另一侧不为空时，视为疑似损坏，用另一侧的版本恢复，而不是同步空文件或当作冲突询问");
+                        ui.end_row();
+
+                        ui.label("同步后核对:");
+                        ui.checkbox(&mut profile.check_after_sync, "完成后快速核对两侧")
+                            .on_hover_text("同步完成后重新查看两侧每个文件的大小和修改时间（不读取内容），发现同步期间被修改的文件时在状态栏提示，可立即重新同步");
+                        ui.end_row();
+
+                        ui.label("小文件提示:");
+                        ui.checkbox(&mut profile.small_files_hint, "大量小文件时提示耗时")
+                            .on_hover_text("计划向U盘复制大量小于 64 KB 的文件时，在状态栏提示同步可能耗时较长及预计时间");
+                        ui.end_row();
+
+                        ui.label("U盘日志:");
+                        ui.horizontal(|ui| {
+                            egui::ComboBox::from_id_salt("device_log_verbosity")
+                                .selected_text(profile.device_log_verbosity.label())
+                                .show_ui(ui, |ui| {
+                                    for verbosity in DeviceLogVerbosity::ALL {
+                                        ui.selectable_value(&mut profile.device_log_verbosity, verbosity, verbosity.label());
+                                    }
+                                })
+                                .response
+                                .on_hover_text("U盘同步文件夹中的日志记录多少内容。「摘要」只记录每次同步的决定、统计、错误和完成时间，不记录同步了哪些文件，适合会交给他人使用的U盘；「关闭」不再写入日志。程序内日志不受影响，已有的日志不会被删除");
+                            ui.add_enabled(profile.device_log_verbosity == DeviceLogVerbosity::Full, egui::Checkbox::new(&mut profile.detailed_device_log, "逐项记录取消的删除"))
+                                .on_hover_text("关闭时，连续取消的删除在U盘日志中合并为一行摘要，以减少对U盘的写入；程序内日志始终显示全部条目");
+                        });
+                        ui.end_row();
+
+                        ui.label("同步记录:");
+                        ui.checkbox(&mut profile.bookkeeping_subfolder, "存放在 .syncu 子文件夹中")
+                            .on_hover_text("同步记录、日志和回收文件夹集中存放在U盘同步文件夹内的隐藏 .syncu 文件夹中；下次同步时自动迁移已有文件");
+                        ui.end_row();
+
+                        ui.label("同步报告:");
+                        ui.add_enabled(profile.bookkeeping_subfolder, egui::Checkbox::new(&mut profile.html_report, "每次同步后生成 HTML 报告"))
+                            .on_hover_text("在 .syncu 文件夹中保存本次同步的报告（各阶段用时、全部操作及其结果、冲突的处理方式和失败原因），可用浏览器打开，保留最近 20 份")
+                            .on_disabled_hover_text("报告存放在 .syncu 文件夹中，请先启用上一项");
+                        ui.end_row();
+
+                        ui.label("备份目标:");
+                        ui.horizontal(|ui| {
+                            match &profile.secondary_destination {
+                                Some(path) => {
+                                    elided_path_label(ui, &path.display().to_string(), 120.0, false);
+                                }
+                                None => {
+                                    ui.label(RichText::new("未设置").weak());
+                                }
+                            }
+                            if ui.button("选择...").clicked()
+                                && let Some(path) = rfd::FileDialog::new().pick_folder()
+                            {
+                                profile.secondary_destination = Some(path);
+                            }
+                            if profile.secondary_destination.is_some() && ui.button("清除").clicked() {
+                                profile.secondary_destination = None;
+                            }
+                        });
+                        ui.end_row();
+
+                        ui.label("备份文件较新时:");
+                        egui::ComboBox::from_id_salt("newer_destination_policy")
+                            .selected_text(profile.newer_destination_policy.label())
+                            .show_ui(ui, |ui| {
+                                for policy in NewerDestinationPolicy::ALL {
+                                    ui.selectable_value(&mut profile.newer_destination_policy, policy, policy.label());
+                                }
+                            })
+                            .response
+                            .on_hover_text("备份目标中的文件比本地文件更新时（例如在备份中直接修改过），是否仍用本地版本覆盖");
+                        ui.end_row();
+
+                        ui.label("目标有硬链接时:");
+                        egui::ComboBox::from_id_salt("hard_link_policy")
+                            .selected_text(profile.hard_link_policy.label())
+                            .show_ui(ui, |ui| {
+                                for policy in HardLinkPolicy::ALL {
+                                    ui.selectable_value(&mut profile.hard_link_policy, policy, policy.label());
+                                }
+                            })
+                            .response
+                            .on_hover_text("要覆盖的文件还有其他硬链接时（例如移动硬盘上基于硬链接的快照），直接写入会同时改变那些位置的内容。「先断开链接再写入」让新版本成为独立的文件，其他链接保留原内容");
+                        ui.end_row();
+                    });
+
+                    ui.add_space(10.0);
+                    let mut forgotten_rule = None;
+                    egui::CollapsingHeader::new(format!("记住的冲突选择 ({})", profile.conflict_rules.len()))
+                        .id_salt("conflict_rules")
+                        .show(ui, |ui| {
+                            if profile.conflict_rules.is_empty() {
+                                ui.label(RichText::new("暂无").weak());
+                            }
+                            egui::ScrollArea::vertical().max_height(150.0).show(ui, |ui| {
+                                for (index, rule) in profile.conflict_rules.iter().enumerate() {
+                                    ui.horizontal(|ui| {
+                                        if ui.small_button("删除").clicked() {
+                                            forgotten_rule = Some(index);
+                                        }
+                                        ui.label(conflict_choice_label(&Some(rule.resolution.clone())));
+                                        elided_path_label(ui, &rule.path.display().to_string(), 0.0, false);
+                                    });
+                                }
+                            });
+                        });
+                    if let Some(index) = forgotten_rule {
+                        profile.conflict_rules.remove(index);
+                    }
+
+                    let mut forgotten = None;
+                    egui::CollapsingHeader::new(format!("不再询问删除的文件 ({})", self.kept_files.len()))
+                        .id_salt("kept_files")
+                        .show(ui, |ui| {
+                            if self.kept_files.is_empty() {
+                                ui.label(RichText::new("暂无").weak());
+                            }
+                            egui::ScrollArea::vertical().max_height(150.0).show(ui, |ui| {
+                                for path in &self.kept_files {
+                                    ui.horizontal(|ui| {
+                                        if ui.small_button("清除").clicked() {
+                                            forgotten = Some(path.clone());
+                                        }
+                                        elided_path_label(ui, &path.display().to_string(), 0.0, false);
+                                    });
+                                }
+                            });
+                        });
+                    if let Some(path) = forgotten {
+                        self.forget_kept_file(&path);
+                    }
+
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.vertical_centered(|ui| {
+                        if ui.button("保存").clicked() {
+                            if let Err(e) = self.settings.save() {
+                                self.error_message = format!("保存设置失败: {}", e);
+                                self.show_error_dialog = true;
+                            }
+                            self.show_options_window = false;
+                        }
+                    });
+                });
+            if !open {
+                self.show_options_window = false;
+            }
+            if let Some(local) = self.local_folder.clone().filter(|_| edit_answer_rules) {
+                self.answer_rules_editor = Some(AnswerRulesEditor::open(self.settings.profile_for(&local).answer_rules_file.clone()));
+            }
+        }
+    }
+
+    fn history_window(&mut self, ctx: &egui::Context) {
+        let Some(window) = &mut self.history_window else { return };
+        if let Some(rx) = &window.loading
+            && let Ok(runs) = rx.try_recv()
+        {
+            window.runs = runs;
+            window.loading = None;
+        }
+        if let Some(detail) = &mut window.opened {
+            if let Some(rx) = &detail.loading
+                && let Ok(result) = rx.try_recv()
+            {
+                match result {
+                    Ok(run) => detail.run = Some(run),
+                    Err(e) => detail.error = Some(e),
+                }
+                detail.loading = None;
+            }
+            detail.refresh_filter();
+        }
+
+        let retention_before = self.settings.history_retention;
+        let mut open = true;
+        let mut open_run = None;
+        let mut back = false;
+        let mut reload = false;
+        let mut export_target = None;
+        let mut export_error = None;
+        egui::Window::new("同步历史")
+            .open(&mut open)
+            .collapsible(false)
+            .default_size([640.0, 460.0])
+            .show(ctx, |ui| {
+                if let Some(detail) = &mut window.opened {
+                    if ui.button("← 返回列表").clicked() {
+                        back = true;
+                    }
+                    ui.label(RichText::new(detail.path.display().to_string()).small().weak());
+                    if let Some(error) = &detail.error {
+                        ui.label(RichText::new(format!("读取失败: {}", error)).color(self.palette.error));
+                        return;
+                    }
+                    let Some(run) = &detail.run else {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.label("正在读取...");
+                        });
+                        return;
+                    };
+                    let summary = &run.summary;
+                    ui.label(format!("{} · {} · {}", summary.started, summary.outcome, summary.counts_text()));
+                    for (label, folder) in [("本地", &summary.local_folder), ("U盘", &summary.usb_sync_path)] {
+                        if let Some(folder) = folder {
+                            ui.label(RichText::new(format!("{}: {}", label, folder.display())).weak());
+                        }
+                    }
+                    if let Some(error) = &summary.error {
+                        ui.label(RichText::new(error).color(self.palette.error));
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("搜索:");
+                        ui.text_edit_singleline(&mut detail.filter).on_hover_text("按路径、操作 (如 \"删除U盘\") 或结果 (如 \"失败\") 筛选");
+                        ui.label(RichText::new(format!("匹配 {} 项", format_count(detail.filtered.len() as u64))).weak());
+                        if ui.button("导出 CSV...").on_hover_text("导出当前匹配的项").clicked() {
+                            export_target = rfd::FileDialog::new().add_filter("CSV", &["csv"]).save_file();
+                        }
+                    });
+                    if let Some(target) = export_target.take()
+                        && let Err(e) = detail.export_csv(&target)
+                    {
+                        export_error = Some(e.to_string());
+                    }
+                    ui.separator();
+
+                    // Only the rows in view are laid out, so runs touching many files scroll smoothly
+                    let row_height = ui.text_style_height(&egui::TextStyle::Monospace) + ui.spacing().item_spacing.y;
+                    egui::ScrollArea::both()
+                        .id_salt("history_actions")
+                        .auto_shrink([false, true])
+                        .show_rows(ui, row_height, detail.filtered.len(), |ui, range| {
+                            for &index in &detail.filtered[range] {
+                                let action = &run.actions[index];
+                                let mut text = format!("{:<6} {:<4} {}", action.action.label(), action.status_label(), action.path_text());
+                                if let Some(error) = &action.error {
+                                    text += &format!("  ({})", error);
+                                }
+                                let mut text = RichText::new(text).monospace();
+                                if action.error.is_some() {
+                                    text = text.color(self.palette.error);
+                                }
+                                ui.label(text);
+                            }
+                        });
+                    return;
+                }
+
+                ui.horizontal(|ui| {
+                    let retention = &mut self.settings.history_retention;
+                    ui.label("保留最近");
+                    ui.add(egui::DragValue::new(&mut retention.max_runs).range(1..=1000).suffix(" 次"));
+                    ui.label("同步，且总大小不超过");
+                    ui.add(egui::DragValue::new(&mut retention.max_megabytes).range(1..=10_000).suffix(" MB"));
+                })
+                .response
+                .on_hover_text("超出时删除最早的记录，下次同步后生效。最近一次同步总会保留");
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new(format!("共 {} 次 · {}", window.runs.len(), history_size_text(&window.runs))).weak());
+                    if ui.add_enabled(window.loading.is_none(), egui::Button::new("刷新")).clicked() {
+                        reload = true;
+                    }
+                });
+                ui.separator();
+                if window.loading.is_some() {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("正在读取...");
+                    });
+                    return;
+                }
+                if window.runs.is_empty() {
+                    ui.label(RichText::new("尚无同步历史。每次同步后，这里会记录其中复制、删除和冲突的文件").weak());
+                    return;
+                }
+                egui::ScrollArea::vertical().id_salt("history_runs").auto_shrink([false, true]).show(ui, |ui| {
+                    for entry in &window.runs {
+                        let summary = &entry.summary;
+                        ui.horizontal(|ui| {
+                            if ui.button("查看").clicked() {
+                                open_run = Some(entry.path.clone());
+                            }
+                            let mut outcome = RichText::new(&summary.outcome);
+                            if summary.failed > 0 || summary.error.is_some() {
+                                outcome = outcome.color(self.palette.error);
+                            }
+                            ui.label(&summary.started);
+                            ui.label(outcome);
+                            ui.label(RichText::new(summary.counts_text()).weak());
+                        });
+                        if let Some(folder) = &summary.local_folder {
+                            elided_path_label(ui, &folder.display().to_string(), 40.0, false);
+                        }
+                        ui.separator();
+                    }
+                });
+            });
+
+        if let Some(path) = open_run {
+            window.opened = Some(HistoryDetail::open(path, ctx.clone()));
+        }
+        if back {
+            window.opened = None;
+        }
+        if reload {
+            window.reload(ctx.clone());
+        }
+        if let Some(e) = export_error {
+            self.error_message = format!("导出失败: {}", e);
+            self.show_error_dialog = true;
+        }
+        if !open {
+            self.history_window = None;
+        }
+        // Saved as the window closes or the run list is reloaded, rather than on every step of a drag
+        if (!open || reload) && self.settings.history_retention != retention_before
+            && let Err(e) = self.settings.save()
+        {
+            self.error_message = format!("保存设置失败: {}", e);
+            self.show_error_dialog = true;
+        }
+    }
+
+    fn metadata_inspector_window(&mut self, ctx: &egui::Context) {
+        if let Some(inspector) = &mut self.metadata_inspector {
+            if let Some(rx) = &inspector.loading
+                && let Ok(result) = rx.try_recv()
+            {
+                match result {
+                    Ok((data, sorted_paths)) => {
+                        inspector.unverified = data.files.values().filter(|info| !info.verified).count();
+                        inspector.data = Some(data);
+                        inspector.sorted_paths = sorted_paths;
+                    }
+                    Err(e) => inspector.error = Some(e),
+                }
+                inspector.loading = None;
+            }
+            inspector.refresh_filter();
+
+            let mut open = true;
+            let mut export_target = None;
+            let mut export_error = None;
+            egui::Window::new("同步记录")
+                .open(&mut open)
+                .collapsible(false)
+                .default_size([640.0, 460.0])
+                .show(ctx, |ui| {
+                    ui.label(RichText::new(inspector.path.display().to_string()).small().weak());
+                    if let Some(error) = &inspector.error {
+                        ui.label(RichText::new(format!("读取失败: {}", error)).color(self.palette.error));
+                        return;
+                    }
+                    let Some(data) = &inspector.data else {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.label("正在读取...");
+                        });
+                        return;
+                    };
+
+                    ui.label(format!(
+                        "上次同步: {} · 文件 {} · 目录 {} · 路由 {} · 不再询问 {}",
+                        data.last_sync_time.map_or("未知".to_owned(), format_time),
+                        data.files.len(),
+                        data.directories.len(),
+                        data.routes.len(),
+                        data.tombstones.len()
+                    ));
+                    if let Some(source) = &data.source_folder {
+                        ui.label(RichText::new(format!("同步来源: {}", source.display())).weak());
+                    }
+                    if let Some(volume_id) = &data.volume_id {
+                        ui.label(RichText::new(format!("U盘卷标识: {}", volume_id)).weak());
+                    }
+                    if inspector.unverified > 0 {
+                        ui.label(RichText::new(format!("上次同步: {} 个文件未校验", format_count(inspector.unverified as u64))).weak());
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("搜索:");
+                        ui.text_edit_singleline(&mut inspector.filter);
+                        ui.label(RichText::new(format!("匹配 {} 项", inspector.filtered.len())).weak());
+                        if ui.button("导出 CSV...").clicked() {
+                            export_target = rfd::FileDialog::new().add_filter("CSV", &["csv"]).save_file();
+                        }
+                    });
+                    // Exported once the closure has released its mutable borrow of the filter
+                    if let Some(target) = export_target.take()
+                        && let Err(e) = inspector.export_csv(&target)
+                    {
+                        export_error = Some(e.to_string());
+                    }
+                    ui.separator();
+
+                    let row_height = ui.text_style_height(&egui::TextStyle::Monospace) + ui.spacing().item_spacing.y;
+                    egui::ScrollArea::both()
+                        .id_salt("metadata_files")
+                        .max_height(300.0)
+                        .auto_shrink([false, true])
+                        .show_rows(ui, row_height, inspector.filtered.len(), |ui, range| {
+                            for &index in &inspector.filtered[range] {
+                                let path = &inspector.sorted_paths[index];
+                                if let Some(info) = data.files.get(path) {
+                                    ui.label(
+                                        RichText::new(format!(
+                                            "{:.12}  {:>12}  {}  {}",
+                                            info.hash,
+                                            info.size,
+                                            format_time(info.modified),
+                                            path.display()
+                                        ))
+                                        .monospace(),
+                                    );
+                                }
+                            }
+                        });
+                    ui.collapsing(format!("目录 ({})", data.directories.len()), |ui| {
+                        let mut directories: Vec<&PathBuf> = data.directories.iter().collect();
+                        directories.sort();
+                        egui::ScrollArea::vertical().id_salt("metadata_dirs").max_height(150.0).show_rows(
+                            ui,
+                            row_height,
+                            directories.len(),
+                            |ui, range| {
+                                for dir in &directories[range] {
+                                    ui.label(RichText::new(dir.display().to_string()).monospace());
+                                }
+                            },
+                        );
+                    });
+                });
+            if let Some(e) = export_error {
+                self.error_message = format!("导出失败: {}", e);
+                self.show_error_dialog = true;
+            }
+            if !open {
+                self.metadata_inspector = None;
+            }
+        }
+    }
+
+    fn completion_summary_window(&mut self, ctx: &egui::Context) {
+        if let Some(summary) = &self.completion_summary {
+            let mut close = false;
+            egui::Window::new("同步结果")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.add_space(15.0);
+                    ui.label(format!("同步已结束，但仍有未同步的项目: {}。", summary));
+                    ui.label("两侧目前并不完全一致，请在处理这些项目后再依赖任一侧的数据。");
+                    if !self.plan_panel.is_empty() {
+                        egui::CollapsingHeader::new("按文件夹查看本次变更").id_salt("completion_plan_tree").show(ui, |ui| {
+                            egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| self.plan_panel.show_tree(ui, &self.palette));
+                        });
+                    }
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        if ui.button("查看未同步项").clicked() {
+                            self.show_unsynced_only = true;
+                            close = true;
+                        }
+                        let close_button = ui.button("关闭");
+                        self.dialog_focus.default_button(egui::Id::new("completion_summary"), &close_button);
+                        if close_button.clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                            close = true;
+                        }
+                    });
+                });
+            if close {
+                self.completion_summary = None;
+            }
+        }
+    }
+
+    fn permission_warning_dialog(&mut self, ctx: &egui::Context) {
+        if let Some((path, probe)) = &self.permission_warning {
+            let mut use_anyway = false;
+            let mut pick_another = false;
+            let mut cancel = false;
+            egui::Window::new("文件夹权限不足")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.add_space(15.0);
+                    if probe.inaccessible.first() == Some(path) {
+                        ui.label(format!("无法读取所选文件夹: {}", path.display()));
+                    } else {
+                        ui.label(format!(
+                            "所选文件夹中有 {} 个子文件夹无法读取（共检查 {} 个）:",
+                            format_count(probe.inaccessible.len() as u64),
+                            format_count((probe.probed - 1) as u64)
+                        ));
+                        egui::ScrollArea::vertical().max_height(150.0).show(ui, |ui| {
+                            for folder in &probe.inaccessible {
+                                let name = folder.strip_prefix(path).unwrap_or(folder);
+                                ui.label(name.display().to_string());
+                            }
+                        });
+                    }
+                    ui.label("同步时无法读取的内容会被跳过，不会复制到U盘。");
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let pick_button = ui.button("选择其他文件夹...");
+                        self.dialog_focus.default_button(egui::Id::new("permission_warning"), &pick_button);
+                        pick_another = pick_button.clicked();
+                        use_anyway = ui.button("仍然使用").clicked();
+                        cancel = ui.button("取消").clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape));
+                    });
+                });
+            if use_anyway {
+                if let Some((path, _)) = self.permission_warning.take() {
+                    self.local_folder = Some(path);
+                }
+            } else if pick_another {
+                self.permission_warning = None;
+                self.pick_local_folder();
+            } else if cancel {
+                self.permission_warning = None;
+            }
+        }
+    }
+
+    fn overlap_warning_dialog(&mut self, ctx: &egui::Context) {
+        if let Some(overlaps) = &self.overlap_warning {
+            let mut proceed = false;
+            let mut pick_another = false;
+            let mut cancel = false;
+            egui::Window::new("文件夹重叠")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.add_space(15.0);
+                    ui.label("以下已保存配置的文件夹与要同步的文件夹重叠:");
+                    ui.add_space(5.0);
+                    for overlap in overlaps {
+                        ui.horizontal(|ui| {
+                            elided_path_label(ui, &overlap.local_folder().display().to_string(), 200.0, false);
+                            ui.label(RichText::new(overlap.description()).weak());
+                        });
+                    }
+                    ui.label("对同一U盘同步这两个文件夹会在U盘上产生重复的副本，两者的同步记录也会互相干扰。");
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let cancel_button = ui.button("取消");
+                        self.dialog_focus.default_button(egui::Id::new("overlap_warning"), &cancel_button);
+                        cancel = cancel_button.clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape));
+                        pick_another = ui.button("选择其他文件夹...").clicked();
+                        proceed = ui.button("仍然同步").clicked();
+                    });
+                });
+            if proceed {
+                self.overlap_warning = None;
+                self.start_sync();
+            } else if pick_another {
+                self.overlap_warning = None;
+                self.pick_local_folder();
+            } else if cancel {
+                self.overlap_warning = None;
+            }
+        }
+    }
+
+    fn consistency_window(&mut self, ctx: &egui::Context) {
+        if self.show_consistency_window {
+            let mismatched = self.consistency.as_ref().map(|report| report.mismatched.clone()).unwrap_or_default();
+            let can_sync = self.state == SyncState::Idle && self.missing_requirement_hint().is_none();
+            let mut close = false;
+            let mut resync = false;
+            egui::Window::new("同步后核对")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.add_space(15.0);
+                    ui.label("以下文件在同步期间或之后被修改，两侧目前不一致:");
+                    ui.add_space(5.0);
+                    egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                        for path in &mismatched {
+                            ui.label(path.display().to_string());
+                        }
+                    });
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        if ui.add_enabled(can_sync, egui::Button::new("立即重新同步")).clicked() {
+                            resync = true;
+                        }
+                        let close_button = ui.button("关闭");
+                        self.dialog_focus.default_button(egui::Id::new("consistency_window"), &close_button);
+                        if close_button.clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                            close = true;
+                        }
+                    });
+                });
+            if close {
+                self.show_consistency_window = false;
+            }
+            if resync {
+                self.request_sync();
+            }
+        }
+    }
+
+    fn support_report_window(&mut self, ctx: &egui::Context) {
+        let Some(mut report) = self.support_report.take() else { return };
+        let mut open = true;
+        let mut regenerate = false;
+        if let Some(rx) = &report.drives_rx
+            && let Ok(drives) = rx.try_recv()
+        {
+            report.drives = drives;
+            report.drives_rx = None;
+            regenerate = true;
+        }
+        let detecting = report.drives_rx.is_some();
+        egui::Window::new("诊断信息")
+            .open(&mut open)
+            .collapsible(false)
+            .default_size([600.0, 460.0])
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    regenerate |= ui
+                        .add_enabled(!detecting, egui::Checkbox::new(&mut report.redact_paths, "隐藏路径"))
+                        .on_hover_text("盘符之后的文件夹名以哈希代替；相同的名字得到相同的哈希")
+                        .changed();
+                    if ui.add_enabled(!detecting, egui::Button::new("重新生成")).on_hover_text("重新检测U盘并读取最新日志").clicked() {
+                        report.drives_rx = Some(self.summarize_drives_in_background());
+                    }
+                    if detecting {
+                        ui.spinner();
+                        ui.label(RichText::new("正在检测U盘...").weak());
+                    }
+                });
+                ui.label(RichText::new("诊断信息只保存在本机，复制或保存后由你决定发给谁。").small().weak());
+                ui.add_space(5.0);
+                egui::ScrollArea::both().auto_shrink([false, false]).max_height(340.0).show(ui, |ui| {
+                    ui.add(egui::TextEdit::multiline(&mut report.text.as_str()).font(egui::TextStyle::Monospace).desired_width(f32::INFINITY));
+                });
+                ui.add_space(5.0);
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(!report.text.is_empty(), egui::Button::new("复制到剪贴板")).clicked() {
+                        ui.ctx().copy_text(report.text.clone());
+                        report.message = Some("已复制".to_owned());
+                    }
+                    if ui.add_enabled(!report.text.is_empty(), egui::Button::new("保存到文件...")).clicked()
+                        && let Some(target) = rfd::FileDialog::new()
+                            .add_filter("文本文件", &["txt"])
+                            .set_file_name(format!("SyncU诊断信息-{}.txt", chrono::Local::now().format("%Y%m%d-%H%M%S")))
+                            .save_file()
+                    {
+                        report.message = Some(match std::fs::write(&target, &report.text) {
+                            Ok(()) => format!("已保存到 {}", target.display()),
+                            Err(e) => format!("保存失败: {}", e),
+                        });
+                    }
+                    if let Some(message) = &report.message {
+                        ui.label(RichText::new(message).weak());
+                    }
+                });
+            });
+        if regenerate {
+            report.text = self.support_report_text(&report.drives, report.redact_paths);
+            report.message = None;
+        }
+        if open {
+            self.support_report = Some(report);
+        }
+    }
+
+    fn previous_session_log_window(&mut self, ctx: &egui::Context) {
+        if let Some(log) = &self.previous_session_log {
+            let mut open = true;
+            egui::Window::new("上次会话日志")
+                .open(&mut open)
+                .collapsible(false)
+                .default_size([560.0, 400.0])
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    egui::ScrollArea::both().auto_shrink([false, false]).show(ui, |ui| {
+                        if log.is_empty() {
+                            ui.label(RichText::new("上次会话没有日志").weak());
+                        }
+                        for line in log.lines() {
+                            ui.label(RichText::new(line).monospace());
+                        }
+                    });
+                });
+            if !open {
+                self.previous_session_log = None;
+            }
+        }
+    }
+
+    fn orphan_report_window(&mut self, ctx: &egui::Context) {
+        if let Some(report) = &mut self.orphan_report {
+            if let Some(rx) = &report.loading
+                && let Ok(result) = rx.try_recv()
+            {
+                match result {
+                    Ok(files) => report.files = files,
+                    Err(e) => report.error = Some(e),
+                }
+                report.loading = None;
+            }
+            let mut refresh = false;
+            if let Some(cleaning) = &mut report.cleaning {
+                for message in cleaning.progress.try_iter() {
+                    if let SyncMessage::Progress(_, status, _) = message {
+                        cleaning.status = status;
+                    }
+                }
+                if let Ok(result) = cleaning.result.try_recv() {
+                    report.message = Some(match result {
+                        Ok(count) if count < report.files.len() => format!("已将 {} 个文件移至回收文件夹，其余 {} 个因停止未移动", count, report.files.len() - count),
+                        Ok(count) => format!("已将 {} 个文件移至回收文件夹", count),
+                        Err(e) => format!("清理失败: {}", e),
+                    });
+                    report.cleaning = None;
+                    refresh = true;
+                } else {
+                    ctx.request_repaint_after(std::time::Duration::from_millis(100));
+                }
+            }
+
+            let mut open = true;
+            let mut clean_up = false;
+            egui::Window::new("残留文件检查")
+                .open(&mut open)
+                .collapsible(false)
+                .default_size([560.0, 400.0])
+                .show(ctx, |ui| {
+                    ui.label("以下U盘文件不会再被同步更新或删除：在本地删除后选择保留的文件，以及中断的复制留下的临时文件。");
+                    ui.label(RichText::new("检查本身不会修改任何文件，只有点击下方按钮才会移动它们。").weak());
+                    ui.add_space(5.0);
+                    if let Some(error) = &report.error {
+                        ui.label(RichText::new(format!("检查失败: {}", error)).color(self.palette.error));
+                        return;
+                    }
+                    if report.loading.is_some() {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.label("正在检查...");
+                        });
+                        return;
+                    }
+                    if let Some(cleaning) = &report.cleaning {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.label(if cleaning.status.is_empty() { "正在移至回收文件夹..." } else { cleaning.status.as_str() });
+                            if ui.button("停止").on_hover_text("已移动的文件留在回收文件夹中，其余文件保持原样").clicked() {
+                                let _ = cleaning.stop.send(SyncMessage::Stop);
+                            }
+                        });
+                        return;
+                    }
+                    if let Some(message) = &report.message {
+                        ui.label(RichText::new(message).color(self.palette.success));
+                    }
+                    if report.files.is_empty() {
+                        ui.label("未发现残留文件。");
+                    }
+                    for (kind, title, hint) in [
+                        (OrphanKind::KeptAfterDeletion, "已在本地删除但选择保留的文件", "删除确认时选择了\"不再询问此文件\""),
+                        (OrphanKind::LeftoverTemp, "残留的临时文件", "中断的复制留下的文件，同步会忽略它们"),
+                    ] {
+                        let files: Vec<&OrphanFile> = report.files.iter().filter(|file| file.kind == kind).collect();
+                        if files.is_empty() {
+                            continue;
+                        }
+                        let size: u64 = files.iter().map(|file| file.size).sum();
+                        ui.collapsing(format!("{} ({} 个, {})", title, files.len(), format_size(size)), |ui| {
+                            ui.label(RichText::new(hint).weak());
+                            egui::ScrollArea::vertical().id_salt(title).max_height(150.0).show(ui, |ui| {
+                                for file in files {
+                                    ui.label(RichText::new(format!("{}  ({})", file.path.display(), format_size(file.size))).monospace());
+                                }
+                            });
+                        });
+                    }
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let total: u64 = report.files.iter().map(|file| file.size).sum();
+                        if ui
+                            .add_enabled(!report.files.is_empty(), egui::Button::new(format!("全部移至回收文件夹 (可释放 {})", format_size(total))))
+                            .on_hover_text("文件被移动到U盘同步文件夹内的回收文件夹（.syncu_trash 或 .syncu/trash），可以手动恢复或删除")
+                            .clicked()
+                        {
+                            clean_up = true;
+                        }
+                        if ui.button("重新检查").clicked() {
+                            refresh = true;
+                        }
+                    });
+                });
+            if clean_up {
+                let log_verbosity = self.settings.profile_for(&report.local_folder).device_log_verbosity;
+                report.clean_up(log_verbosity, ctx.clone());
+            }
+            if refresh {
+                report.refresh(ctx.clone());
+            }
+            if !open {
+                self.orphan_report = None;
+            }
+        }
+    }
+
+    fn diagnostics_window(&mut self, ctx: &egui::Context) {
+        if let Some(diagnostics) = &mut self.diagnostics {
+            match diagnostics.poll() {
+                Some(Ok(Some(results))) => {
+                    let report = results.report();
+                    for line in report.lines() {
+                        let log = format!("[{}] 诊断: {}", chrono::Local::now().format("%H:%M:%S"), line);
+                        self.session_log.append(&log);
+                        self.sync_log.push(RichText::new(log).color(self.palette.success));
+                    }
+                    diagnostics.report = Some(report);
+                }
+                Some(Ok(None)) => diagnostics.status = "诊断已停止".to_owned(),
+                Some(Err(e)) => diagnostics.error = Some(e),
+                None => {}
+            }
+
+            let mut open = true;
+            let can_start = self.state == SyncState::Idle && !diagnostics.is_running();
+            let targets = self.selected_usb_drive.clone().zip(self.local_folder.clone());
+            egui::Window::new("诊断")
+                .open(&mut open)
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.label("测试U盘的顺序读写速度，以及内存和本地磁盘上的哈希速度，用于判断同步慢的原因。");
+                    ui.label(RichText::new("将在U盘和本地文件夹中各写入一个 256 MB 的临时文件，测试结束后自动删除。").weak());
+                    ui.add_space(10.0);
+                    if diagnostics.is_running() {
+                        ui.add(egui::ProgressBar::new(diagnostics.progress).show_percentage());
+                        ui.label(&diagnostics.status);
+                        if ui.button("停止").clicked() {
+                            diagnostics.stop();
+                        }
+                    } else {
+                        if let Some(error) = &diagnostics.error {
+                            ui.label(RichText::new(format!("诊断失败: {}", error)).color(self.palette.error));
+                        } else if let Some(report) = &diagnostics.report {
+                            ui.label(RichText::new(report).monospace());
+                            if ui.button("复制结果").clicked() {
+                                ui.ctx().copy_text(report.clone());
+                            }
+                        } else if !diagnostics.status.is_empty() {
+                            ui.label(&diagnostics.status);
+                        }
+                        ui.add_space(5.0);
+                        if ui
+                            .add_enabled(can_start && targets.is_some(), egui::Button::new("开始测试"))
+                            .on_disabled_hover_text("请先选择本地文件夹和U盘，且不能在同步时运行")
+                            .clicked()
+                            && let Some((usb_root, local_folder)) = targets
+                        {
+                            diagnostics.start(usb_root, local_folder, ctx.clone());
+                        }
+                    }
+                });
+            if !open {
+                // Closing the window stops a running benchmark; its temporary files are removed on the way out
+                diagnostics.stop();
+                self.diagnostics = None;
+            }
+        }
+    }
+
+    fn about_window(&mut self, ctx: &egui::Context) {
+        if self.show_about_window {
+            egui::Window::new("关于 SyncU")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.add_space(15.0);
+                    ui.vertical_centered(|ui| {
+                        ui.heading("SyncU");
+                        ui.add_space(10.0);
+                        ui.label(format!("版本: {}", APP_VERSION));
+                        ui.add_space(10.0);
+                        ui.label("作者: Eight_Eggs");
+                        ui.add_space(10.0);
+                        ui.hyperlink_to("访问 GitHub 仓库", "https://github.com/EightEggs/SyncU");
+                    });
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.vertical_centered(|ui| {
+                        let close = ui.button("关闭");
+                        self.dialog_focus.default_button(egui::Id::new("about"), &close);
+                        if close.clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                            self.show_about_window = false;
+                        }
+                    });
+                });
+        }
+    }
+
+    fn routing_window(&mut self, ctx: &egui::Context) {
+        if self.show_routing_window {
+            let mut open = true;
+            egui::Window::new("路由规则")
+                .open(&mut open)
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    let Some(local) = self.local_folder.clone() else {
+                        ui.label("请先选择本地文件夹。");
+                        return;
+                    };
+                    ui.label("匹配的文件将放到U盘上的指定目录下（保留其原有子目录），按顺序取第一条匹配的规则。");
+                    ui.label(RichText::new("示例: *.jpg → Photos").weak());
+                    ui.add_space(10.0);
+
+                    let rules = &mut self.settings.profile_mut_for(&local).routing_rules;
+                    let mut to_remove = None;
+                    let mut to_raise = None;
+                    egui::Grid::new("routing_rules_grid").striped(true).show(ui, |ui| {
+                        ui.label("匹配模式");
+                        ui.label("U盘目录");
+                        ui.end_row();
+                        for (i, rule) in rules.iter_mut().enumerate() {
+                            ui.text_edit_singleline(&mut rule.pattern);
+                            let mut destination = rule.destination.to_string_lossy().into_owned();
+                            if ui.text_edit_singleline(&mut destination).changed() {
+                                rule.destination = PathBuf::from(destination);
+                            }
+                            ui.horizontal(|ui| {
+                                if ui.add_enabled(i > 0, egui::Button::new("上移")).clicked() {
+                                    to_raise = Some(i);
+                                }
+                                if ui.button("删除").clicked() {
+                                    to_remove = Some(i);
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    });
+                    if let Some(i) = to_raise {
+                        rules.swap(i - 1, i);
+                    }
+                    if let Some(i) = to_remove {
+                        rules.remove(i);
+                    }
+                    if ui.button("添加规则").clicked() {
+                        rules.push(RoutingRule::default());
+                    }
+
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.vertical_centered(|ui| {
+                        if ui.button("保存").clicked() {
+                            if let Err(e) = self.settings.save() {
+                                self.error_message = format!("保存设置失败: {}", e);
+                                self.show_error_dialog = true;
+                            }
+                            self.show_routing_window = false;
+                        }
+                    });
+                });
+            if !open {
+                self.show_routing_window = false;
+            }
+        }
+    }
+
+    fn answer_rules_window(&mut self, ctx: &egui::Context) {
+        let Some(editor) = &mut self.answer_rules_editor else { return };
+        let mut open = true;
+        let mut save = false;
+        egui::Window::new("应答规则")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label("按顺序检查规则，删除确认和冲突各采用第一条匹配且设置了回答的规则；「询问」让匹配的文件照常询问。");
+                ui.label("没有规则匹配时照常询问，无人值守的同步则跳过。每次自动回答都会记录在日志中。");
+                ui.label(RichText::new("示例: Old Projects/** → 删除；** → 本次保留、采用本地版本").weak());
+                match &editor.path {
+                    Some(path) => {
+                        elided_path_label(ui, &path.display().to_string(), 0.0, true);
+                    }
+                    None => {
+                        ui.label(RichText::new("新规则文件，保存时选择位置").weak());
+                    }
+                }
+                ui.add_space(10.0);
+
+                let rules = &mut editor.rules.0;
+                let mut to_remove = None;
+                let mut to_raise = None;
+                egui::Grid::new("answer_rules_grid").striped(true).show(ui, |ui| {
+                    ui.label("匹配模式");
+                    ui.label("删除确认");
+                    ui.label("冲突");
+                    ui.end_row();
+                    for (i, rule) in rules.iter_mut().enumerate() {
+                        ui.text_edit_singleline(&mut rule.pattern);
+                        egui::ComboBox::from_id_salt(("answer_rule_delete", i))
+                            .selected_text(rule.on_delete.map_or("不处理", |answer| answer.label()))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut rule.on_delete, None, "不处理");
+                                for answer in DeletionAnswer::ALL {
+                                    ui.selectable_value(&mut rule.on_delete, Some(answer), answer.label());
+                                }
+                            });
+                        egui::ComboBox::from_id_salt(("answer_rule_conflict", i))
+                            .selected_text(rule.on_conflict.map_or("不处理", |answer| answer.label()))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut rule.on_conflict, None, "不处理");
+                                for answer in ConflictAnswer::ALL {
+                                    ui.selectable_value(&mut rule.on_conflict, Some(answer), answer.label());
+                                }
+                            });
+                        ui.horizontal(|ui| {
+                            if ui.add_enabled(i > 0, egui::Button::new("上移")).clicked() {
+                                to_raise = Some(i);
+                            }
+                            if ui.button("删除").clicked() {
+                                to_remove = Some(i);
+                            }
+                        });
+                        match rule.problem() {
+                            Some(problem) => ui.label(RichText::new(problem).small().color(self.palette.error)),
+                            None => ui.label(""),
+                        };
+                        ui.end_row();
+                    }
+                });
+                if let Some(i) = to_raise {
+                    rules.swap(i - 1, i);
+                }
+                if let Some(i) = to_remove {
+                    rules.remove(i);
+                }
+                if ui.button("添加规则").clicked() {
+                    rules.push(AnswerRule { pattern: "**".to_owned(), ..Default::default() });
+                }
+                if let Some(error) = &editor.error {
+                    ui.label(RichText::new(error).color(self.palette.error));
+                }
+
+                ui.add_space(10.0);
+                ui.separator();
+                ui.vertical_centered(|ui| {
+                    save = ui.add_enabled(editor.rules.check().is_ok(), egui::Button::new("保存")).clicked();
+                });
+            });
+        if save {
+            let path = editor.path.clone().or_else(|| rfd::FileDialog::new().add_filter("JSON", &["json"]).set_file_name("rules.json").save_file());
+            let Some(path) = path else { return };
+            match editor.rules.save(&path) {
+                Ok(()) => {
+                    open = false;
+                    self.remember_in_profile(|profile| profile.answer_rules_file = Some(path));
+                }
+                Err(e) => editor.error = Some(format!("保存规则文件失败: {}", e)),
+            }
+        }
+        if !open {
+            self.answer_rules_editor = None;
+        }
+    }
+}
@@ -0,0 +1,286 @@
+//! The central panel: folder and drive selection, the sync button and controls, and the log.
+
+use crate::app::{drive_text, elided_path_label, is_error_log_line, is_unsynced_log_line, is_warning_log_line, log_item_kind, DiagnosticsWindow, SyncApp, SyncState, LOG_GROUP_MIN_LINES};
+use crate::models::SyncMessage;
+use egui::RichText;
+
+impl SyncApp {
+    pub(in crate::app) fn show_main_panel(&mut self, ctx: &egui::Context) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            // When a dialog is shown, disable the main UI
+            let main_ui_enabled = self.pending_prompts.is_empty()
+                && !self.show_about_window
+                && !self.show_error_dialog
+                && !self.show_clock_warning
+                && !self.show_routing_window
+                && self.answer_rules_editor.is_none()
+                && !self.show_options_window
+                && !self.show_in_use_confirmation
+                && self.newer_destination.is_none()
+                && self.denied_copy.is_none()
+                && self.remote_missing_state.is_none()
+                && self.long_paths_state.is_none()
+                && self.unreadable_sizes_prompt.is_none()
+                && self.crowded_directories_state.is_none()
+                && self.name_collisions_state.is_none()
+                && self.relink_prompt.is_none()
+                && self.resume_plan_prompt.is_none()
+                && self.newer_metadata_prompt.is_none()
+                && self.completion_summary.is_none()
+                && !self.show_consistency_window
+                && self.permission_warning.is_none()
+                && self.overlap_warning.is_none()
+                && !self.diagnostics.as_ref().is_some_and(DiagnosticsWindow::is_running);
+            self.onboarding.main_ui_enabled = main_ui_enabled;
+            ui.add_enabled_ui(main_ui_enabled, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.add_space(5.0);
+                    ui.heading("SyncU: Sync Your Files with USB");
+                    ui.add_space(5.0);
+                });
+
+                ui.add_space(1.0);
+                let large_transfer = self.large_transfer_expected();
+                ui.add_enabled_ui(self.state == SyncState::Idle, |ui| {
+                    ui.vertical_centered(|ui| {
+                        egui::Frame::group(ui.style())
+                            .corner_radius(egui::CornerRadius::same(8))
+                            .inner_margin(egui::Margin::same(12))
+                            .show(ui, |ui| {
+                                // Use vertical layout for rows
+                                ui.vertical(|ui| {
+                                    // First row: Local folder
+                                    ui.horizontal(|ui| {
+                                        ui.label("本地:");
+                                        let local_path_text = self
+                                            .local_folder
+                                            .as_ref()
+                                            .map_or("未选择", |p| p.to_str().unwrap_or(""));
+                                        elided_path_label(ui, local_path_text, 80.0, true);
+
+                                        // Align button to the right
+                                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                            let choose = ui.button("选择...");
+                                            self.onboarding.folder_button = Some(choose.rect);
+                                            if choose.clicked() {
+                                                self.pick_local_folder();
+                                            }
+                                        });
+                                    });
+                                    self.local_totals.show(ui, large_transfer.then_some(self.palette.warning));
+
+                                    ui.add_space(5.0); // spacing between rows
+
+                                    // Second row: USB drive
+                                    let usb_row = ui.horizontal(|ui| {
+                                        ui.label("U盘:");
+                                        let unavailable = self.selected_drive_unavailable();
+                                        if self.usb_drives.len() > 1 {
+                                            let selected_text = self.selected_usb_drive.as_ref().map_or(
+                                                RichText::new("请选择U盘"),
+                                                |p| drive_text(p, unavailable),
+                                            );
+                                            egui::ComboBox::from_label("")
+                                                .selected_text(selected_text)
+                                                .show_ui(ui, |ui| {
+                                                    // Unusable drives stay selectable, so their instructions can be shown
+                                                    for drive in &self.usb_drives {
+                                                        ui.selectable_value(
+                                                            &mut self.selected_usb_drive,
+                                                            Some(drive.mount_point.clone()),
+                                                            drive_text(&drive.mount_point, drive.unavailable),
+                                                        );
+                                                    }
+                                                });
+                                        } else {
+                                            let usb_path_text = self
+                                                .selected_usb_drive
+                                                .as_ref()
+                                                .map_or("未检测到", |p| {
+                                                    p.to_str().unwrap_or("")
+                                                });
+                                            elided_path_label(ui, usb_path_text, 80.0, true);
+                                            if let Some(reason) = unavailable {
+                                                ui.label(RichText::new(format!("({})", reason.label())).weak());
+                                            }
+                                        }
+
+                                        // Align button to the right
+                                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                            let listing = self.drives_rx.is_some();
+                                            if ui.add_enabled(!listing, egui::Button::new(" 刷新 ")).clicked() {
+                                                self.refresh_usb_drives();
+                                            }
+                                            if listing {
+                                                ui.spinner().on_hover_text("正在检测U盘...");
+                                            }
+                                        });
+                                    });
+                                    self.onboarding.usb_row = Some(usb_row.response.rect);
+                                    if let Some(reason) = self.selected_drive_unavailable() {
+                                        ui.horizontal_wrapped(|ui| {
+                                            ui.label(RichText::new(reason.instructions()).small().color(self.palette.warning));
+                                            if ui.add_enabled(self.drives_rx.is_none(), egui::Button::new("重新检查").small()).clicked() {
+                                                self.refresh_usb_drives();
+                                            }
+                                        });
+                                    }
+                                    self.usb_totals.show(ui, large_transfer.then_some(self.palette.warning));
+                                    if large_transfer {
+                                        ui.label(RichText::new("两侧大小相差较大，本次同步可能需要传输大量数据。").small().color(self.palette.warning));
+                                    }
+                                });
+                            });
+                    });
+                });
+
+                if let Some(warning) = &self.nested_root_warning {
+                    ui.vertical_centered(|ui| {
+                        ui.label(RichText::new(warning).small().color(self.palette.warning));
+                    });
+                }
+
+                ui.add_space(5.0);
+
+                ui.vertical_centered(|ui| {
+                    match self.state {
+                        SyncState::Idle => {
+                            let hint = self.missing_requirement_hint();
+                            let label = match self.change_estimate {
+                                Some(count) if hint.is_none() => format!("立即同步 (约 {} 个变更)", count),
+                                _ => "立即同步".to_owned(),
+                            };
+                            let sync_button = egui::Button::new(RichText::new(label).color(self.palette.on_accent))
+                                .corner_radius(egui::CornerRadius::same(6))
+                                .min_size(egui::vec2(250.0, 40.0))
+                                .fill(self.palette.accent);
+                            let mut response = ui.add_enabled(hint.is_none(), sync_button);
+                            self.onboarding.sync_button = Some(response.rect);
+                            if let Some(hint) = hint {
+                                response = response.on_disabled_hover_text(hint);
+                                ui.label(RichText::new(hint).small().weak());
+                            }
+                            if response.clicked() {
+                                self.request_sync();
+                            }
+                        }
+                        SyncState::Syncing => self.show_sync_controls(ui),
+                        SyncState::Stopping => {
+                            let stop_button = egui::Button::new(
+                                RichText::new("正在停止...").color(egui::Color32::WHITE),
+                            )
+                            .corner_radius(egui::CornerRadius::same(6))
+                            .min_size(egui::vec2(250.0, 40.0))
+                            .fill(self.palette.stop);
+                            ui.add_enabled(false, stop_button);
+                            // A soft stop can take as long as the copy in progress
+                            if self.soft_stop && ui.small_button("立即停止").clicked() {
+                                self.send_to_sync(SyncMessage::Stop);
+                                self.soft_stop = false;
+                            }
+                        }
+                    }
+                });
+            });
+
+            ui.add_space(5.0);
+
+            // Reading the log changes nothing, so it stays scrollable under a dialog while its filters are disabled
+            egui::Frame::group(ui.style())
+                .corner_radius(egui::CornerRadius::same(8))
+                .inner_margin(egui::Margin::same(12))
+                .show(ui, |ui| {
+                    ui.add_enabled_ui(main_ui_enabled, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.heading(RichText::new("日志").size(16.0));
+                            self.show_log_problem_badge(ui);
+                            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                ui.checkbox(&mut self.show_unsynced_only, "仅显示未同步项");
+                                ui.checkbox(&mut self.group_log, "合并相同操作");
+                            });
+                        });
+                    });
+                    ui.separator();
+                    egui::ScrollArea::vertical()
+                        .max_height(234.0)
+                        .stick_to_bottom(true)
+                        .auto_shrink([false; 2])
+                        .show(ui, |ui| {
+                            // Group after filtering, so only lines that are shown can be folded together
+                            let visible: Vec<usize> = (0..self.sync_log.len())
+                                .filter(|&index| !self.show_unsynced_only || is_unsynced_log_line(self.sync_log[index].text()))
+                                .filter(|&index| !self.show_problems_only || is_problem_log_line(self.sync_log[index].text()))
+                                .collect();
+                            let mut start = 0;
+                            while start < visible.len() {
+                                let kind = if self.group_log { log_item_kind(self.sync_log[visible[start]].text()) } else { None };
+                                let run = kind.map_or(1, |kind| {
+                                    visible[start..]
+                                        .iter()
+                                        .take_while(|&&index| log_item_kind(self.sync_log[index].text()) == Some(kind))
+                                        .count()
+                                });
+                                let lines = &visible[start..start + run];
+                                match kind {
+                                    Some(kind) if run >= LOG_GROUP_MIN_LINES => {
+                                        let header = RichText::new(format!("{} × {} (点击展开)", kind, run)).color(self.palette.success);
+                                        // Keyed by the first line, which stays put while the group grows
+                                        egui::CollapsingHeader::new(header).id_salt(("log_group", visible[start])).show(ui, |ui| {
+                                            for &index in lines {
+                                                ui.label(self.sync_log[index].clone());
+                                            }
+                                        });
+                                    }
+                                    _ => {
+                                        for &index in lines {
+                                            let response = ui.label(self.sync_log[index].clone());
+                                            // Errors are never grouped, so the first one is always a plain row
+                                            if self.scroll_to_first_error && is_error_log_line(self.sync_log[index].text()) {
+                                                response.scroll_to_me(Some(egui::Align::Center));
+                                                self.scroll_to_first_error = false;
+                                            }
+                                        }
+                                    }
+                                }
+                                start += run;
+                            }
+                        });
+                });
+        });
+    }
+
+    // The live count of errors and warnings next to the log heading; clicking it shows only those lines.
+    fn show_log_problem_badge(&mut self, ui: &mut egui::Ui) {
+        let errors = self.sync_log.iter().filter(|line| is_error_log_line(line.text())).count();
+        let warnings = self.sync_log.iter().filter(|line| is_warning_log_line(line.text())).count();
+        let mut badge = format!("({} 错误", errors);
+        if warnings > 0 {
+            badge += &format!(" · {} 警告", warnings);
+        }
+        badge += ")";
+        let color = if errors > 0 {
+            self.palette.error
+        } else if warnings > 0 {
+            self.palette.warning
+        } else {
+            ui.visuals().weak_text_color()
+        };
+        let response = ui
+            .selectable_label(self.show_problems_only, RichText::new(badge).color(color))
+            .on_hover_text(if self.show_problems_only { "点击显示全部日志" } else { "点击只显示错误和警告；右键可复制" });
+        if response.clicked() {
+            self.show_problems_only = !self.show_problems_only;
+        }
+        response.context_menu(|ui| {
+            if ui.add_enabled(errors + warnings > 0, egui::Button::new("复制错误和警告")).clicked() {
+                let lines: Vec<&str> = self.sync_log.iter().map(RichText::text).filter(|line| is_problem_log_line(line)).collect();
+                ui.ctx().copy_text(lines.join("\n"));
+                ui.close();
+            }
+        });
+    }
+}
+
+fn is_problem_log_line(line: &str) -> bool {
+    is_error_log_line(line) || is_warning_log_line(line)
+}
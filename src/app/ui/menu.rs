@@ -0,0 +1,208 @@
+//! The menu bar: records and tools, settings, theme and help.
+
+use crate::app::{DiagnosticsWindow, HistoryWindow, MetadataInspector, OrphanReport, SyncApp, SyncState};
+use crate::models::Theme;
+use crate::monitor::DEFAULT_MONITOR_PORT;
+use crate::palette::{contrast_ratio, MIN_LINK_CONTRAST};
+use crate::report::file_url;
+use crate::session_log::SessionLog;
+use crate::shortcuts;
+use egui::RichText;
+
+impl SyncApp {
+    pub(in crate::app) fn show_menu_bar(&mut self, ctx: &egui::Context) {
+        egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.menu_button("文件", |ui| {
+                    let metadata_path = self.metadata_path();
+                    if ui
+                        .add_enabled(metadata_path.is_some(), egui::Button::new("查看同步记录..."))
+                        .on_disabled_hover_text("请先选择本地文件夹和U盘")
+                        .clicked()
+                    {
+                        if let Some(path) = &metadata_path {
+                            self.metadata_inspector = Some(MetadataInspector::open(path.clone(), ctx.clone()));
+                        }
+                        ui.close();
+                    }
+                    let report = self.latest_report();
+                    if ui
+                        .add_enabled(report.is_some(), egui::Button::new("打开最新报告"))
+                        .on_hover_text("在浏览器中打开最近一次同步的 HTML 报告")
+                        .on_disabled_hover_text("尚无同步报告。可在同步选项中开启「同步报告」")
+                        .clicked()
+                    {
+                        if let Some(path) = report {
+                            ctx.open_url(egui::OpenUrl::new_tab(file_url(&path)));
+                        }
+                        ui.close();
+                    }
+                    if ui.button("同步历史...").on_hover_text("查看最近每次同步复制、删除了哪些文件").clicked() {
+                        self.history_window = Some(HistoryWindow::open(ctx.clone()));
+                        ui.close();
+                    }
+                    if ui
+                        .add_enabled(self.state == SyncState::Idle && metadata_path.is_some(), egui::Button::new("残留文件检查..."))
+                        .on_hover_text("列出U盘上同步不会再更新或删除的文件：在本地删除后选择保留的文件，以及中断的复制留下的临时文件")
+                        .on_disabled_hover_text("请先选择本地文件夹和U盘，且不能在同步时运行")
+                        .clicked()
+                    {
+                        if let (Some(local), Some(usb)) = (self.local_folder.clone(), self.selected_usb_drive.clone()) {
+                            self.orphan_report = Some(OrphanReport::open(local, usb, ctx.clone()));
+                        }
+                        ui.close();
+                    }
+                    if ui
+                        .add_enabled(self.local_folder.is_some(), egui::Button::new("保存同步快捷文件..."))
+                        .on_hover_text("保存一个 .syncu 文件，双击即可打开 SyncU 并选好此文件夹和U盘。将文件中的 \"auto\" 改为 true 可在打开后自动开始同步")
+                        .on_disabled_hover_text("请先选择本地文件夹")
+                        .clicked()
+                    {
+                        self.save_launch_file();
+                        ui.close();
+                    }
+                    if ui
+                        .add_enabled(self.state == SyncState::Idle, egui::Button::new("诊断..."))
+                        .on_disabled_hover_text("同步进行中，无法运行诊断")
+                        .clicked()
+                    {
+                        self.diagnostics.get_or_insert_with(DiagnosticsWindow::new);
+                        ui.close();
+                    }
+                    if ui.button("查看上次会话日志").clicked() {
+                        match SessionLog::read_previous() {
+                            Ok(log) => self.previous_session_log = Some(log),
+                            Err(e) => {
+                                self.error_message = format!("无法读取上次会话日志: {}", e);
+                                self.show_error_dialog = true;
+                            }
+                        }
+                        ui.close();
+                    }
+                    if ui.button("退出").clicked() {
+                        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                    }
+                });
+                ui.separator();
+                ui.menu_button("设置", |ui| {
+                    if ui
+                        .add_enabled(self.state == SyncState::Idle, egui::Button::new("同步选项..."))
+                        .clicked()
+                    {
+                        self.show_options_window = true;
+                        if let Some(local) = &self.local_folder {
+                            self.line_ending_extensions = self.settings.profile_for(local).line_ending_extensions.join(", ");
+                        }
+                        self.load_kept_files();
+                        ui.close();
+                    }
+                    if ui
+                        .add_enabled(self.state == SyncState::Idle, egui::Button::new("路由规则..."))
+                        .clicked()
+                    {
+                        self.show_routing_window = true;
+                        ui.close();
+                    }
+                    ui.separator();
+                    let mut desktop_shortcut = self.desktop_shortcut;
+                    if ui.checkbox(&mut desktop_shortcut, "创建桌面快捷方式").changed() {
+                        let result = if desktop_shortcut {
+                            shortcuts::create_desktop_shortcut().map(|_| ())
+                        } else {
+                            shortcuts::remove_desktop_shortcut()
+                        };
+                        if let Err(e) = result {
+                            self.error_message = format!("更改桌面快捷方式失败: {}", e);
+                            self.show_error_dialog = true;
+                        }
+                        self.desktop_shortcut = shortcuts::desktop_shortcut_exists();
+                    }
+                    let mut autostart = self.autostart;
+                    if ui.checkbox(&mut autostart, "开机自动启动（最小化）").changed() {
+                        if let Err(e) = shortcuts::set_autostart(autostart) {
+                            self.error_message = format!("更改开机自动启动失败: {}", e);
+                            self.show_error_dialog = true;
+                        }
+                        self.autostart = shortcuts::autostart_enabled();
+                    }
+                    // Only registered on Windows; elsewhere launch files are opened with SyncU by hand
+                    if cfg!(windows) {
+                        let mut file_association = self.file_association;
+                        if ui.checkbox(&mut file_association, "双击 .syncu 文件时用 SyncU 打开").changed() {
+                            if let Err(e) = shortcuts::set_file_association(file_association) {
+                                self.error_message = format!("更改文件关联失败: {}", e);
+                                self.show_error_dialog = true;
+                            }
+                            self.file_association = shortcuts::file_association_enabled();
+                        }
+                    }
+                    let port = self.settings.monitor_port.unwrap_or(DEFAULT_MONITOR_PORT);
+                    let mut monitor = self.monitor.is_some();
+                    if ui
+                        .checkbox(&mut monitor, format!("允许外部程序读取同步进度（本机端口 {}）", port))
+                        .on_hover_text("以每行一条 JSON 的形式发送进度、错误和同步结果，只读，仅限本机连接。下次同步开始时生效。")
+                        .changed()
+                    {
+                        self.set_monitor(monitor.then_some(port));
+                    }
+                });
+                ui.separator();
+                ui.menu_button("主题", |ui| {
+                    if ui
+                        .selectable_value(&mut self.current_theme, Theme::Light, "明亮")
+                        .clicked()
+                    {
+                        ui.close();
+                    }
+                    if ui
+                        .selectable_value(&mut self.current_theme, Theme::Dark, "暗黑")
+                        .clicked()
+                    {
+                        ui.close();
+                    }
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.label("强调色:");
+                        let [r, g, b, _] = self.palette.accent.to_array();
+                        let mut rgb = [r, g, b];
+                        let mut changed = egui::color_picker::color_edit_button_srgb(ui, &mut rgb).changed();
+                        if changed {
+                            self.settings.accent_color = Some(rgb);
+                        }
+                        if ui.add_enabled(self.settings.accent_color.is_some(), egui::Button::new("恢复默认")).clicked() {
+                            self.settings.accent_color = None;
+                            changed = true;
+                        }
+                        if changed
+                            && let Err(e) = self.settings.save()
+                        {
+                            self.error_message = format!("保存设置失败: {}", e);
+                            self.show_error_dialog = true;
+                        }
+                    });
+                    // Text on the accent is picked automatically; links only take the accent where it stays readable
+                    let text_contrast = contrast_ratio(self.palette.on_accent, self.palette.accent);
+                    ui.label(RichText::new(format!("按钮文字对比度 {:.1}:1", text_contrast)).small().weak());
+                    if contrast_ratio(self.palette.accent, ui.visuals().panel_fill) < MIN_LINK_CONTRAST {
+                        ui.label(RichText::new("此颜色与背景对比度较低，链接将保留默认颜色").small().color(self.palette.warning));
+                    }
+                });
+                ui.separator();
+                ui.menu_button("帮助", |ui| {
+                    if ui
+                        .button("生成诊断信息...")
+                        .on_hover_text("汇总版本、设置、U盘和最近的日志，方便发给帮你排查问题的人。只在本机生成，不会自动发送")
+                        .clicked()
+                    {
+                        self.open_support_report();
+                        ui.close();
+                    }
+                    if ui.button("关于").clicked() {
+                        self.show_about_window = true;
+                        ui.close();
+                    }
+                });
+            });
+        });
+    }
+}
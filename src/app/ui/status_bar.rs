@@ -0,0 +1,91 @@
+//! The status bar: progress of the running sync and the outcome of the last one.
+
+use crate::app::{elided_path_label, SyncApp, SyncState};
+use crate::utils::format_count;
+use egui::RichText;
+
+impl SyncApp {
+    pub(in crate::app) fn show_status_bar(&mut self, ctx: &egui::Context) {
+        egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
+            ui.add_space(4.0);
+            if self.state != SyncState::Idle {
+                ui.horizontal(|ui| {
+                    if self.paused {
+                        ui.label(RichText::new("已暂停").color(self.palette.warning));
+                    } else if self.state == SyncState::Syncing {
+                        ui.add(egui::Spinner::new());
+                    }
+                    ui.add(egui::ProgressBar::new(self.progress).desired_width(200.0));
+                    elided_path_label(ui, &self.current_file, 0.0, false);
+                });
+                let mut enter_compact = false;
+                ui.horizontal(|ui| {
+                    if let Some(stats) = &self.stats {
+                        ui.label(RichText::new(stats.summary()).small());
+                    }
+                    if self.state == SyncState::Syncing {
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            // A question open now would bring the full window straight back
+                            enter_compact = ui
+                                .add_enabled(!self.waiting_for_answer(), egui::Button::new("紧凑模式").small())
+                                .on_hover_text("缩小为置顶的进度条，同步结束后恢复")
+                                .clicked();
+                        });
+                    }
+                });
+                if enter_compact {
+                    self.enter_compact_mode(ctx);
+                }
+                if let Some(hint) = &self.small_files_hint {
+                    let (mut dismiss, mut never) = (false, false);
+                    ui.horizontal(|ui| {
+                        ui.label(RichText::new(hint.text(self.stats.as_ref())).small().color(self.palette.warning));
+                        dismiss = ui.small_button("知道了").clicked();
+                        never = ui.small_button("不再提示").on_hover_text("对此文件夹不再提示").clicked();
+                    });
+                    if never {
+                        self.remember_in_profile(|profile| profile.small_files_hint = false);
+                    }
+                    if dismiss || never {
+                        self.small_files_hint = None;
+                    }
+                }
+                if self.state == SyncState::Syncing {
+                    self.show_rate_limit_control(ui);
+                }
+            } else if let Some(last_run) = &self.last_run {
+                let mut dismiss = false;
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new(last_run.summary()).color(self.palette.stopped));
+                    elided_path_label(ui, &last_run.current_file, 30.0, true);
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        dismiss = ui.small_button("×").on_hover_text("不再显示").clicked();
+                    });
+                });
+                if dismiss {
+                    self.last_run = None;
+                }
+            } else {
+                ui.horizontal(|ui| {
+                    ui.label(
+                        self.sync_log
+                            .last()
+                            .cloned()
+                            .unwrap_or_else(|| RichText::new("准备就绪")),
+                    );
+                });
+                if let Some(report) = &self.consistency {
+                    if report.mismatched.is_empty() {
+                        ui.label(RichText::new(format!("两侧一致 ({} 个文件)", format_count(report.checked as u64))).small().color(self.palette.ready));
+                    } else {
+                        let text = format!("发现 {} 个文件在同步期间被修改", format_count(report.mismatched.len() as u64));
+                        if ui.link(RichText::new(text).small().color(self.palette.warning)).clicked() {
+                            self.show_consistency_window = true;
+                        }
+                    }
+                }
+            }
+            ui.add_space(4.0);
+        });
+    }
+}
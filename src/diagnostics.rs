@@ -0,0 +1,295 @@
+use crate::error::{IoResultExt, SyncError};
+use crate::models::{DriveUnavailable, SYNCU_VERSION};
+use crate::observer::SyncObserver;
+use crate::settings::Settings;
+use crate::utils::{app_data_dir, available_space, file_system_name, find_usb_drives, format_size};
+use chrono::Local;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::Instant;
+use sysinfo::System;
+
+const BENCHMARK_FILE_NAME: &str = ".syncu_benchmark.tmp";
+const BENCHMARK_SIZE: u64 = 256 * 1024 * 1024;
+const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+/// Free space required on each volume before a benchmark file is written.
+const REQUIRED_FREE_SPACE: u64 = 300 * 1024 * 1024;
+/// How many of the most recent log lines a support report includes.
+pub const SUPPORT_LOG_LINES: usize = 50;
+/// Hex digits kept of the hash that stands in for a redacted path component.
+const REDACTED_COMPONENT_LEN: usize = 8;
+
+/// Throughput in MB/s for each benchmark.
+#[derive(Clone, Debug)]
+pub struct BenchmarkResults {
+    pub usb_write: f64,
+    pub usb_read: f64,
+    pub hash_memory: f64,
+    pub hash_local_disk: f64,
+}
+
+impl BenchmarkResults {
+    /// One line per benchmark, suitable for the log and the clipboard.
+    pub fn report(&self) -> String {
+        [
+            ("U盘顺序写入", self.usb_write),
+            ("U盘顺序读取", self.usb_read),
+            ("内存哈希", self.hash_memory),
+            ("本地磁盘读取并哈希", self.hash_local_disk),
+        ]
+        .iter()
+        .map(|(label, speed)| format!("{}: {:.1} MB/s", label, speed))
+        .collect::<Vec<_>>()
+        .join("\n")
+    }
+}
+
+// Removes a benchmark file however the benchmark ends.
+struct TempFile(PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+fn megabytes_per_second(bytes: u64, started: Instant) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0) / started.elapsed().as_secs_f64().max(f64::EPSILON)
+}
+
+// Incompressible filler, so drives that compress or deduplicate can't shortcut the writes.
+fn filler_chunk() -> Vec<u8> {
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    (0..CHUNK_SIZE)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+fn ensure_free_space(path: &Path, message: &'static str) -> Result<(), SyncError> {
+    match available_space(path) {
+        Some(free) if free < REQUIRED_FREE_SPACE => Err(SyncError::InvalidSelection(message)),
+        _ => Ok(()),
+    }
+}
+
+/// Writes `BENCHMARK_SIZE` bytes to `path`, reporting progress between `progress_range`.
+/// Returns the elapsed start time, or None if stopped.
+fn write_benchmark_file(path: &Path, chunk: &[u8], observer: &impl SyncObserver, progress_range: (f32, f32), message: &str) -> Result<Option<Instant>, SyncError> {
+    let started = Instant::now();
+    let mut file = File::create(path).at(path)?;
+    let mut written = 0u64;
+    while written < BENCHMARK_SIZE {
+        if observer.should_stop() {
+            return Ok(None);
+        }
+        file.write_all(chunk).at(path)?;
+        written += chunk.len() as u64;
+        let fraction = written as f32 / BENCHMARK_SIZE as f32;
+        observer.on_progress(progress_range.0 + (progress_range.1 - progress_range.0) * fraction, message.to_string());
+    }
+    // Include the time the data takes to actually reach the device
+    file.sync_all().at(path)?;
+    Ok(Some(started))
+}
+
+/// Reads `path` to the end, optionally hashing it. Returns None if stopped.
+fn read_benchmark_file(path: &Path, hash: bool, observer: &impl SyncObserver, progress_range: (f32, f32), message: &str) -> Result<Option<()>, SyncError> {
+    let mut file = File::open(path).at(path)?;
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut hasher = Sha256::new();
+    let mut read = 0u64;
+    loop {
+        if observer.should_stop() {
+            return Ok(None);
+        }
+        let count = file.read(&mut buffer).at(path)?;
+        if count == 0 {
+            break;
+        }
+        if hash {
+            hasher.update(&buffer[..count]);
+        }
+        read += count as u64;
+        let fraction = read as f32 / BENCHMARK_SIZE as f32;
+        observer.on_progress(progress_range.0 + (progress_range.1 - progress_range.0) * fraction, message.to_string());
+    }
+    let _ = hasher.finalize();
+    Ok(Some(()))
+}
+
+/// Measures USB write and read speed, hashing speed in memory, and reading plus hashing from the local folder's volume.
+/// Temporary files are removed afterwards. Returns Ok(None) if stopped.
+pub fn run_benchmarks(usb_root: &Path, local_folder: &Path, observer: &impl SyncObserver) -> Result<Option<BenchmarkResults>, SyncError> {
+    ensure_free_space(usb_root, "U盘可用空间不足 300 MB，无法运行诊断")?;
+    ensure_free_space(local_folder, "本地磁盘可用空间不足 300 MB，无法运行诊断")?;
+    let chunk = filler_chunk();
+
+    let usb_file = TempFile(usb_root.join(BENCHMARK_FILE_NAME));
+    let Some(started) = write_benchmark_file(&usb_file.0, &chunk, observer, (0.0, 0.3), "正在测试U盘写入速度...")? else {
+        return Ok(None);
+    };
+    let usb_write = megabytes_per_second(BENCHMARK_SIZE, started);
+
+    let started = Instant::now();
+    if read_benchmark_file(&usb_file.0, false, observer, (0.3, 0.5), "正在测试U盘读取速度...")?.is_none() {
+        return Ok(None);
+    }
+    let usb_read = megabytes_per_second(BENCHMARK_SIZE, started);
+    drop(usb_file);
+
+    let started = Instant::now();
+    let mut hasher = Sha256::new();
+    for index in 0..BENCHMARK_SIZE / CHUNK_SIZE as u64 {
+        if observer.should_stop() {
+            return Ok(None);
+        }
+        hasher.update(&chunk);
+        let fraction = (index + 1) as f32 * CHUNK_SIZE as f32 / BENCHMARK_SIZE as f32;
+        observer.on_progress(0.5 + 0.1 * fraction, "正在测试内存哈希速度...".to_string());
+    }
+    let _ = hasher.finalize();
+    let hash_memory = megabytes_per_second(BENCHMARK_SIZE, started);
+
+    let local_file = TempFile(local_folder.join(BENCHMARK_FILE_NAME));
+    if write_benchmark_file(&local_file.0, &chunk, observer, (0.6, 0.8), "正在准备本地测试文件...")?.is_none() {
+        return Ok(None);
+    }
+    let started = Instant::now();
+    if read_benchmark_file(&local_file.0, true, observer, (0.8, 1.0), "正在测试本地磁盘哈希速度...")?.is_none() {
+        return Ok(None);
+    }
+    let hash_local_disk = megabytes_per_second(BENCHMARK_SIZE, started);
+
+    Ok(Some(BenchmarkResults { usb_write, usb_read, hash_memory, hash_local_disk }))
+}
+
+/// A removable drive as a support report lists it.
+#[derive(Clone, Debug)]
+pub struct DriveSummary {
+    pub mount_point: PathBuf,
+    pub file_system: Option<String>,
+    pub available_space: Option<u64>,
+    pub unavailable: Option<DriveUnavailable>,
+}
+
+/// The removable drives connected right now, with their file systems and free space.
+pub fn summarize_drives() -> Vec<DriveSummary> {
+    find_usb_drives()
+        .into_iter()
+        .map(|drive| DriveSummary {
+            file_system: file_system_name(&drive.mount_point),
+            available_space: available_space(&drive.mount_point),
+            unavailable: drive.unavailable,
+            mount_point: drive.mount_point,
+        })
+        .collect()
+}
+
+/// What a support report is assembled from. The report is only text; nothing is sent anywhere.
+pub struct SupportInfo<'a> {
+    pub settings: &'a Settings,
+    pub drives: &'a [DriveSummary],
+    /// The log shown in the app, oldest first.
+    pub log: &'a [String],
+    pub last_error: Option<&'a str>,
+    /// Whether folder names in paths are replaced by hashes.
+    pub redact_paths: bool,
+}
+
+/// `path` with every component after the drive or root replaced by a short hash of its name, e.g. `E:\3f2a91c0\b1d4e7aa`.
+/// Equal names give equal hashes, so redacted paths can still be compared with each other.
+pub fn redact_path(path: &Path) -> PathBuf {
+    path.components()
+        .map(|component| match component {
+            Component::Normal(name) => {
+                let digest = format!("{:x}", Sha256::digest(name.to_string_lossy().as_bytes()));
+                PathBuf::from(&digest[..REDACTED_COMPONENT_LEN])
+            }
+            other => PathBuf::from(other.as_os_str()),
+        })
+        .collect()
+}
+
+// Redacts every absolute path among the string values of `value`, collecting the originals with their replacements
+fn redact_json_paths(value: &mut serde_json::Value, replaced: &mut Vec<(String, String)>) {
+    match value {
+        serde_json::Value::String(text) if Path::new(text.as_str()).has_root() => {
+            let redacted = redact_path(Path::new(text.as_str())).display().to_string();
+            replaced.push((std::mem::replace(text, redacted.clone()), redacted));
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(|item| redact_json_paths(item, replaced)),
+        serde_json::Value::Object(fields) => fields.values_mut().for_each(|field| redact_json_paths(field, replaced)),
+        _ => {}
+    }
+}
+
+/// A plain-text report for whoever helps with a problem: versions, settings, connected drives, the last error
+/// and the end of the log. With `redact_paths` the paths from the settings are redacted wherever they appear.
+pub fn support_report(info: &SupportInfo) -> String {
+    let mut settings = serde_json::to_value(info.settings).unwrap_or_default();
+    let mut replaced = Vec::new();
+    let mut show_path = |path: &Path| {
+        if info.redact_paths {
+            let redacted = redact_path(path).display().to_string();
+            replaced.push((path.display().to_string(), redacted.clone()));
+            redacted
+        } else {
+            path.display().to_string()
+        }
+    };
+    let data_dir = show_path(&app_data_dir());
+    let drives: Vec<String> = info
+        .drives
+        .iter()
+        .map(|drive| {
+            let mount_point = show_path(&drive.mount_point);
+            match drive.unavailable {
+                Some(reason) => format!("{}  {}", mount_point, reason.label()),
+                None => format!(
+                    "{}  {}, 可用 {}",
+                    mount_point,
+                    drive.file_system.as_deref().unwrap_or("未知文件系统"),
+                    drive.available_space.map_or_else(|| "未知".to_owned(), format_size)
+                ),
+            }
+        })
+        .collect();
+    if info.redact_paths {
+        redact_json_paths(&mut settings, &mut replaced);
+    }
+    // Longest first, so a folder doesn't replace the start of a path inside it
+    replaced.sort_by_key(|(original, _)| std::cmp::Reverse(original.len()));
+    let redact_text = |text: &str| replaced.iter().fold(text.to_owned(), |text, (original, redacted)| text.replace(original, redacted));
+
+    let mut report = vec![
+        "SyncU 诊断信息".to_owned(),
+        format!("生成时间: {}", Local::now().format("%Y-%m-%d %H:%M:%S")),
+        format!("版本: SyncU {}", SYNCU_VERSION),
+        format!("操作系统: {} ({})", System::long_os_version().unwrap_or_else(|| "未知".to_owned()), std::env::consts::ARCH),
+        format!("应用数据目录: {}", data_dir),
+        String::new(),
+        "== 设置 ==".to_owned(),
+        serde_json::to_string_pretty(&settings).unwrap_or_default(),
+        String::new(),
+        "== U盘 ==".to_owned(),
+    ];
+    if drives.is_empty() {
+        report.push("(未检测到U盘)".to_owned());
+    }
+    report.extend(drives);
+    report.push(String::new());
+    report.push("== 最近的错误 ==".to_owned());
+    report.push(info.last_error.map_or_else(|| "(无)".to_owned(), redact_text));
+    report.push(String::new());
+    report.push(format!("== 最近 {} 行日志 ==", SUPPORT_LOG_LINES));
+    let start = info.log.len().saturating_sub(SUPPORT_LOG_LINES);
+    report.extend(info.log[start..].iter().map(|line| redact_text(line)));
+    report.join("\n")
+}
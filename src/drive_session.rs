@@ -0,0 +1,137 @@
+//! Facts about one USB drive, found out once and shared by consecutive runs against it,
+//! e.g. several profiles synced to the same stick in a row.
+
+use crate::error::{IoResultExt, SyncError};
+use crate::utils::{available_space, file_system_name, volume_id};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+/// File created and removed in the drive root to find out whether the drive accepts writes.
+const WRITE_PROBE_NAME: &str = ".syncu_probe";
+
+/// A drive as seen by the runs of one or more user actions. Scans stay per folder; what is drive-wide
+/// (file system, free space, whether it is writable, the final flush) happens once.
+/// Only valid while the list of connected drives is the one it was created with; see `matches`.
+pub struct DriveSession {
+    root: PathBuf,
+    drives: Vec<PathBuf>,
+    file_system: OnceLock<Option<String>>,
+    volume_id: OnceLock<Option<String>>,
+    writable: OnceLock<Result<(), (io::ErrorKind, String)>>,
+    // Free space when first asked during the current action, and what the action's runs have added since
+    free_space: Mutex<Option<Option<u64>>>,
+    space_used: AtomicI64,
+    // Sync folders written during the current action, flushed when it ends
+    written_folders: Mutex<Vec<PathBuf>>,
+}
+
+impl DriveSession {
+    /// A session for the drive mounted at `root`, chosen from the connected `drives`.
+    pub fn new(root: PathBuf, drives: Vec<PathBuf>) -> Self {
+        Self {
+            root,
+            drives,
+            file_system: OnceLock::new(),
+            volume_id: OnceLock::new(),
+            writable: OnceLock::new(),
+            free_space: Mutex::new(None),
+            space_used: AtomicI64::new(0),
+            written_folders: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// A session for a single run, when no list of connected drives is at hand.
+    pub fn single_run(root: PathBuf) -> Self {
+        Self::new(root, Vec::new())
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Whether the session still describes `root` among the connected `drives`.
+    /// A changed drive list may mean the stick was swapped for another one under the same mount point.
+    pub fn matches(&self, root: &Path, drives: &[PathBuf]) -> bool {
+        self.root == root && self.drives == drives
+    }
+
+    /// The drive's file system as the OS reports it, e.g. "NTFS" or "vfat".
+    pub fn file_system(&self) -> Option<&str> {
+        self.file_system.get_or_init(|| file_system_name(&self.root)).as_deref()
+    }
+
+    /// The drive's volume identifier, the same under any drive letter; see `utils::volume_id`.
+    pub fn volume_id(&self) -> Option<&str> {
+        self.volume_id.get_or_init(|| volume_id(&self.root)).as_deref()
+    }
+
+    /// Fails if the drive doesn't accept writes, e.g. a write-protected stick. Only the first call touches the drive.
+    pub fn check_writable(&self) -> Result<(), SyncError> {
+        let probe = self.root.join(WRITE_PROBE_NAME);
+        let result = self.writable.get_or_init(|| {
+            File::create(&probe)
+                .and_then(|_| fs::remove_file(&probe))
+                .map_err(|e| (e.kind(), e.to_string()))
+        });
+        match result {
+            Ok(()) => Ok(()),
+            Err((kind, message)) => Err(io::Error::new(*kind, message.clone())).at(&probe),
+        }
+    }
+
+    /// Free space on the drive: read once per action, then lowered by what its runs reported with `record_space_used`.
+    pub fn available_space(&self) -> Option<u64> {
+        let free = *self.free_space.lock().unwrap().get_or_insert_with(|| available_space(&self.root));
+        free.map(|free| (free as i64 - self.space_used.load(Ordering::Relaxed)).max(0) as u64)
+    }
+
+    /// Accounts for `bytes` a run added to the drive (negative if it freed space).
+    pub fn record_space_used(&self, bytes: i64) {
+        self.space_used.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Notes that a run wrote to `sync_folder`, so `finish_action` flushes it.
+    pub fn record_written(&self, sync_folder: &Path) {
+        let mut folders = self.written_folders.lock().unwrap();
+        if !folders.iter().any(|folder| folder == sync_folder) {
+            folders.push(sync_folder.to_path_buf());
+        }
+    }
+
+    /// Ends one user action: pushes what its runs wrote to the drive, once, and forgets the free space,
+    /// which other programs may change before the next action. Flush failures are left to the OS.
+    pub fn finish_action(&self) {
+        let folders = std::mem::take(&mut *self.written_folders.lock().unwrap());
+        if !folders.is_empty() {
+            flush_volume(&self.root, &folders);
+        }
+        *self.free_space.lock().unwrap() = None;
+        self.space_used.store(0, Ordering::Relaxed);
+    }
+}
+
+// Windows flushes a whole volume through its device handle, which needs administrator rights; without them
+// removable drives are written through anyway. Elsewhere the sync folders' directory entries are flushed.
+fn flush_volume(root: &Path, folders: &[PathBuf]) {
+    #[cfg(windows)]
+    {
+        let _ = folders;
+        let drive = root.to_string_lossy();
+        let drive = drive.trim_end_matches(['\\', '/']);
+        let _ = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(format!(r"\\.\{}", drive))
+            .and_then(|volume| volume.sync_all());
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = root;
+        for folder in folders {
+            let _ = File::open(folder).and_then(|dir| dir.sync_all());
+        }
+    }
+}
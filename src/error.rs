@@ -0,0 +1,74 @@
+use crate::models::SyncMessage;
+use crossbeam_channel::SendError;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Errors raised by the synchronization engine.
+#[derive(Debug, Error)]
+pub enum SyncError {
+    /// A filesystem operation failed on the given path.
+    #[error("{}: {source}", .path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// A copy finished but the destination doesn't have the size of the source.
+    #[error("复制不完整 ({}): 应为 {expected} 字节，实际 {actual} 字节", .path.display())]
+    SizeMismatch { path: PathBuf, expected: u64, actual: u64 },
+    /// The metadata file exists but could not be parsed.
+    #[error("同步记录已损坏 ({}): {source}", .path.display())]
+    MetadataParse {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+    /// The USB drive was removed or can no longer be accessed.
+    #[error("U盘已移除或无法访问: {}", .0.display())]
+    DeviceMissing(PathBuf),
+    /// A copy ran out of space on the volume holding the given path, which every further copy would too.
+    #[error("磁盘空间不足，同步已停止 ({}): 请清理空间后重新同步", .0.display())]
+    DiskFull(PathBuf),
+    /// The user stopped the sync. Not an error from the user's point of view.
+    #[error("同步已取消")]
+    Cancelled,
+    /// The user skipped the action in progress, which is then counted as skipped rather than failed.
+    #[error("已跳过")]
+    Skipped,
+    /// The selected folders can't be synced.
+    #[error("{0}")]
+    InvalidSelection(&'static str),
+    /// Files were synced, but saving the metadata or log durably failed afterwards.
+    #[error("完成但写入状态失败: {0}")]
+    StateNotPersisted(Box<SyncError>),
+    /// The metadata was written by a newer SyncU, which may have stored information this version would lose on saving.
+    #[error("同步记录由较新版本的 SyncU ({version}) 写入 ({}), 当前版本 {} 保存时可能丢失其中的信息，已停止。请使用 {version} 或更新版本", .path.display(), crate::models::SYNCU_VERSION)]
+    NewerMetadata { path: PathBuf, version: String },
+    /// The profile's rules file can't be read or has an unusable rule.
+    #[error("应答规则文件无效 ({}): {message}", .path.display())]
+    InvalidRules { path: PathBuf, message: String },
+    /// The UI side of the channel is gone.
+    #[error("与界面的连接已断开")]
+    Disconnected,
+}
+
+impl From<SendError<SyncMessage>> for SyncError {
+    fn from(_: SendError<SyncMessage>) -> Self {
+        SyncError::Disconnected
+    }
+}
+
+/// Attaches the path an I/O operation was working on to its error.
+pub trait IoResultExt<T> {
+    fn at(self, path: &Path) -> Result<T, SyncError>;
+}
+
+impl<T> IoResultExt<T> for io::Result<T> {
+    fn at(self, path: &Path) -> Result<T, SyncError> {
+        self.map_err(|source| SyncError::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+}
@@ -0,0 +1,110 @@
+//! Metadata that a plain content copy drops: NTFS alternate data streams on Windows
+//! (e.g. the Zone.Identifier that marks downloaded files) and user extended attributes on Unix.
+//! FAT and exFAT can store neither, so copies to such drives go without them.
+
+use std::io;
+use std::path::Path;
+
+/// Whether a file system of this name (as the OS reports it, e.g. "NTFS", "vfat", "exFAT") can store
+/// streams or extended attributes. Unknown names are assumed to.
+pub fn supported_on(file_system: &str) -> bool {
+    let name = file_system.to_ascii_lowercase();
+    !(name.contains("fat") || name == "msdos")
+}
+
+/// Copies the alternate data streams or user extended attributes of `from` onto `to`, which must already exist.
+/// Returns how many were copied; platforms without either copy nothing.
+pub fn copy_extended_attributes(from: &Path, to: &Path) -> io::Result<usize> {
+    #[cfg(windows)]
+    {
+        windows_streams::copy(from, to)
+    }
+    #[cfg(unix)]
+    {
+        unix_xattrs::copy(from, to)
+    }
+    #[cfg(not(any(windows, unix)))]
+    {
+        let _ = (from, to);
+        Ok(0)
+    }
+}
+
+#[cfg(windows)]
+mod windows_streams {
+    use std::ffi::{OsStr, OsString};
+    use std::fs::File;
+    use std::io;
+    use std::os::windows::ffi::OsStringExt;
+    use std::path::{Path, PathBuf};
+    use windows::Win32::Foundation::HANDLE;
+    use windows::Win32::Storage::FileSystem::{FindClose, FindFirstStreamW, FindNextStreamW, FindStreamInfoStandard, WIN32_FIND_STREAM_DATA};
+    use windows::core::HSTRING;
+
+    /// Name of the unnamed stream that holds the file contents, which the copy itself already wrote.
+    const MAIN_STREAM: &str = "::$DATA";
+
+    pub fn copy(from: &Path, to: &Path) -> io::Result<usize> {
+        let mut data = WIN32_FIND_STREAM_DATA::default();
+        let handle: HANDLE = unsafe {
+            FindFirstStreamW(&HSTRING::from(from), FindStreamInfoStandard, &mut data as *mut _ as *mut _, None)
+        }
+        .map_err(io::Error::other)?;
+
+        let mut copy_all = || -> io::Result<usize> {
+            let mut copied = 0;
+            loop {
+                let len = data.cStreamName.iter().position(|&unit| unit == 0).unwrap_or(data.cStreamName.len());
+                let name = OsString::from_wide(&data.cStreamName[..len]);
+                if name != MAIN_STREAM {
+                    let mut source = File::open(stream_path(from, &name))?;
+                    let mut dest = File::create(stream_path(to, &name))?;
+                    io::copy(&mut source, &mut dest)?;
+                    copied += 1;
+                }
+                // Fails with ERROR_HANDLE_EOF once every stream was listed
+                if unsafe { FindNextStreamW(handle, &mut data as *mut _ as *mut _) }.is_err() {
+                    return Ok(copied);
+                }
+            }
+        };
+        let result = copy_all();
+        unsafe {
+            let _ = FindClose(handle);
+        }
+        result
+    }
+
+    // Streams are opened as "file.txt:Zone.Identifier:$DATA"; listed names already start with the colon
+    fn stream_path(file: &Path, stream_name: &OsStr) -> PathBuf {
+        let mut path = file.as_os_str().to_owned();
+        path.push(stream_name);
+        PathBuf::from(path)
+    }
+}
+
+#[cfg(unix)]
+mod unix_xattrs {
+    use std::ffi::OsStr;
+    use std::io;
+    use std::path::Path;
+
+    pub fn copy(from: &Path, to: &Path) -> io::Result<usize> {
+        let mut copied = 0;
+        for name in xattr::list(from)? {
+            if !is_user_attribute(&name) {
+                continue;
+            }
+            if let Some(value) = xattr::get(from, &name)? {
+                xattr::set(to, &name, &value)?;
+                copied += 1;
+            }
+        }
+        Ok(copied)
+    }
+
+    // Linux keeps system, security and trusted attributes in their own namespaces, which users can't set anyway
+    fn is_user_attribute(name: &OsStr) -> bool {
+        !cfg!(target_os = "linux") || name.to_string_lossy().starts_with("user.")
+    }
+}
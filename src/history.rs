@@ -0,0 +1,209 @@
+//! The history of past runs: what each one copied, deleted or left in conflict, kept as one compact JSON file
+//! per run in the app data directory. Old runs are removed once there are too many or they take too much space.
+
+use crate::error::{IoResultExt, SyncError};
+use crate::models::ActionStatus;
+use crate::report::{ReportedAction, RunReport};
+use crate::settings::HistoryRetention;
+use crate::utils::{app_data_dir, format_size};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const HISTORY_DIR_NAME: &str = "history";
+const RUN_FILE_PREFIX: &str = "run_";
+const RUN_FILE_EXTENSION: &str = "json";
+
+/// Where the run history is kept.
+pub fn history_dir() -> PathBuf {
+    app_data_dir().join(HISTORY_DIR_NAME)
+}
+
+/// What the list of runs shows about a run, stored ahead of its actions so listing needn't keep them.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct RunSummary {
+    /// Local time the run started, e.g. "2024-05-01 14:03:12".
+    pub started: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_folder: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usb_sync_path: Option<PathBuf>,
+    pub outcome: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub done: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    /// Actions the run ended before.
+    pub pending: usize,
+}
+
+impl RunSummary {
+    pub fn of(report: &RunReport) -> Self {
+        let count = |status: Option<ActionStatus>| report.actions.iter().filter(|action| action.status == status).count();
+        Self {
+            started: report.started.clone(),
+            local_folder: report.local_folder.clone(),
+            usb_sync_path: report.usb_sync_path.clone(),
+            outcome: report.outcome.clone(),
+            error: report.error.clone(),
+            done: count(Some(ActionStatus::Done)),
+            skipped: count(Some(ActionStatus::Skipped)),
+            failed: count(Some(ActionStatus::Failed)),
+            pending: count(None),
+        }
+    }
+
+    /// The counts in one line, e.g. "完成 12 · 失败 1".
+    pub fn counts_text(&self) -> String {
+        let counts: Vec<String> = [("完成", self.done), ("跳过", self.skipped), ("失败", self.failed), ("未执行", self.pending)]
+            .into_iter()
+            .filter(|&(_, count)| count > 0)
+            .map(|(label, count)| format!("{} {}", label, count))
+            .collect();
+        counts.join(" · ")
+    }
+}
+
+/// A run as the history keeps it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HistoryRun {
+    pub summary: RunSummary,
+    pub actions: Vec<ReportedAction>,
+}
+
+impl HistoryRun {
+    /// Indices of the actions whose path, kind or result contains `query`, ignoring case. An empty query matches all.
+    pub fn search(&self, query: &str) -> Vec<usize> {
+        let query = query.trim().to_lowercase();
+        (0..self.actions.len())
+            .filter(|&index| {
+                let action = &self.actions[index];
+                query.is_empty()
+                    || action.path_text().to_lowercase().contains(&query)
+                    || action.action.label().to_lowercase().contains(&query)
+                    || action.status_label().contains(&query)
+            })
+            .collect()
+    }
+}
+
+/// A run file in the history directory.
+#[derive(Clone, Debug)]
+pub struct HistoryEntry {
+    pub path: PathBuf,
+    /// Size of the file in bytes.
+    pub size: u64,
+    pub summary: RunSummary,
+}
+
+// Reads the summary alone; serde skips over the actions without building them
+#[derive(Deserialize)]
+struct SummaryOnly {
+    summary: RunSummary,
+}
+
+// Run files in `dir` with their sizes, oldest first. The names sort by the time the runs started.
+fn run_files(dir: &Path) -> Vec<(PathBuf, u64)> {
+    let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+    let mut files: Vec<(PathBuf, u64)> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.starts_with(RUN_FILE_PREFIX) && name.ends_with(&format!(".{}", RUN_FILE_EXTENSION))
+        })
+        .filter_map(|entry| Some((entry.path(), entry.metadata().ok()?.len())))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Saves the run in `dir` and removes runs beyond `retention`. Returns the path of the new file.
+pub fn save_run(dir: &Path, report: &RunReport, retention: HistoryRetention) -> Result<PathBuf, SyncError> {
+    fs::create_dir_all(dir).at(dir)?;
+    // "2024-05-01 14:03:12" becomes "2024-05-01_140312"; runs started within the same second get a counter
+    let stamp = report.started.replace(' ', "_").replace(':', "");
+    let mut path = dir.join(format!("{}{}.{}", RUN_FILE_PREFIX, stamp, RUN_FILE_EXTENSION));
+    let mut counter = 2;
+    while path.exists() {
+        path = dir.join(format!("{}{}_{}.{}", RUN_FILE_PREFIX, stamp, counter, RUN_FILE_EXTENSION));
+        counter += 1;
+    }
+    let run = HistoryRun { summary: RunSummary::of(report), actions: report.actions.clone() };
+    let json = serde_json::to_vec(&run).map_err(io::Error::from).at(&path)?;
+    fs::write(&path, json).at(&path)?;
+    prune_history(dir, retention);
+    Ok(path)
+}
+
+/// The runs in `dir`, newest first. Files that can't be read are left out.
+pub fn list_runs(dir: &Path) -> Vec<HistoryEntry> {
+    run_files(dir)
+        .into_iter()
+        .rev()
+        .filter_map(|(path, size)| {
+            let file = fs::File::open(&path).ok()?;
+            let SummaryOnly { summary } = serde_json::from_reader(io::BufReader::new(file)).ok()?;
+            Some(HistoryEntry { path, size, summary })
+        })
+        .collect()
+}
+
+/// Reads a run with all its actions.
+pub fn load_run(path: &Path) -> Result<HistoryRun, SyncError> {
+    let file = fs::File::open(path).at(path)?;
+    serde_json::from_reader(io::BufReader::new(file)).map_err(io::Error::from).at(path)
+}
+
+/// Removes the oldest runs until at most `max_runs` remain and together they take at most `max_megabytes`.
+/// The newest run is always kept. Returns the number of runs removed.
+pub fn prune_history(dir: &Path, retention: HistoryRetention) -> usize {
+    let files = run_files(dir);
+    let limit = retention.max_megabytes.saturating_mul(1024 * 1024);
+    // Counted from the newest; once a run doesn't fit, neither does anything older
+    let mut total = 0;
+    let kept = files
+        .iter()
+        .rev()
+        .enumerate()
+        .take_while(|&(index, (_, size))| {
+            total += size;
+            index == 0 || (index < retention.max_runs && total <= limit)
+        })
+        .count();
+    files[..files.len() - kept].iter().filter(|(path, _)| fs::remove_file(path).is_ok()).count()
+}
+
+/// Total size of the run history, e.g. "3.2 MB".
+pub fn history_size_text(entries: &[HistoryEntry]) -> String {
+    format_size(entries.iter().map(|entry| entry.size).sum())
+}
+
+// Quotes a CSV field when it holds a separator, a quote or a line break.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// The actions as CSV, one per line. Starts with a byte order mark, so spreadsheet programs read it as UTF-8.
+pub fn actions_csv<'a>(actions: impl IntoIterator<Item = &'a ReportedAction>) -> String {
+    let mut csv = String::from("\u{feff}操作,路径,大小,结果,错误\r\n");
+    for action in actions {
+        let fields = [
+            action.action.label().to_string(),
+            action.path_text(),
+            action.size.map_or_else(String::new, |size| size.to_string()),
+            action.status_label().to_string(),
+            action.error.clone().unwrap_or_default(),
+        ];
+        let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
@@ -0,0 +1,16 @@
+//! The sync engine and its supporting modules, shared by the GUI binary and the integration tests.
+
+pub mod diagnostics;
+pub mod drive_session;
+pub mod error;
+pub mod extended_attributes;
+pub mod history;
+pub mod models;
+pub mod monitor;
+pub mod observer;
+pub mod plan_tree;
+pub mod report;
+pub mod session_log;
+pub mod settings;
+pub mod sync;
+pub mod utils;
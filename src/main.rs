@@ -1,20 +1,55 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
 mod app;
-mod models;
-mod sync;
-mod utils;
+mod palette;
+mod plan_panel;
+mod shortcuts;
+mod taskbar;
+
+use syncu::{diagnostics, history, models, monitor, observer, plan_tree, report, session_log, settings, sync, utils};
 
 use app::SyncApp;
 use eframe::egui;
 use image::{ImageBuffer, Rgba};
 use models::Theme;
+use palette::{base_visuals, Palette};
+use std::path::PathBuf;
 
-// Embed the font directly into the binary to ensure portability.
+// Portable builds embed a CJK font in case the system has none.
+#[cfg(feature = "embedded-font")]
 const FONT_MSYH: &[u8] = include_bytes!("../assets/msyh.ttc");
 
+// System fonts tried at startup, in order; the first one found in each group is used.
+const SYSTEM_UI_FONTS: &[&str] = &[
+    "%WINDIR%/Fonts/segoeui.ttf",
+    "/System/Library/Fonts/Helvetica.ttc",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+];
+const SYSTEM_CJK_FONTS: &[&str] = &[
+    "%WINDIR%/Fonts/msyh.ttc",
+    "%WINDIR%/Fonts/simsun.ttc",
+    "/System/Library/Fonts/PingFang.ttc",
+    "/System/Library/Fonts/STHeiti Light.ttc",
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/truetype/wqy/wqy-microhei.ttc",
+];
+const SYSTEM_SYMBOL_FONTS: &[&str] = &[
+    "%WINDIR%/Fonts/seguisym.ttf",
+    "/System/Library/Fonts/Apple Symbols.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+];
+
 fn main() -> Result<(), eframe::Error> {
     let icon = create_icon();
+    // The startup entry passes this so signing in doesn't put a window in front of the user
+    let minimized = std::env::args().any(|arg| arg == shortcuts::MINIMIZED_ARG);
+    // Double-clicking a launch file passes its path
+    let launch_file = std::env::args_os()
+        .skip(1)
+        .map(PathBuf::from)
+        .find(|arg| arg.extension().is_some_and(|extension| extension.eq_ignore_ascii_case(settings::LAUNCH_FILE_EXTENSION)));
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([680.0, 600.0])
@@ -29,39 +64,63 @@ fn main() -> Result<(), eframe::Error> {
     eframe::run_native(
         "SyncU",
         options,
-        Box::new(|cc| {
-            let app = SyncApp::new(cc.egui_ctx.clone());
+        Box::new(move |cc| {
+            let app = SyncApp::new(cc.egui_ctx.clone(), launch_file);
             setup_fonts(&cc.egui_ctx);
-            apply_theme(&cc.egui_ctx, &app.current_theme);
+            apply_theme(&cc.egui_ctx, &app.current_theme, &app.palette);
+            // The viewport builder can't start minimized, so the window is minimized as the first frame shows
+            if minimized {
+                cc.egui_ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(true));
+            }
             Ok(Box::new(app))
         }),
     )
 }
 
+// Reads the first font in `candidates` that exists on this system.
+fn load_system_font(candidates: &[&str]) -> Option<Vec<u8>> {
+    let windows_dir = std::env::var("WINDIR").unwrap_or_else(|_| "C:/Windows".to_owned());
+    candidates
+        .iter()
+        .map(|path| path.replace("%WINDIR%", &windows_dir))
+        .find_map(|path| std::fs::read(path).ok())
+}
+
+// Uses the system UI font for Latin text, then falls back to egui's built-in fonts (including emoji),
+// a system CJK font, symbol fonts and finally the embedded font in portable builds.
 fn setup_fonts(ctx: &egui::Context) {
     let mut fonts = egui::FontDefinitions::default();
-    fonts.font_data.insert(
-        "chinese_font".to_owned(),
-        egui::FontData::from_static(FONT_MSYH).into(),
-    );
-    fonts
-        .families
-        .entry(egui::FontFamily::Proportional)
-        .or_default()
-        .insert(0, "chinese_font".to_owned());
-    fonts
-        .families
-        .entry(egui::FontFamily::Monospace)
-        .or_default()
-        .insert(0, "chinese_font".to_owned());
+    let mut add_font = |name: &str, data: egui::FontData, primary: bool| {
+        fonts.font_data.insert(name.to_owned(), data.into());
+        for family in [egui::FontFamily::Proportional, egui::FontFamily::Monospace] {
+            let list = fonts.families.entry(family.clone()).or_default();
+            // Monospace keeps its own primary font so columns stay aligned
+            if primary && family == egui::FontFamily::Proportional {
+                list.insert(0, name.to_owned());
+            } else {
+                list.push(name.to_owned());
+            }
+        }
+    };
+
+    if let Some(data) = load_system_font(SYSTEM_UI_FONTS) {
+        add_font("system_ui", egui::FontData::from_owned(data), true);
+    }
+    if let Some(data) = load_system_font(SYSTEM_CJK_FONTS) {
+        add_font("system_cjk", egui::FontData::from_owned(data), false);
+    }
+    if let Some(data) = load_system_font(SYSTEM_SYMBOL_FONTS) {
+        add_font("system_symbols", egui::FontData::from_owned(data), false);
+    }
+    #[cfg(feature = "embedded-font")]
+    add_font("chinese_font", egui::FontData::from_static(FONT_MSYH), false);
+
     ctx.set_fonts(fonts);
 }
 
-pub fn apply_theme(ctx: &egui::Context, theme: &Theme) {
-    let visuals = match theme {
-        Theme::Light => egui::Visuals::light(),
-        Theme::Dark => egui::Visuals::dark(),
-    };
+pub fn apply_theme(ctx: &egui::Context, theme: &Theme, palette: &Palette) {
+    let mut visuals = base_visuals(theme);
+    palette.apply_to(&mut visuals);
 
     let mut style = (*ctx.style()).clone();
     style.visuals = visuals;
@@ -122,15 +181,14 @@ fn create_icon() -> ImageBuffer<Rgba<u8>, Vec<u8>> {
         let center_y = y as f32 - 32.0;
         let distance_from_center = (center_x * center_x + center_y * center_y).sqrt();
         
-        if distance_from_center < 28.0 {
-            if (x > 18 && x < 46) && 
+        if distance_from_center < 28.0
+            && (x > 18 && x < 46) && 
                ((y > 18 && y < 24) ||
                 (x > 18 && x < 26 && y > 24 && y < 32) ||
                 (y > 32 && y < 38) ||
                 (x > 38 && x < 46 && y > 38 && y < 46) ||
                 (y > 46 && y < 52)) {
-                *pixel = Rgba([0, 120, 215, 255]);
-            }
+            *pixel = Rgba([0, 120, 215, 255]);
         }
         
         if distance_from_center > 26.0 && distance_from_center < 28.0 {
@@ -1,16 +1,132 @@
+use crate::utils::format_size;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
 /// Defines the user's choice when resolving a file conflict.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum Resolution {
     KeepLocal,
     KeepRemote,
     Skip,
 }
 
+/// Which version of a conflicting file looks newer, offered as a hint when resolving it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictSuggestion {
+    LocalNewer,
+    RemoteNewer,
+    /// The modification times are missing, too close to tell apart, or not to be trusted.
+    Undecided,
+}
+
+impl ConflictSuggestion {
+    /// Short text for the hint, e.g. "较新: 本地".
+    pub fn label(self) -> &'static str {
+        match self {
+            ConflictSuggestion::LocalNewer => "较新: 本地",
+            ConflictSuggestion::RemoteNewer => "较新: U盘",
+            ConflictSuggestion::Undecided => "无法判断",
+        }
+    }
+
+    /// The resolution that keeps the newer version, if there is one.
+    pub fn resolution(self) -> Option<Resolution> {
+        match self {
+            ConflictSuggestion::LocalNewer => Some(Resolution::KeepLocal),
+            ConflictSuggestion::RemoteNewer => Some(Resolution::KeepRemote),
+            ConflictSuggestion::Undecided => None,
+        }
+    }
+}
+
+/// How a sync run ended.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RunOutcome {
+    Completed,
+    /// The user stopped the run.
+    Stopped,
+    /// The files were synced, but the metadata or log couldn't be made durable; holds the details.
+    StateNotPersisted(String),
+}
+
+/// Defines the user's choice when the system clock looks unreliable.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClockSkewChoice {
+    /// Proceed as usual, trusting the mtime shortcut.
+    Continue,
+    /// Proceed, but hash every file instead of trusting size and mtime.
+    FullRehash,
+    /// Cancel the sync.
+    Abort,
+}
+
+/// A changed line in a conflict preview, going from the USB version to the local version.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DiffLine {
+    /// The line only exists in the local version.
+    Added(String),
+    /// The line only exists in the USB version.
+    Removed(String),
+}
+
+/// Defines the user's choice when some destination paths exceed the target's length limit.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LongPathChoice {
+    /// Leave those files out of this sync.
+    Skip,
+    /// Copy them anyway and report each failure.
+    Attempt,
+    /// Cancel the sync.
+    Abort,
+}
+
+/// A folder on the USB drive that would hold more entries after the sync than its file system allows or the profile
+/// warns about. `path` is relative to the sync folder, empty for the sync folder itself.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrowdedDirectory {
+    pub path: PathBuf,
+    pub entries: usize,
+    /// The soft limit, or roughly how many entries of these names fit on a FAT drive.
+    pub limit: usize,
+    /// Whether the file system itself would refuse entries, not just the soft limit.
+    pub exceeds_file_system: bool,
+}
+
+/// Defines the user's choice when folders on the USB drive would hold too many entries.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CrowdedDirectoryChoice {
+    /// Leave files that would be added to those folders out of this sync.
+    Skip,
+    /// Copy them anyway and report each failure.
+    Attempt,
+    /// Cancel the sync.
+    Abort,
+}
+
+/// Defines the user's choice when several local files would end up under one name on the USB drive.
+#[derive(Clone, Debug, PartialEq)]
+pub enum NameCollisionChoice {
+    /// Sync only the file that keeps the plain name and leave the others out of this sync.
+    Skip,
+    /// Store the others under numbered names on the USB drive, remembered like a routing rule.
+    Rename,
+    /// Cancel the sync.
+    Abort,
+}
+
+/// Defines the user's choice when many known files are missing from the USB drive.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RemoteMissingChoice {
+    /// Treat the missing files as never synced and copy them to the USB drive again.
+    Recopy,
+    /// Treat the missing files as intentional deletions and delete them locally.
+    DeleteLocal,
+    /// Cancel the sync.
+    Abort,
+}
+
 /// Defines the available UI themes.
 #[derive(Clone, Debug, PartialEq)]
 pub enum Theme {
@@ -18,59 +134,808 @@ pub enum Theme {
     Dark,
 }
 
+/// A removable drive found by `find_usb_drives`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UsbDrive {
+    pub mount_point: PathBuf,
+    /// Identifies the volume under whatever letter it is mounted at, e.g. "1A2B-3C4D"; None if it can't be read.
+    pub volume_id: Option<String>,
+    /// Why the drive can't be synced to right now, or None if it can.
+    pub unavailable: Option<DriveUnavailable>,
+}
+
+/// Why a detected drive can't be used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DriveUnavailable {
+    /// Encrypted with BitLocker and not unlocked yet.
+    BitLockerLocked,
+    /// Its contents can't be read, e.g. while the system is still mounting it.
+    Inaccessible,
+}
+
+impl DriveUnavailable {
+    /// Short tag shown next to the drive, e.g. "BitLocker 已锁定".
+    pub fn label(self) -> &'static str {
+        match self {
+            DriveUnavailable::BitLockerLocked => "BitLocker 已锁定",
+            DriveUnavailable::Inaccessible => "无法访问",
+        }
+    }
+
+    /// What the user can do about it.
+    pub fn instructions(self) -> &'static str {
+        match self {
+            DriveUnavailable::BitLockerLocked => {
+                "此U盘已被 BitLocker 锁定。请先在资源管理器中打开该驱动器并输入密码或恢复密钥解锁，然后点击“重新检查”。"
+            }
+            DriveUnavailable::Inaccessible => {
+                "暂时无法读取此U盘。请稍等片刻或重新插入，确认能在资源管理器中打开后点击“重新检查”。"
+            }
+        }
+    }
+}
+
 /// Messages passed between the UI thread and the synchronization thread.
 #[derive(Clone, Debug, PartialEq)]
 pub enum SyncMessage {
     // --- UI to Sync Thread ---
-    /// Confirms or denies a deletion request from the sync thread.
-    DeletionConfirmed(bool),
-    /// Provides the resolution for a file conflict.
-    ConflictResolved(Resolution),
+    /// Confirms or denies the deletion request with the given prompt id.
+    DeletionConfirmed { id: u64, confirmed: bool },
+    /// Keeps the file and stops asking about deleting it in future syncs.
+    DeletionDeclinedPermanently { id: u64 },
+    /// Provides the resolution for the conflict with the given prompt id.
+    ConflictResolved { id: u64, resolution: Resolution },
+    /// Provides the user's choice after a clock skew warning.
+    ClockSkewResolved(ClockSkewChoice),
+    /// Confirms or denies copying a file that is in use by another program.
+    CopyInUseConfirmed(bool),
+    /// Provides the user's choice after the remote safety check.
+    RemoteMissingResolved(RemoteMissingChoice),
+    /// Provides the user's choice for destination paths that are too long.
+    LongPathsResolved(LongPathChoice),
+    /// Provides the user's choice for local files that would share a name on the USB drive.
+    NameCollisionsResolved(NameCollisionChoice),
+    /// Continues (true) or cancels a run although the size of some files couldn't be read.
+    UnreadableSizesConfirmed(bool),
+    /// Provides the user's choice for folders that would hold too many entries.
+    CrowdedDirectoriesResolved(CrowdedDirectoryChoice),
+    /// Confirms or denies overwriting a backup file that is newer than its source.
+    OverwriteNewerConfirmed(bool),
+    /// Retries or gives up a copy whose destination or source refused access.
+    RetryDeniedConfirmed(bool),
+    /// Confirms or denies reusing a USB folder that appears to belong to the renamed local folder.
+    RelinkConfirmed(bool),
+    /// Continues an interrupted run's plan (true) or starts over (false).
+    ResumePlanConfirmed(bool),
+    /// Syncs without updating a record written by a newer version (true) or stops (false).
+    NewerMetadataConfirmed(bool),
     /// Signals the sync thread to stop its current operation.
     Stop,
+    /// Lets the action in progress finish, then stops the run.
+    StopAfterAction,
+    /// Abandons the copy in progress and moves on to the next action. Ignored while nothing skippable runs.
+    SkipCurrent,
+    /// Holds the run at its next check until `Resume` or `Stop` arrives.
+    Pause,
+    Resume,
+    /// Changes the copy speed limit of the running sync, in bytes per second; None removes it.
+    SetRateLimit(Option<u64>),
 
     // --- Sync Thread to UI ---
     /// Sends a log message to be displayed in the UI.
     Log(String),
-    /// Asks the user to confirm the deletion of a file.
-    ConfirmDeletion(PathBuf),
-    /// Asks the user to resolve a conflict between two file versions.
-    AskForConflictResolution { path: PathBuf },
-    /// Reports the progress of the current operation.
-    Progress(f32, String),
+    /// Asks the user to confirm the deletion of a file. The answer echoes the id.
+    /// `position` counts this deletion among the run's `total` planned deletions, starting at 1.
+    ConfirmDeletion { id: u64, path: PathBuf, position: usize, total: usize },
+    /// Withdraws the deletion or conflict prompt with the given id after it went unanswered for too long.
+    PromptExpired { id: u64 },
+    /// Asks the user to resolve a conflict between two file versions. The answer echoes the id.
+    /// Small text files carry a preview of the changed lines; `suggestion` tells which version looks newer.
+    AskForConflictResolution { id: u64, path: PathBuf, diff: Option<Vec<DiffLine>>, suggestion: ConflictSuggestion },
+    /// Warns that the system clock looks wrong and asks how to proceed.
+    AskForClockSkewResolution(String),
+    /// Asks whether to copy a file that is in use by another program.
+    ConfirmCopyInUse(PathBuf),
+    /// Reports that many previously synced files are missing from the USB drive.
+    /// `examples` holds the first few candidate local deletions.
+    ConfirmRemoteMissing { missing: usize, known: usize, examples: Vec<PathBuf> },
+    /// Asks what to do with files whose destination path exceeds `limit` characters.
+    /// `examples` holds the first few affected destinations.
+    ConfirmLongPaths { limit: usize, count: usize, examples: Vec<PathBuf> },
+    /// Asks whether to continue although the size of `count` files to copy couldn't be read.
+    /// `examples` holds the first few of their paths.
+    ConfirmUnreadableSizes { count: usize, examples: Vec<PathBuf> },
+    /// Asks what to do with `count` groups of local files whose names differ only by trailing dots or spaces,
+    /// which the USB drive would store as one file. `examples` holds the first few groups.
+    ConfirmNameCollisions { count: usize, examples: Vec<Vec<PathBuf>> },
+    /// Asks what to do with files that would be added to folders holding too many entries.
+    ConfirmCrowdedDirectories(Vec<CrowdedDirectory>),
+    /// Asks whether to overwrite a backup file that is newer than the local file it would be replaced with.
+    ConfirmOverwriteNewer(PathBuf),
+    /// Asks whether to try again a copy that was refused access to `path`, e.g. after clearing its read-only flag.
+    ConfirmRetryDenied { path: PathBuf, error: String },
+    /// Asks whether to rename the USB folder `old_name` to `new_name` and keep its sync record.
+    ConfirmRelink { old_name: String, new_name: String },
+    /// Asks whether to continue an interrupted run whose plan of `total` actions still has `remaining` to go.
+    ConfirmResumePlan { remaining: usize, total: usize },
+    /// Asks whether to sync although the record was written by a newer version, which then isn't updated.
+    ConfirmNewerMetadata(String),
+    /// Reports the progress of the current operation and what kind of step it is.
+    Progress(f32, String, ActivityKind),
+    /// Reports that the USB drive disappeared while syncing.
+    DeviceRemoved(PathBuf),
+    /// Reports how many planned actions have been handled so far.
+    Stats(SyncStats),
+    /// Reports how the plan is expected to change the free space on the USB drive.
+    SpaceEstimate(SpaceEstimate),
+    /// The actions the run is about to carry out, in order. Sent once per run, also when the plan is empty.
+    Plan(Vec<SyncAction>),
+    /// Reports how the action at `index` in the plan ended.
+    ActionFinished { index: usize, status: ActionStatus },
+    /// Reports the quick check of both sides against the record that a completed run saved.
+    ConsistencyChecked(ConsistencyReport),
+    /// Reports that the plan copies this many small files to the USB drive, which makes a run slow.
+    ManySmallFiles(usize),
     /// Indicates that the synchronization process has completed successfully.
     Complete,
+    /// Indicates that the files were synced but the sync state could not be saved safely.
+    CompleteWithoutState(String),
     /// Indicates that the synchronization process was stopped by the user.
     Stopped,
 }
 
+/// How a single planned action ended, as shown next to it in the plan overview.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActionStatus {
+    Done,
+    /// Deliberately not carried out, e.g. a declined deletion or a skipped conflict.
+    Skipped,
+    Failed,
+}
+
+/// How much an entry of the log on the USB drive tells. Entries naming single files are details;
+/// decisions, counts and errors are summary.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogLevel {
+    Summary,
+    Detail,
+}
+
+/// What a run is busy with, as far as the controls shown during a sync care.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ActivityKind {
+    /// Scanning, planning, saving the record and other steps outside the plan; they always run to the end.
+    #[default]
+    Bookkeeping,
+    /// Copying a file, which can be abandoned to move on to the next action.
+    Copy,
+    /// Any other planned action, which finishes too quickly to be worth skipping.
+    Other,
+}
+
+impl ActivityKind {
+    pub fn of(action: &SyncAction) -> Self {
+        match action {
+            SyncAction::LocalToRemote(_) | SyncAction::RemoteToLocal(_) | SyncAction::Conflict { .. } => ActivityKind::Copy,
+            _ => ActivityKind::Other,
+        }
+    }
+
+    pub fn skippable(self) -> bool {
+        self == ActivityKind::Copy
+    }
+}
+
+/// Result of comparing a saved record with a fresh stat of both sides.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConsistencyReport {
+    /// How many recorded files were looked at.
+    pub checked: usize,
+    /// Recorded files that changed or disappeared on either side since their state was taken, sorted.
+    pub mismatched: Vec<PathBuf>,
+}
+
+/// Counts of how the planned actions of a sync have been handled.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SyncStats {
+    pub completed: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub remaining: usize,
+    /// Bytes the plan copies, which the progress fraction counts through; it gives the speed shown while copying.
+    pub total_bytes: u64,
+    /// Part of `skipped`: conflicts the user chose to skip.
+    pub skipped_conflicts: usize,
+    /// Part of `skipped`: deletions the user declined.
+    pub declined_deletions: usize,
+    /// Backup files left alone because they were newer than the local file. Not part of `skipped`.
+    pub newer_destinations_skipped: usize,
+    /// Overwritten files that had other hard links, whether the link was broken or written through. Not part of any other count.
+    pub hard_linked_destinations: usize,
+    /// Part of `failed`: copies refused for lack of permission.
+    pub permission_failures: usize,
+    /// Part of `failed`: paths too long or names the destination doesn't accept.
+    pub path_failures: usize,
+    /// Part of `failed`: device errors that persisted through the retries.
+    pub device_failures: usize,
+}
+
+impl SyncStats {
+    /// Formats the counters for display, e.g. "已完成 120 · 跳过 3 · 失败 1 · 剩余 221".
+    pub fn summary(&self) -> String {
+        format!(
+            "已完成 {} · 跳过 {} · 失败 {} · 剩余 {}",
+            self.completed, self.skipped, self.failed, self.remaining
+        )
+    }
+
+    /// Describes what the run left unsynced, e.g. "2 个冲突被跳过，1 个失败", or None if nothing was.
+    pub fn unsynced_summary(&self) -> Option<String> {
+        let other_skipped = self.skipped - self.skipped_conflicts - self.declined_deletions;
+        let failed = match self.failure_breakdown() {
+            Some(breakdown) => format!("个失败（{}）", breakdown),
+            None => "个失败".to_owned(),
+        };
+        let parts: Vec<String> = [
+            (self.skipped_conflicts, "个冲突被跳过"),
+            (self.declined_deletions, "个删除被取消"),
+            (other_skipped, "个文件被跳过"),
+            (self.failed, failed.as_str()),
+            (self.newer_destinations_skipped, "个较新的备份文件未覆盖"),
+        ]
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, label)| format!("{} {}", count, label))
+        .collect();
+        (!parts.is_empty()).then(|| parts.join("，"))
+    }
+
+    /// Failures by cause, e.g. "权限不足 1，设备错误 2", or None if no cause was recognized.
+    pub fn failure_breakdown(&self) -> Option<String> {
+        let parts: Vec<String> = [
+            (self.permission_failures, "权限不足"),
+            (self.path_failures, "路径问题"),
+            (self.device_failures, "设备错误"),
+        ]
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, label)| format!("{} {}", label, count))
+        .collect();
+        (!parts.is_empty()).then(|| parts.join("，"))
+    }
+}
+
+/// The plan's expected effect on the free space of the USB drive. Filesystem overhead isn't counted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpaceEstimate {
+    /// Free space before the plan runs.
+    pub available: u64,
+    /// Free space the plan should leave; negative when it likely doesn't fit.
+    pub after: i64,
+}
+
+impl SpaceEstimate {
+    /// Formats the estimate for display, e.g. "U盘: 14.2 GB 可用 → 约 9.8 GB 可用 (估算，未计文件系统开销)".
+    pub fn summary(&self) -> String {
+        format!("U盘: {} 可用 → 约 {} 可用 (估算，未计文件系统开销)", format_size(self.available), format_size(self.after.max(0) as u64))
+    }
+
+    /// Bytes the drive is likely short of, or None if the plan should fit.
+    pub fn shortfall(&self) -> Option<u64> {
+        (self.after < 0).then(|| self.after.unsigned_abs())
+    }
+}
+
 /// Holds metadata about a single file for synchronization purposes.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct FileInfo {
+    #[serde(with = "portable_path")]
     pub path: PathBuf,
     pub hash: String,
     pub modified: SystemTime,
     pub size: u64,
+    /// Whether the last write of this version was read back and matched its source. Records from before
+    /// this was tracked count as unverified.
+    #[serde(default)]
+    pub verified: bool,
+}
+
+/// A file as one machine last saw it locally. Its hash is the one in the shared record, which is why an
+/// observation only stays while the shared entry keeps the hash the machine saw.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Observation {
+    pub modified: SystemTime,
+    pub size: u64,
+}
+
+/// The two versions of a conflict the user skipped, identified by their hashes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SkippedConflict {
+    pub local_hash: String,
+    pub remote_hash: String,
 }
 
 /// Represents the entire state of a synchronized directory, containing all file metadata.
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct SyncData {
+    #[serde(with = "portable_path::keys")]
     pub files: HashMap<PathBuf, FileInfo>,
+    #[serde(with = "portable_path::set")]
     pub directories: HashSet<PathBuf>,
+    /// The time the last successful sync finished, as seen by the machine that ran it.
+    #[serde(default)]
+    pub last_sync_time: Option<SystemTime>,
+    /// Maps local relative paths to their location on the USB drive, for files placed by a routing rule.
+    #[serde(default, with = "portable_path::pairs")]
+    pub routes: HashMap<PathBuf, PathBuf>,
+    /// Paths the user chose to keep after a declined deletion, with the hash of the copy that was kept.
+    /// No deletion is proposed for them again until that copy changes.
+    #[serde(default, with = "portable_path::keys")]
+    pub tombstones: HashMap<PathBuf, String>,
+    /// Conflicts the user skipped. The same pair of versions isn't raised again; a change on either side is.
+    #[serde(default, with = "portable_path::keys")]
+    pub skipped_conflicts: HashMap<PathBuf, SkippedConflict>,
+    /// The local folder this record was last synced from, used to recognize a renamed local folder.
+    #[serde(default)]
+    pub source_folder: Option<PathBuf>,
+    /// The volume the record was last saved on, as in `UsbDrive::volume_id`.
+    #[serde(default)]
+    pub volume_id: Option<String>,
+    /// Local file states as last seen by each machine, keyed by host name.
+    /// Copies of a file get different mtimes on every machine, so each one's mtime shortcut only trusts its own entries.
+    /// The shared `files` map needs no merging: the stick is in one machine at a time and every run starts from the
+    /// record the previous one left, so it already holds what the other machines synced.
+    #[serde(default, with = "portable_path::nested_keys")]
+    pub observations: HashMap<String, HashMap<PathBuf, Observation>>,
+    /// Version of SyncU that wrote the record; None for records from before versions were stored.
+    /// Always saved as the running version.
+    #[serde(default, serialize_with = "running_version")]
+    pub written_by: Option<String>,
+    /// Whether the record was read with `\` between path components, as Windows builds wrote them before paths
+    /// were stored portably. Not saved.
+    #[serde(skip)]
+    pub backslash_separators: bool,
+}
+
+/// Version of this build of SyncU, as recorded in the metadata it writes.
+pub const SYNCU_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+fn running_version<S: serde::Serializer>(_: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_some(SYNCU_VERSION)
+}
+
+impl SyncData {
+    /// The version that wrote this record, if it is newer than the running one. Such a record may hold
+    /// information this version doesn't know and would drop when saving it again.
+    pub fn written_by_newer_version(&self) -> Option<&str> {
+        self.written_by.as_deref().filter(|version| is_newer_version(version, SYNCU_VERSION))
+    }
+
+    /// Reads a record, noting whether it still separates path components with `\`.
+    pub fn from_reader(reader: impl std::io::Read) -> serde_json::Result<SyncData> {
+        let (result, backslash_separators) = portable_path::noting_backslash_separators(|| serde_json::from_reader::<_, SyncData>(reader));
+        result.map(|sync_data| SyncData { backslash_separators, ..sync_data })
+    }
+
+    /// Whether saving should rewrite the record even if its state is unchanged: it still has `\` separators or
+    /// comes from an older version, and either is only migrated when the record is saved.
+    pub fn needs_migration(&self) -> bool {
+        self.backslash_separators || self.written_by.as_deref().is_none_or(|version| is_newer_version(SYNCU_VERSION, version))
+    }
+
+    /// The reference for scanning this machine's local folder: its own observations if it has synced before,
+    /// otherwise None and the shared record is used.
+    pub fn local_reference(&self, machine: &str) -> Option<SyncData> {
+        let observed = self.observations.get(machine)?;
+        let files = observed
+            .iter()
+            .filter_map(|(path, seen)| {
+                let shared = self.files.get(path)?;
+                Some((path.clone(), FileInfo { modified: seen.modified, size: seen.size, ..shared.clone() }))
+            })
+            .collect();
+        Some(SyncData { files, ..Default::default() })
+    }
+
+    /// Records `seen`, this machine's local files as just scanned, as its observations. Other machines keep theirs
+    /// for files whose shared entry still has the hash it had in `previous`, the record this run started from.
+    pub fn record_observations(&mut self, machine: &str, seen: &HashMap<PathBuf, FileInfo>, previous: &SyncData) {
+        let same_hash = |path: &PathBuf, hash: &str| self.files.get(path).is_some_and(|shared| shared.hash == hash);
+        let mut observations = previous.observations.clone();
+        for files in observations.values_mut() {
+            files.retain(|path, _| previous.files.get(path).is_some_and(|before| same_hash(path, &before.hash)));
+        }
+        observations.retain(|_, files| !files.is_empty());
+        let own = seen
+            .iter()
+            .filter(|(path, info)| same_hash(path, &info.hash))
+            .map(|(path, info)| (path.clone(), Observation { modified: info.modified, size: info.size }))
+            .collect();
+        observations.insert(machine.to_owned(), own);
+        self.observations = observations;
+    }
+
+    /// Whether both records describe the same state, ignoring when each was written.
+    /// Maps compare their lengths before any entries, so records of different size are told apart at once.
+    pub fn same_state(&self, other: &SyncData) -> bool {
+        self.files == other.files
+            && self.directories == other.directories
+            && self.routes == other.routes
+            && self.tombstones == other.tombstones
+            && self.skipped_conflicts == other.skipped_conflicts
+            && self.source_folder == other.source_folder
+            && self.volume_id == other.volume_id
+            && self.observations == other.observations
+    }
+}
+
+/// Compares dotted version numbers part by part; anything after the digits of a part, like "-beta", is ignored.
+pub fn is_newer_version(version: &str, than: &str) -> bool {
+    let parts = |version: &str| -> Vec<u64> {
+        version
+            .split('.')
+            .map(|part| part.chars().take_while(char::is_ascii_digit).collect::<String>().parse().unwrap_or(0))
+            .collect()
+    };
+    let (mut version, mut than) = (parts(version), parts(than));
+    let len = version.len().max(than.len());
+    version.resize(len, 0);
+    than.resize(len, 0);
+    version > than
 }
 
 /// Defines a specific synchronization action to be performed.
-#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum SyncAction {
-    LocalToRemote(PathBuf),
-    RemoteToLocal(PathBuf),
-    DeleteLocal(PathBuf),
-    DeleteRemote(PathBuf),
-    Conflict { path: PathBuf },
+    // Relocations on the USB drive run before anything else touches the moved files
+    MoveRemote {
+        #[serde(with = "portable_path")]
+        from: PathBuf,
+        #[serde(with = "portable_path")]
+        to: PathBuf,
+    },
+    LocalToRemote(#[serde(with = "portable_path")] PathBuf),
+    RemoteToLocal(#[serde(with = "portable_path")] PathBuf),
+    DeleteLocal(#[serde(with = "portable_path")] PathBuf),
+    DeleteRemote(#[serde(with = "portable_path")] PathBuf),
+    Conflict {
+        #[serde(with = "portable_path")]
+        path: PathBuf,
+    },
     // Directory actions
-    CreateLocalDir(PathBuf),
-    CreateRemoteDir(PathBuf),
-    DeleteLocalDir(PathBuf),
-    DeleteRemoteDir(PathBuf),
+    CreateLocalDir(#[serde(with = "portable_path")] PathBuf),
+    CreateRemoteDir(#[serde(with = "portable_path")] PathBuf),
+    DeleteLocalDir(#[serde(with = "portable_path")] PathBuf),
+    DeleteRemoteDir(#[serde(with = "portable_path")] PathBuf),
+}
+
+impl SyncAction {
+    /// The path the action is listed under: where a moved entry ends up.
+    pub fn path(&self) -> &Path {
+        match self {
+            SyncAction::MoveRemote { to, .. } => to,
+            SyncAction::LocalToRemote(path)
+            | SyncAction::RemoteToLocal(path)
+            | SyncAction::DeleteLocal(path)
+            | SyncAction::DeleteRemote(path)
+            | SyncAction::Conflict { path }
+            | SyncAction::CreateLocalDir(path)
+            | SyncAction::CreateRemoteDir(path)
+            | SyncAction::DeleteLocalDir(path)
+            | SyncAction::DeleteRemoteDir(path) => path,
+        }
+    }
+
+    /// Short name of the kind of action, e.g. "→U盘".
+    pub fn label(&self) -> &'static str {
+        match self {
+            SyncAction::MoveRemote { .. } => "移动",
+            SyncAction::LocalToRemote(_) => "→U盘",
+            SyncAction::RemoteToLocal(_) => "→本地",
+            SyncAction::DeleteLocal(_) | SyncAction::DeleteLocalDir(_) => "删除本地",
+            SyncAction::DeleteRemote(_) | SyncAction::DeleteRemoteDir(_) => "删除U盘",
+            SyncAction::Conflict { .. } => "冲突",
+            SyncAction::CreateLocalDir(_) => "新建本地",
+            SyncAction::CreateRemoteDir(_) => "新建U盘",
+        }
+    }
+}
+
+/// Which side of a sync a file is on.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Side {
+    Local,
+    Usb,
+}
+
+/// A file as a plan checkpoint saw it. `info.path` is relative to the root of its side, after routing on the USB drive.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecordedFile {
+    pub side: Side,
+    pub info: FileInfo,
+}
+
+/// The file a copy would overwrite, as the planning scan saw it, or its absence.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecordedTarget {
+    pub side: Side,
+    /// Relative to the root of its side, after routing on the USB drive.
+    #[serde(with = "portable_path")]
+    pub path: PathBuf,
+    /// None if nothing was there.
+    pub info: Option<FileInfo>,
+}
+
+/// One action of a checkpointed plan.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlanItem {
+    pub action: SyncAction,
+    /// The file the action copies or deletes, as the planning scan saw it. None for folders and moves.
+    pub source: Option<RecordedFile>,
+    /// Where a copy writes, as the planning scan saw it. None for other actions, and in plans from before it was kept.
+    #[serde(default)]
+    pub target: Option<RecordedTarget>,
+    /// What a finished copy wrote, so the next scan of that side needn't hash it again.
+    pub written: Option<RecordedFile>,
+    /// None until the action has run.
+    pub status: Option<ActionStatus>,
+}
+
+/// The plan of a long run, kept on the USB drive while it executes so an interrupted run can be continued.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlanCheckpoint {
+    pub source_folder: PathBuf,
+    pub items: Vec<PlanItem>,
+}
+
+impl PlanCheckpoint {
+    /// How many actions haven't run yet.
+    pub fn remaining(&self) -> usize {
+        self.items.iter().filter(|item| item.status.is_none()).count()
+    }
+
+    /// The recorded file states on `side`, keyed by their relative path, for use as a scan's hash reference.
+    /// What a finished copy wrote takes precedence over what was planned for the same path.
+    pub fn recorded_files(&self, side: Side) -> HashMap<PathBuf, FileInfo> {
+        let mut files = HashMap::new();
+        for recorded in self.items.iter().filter_map(|item| item.source.as_ref()).filter(|recorded| recorded.side == side) {
+            files.insert(recorded.info.path.clone(), recorded.info.clone());
+        }
+        for recorded in self.items.iter().filter_map(|item| item.written.as_ref()).filter(|recorded| recorded.side == side) {
+            files.insert(recorded.info.path.clone(), recorded.info.clone());
+        }
+        files
+    }
+}
+
+/// Serde helpers for relative paths in sync records. Paths are written with `/` between components on every
+/// platform, and both `/` and `\` are accepted when reading, so a stick synced from Windows and from Linux or
+/// macOS reads the same records. Older records written with `\` are converted on their next save.
+/// A `\` inside a component, legal in Linux and macOS file names, is written doubled so it isn't read as a separator.
+mod portable_path {
+    use serde::ser::Error as _;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::cell::Cell;
+    use std::collections::{HashMap, HashSet};
+    use std::path::{Path, PathBuf};
+
+    thread_local! {
+        // Set when a lone `\` separated components, as in records from Windows builds before this format
+        static READ_BACKSLASH_SEPARATOR: Cell<bool> = const { Cell::new(false) };
+    }
+
+    /// Runs `read` and returns whether any path it read used `\` separators.
+    pub fn noting_backslash_separators<T>(read: impl FnOnce() -> T) -> (T, bool) {
+        READ_BACKSLASH_SEPARATOR.set(false);
+        let result = read();
+        (result, READ_BACKSLASH_SEPARATOR.take())
+    }
+
+    struct Portable<'a>(&'a Path);
+
+    impl Serialize for Portable<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let components = self
+                .0
+                .components()
+                .map(|component| component.as_os_str().to_str().map(|part| part.replace('\\', "\\\\")))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| S::Error::custom(format!("path contains invalid UTF-8: {}", self.0.display())))?;
+            serializer.serialize_str(&components.join("/"))
+        }
+    }
+
+    #[derive(PartialEq, Eq, Hash)]
+    struct PortableBuf(PathBuf);
+
+    impl<'de> Deserialize<'de> for PortableBuf {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let text = String::deserialize(deserializer)?;
+            Ok(Self(split_components(&text).into_iter().filter(|part| !part.is_empty()).collect()))
+        }
+    }
+
+    // Splits on `/` and on a single `\`; a doubled `\` is one literal backslash within a component
+    fn split_components(text: &str) -> Vec<String> {
+        let mut components = vec![String::new()];
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if chars.next_if_eq(&'\\').is_some() => components.last_mut().unwrap().push('\\'),
+                '\\' => {
+                    READ_BACKSLASH_SEPARATOR.set(true);
+                    components.push(String::new());
+                }
+                '/' => components.push(String::new()),
+                c => components.last_mut().unwrap().push(c),
+            }
+        }
+        components
+    }
+
+    struct PortableKeys<'a, V>(&'a HashMap<PathBuf, V>);
+
+    impl<V: Serialize> Serialize for PortableKeys<'_, V> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_map(self.0.iter().map(|(path, value)| (Portable(path), value)))
+        }
+    }
+
+    fn native_keys<V>(map: HashMap<PortableBuf, V>) -> HashMap<PathBuf, V> {
+        map.into_iter().map(|(path, value)| (path.0, value)).collect()
+    }
+
+    pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        Portable(path).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+        PortableBuf::deserialize(deserializer).map(|path| path.0)
+    }
+
+    /// Maps keyed by relative path.
+    pub mod keys {
+        use super::*;
+
+        pub fn serialize<S: Serializer, V: Serialize>(map: &HashMap<PathBuf, V>, serializer: S) -> Result<S::Ok, S::Error> {
+            PortableKeys(map).serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>, V: Deserialize<'de>>(deserializer: D) -> Result<HashMap<PathBuf, V>, D::Error> {
+            HashMap::<PortableBuf, V>::deserialize(deserializer).map(native_keys)
+        }
+    }
+
+    /// Maps from relative path to relative path.
+    pub mod pairs {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(map: &HashMap<PathBuf, PathBuf>, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_map(map.iter().map(|(from, to)| (Portable(from), Portable(to))))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<PathBuf, PathBuf>, D::Error> {
+            let map = HashMap::<PortableBuf, PortableBuf>::deserialize(deserializer)?;
+            Ok(map.into_iter().map(|(from, to)| (from.0, to.0)).collect())
+        }
+    }
+
+    /// Sets of relative paths.
+    pub mod set {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(set: &HashSet<PathBuf>, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(set.iter().map(|path| Portable(path)))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashSet<PathBuf>, D::Error> {
+            let paths = Vec::<PortableBuf>::deserialize(deserializer)?;
+            Ok(paths.into_iter().map(|path| path.0).collect())
+        }
+    }
+
+    /// Maps of relative-path-keyed maps, e.g. one per machine.
+    pub mod nested_keys {
+        use super::*;
+
+        pub fn serialize<S: Serializer, V: Serialize>(map: &HashMap<String, HashMap<PathBuf, V>>, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_map(map.iter().map(|(name, inner)| (name, PortableKeys(inner))))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>, V: Deserialize<'de>>(
+            deserializer: D,
+        ) -> Result<HashMap<String, HashMap<PathBuf, V>>, D::Error> {
+            let map = HashMap::<String, HashMap<PortableBuf, V>>::deserialize(deserializer)?;
+            Ok(map.into_iter().map(|(name, inner)| (name, native_keys(inner))).collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_compare_part_by_part() {
+        assert!(is_newer_version("0.10.0", "0.9.3"));
+        assert!(is_newer_version("1.0", "0.99.99"));
+        assert!(is_newer_version("0.5.4-beta", "0.5.3"));
+        assert!(!is_newer_version("0.5.3", "0.5.3"));
+        assert!(!is_newer_version("0.5", "0.5.0"));
+        assert!(!is_newer_version("0.4.9", "0.5.0"));
+    }
+
+    #[test]
+    fn the_summary_breaks_failures_down_by_cause() {
+        let stats = SyncStats { failed: 4, permission_failures: 1, device_failures: 2, ..Default::default() };
+        assert_eq!(stats.failure_breakdown().as_deref(), Some("权限不足 1，设备错误 2"));
+        assert_eq!(stats.unsynced_summary().as_deref(), Some("4 个失败（权限不足 1，设备错误 2）"));
+
+        let stats = SyncStats { failed: 1, ..Default::default() };
+        assert_eq!(stats.failure_breakdown(), None);
+        assert_eq!(stats.unsynced_summary().as_deref(), Some("1 个失败"));
+    }
+
+    fn files(entries: &[(&str, &str, u64)]) -> HashMap<PathBuf, FileInfo> {
+        entries
+            .iter()
+            .map(|&(path, hash, secs)| {
+                let modified = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+                (PathBuf::from(path), FileInfo { path: PathBuf::from(path), hash: hash.to_owned(), modified, size: 1, verified: true })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn each_machine_keeps_its_own_observations_across_the_others_syncs() {
+        let mut desk = SyncData { files: files(&[("a", "1", 100), ("b", "2", 100)]), ..Default::default() };
+        let seen = desk.files.clone();
+        desk.record_observations("desk", &seen, &SyncData::default());
+
+        // The laptop's copies have their own mtimes, and it edits b
+        let seen = files(&[("a", "1", 200), ("b", "3", 250)]);
+        let mut laptop = SyncData { files: seen.clone(), ..Default::default() };
+        laptop.record_observations("laptop", &seen, &desk);
+        // The desk still trusts its mtime of a, but must hash its old b again
+        assert_eq!(laptop.local_reference("desk").unwrap().files, files(&[("a", "1", 100)]));
+        assert_eq!(laptop.local_reference("laptop").unwrap().files, seen);
+
+        // Back on the desk, b arrives from the stick; the laptop's observations all survive
+        let seen = files(&[("a", "1", 100), ("b", "3", 300)]);
+        let mut desk_again = SyncData { files: seen.clone(), ..Default::default() };
+        desk_again.record_observations("desk", &seen, &laptop);
+        assert_eq!(desk_again.local_reference("laptop").unwrap().files, files(&[("a", "1", 200), ("b", "3", 250)]));
+        assert_eq!(desk_again.local_reference("desk").unwrap().files, seen);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_backslash_in_a_file_name_survives_a_round_trip() {
+        let path: PathBuf = ["notes", "a\\b.txt"].iter().collect();
+        let info = FileInfo { path: path.clone(), hash: "abc".to_owned(), modified: SystemTime::UNIX_EPOCH, size: 3, verified: true };
+        let data = SyncData { files: HashMap::from([(path.clone(), info)]), directories: HashSet::from([path.clone()]), ..Default::default() };
+
+        let json = serde_json::to_string(&data).unwrap();
+        let loaded: SyncData = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.files.keys().collect::<Vec<_>>(), [&path]);
+        assert_eq!(loaded.files[&path].path.components().count(), 2);
+        assert_eq!(loaded.directories, data.directories);
+    }
+
+    #[test]
+    fn single_backslashes_from_older_windows_records_separate_components() {
+        let loaded: SyncData = serde_json::from_str(r#"{"files":{},"directories":["notes\\deep","a\\\\b\\c"]}"#).unwrap();
+        let expected: HashSet<PathBuf> = HashSet::from([["notes", "deep"].iter().collect(), ["a\\b", "c"].iter().collect()]);
+        assert_eq!(loaded.directories, expected);
+    }
+
+    #[test]
+    fn space_estimates_report_a_shortfall_only_below_zero() {
+        let fits = SpaceEstimate { available: 3 * 1024 * 1024, after: 1024 * 1024 };
+        assert_eq!(fits.summary(), "U盘: 3.0 MB 可用 → 约 1.0 MB 可用 (估算，未计文件系统开销)");
+        assert_eq!(fits.shortfall(), None);
+        let short = SpaceEstimate { available: 1024, after: -2048 };
+        assert_eq!(short.summary(), "U盘: 1.0 KB 可用 → 约 0 B 可用 (估算，未计文件系统开销)");
+        assert_eq!(short.shortfall(), Some(2048));
+    }
 }
@@ -0,0 +1,168 @@
+//! An opt-in endpoint on the loopback interface that streams the progress of the running sync
+//! as newline-delimited JSON, for dashboards and scripts watching SyncU from the outside.
+//!
+//! The endpoint is read-only: anything a client sends is ignored. Events are handed over
+//! without ever blocking the sync; when the monitor or a client falls behind, events are dropped.
+
+use crate::models::{ActivityKind, SyncMessage};
+use crossbeam_channel::{bounded, Receiver, RecvTimeoutError, Sender};
+use serde::{Deserialize, Serialize};
+use std::io::{self, ErrorKind, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// The port offered when the monitor is turned on for the first time.
+pub const DEFAULT_MONITOR_PORT: u16 = 47815;
+
+// Events waiting for the monitor thread; more than this and new ones are dropped
+const EVENT_BACKLOG: usize = 256;
+// How often the monitor thread looks for new clients and for shutdown while no events arrive
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// One line of the stream, tagged with its kind in the `event` field.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum MonitorEvent {
+    /// `fraction` runs from 0 to 1; `phase` is "copy", "action" or "bookkeeping".
+    Progress { fraction: f32, current: String, phase: String },
+    Stats { completed: usize, skipped: usize, failed: usize, remaining: usize },
+    Error { message: String },
+    DeviceRemoved { path: PathBuf },
+    Completed,
+    /// The files were synced but the sync state could not be saved.
+    CompletedWithoutState { details: String },
+    Stopped,
+}
+
+impl MonitorEvent {
+    /// The event mirroring `message`, or None for prompts, answers and other messages the stream leaves out.
+    fn from_message(message: &SyncMessage) -> Option<Self> {
+        Some(match message {
+            SyncMessage::Progress(fraction, current, activity) => MonitorEvent::Progress {
+                fraction: *fraction,
+                current: current.clone(),
+                phase: match activity {
+                    ActivityKind::Copy => "copy",
+                    ActivityKind::Other => "action",
+                    ActivityKind::Bookkeeping => "bookkeeping",
+                }
+                .to_string(),
+            },
+            SyncMessage::Stats(stats) => MonitorEvent::Stats {
+                completed: stats.completed,
+                skipped: stats.skipped,
+                failed: stats.failed,
+                remaining: stats.remaining,
+            },
+            SyncMessage::Log(line) if line.starts_with("错误") => MonitorEvent::Error { message: line.clone() },
+            SyncMessage::DeviceRemoved(path) => MonitorEvent::DeviceRemoved { path: path.clone() },
+            SyncMessage::Complete => MonitorEvent::Completed,
+            SyncMessage::CompleteWithoutState(details) => MonitorEvent::CompletedWithoutState { details: details.clone() },
+            SyncMessage::Stopped => MonitorEvent::Stopped,
+            _ => return None,
+        })
+    }
+}
+
+/// Hands events to a running monitor. Cheap to clone, one per sync run.
+#[derive(Clone, Debug)]
+pub struct MonitorSender(Sender<MonitorEvent>);
+
+impl MonitorSender {
+    /// Passes on the event mirroring `message`, if any. Never blocks; drops the event when the monitor falls behind.
+    pub fn forward(&self, message: &SyncMessage) {
+        if let Some(event) = MonitorEvent::from_message(message) {
+            let _ = self.0.try_send(event);
+        }
+    }
+}
+
+/// Listens on a loopback port and writes every event to all connected clients.
+/// Dropping it closes the port and disconnects the clients.
+pub struct Monitor {
+    address: SocketAddr,
+    tx: Sender<MonitorEvent>,
+    shutdown: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Monitor {
+    /// Starts listening on `127.0.0.1:port`. Port 0 picks a free one, see `local_addr`.
+    pub fn start(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        listener.set_nonblocking(true)?;
+        let address = listener.local_addr()?;
+        let (tx, rx) = bounded(EVENT_BACKLOG);
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let shutdown = shutdown.clone();
+            thread::spawn(move || serve(listener, rx, &shutdown))
+        };
+        Ok(Self { address, tx, shutdown, thread: Some(thread) })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn sender(&self) -> MonitorSender {
+        MonitorSender(self.tx.clone())
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        // Senders held by a run still in progress keep the channel open, so the flag ends the thread
+        self.shutdown.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn serve(listener: TcpListener, rx: Receiver<MonitorEvent>, shutdown: &AtomicBool) {
+    let mut clients: Vec<TcpStream> = Vec::new();
+    while !shutdown.load(Ordering::Relaxed) {
+        loop {
+            match listener.accept() {
+                Ok((client, _)) => {
+                    if client.set_nonblocking(true).is_ok() {
+                        clients.push(client);
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                // WouldBlock once every waiting client is accepted
+                Err(_) => break,
+            }
+        }
+        match rx.recv_timeout(POLL_INTERVAL) {
+            Ok(event) => {
+                let Ok(mut line) = serde_json::to_vec(&event) else { continue };
+                line.push(b'\n');
+                // A client that can't take a whole line at once is too slow; a partial line would garble its stream
+                clients.retain_mut(|client| matches!(client.write(&line), Ok(written) if written == line.len()));
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prompts_and_ordinary_log_lines_are_left_out() {
+        assert_eq!(MonitorEvent::from_message(&SyncMessage::Log("复制: a.txt".to_string())), None);
+        assert_eq!(MonitorEvent::from_message(&SyncMessage::ConfirmResumePlan { remaining: 1, total: 2 }), None);
+        assert_eq!(
+            MonitorEvent::from_message(&SyncMessage::Progress(1.0, "a.txt".to_string(), ActivityKind::Copy)),
+            Some(MonitorEvent::Progress { fraction: 1.0, current: "a.txt".to_string(), phase: "copy".to_string() })
+        );
+    }
+}
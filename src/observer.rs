@@ -0,0 +1,596 @@
+use crate::error::SyncError;
+use crate::history::{history_dir, save_run};
+use crate::models::{ActionStatus, ActivityKind, ClockSkewChoice, ConsistencyReport, ConflictSuggestion, CrowdedDirectory, CrowdedDirectoryChoice, DiffLine, LongPathChoice, NameCollisionChoice, RemoteMissingChoice, Resolution, RunOutcome, SpaceEstimate, SyncAction, SyncMessage, SyncStats};
+use crate::monitor::MonitorSender;
+use crate::report::RunReport;
+use crate::settings::{HistoryRetention, Profile};
+use chrono::Local;
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// The user's answer to a deletion prompt.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeletionDecision {
+    Delete,
+    Keep,
+    /// Keep the file and don't ask about it again until it changes.
+    KeepPermanently,
+}
+
+/// Receives progress from the sync engine and answers its questions.
+/// Questions return `Err(SyncError::Cancelled)` when the user stops the sync instead of answering.
+pub trait SyncObserver: Sync {
+    fn on_progress(&self, progress: f32, message: String);
+    fn on_log(&self, message: String);
+    fn on_stats(&self, stats: SyncStats);
+    /// How the plan is expected to change the free space on the USB drive. Purely informational.
+    fn on_space_estimate(&self, estimate: SpaceEstimate);
+    /// The actions about to be carried out; `on_action_started` and `on_action_finished` later refer to them by index.
+    fn on_plan(&self, plan: &[SyncAction]);
+    fn on_action_started(&self, index: usize, action: &SyncAction);
+    fn on_action_finished(&self, index: usize, status: ActionStatus);
+    fn on_consistency_checked(&self, report: &ConsistencyReport);
+    /// The plan copies `count` small files to the USB drive, enough to make the run slow. Purely informational.
+    fn on_many_small_files(&self, count: usize);
+    fn on_device_removed(&self, usb_drive: &Path);
+    /// The report of a run that planned any actions, once its outcome is known. Called just before `on_finished`.
+    fn on_run_recorded(&self, report: &RunReport);
+    /// Called once when the run ends, after its metadata and log have reached the disk.
+    fn on_finished(&self, outcome: RunOutcome);
+    fn should_stop(&self) -> bool;
+    /// Whether the user asked to skip the action in progress. Answering true takes the request.
+    fn should_skip_current(&self) -> bool;
+    /// Bytes per second copies may use right now, or None for no limit. May change during a run.
+    fn rate_limit(&self) -> Option<u64>;
+
+    /// `position` counts this deletion among the run's `total` planned deletions, starting at 1.
+    fn confirm_deletion(&self, path: &Path, position: usize, total: usize) -> Result<DeletionDecision, SyncError>;
+    fn resolve_conflict(&self, path: &Path, diff: Option<Vec<DiffLine>>, suggestion: ConflictSuggestion) -> Result<Resolution, SyncError>;
+    fn resolve_clock_skew(&self, description: String) -> Result<ClockSkewChoice, SyncError>;
+    fn confirm_copy_in_use(&self, path: &Path) -> Result<bool, SyncError>;
+    fn resolve_remote_missing(&self, missing: usize, known: usize, examples: Vec<PathBuf>) -> Result<RemoteMissingChoice, SyncError>;
+    fn resolve_long_paths(&self, limit: usize, count: usize, examples: Vec<PathBuf>) -> Result<LongPathChoice, SyncError>;
+    /// Whether to go on although the size of `count` files to copy couldn't be read; they are tried again when
+    /// their turn comes and skipped if still unreadable. `examples` holds the first few plan paths.
+    fn confirm_unreadable_sizes(&self, count: usize, examples: Vec<PathBuf>) -> Result<bool, SyncError>;
+    fn resolve_crowded_directories(&self, directories: Vec<CrowdedDirectory>) -> Result<CrowdedDirectoryChoice, SyncError>;
+    /// `examples` holds the first few groups of colliding local paths, out of `count`.
+    fn resolve_name_collisions(&self, count: usize, examples: Vec<Vec<PathBuf>>) -> Result<NameCollisionChoice, SyncError>;
+    fn confirm_relink(&self, old_name: &str, new_name: &str) -> Result<bool, SyncError>;
+    fn confirm_overwrite_newer(&self, path: &Path) -> Result<bool, SyncError>;
+    /// Whether to try a copy again after access to `path` was refused; `error` says what the system reported.
+    fn retry_denied_copy(&self, path: &Path, error: &str) -> Result<bool, SyncError>;
+    /// Whether to continue an interrupted run's plan (true) or plan again from scratch (false).
+    fn confirm_resume_plan(&self, remaining: usize, total: usize) -> Result<bool, SyncError>;
+    /// Whether to sync although the record was written by the newer `version`, leaving the record as it is (true),
+    /// or to stop (false).
+    fn confirm_newer_metadata(&self, version: &str) -> Result<bool, SyncError>;
+}
+
+/// Drives the GUI by translating observer calls into `SyncMessage`s on a channel pair.
+pub struct ChannelObserver {
+    tx: Sender<SyncMessage>,
+    rx: Receiver<SyncMessage>,
+    // Ids let the UI answer prompts in any order
+    next_prompt_id: AtomicU64,
+    prompt_timeout: Option<Duration>,
+    // Bytes per second, 0 for no limit; the UI can change it while the run goes on
+    rate_limit: AtomicU64,
+    // What the run is doing, reported with every progress update
+    activity: Mutex<ActivityKind>,
+    skip_requested: AtomicBool,
+    stop_after_action: AtomicBool,
+    paused: AtomicBool,
+    // External progress readers, when the user turned them on
+    monitor: Option<MonitorSender>,
+    // How much run history to keep; None records none
+    history: Option<HistoryRetention>,
+}
+
+impl ChannelObserver {
+    pub fn new(tx: Sender<SyncMessage>, rx: Receiver<SyncMessage>) -> Self {
+        Self {
+            tx,
+            rx,
+            next_prompt_id: AtomicU64::new(1),
+            prompt_timeout: None,
+            rate_limit: AtomicU64::new(0),
+            activity: Mutex::new(ActivityKind::Bookkeeping),
+            skip_requested: AtomicBool::new(false),
+            stop_after_action: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            monitor: None,
+            history: None,
+        }
+    }
+
+    /// Starts the run with a copy speed limit in bytes per second. The UI may change it later with `SetRateLimit`.
+    pub fn with_rate_limit(self, limit: Option<u64>) -> Self {
+        self.set_rate_limit(limit);
+        self
+    }
+
+    fn set_rate_limit(&self, limit: Option<u64>) {
+        self.rate_limit.store(limit.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    /// Skips deletion and conflict prompts that go unanswered for `timeout`, so an unnoticed dialog can't stall a run.
+    pub fn with_prompt_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.prompt_timeout = timeout;
+        self
+    }
+
+    /// Also passes progress, errors and the outcome on to an external monitor.
+    pub fn with_monitor(mut self, monitor: Option<MonitorSender>) -> Self {
+        self.monitor = monitor;
+        self
+    }
+
+    /// Records each run's actions in the history in the app data directory, keeping as much as `retention` allows.
+    pub fn with_history(mut self, retention: Option<HistoryRetention>) -> Self {
+        self.history = retention;
+        self
+    }
+
+    // Applies a control message that may arrive at any time, also while a question waits for its answer.
+    // Returns false for any other message.
+    fn handle_control(&self, message: &SyncMessage) -> bool {
+        match message {
+            SyncMessage::SetRateLimit(limit) => self.set_rate_limit(*limit),
+            // A request that arrives after its copy ended must not skip the next one
+            SyncMessage::SkipCurrent => {
+                let skippable = self.activity.lock().unwrap().skippable();
+                self.skip_requested.store(skippable, Ordering::Relaxed);
+            }
+            SyncMessage::StopAfterAction => self.stop_after_action.store(true, Ordering::Relaxed),
+            SyncMessage::Pause => self.paused.store(true, Ordering::Relaxed),
+            SyncMessage::Resume => self.paused.store(false, Ordering::Relaxed),
+            _ => return false,
+        }
+        true
+    }
+
+    // Blocks while the run is paused. Returns true if it was stopped instead of resumed.
+    fn wait_while_paused(&self) -> bool {
+        while self.paused.load(Ordering::Relaxed) {
+            match self.rx.recv() {
+                Ok(SyncMessage::Stop) | Err(_) => return true,
+                Ok(message) => {
+                    self.handle_control(&message);
+                }
+            }
+        }
+        false
+    }
+
+    fn send(&self, message: SyncMessage) {
+        if let Some(monitor) = &self.monitor {
+            monitor.forward(&message);
+        }
+        // A closed UI is noticed through should_stop and the next question
+        let _ = self.tx.send(message);
+    }
+
+    /// Sends a question and waits for the answer picked out by `condition`, while checking for a stop signal.
+    fn ask<F, T>(&self, question: SyncMessage, condition: F) -> Result<T, SyncError>
+    where
+        F: FnMut(SyncMessage) -> Option<T>,
+    {
+        self.ask_until(question, condition, None).map(|answer| answer.expect("no deadline was set"))
+    }
+
+    /// Like `ask`, but gives up with Ok(None) once `deadline` has passed.
+    fn ask_until<F, T>(&self, question: SyncMessage, mut condition: F, deadline: Option<Instant>) -> Result<Option<T>, SyncError>
+    where
+        F: FnMut(SyncMessage) -> Option<T>,
+    {
+        self.tx.send(question)?;
+        loop {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Ok(None);
+            }
+            // Use a timeout to prevent blocking indefinitely.
+            match self.rx.recv_timeout(Duration::from_millis(100)) {
+                Ok(SyncMessage::Stop) => return Err(SyncError::Cancelled),
+                Ok(msg) if self.handle_control(&msg) => {}
+                Ok(msg) => {
+                    if let Some(result) = condition(msg) {
+                        return Ok(Some(result));
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                // Channel disconnected, treat as a stop.
+                Err(RecvTimeoutError::Disconnected) => return Err(SyncError::Cancelled),
+            }
+        }
+    }
+
+    fn prompt_id(&self) -> u64 {
+        self.next_prompt_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    // Withdraws a prompt nobody answered in time and notes the fallback in the log.
+    fn expire_prompt(&self, id: u64, path: &Path) {
+        self.send(SyncMessage::PromptExpired { id });
+        self.send(SyncMessage::Log(format!("[{}] 等待回答超时，已跳过: {}", Local::now().format("%H:%M:%S"), path.display())));
+    }
+}
+
+impl SyncObserver for ChannelObserver {
+    fn on_progress(&self, progress: f32, message: String) {
+        let activity = *self.activity.lock().unwrap();
+        self.send(SyncMessage::Progress(progress, message, activity));
+    }
+
+    fn on_log(&self, message: String) {
+        self.send(SyncMessage::Log(message));
+    }
+
+    fn on_stats(&self, stats: SyncStats) {
+        self.send(SyncMessage::Stats(stats));
+    }
+
+    fn on_space_estimate(&self, estimate: SpaceEstimate) {
+        self.send(SyncMessage::SpaceEstimate(estimate));
+    }
+
+    fn on_plan(&self, plan: &[SyncAction]) {
+        self.send(SyncMessage::Plan(plan.to_vec()));
+    }
+
+    fn on_action_started(&self, _index: usize, action: &SyncAction) {
+        *self.activity.lock().unwrap() = ActivityKind::of(action);
+        self.skip_requested.store(false, Ordering::Relaxed);
+    }
+
+    fn on_action_finished(&self, index: usize, status: ActionStatus) {
+        *self.activity.lock().unwrap() = ActivityKind::Bookkeeping;
+        self.send(SyncMessage::ActionFinished { index, status });
+    }
+
+    fn on_consistency_checked(&self, report: &ConsistencyReport) {
+        self.send(SyncMessage::ConsistencyChecked(report.clone()));
+    }
+
+    fn on_many_small_files(&self, count: usize) {
+        self.send(SyncMessage::ManySmallFiles(count));
+    }
+
+    fn on_device_removed(&self, usb_drive: &Path) {
+        self.send(SyncMessage::DeviceRemoved(usb_drive.to_path_buf()));
+    }
+
+    fn on_run_recorded(&self, report: &RunReport) {
+        let Some(retention) = self.history else { return };
+        if let Err(e) = save_run(&history_dir(), report, retention) {
+            self.on_log(format!("警告: 无法保存同步历史: {}", e));
+        }
+    }
+
+    fn on_finished(&self, outcome: RunOutcome) {
+        self.send(match outcome {
+            RunOutcome::Completed => SyncMessage::Complete,
+            RunOutcome::Stopped => SyncMessage::Stopped,
+            RunOutcome::StateNotPersisted(details) => SyncMessage::CompleteWithoutState(details),
+        });
+    }
+
+    fn should_stop(&self) -> bool {
+        loop {
+            match self.rx.try_recv() {
+                Ok(SyncMessage::Stop) | Err(TryRecvError::Disconnected) => return true,
+                Ok(message) => {
+                    self.handle_control(&message);
+                }
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+        if self.wait_while_paused() {
+            return true;
+        }
+        // A soft stop waits for the action in progress, so it takes effect at the first check outside one
+        self.stop_after_action.load(Ordering::Relaxed) && *self.activity.lock().unwrap() == ActivityKind::Bookkeeping
+    }
+
+    fn should_skip_current(&self) -> bool {
+        self.skip_requested.swap(false, Ordering::Relaxed)
+    }
+
+    fn rate_limit(&self) -> Option<u64> {
+        Some(self.rate_limit.load(Ordering::Relaxed)).filter(|&limit| limit > 0)
+    }
+
+    fn confirm_deletion(&self, path: &Path, position: usize, total: usize) -> Result<DeletionDecision, SyncError> {
+        let prompt_id = self.prompt_id();
+        let deadline = self.prompt_timeout.map(|timeout| Instant::now() + timeout);
+        let question = SyncMessage::ConfirmDeletion { id: prompt_id, path: path.to_path_buf(), position, total };
+        let answer = self.ask_until(
+            question,
+            |msg| match msg {
+                SyncMessage::DeletionConfirmed { id, confirmed: true } if id == prompt_id => Some(DeletionDecision::Delete),
+                SyncMessage::DeletionConfirmed { id, confirmed: false } if id == prompt_id => Some(DeletionDecision::Keep),
+                SyncMessage::DeletionDeclinedPermanently { id } if id == prompt_id => Some(DeletionDecision::KeepPermanently),
+                _ => None,
+            },
+            deadline,
+        )?;
+        Ok(answer.unwrap_or_else(|| {
+            self.expire_prompt(prompt_id, path);
+            DeletionDecision::Keep
+        }))
+    }
+
+    fn resolve_conflict(&self, path: &Path, diff: Option<Vec<DiffLine>>, suggestion: ConflictSuggestion) -> Result<Resolution, SyncError> {
+        let prompt_id = self.prompt_id();
+        let deadline = self.prompt_timeout.map(|timeout| Instant::now() + timeout);
+        let question = SyncMessage::AskForConflictResolution { id: prompt_id, path: path.to_path_buf(), diff, suggestion };
+        let answer = self.ask_until(
+            question,
+            |msg| match msg {
+                SyncMessage::ConflictResolved { id, resolution } if id == prompt_id => Some(resolution),
+                _ => None,
+            },
+            deadline,
+        )?;
+        Ok(answer.unwrap_or_else(|| {
+            self.expire_prompt(prompt_id, path);
+            Resolution::Skip
+        }))
+    }
+
+    fn resolve_clock_skew(&self, description: String) -> Result<ClockSkewChoice, SyncError> {
+        self.ask(SyncMessage::AskForClockSkewResolution(description), |msg| match msg {
+            SyncMessage::ClockSkewResolved(choice) => Some(choice),
+            _ => None,
+        })
+    }
+
+    fn confirm_copy_in_use(&self, path: &Path) -> Result<bool, SyncError> {
+        self.ask(SyncMessage::ConfirmCopyInUse(path.to_path_buf()), |msg| match msg {
+            SyncMessage::CopyInUseConfirmed(confirmed) => Some(confirmed),
+            _ => None,
+        })
+    }
+
+    fn resolve_remote_missing(&self, missing: usize, known: usize, examples: Vec<PathBuf>) -> Result<RemoteMissingChoice, SyncError> {
+        self.ask(SyncMessage::ConfirmRemoteMissing { missing, known, examples }, |msg| match msg {
+            SyncMessage::RemoteMissingResolved(choice) => Some(choice),
+            _ => None,
+        })
+    }
+
+    fn resolve_long_paths(&self, limit: usize, count: usize, examples: Vec<PathBuf>) -> Result<LongPathChoice, SyncError> {
+        self.ask(SyncMessage::ConfirmLongPaths { limit, count, examples }, |msg| match msg {
+            SyncMessage::LongPathsResolved(choice) => Some(choice),
+            _ => None,
+        })
+    }
+
+    fn confirm_unreadable_sizes(&self, count: usize, examples: Vec<PathBuf>) -> Result<bool, SyncError> {
+        self.ask(SyncMessage::ConfirmUnreadableSizes { count, examples }, |msg| match msg {
+            SyncMessage::UnreadableSizesConfirmed(proceed) => Some(proceed),
+            _ => None,
+        })
+    }
+
+    fn resolve_crowded_directories(&self, directories: Vec<CrowdedDirectory>) -> Result<CrowdedDirectoryChoice, SyncError> {
+        self.ask(SyncMessage::ConfirmCrowdedDirectories(directories), |msg| match msg {
+            SyncMessage::CrowdedDirectoriesResolved(choice) => Some(choice),
+            _ => None,
+        })
+    }
+
+    fn resolve_name_collisions(&self, count: usize, examples: Vec<Vec<PathBuf>>) -> Result<NameCollisionChoice, SyncError> {
+        self.ask(SyncMessage::ConfirmNameCollisions { count, examples }, |msg| match msg {
+            SyncMessage::NameCollisionsResolved(choice) => Some(choice),
+            _ => None,
+        })
+    }
+
+    fn confirm_relink(&self, old_name: &str, new_name: &str) -> Result<bool, SyncError> {
+        self.ask(SyncMessage::ConfirmRelink { old_name: old_name.to_string(), new_name: new_name.to_string() }, |msg| match msg {
+            SyncMessage::RelinkConfirmed(confirmed) => Some(confirmed),
+            _ => None,
+        })
+    }
+
+    fn confirm_overwrite_newer(&self, path: &Path) -> Result<bool, SyncError> {
+        self.ask(SyncMessage::ConfirmOverwriteNewer(path.to_path_buf()), |msg| match msg {
+            SyncMessage::OverwriteNewerConfirmed(confirmed) => Some(confirmed),
+            _ => None,
+        })
+    }
+
+    fn retry_denied_copy(&self, path: &Path, error: &str) -> Result<bool, SyncError> {
+        self.ask(SyncMessage::ConfirmRetryDenied { path: path.to_path_buf(), error: error.to_owned() }, |msg| match msg {
+            SyncMessage::RetryDeniedConfirmed(retry) => Some(retry),
+            _ => None,
+        })
+    }
+
+    fn confirm_resume_plan(&self, remaining: usize, total: usize) -> Result<bool, SyncError> {
+        self.ask(SyncMessage::ConfirmResumePlan { remaining, total }, |msg| match msg {
+            SyncMessage::ResumePlanConfirmed(confirmed) => Some(confirmed),
+            _ => None,
+        })
+    }
+
+    fn confirm_newer_metadata(&self, version: &str) -> Result<bool, SyncError> {
+        self.ask(SyncMessage::ConfirmNewerMetadata(version.to_owned()), |msg| match msg {
+            SyncMessage::NewerMetadataConfirmed(proceed) => Some(proceed),
+            _ => None,
+        })
+    }
+}
+
+/// Answers every question without waiting, for runs nobody is watching (scheduled or command line).
+/// Deletions and conflicts follow the profile's remembered answers; where the profile says to ask, and for
+/// every other question, the answer that changes the least is taken and logged.
+pub struct UnattendedObserver<'a, O: SyncObserver> {
+    inner: &'a O,
+    deletion_choice: Option<bool>,
+    conflict_resolution: Option<Resolution>,
+    rate_limit: Option<u64>,
+}
+
+impl<'a, O: SyncObserver> UnattendedObserver<'a, O> {
+    pub fn new(inner: &'a O, profile: &Profile) -> Self {
+        Self {
+            inner,
+            deletion_choice: profile.default_deletion_choice,
+            conflict_resolution: profile.default_conflict_resolution.clone(),
+            rate_limit: profile.rate_limit_bytes(),
+        }
+    }
+
+    fn log_answer(&self, answer: &str, subject: impl std::fmt::Display) {
+        self.inner.on_log(format!("[{}] 无人值守: {}: {}", Local::now().format("%H:%M:%S"), answer, subject));
+    }
+}
+
+impl<O: SyncObserver> SyncObserver for UnattendedObserver<'_, O> {
+    fn on_progress(&self, progress: f32, message: String) {
+        self.inner.on_progress(progress, message);
+    }
+
+    fn on_log(&self, message: String) {
+        self.inner.on_log(message);
+    }
+
+    fn on_stats(&self, stats: SyncStats) {
+        self.inner.on_stats(stats);
+    }
+
+    fn on_space_estimate(&self, estimate: SpaceEstimate) {
+        self.inner.on_space_estimate(estimate);
+    }
+
+    fn on_plan(&self, plan: &[SyncAction]) {
+        self.inner.on_plan(plan);
+    }
+
+    fn on_action_started(&self, index: usize, action: &SyncAction) {
+        self.inner.on_action_started(index, action);
+    }
+
+    fn on_action_finished(&self, index: usize, status: ActionStatus) {
+        self.inner.on_action_finished(index, status);
+    }
+
+    fn on_consistency_checked(&self, report: &ConsistencyReport) {
+        self.inner.on_consistency_checked(report);
+    }
+
+    fn on_many_small_files(&self, count: usize) {
+        self.inner.on_many_small_files(count);
+    }
+
+    fn on_device_removed(&self, usb_drive: &Path) {
+        self.inner.on_device_removed(usb_drive);
+    }
+
+    fn on_run_recorded(&self, report: &RunReport) {
+        self.inner.on_run_recorded(report);
+    }
+
+    fn on_finished(&self, outcome: RunOutcome) {
+        self.inner.on_finished(outcome);
+    }
+
+    fn should_stop(&self) -> bool {
+        self.inner.should_stop()
+    }
+
+    fn should_skip_current(&self) -> bool {
+        self.inner.should_skip_current()
+    }
+
+    fn rate_limit(&self) -> Option<u64> {
+        self.inner.rate_limit().or(self.rate_limit)
+    }
+
+    fn confirm_deletion(&self, path: &Path, _position: usize, _total: usize) -> Result<DeletionDecision, SyncError> {
+        Ok(match self.deletion_choice {
+            Some(true) => DeletionDecision::Delete,
+            Some(false) => DeletionDecision::Keep,
+            None => {
+                self.log_answer("未确认删除，已跳过", path.display());
+                DeletionDecision::Keep
+            }
+        })
+    }
+
+    fn resolve_conflict(&self, path: &Path, _diff: Option<Vec<DiffLine>>, _suggestion: ConflictSuggestion) -> Result<Resolution, SyncError> {
+        Ok(self.conflict_resolution.clone().unwrap_or_else(|| {
+            self.log_answer("冲突未解决，已跳过", path.display());
+            Resolution::Skip
+        }))
+    }
+
+    fn resolve_clock_skew(&self, description: String) -> Result<ClockSkewChoice, SyncError> {
+        // Hashing everything is slow but never trusts the broken clock
+        self.log_answer("系统时间异常，将完整校验所有文件", description);
+        Ok(ClockSkewChoice::FullRehash)
+    }
+
+    fn confirm_copy_in_use(&self, path: &Path) -> Result<bool, SyncError> {
+        self.log_answer("文件正在使用，已跳过", path.display());
+        Ok(false)
+    }
+
+    fn resolve_remote_missing(&self, missing: usize, _known: usize, _examples: Vec<PathBuf>) -> Result<RemoteMissingChoice, SyncError> {
+        self.log_answer("U盘缺少文件，将重新复制到U盘", format!("{} 个文件", missing));
+        Ok(RemoteMissingChoice::Recopy)
+    }
+
+    fn resolve_long_paths(&self, _limit: usize, count: usize, _examples: Vec<PathBuf>) -> Result<LongPathChoice, SyncError> {
+        self.log_answer("路径过长，已跳过", format!("{} 个文件", count));
+        Ok(LongPathChoice::Skip)
+    }
+
+    fn confirm_unreadable_sizes(&self, count: usize, _examples: Vec<PathBuf>) -> Result<bool, SyncError> {
+        // The files are tried again when their turn comes, so the rest of the run isn't held up by them
+        self.log_answer("无法读取文件大小，继续同步", format!("{} 个文件", count));
+        Ok(true)
+    }
+
+    fn resolve_crowded_directories(&self, directories: Vec<CrowdedDirectory>) -> Result<CrowdedDirectoryChoice, SyncError> {
+        self.log_answer("文件夹项目过多，已跳过新增文件", format!("{} 个文件夹", directories.len()));
+        Ok(CrowdedDirectoryChoice::Skip)
+    }
+
+    fn resolve_name_collisions(&self, count: usize, _examples: Vec<Vec<PathBuf>>) -> Result<NameCollisionChoice, SyncError> {
+        self.log_answer("文件名仅末尾的点或空格不同，已跳过", format!("{} 组文件", count));
+        Ok(NameCollisionChoice::Skip)
+    }
+
+    fn confirm_relink(&self, old_name: &str, new_name: &str) -> Result<bool, SyncError> {
+        self.log_answer("未沿用已重命名的U盘文件夹", format!("{} -> {}", old_name, new_name));
+        Ok(false)
+    }
+
+    fn confirm_overwrite_newer(&self, path: &Path) -> Result<bool, SyncError> {
+        self.log_answer("备份文件较新，已跳过", path.display());
+        Ok(false)
+    }
+
+    fn retry_denied_copy(&self, path: &Path, _error: &str) -> Result<bool, SyncError> {
+        // Nobody can fix the permissions in the meantime, so another attempt would only fail again
+        self.log_answer("没有访问权限，不再重试", path.display());
+        Ok(false)
+    }
+
+    fn confirm_resume_plan(&self, remaining: usize, _total: usize) -> Result<bool, SyncError> {
+        self.log_answer("继续上次未完成的同步", format!("还剩 {} 项", remaining));
+        Ok(true)
+    }
+
+    fn confirm_newer_metadata(&self, version: &str) -> Result<bool, SyncError> {
+        // Syncing without a record to show for it is for someone who can see the warning
+        self.log_answer("同步记录由较新版本写入，已停止", version);
+        Ok(false)
+    }
+}
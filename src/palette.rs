@@ -0,0 +1,88 @@
+//! Colours shared by the whole UI. The accent follows the user's choice; the rest are fixed.
+
+use crate::models::Theme;
+use egui::Color32;
+
+/// Lowest contrast ratio at which accent-coloured links stay readable on the panel background.
+pub const MIN_LINK_CONTRAST: f32 = 3.0;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Palette {
+    /// Selected items, the progress bar and the sync button.
+    pub accent: Color32,
+    /// Text drawn over the accent: black or white, whichever contrasts more.
+    pub on_accent: Color32,
+    /// Fill of the stop button.
+    pub stop: Color32,
+    /// "Ready" and a clean finish.
+    pub ready: Color32,
+    /// Progress lines in the log, and additions in diffs.
+    pub success: Color32,
+    pub warning: Color32,
+    /// Errors, and removals in diffs.
+    pub error: Color32,
+    /// A stopped run.
+    pub stopped: Color32,
+}
+
+impl Palette {
+    /// The palette for `theme`, with `accent` as RGB, or the theme's own selection colour if None.
+    pub fn new(theme: &Theme, accent: Option<[u8; 3]>) -> Self {
+        let accent = match accent {
+            Some([r, g, b]) => Color32::from_rgb(r, g, b),
+            None => base_visuals(theme).selection.bg_fill,
+        };
+        Self {
+            accent,
+            on_accent: readable_text_on(accent),
+            stop: Color32::from_rgb(200, 30, 70),
+            ready: Color32::from_rgb(0, 100, 0),
+            success: Color32::from_rgb(100, 180, 100),
+            warning: Color32::from_rgb(210, 160, 60),
+            error: Color32::from_rgb(210, 90, 90),
+            stopped: Color32::from_rgb(210, 210, 90),
+        }
+    }
+
+    /// Puts the accent into `visuals`. Links keep the theme's colour when the accent would be hard to read as text.
+    pub fn apply_to(&self, visuals: &mut egui::Visuals) {
+        visuals.selection.bg_fill = self.accent;
+        visuals.selection.stroke.color = self.on_accent;
+        if contrast_ratio(self.accent, visuals.panel_fill) >= MIN_LINK_CONTRAST {
+            visuals.hyperlink_color = self.accent;
+        }
+    }
+}
+
+pub fn base_visuals(theme: &Theme) -> egui::Visuals {
+    match theme {
+        Theme::Light => egui::Visuals::light(),
+        Theme::Dark => egui::Visuals::dark(),
+    }
+}
+
+/// Black or white, whichever has the higher contrast over `background`.
+pub fn readable_text_on(background: Color32) -> Color32 {
+    if contrast_ratio(Color32::WHITE, background) >= contrast_ratio(Color32::BLACK, background) {
+        Color32::WHITE
+    } else {
+        Color32::BLACK
+    }
+}
+
+/// WCAG contrast ratio between two opaque colours, from 1.0 (identical) to 21.0 (black on white).
+pub fn contrast_ratio(a: Color32, b: Color32) -> f32 {
+    let (lighter, darker) = {
+        let (a, b) = (relative_luminance(a), relative_luminance(b));
+        if a >= b { (a, b) } else { (b, a) }
+    };
+    (lighter + 0.05) / (darker + 0.05)
+}
+
+fn relative_luminance(color: Color32) -> f32 {
+    let channel = |value: u8| {
+        let value = f32::from(value) / 255.0;
+        if value <= 0.039_28 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) }
+    };
+    0.2126 * channel(color.r()) + 0.7152 * channel(color.g()) + 0.0722 * channel(color.b())
+}
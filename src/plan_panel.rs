@@ -0,0 +1,292 @@
+//! The read-only "本次变更" side panel: the run's plan grouped by top-level folder, ticked off as actions finish.
+//! Plans can hold hundreds of thousands of actions, so rows are laid out only while scrolled into view.
+//! The same plan can be shown as a folder tree with per-folder counts, also in the 同步结果 window.
+
+use crate::models::{ActionStatus, SpaceEstimate, SyncAction};
+use crate::palette::Palette;
+use crate::plan_tree::{build_plan_tree, is_folder_action, PlanFolder};
+use crate::utils::format_size;
+use egui::{Color32, RichText};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Group of the files directly in the sync folder.
+const ROOT_GROUP: &str = "(根目录)";
+/// Deeper folders list everything below them as one flat list.
+const TREE_MAX_DEPTH: usize = 8;
+/// Rows listed in one open folder of the tree; the rest are only counted.
+const TREE_ROW_LIMIT: usize = 500;
+
+struct PlanGroup {
+    name: String,
+    // Range of the group's actions in `PlanPanel::order`
+    start: usize,
+    len: usize,
+    done: usize,
+    failed: usize,
+    expanded: bool,
+}
+
+#[derive(Default)]
+pub struct PlanPanel {
+    actions: Vec<SyncAction>,
+    statuses: Vec<Option<ActionStatus>>,
+    // Action indices sorted by group, and each action's group
+    order: Vec<usize>,
+    group_of: Vec<usize>,
+    groups: Vec<PlanGroup>,
+    finished: usize,
+    collapsed: bool,
+    space_estimate: Option<SpaceEstimate>,
+    tree: PlanFolder,
+    // Show the folder tree instead of the grouped list
+    tree_view: bool,
+}
+
+impl PlanPanel {
+    /// Replaces the shown plan. Groups come in the order their first action appears in the plan.
+    pub fn set_plan(&mut self, actions: Vec<SyncAction>) {
+        let mut group_index: HashMap<String, usize> = HashMap::new();
+        let mut names = Vec::new();
+        let mut members: Vec<Vec<usize>> = Vec::new();
+        let mut group_of = Vec::with_capacity(actions.len());
+        for (index, action) in actions.iter().enumerate() {
+            let name = top_level_folder(action.path(), is_folder_action(action));
+            let group = *group_index.entry(name.clone()).or_insert_with(|| {
+                names.push(name);
+                members.push(Vec::new());
+                members.len() - 1
+            });
+            members[group].push(index);
+            group_of.push(group);
+        }
+
+        let mut order = Vec::with_capacity(actions.len());
+        let mut groups = Vec::with_capacity(names.len());
+        // A plan with one group has nothing to fold, so it starts open
+        let single = names.len() == 1;
+        for (name, indices) in names.into_iter().zip(members) {
+            groups.push(PlanGroup { name, start: order.len(), len: indices.len(), done: 0, failed: 0, expanded: single });
+            order.extend(indices);
+        }
+
+        self.statuses = vec![None; actions.len()];
+        self.tree = build_plan_tree(&actions);
+        self.actions = actions;
+        self.order = order;
+        self.group_of = group_of;
+        self.groups = groups;
+        self.finished = 0;
+    }
+
+    /// Shows the plan's estimated effect on the USB drive's free space below the progress count.
+    pub fn set_space_estimate(&mut self, estimate: SpaceEstimate) {
+        self.space_estimate = Some(estimate);
+    }
+
+    /// Marks the action at `index` in the plan. Indices outside the plan are ignored.
+    pub fn action_finished(&mut self, index: usize, status: ActionStatus) {
+        let Some(slot) = self.statuses.get_mut(index) else { return };
+        if slot.is_some() {
+            return;
+        }
+        *slot = Some(status);
+        self.finished += 1;
+        let group = &mut self.groups[self.group_of[index]];
+        match status {
+            ActionStatus::Failed => group.failed += 1,
+            ActionStatus::Done | ActionStatus::Skipped => group.done += 1,
+        }
+    }
+
+    /// Forgets the plan, e.g. when a new run starts. The panel stays folded or open as the user left it.
+    pub fn clear(&mut self) {
+        let (collapsed, tree_view) = (self.collapsed, self.tree_view);
+        *self = Self { collapsed, tree_view, ..Self::default() };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// Shows the panel on the right of the window; nothing is shown before the first plan arrives.
+    pub fn show(&mut self, ctx: &egui::Context, palette: &Palette) {
+        if self.is_empty() {
+            return;
+        }
+        if self.collapsed {
+            egui::SidePanel::right("plan_panel_collapsed").resizable(false).exact_width(28.0).show(ctx, |ui| {
+                ui.add_space(6.0);
+                if ui.button("◀").on_hover_text("展开本次变更").clicked() {
+                    self.collapsed = false;
+                }
+            });
+            return;
+        }
+
+        egui::SidePanel::right("plan_panel").resizable(true).default_width(280.0).min_width(200.0).show(ctx, |ui| {
+            ui.add_space(6.0);
+            ui.horizontal(|ui| {
+                ui.heading("本次变更");
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui.small_button("▶").on_hover_text("收起").clicked() {
+                        self.collapsed = true;
+                    }
+                });
+            });
+            ui.horizontal(|ui| {
+                ui.label(RichText::new(format!("已完成 {} / {}", self.finished, self.actions.len())).small());
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    ui.selectable_value(&mut self.tree_view, true, "目录树").on_hover_text("按文件夹汇总，逐级展开");
+                    ui.selectable_value(&mut self.tree_view, false, "列表");
+                });
+            });
+            if let Some(estimate) = self.space_estimate {
+                let text = RichText::new(estimate.summary()).small();
+                match estimate.shortfall() {
+                    Some(shortfall) => ui.label(text.color(palette.warning)).on_hover_text(format!("U盘空间可能不足，约缺少 {}", format_size(shortfall))),
+                    None => ui.label(text.weak()),
+                };
+            }
+            ui.separator();
+            if self.tree_view {
+                egui::ScrollArea::vertical().auto_shrink([false, false]).show(ui, |ui| self.show_tree(ui, palette));
+                return;
+            }
+
+            let row_height = ui.text_style_height(&egui::TextStyle::Body) + ui.spacing().item_spacing.y;
+            let row_count: usize = self.groups.iter().map(|group| 1 + if group.expanded { group.len } else { 0 }).sum();
+            let mut toggled = None;
+            egui::ScrollArea::vertical().auto_shrink([false, false]).show_rows(ui, row_height, row_count, |ui, range| {
+                for row in range {
+                    match self.row(row) {
+                        PlanRow::Group(group_index) => {
+                            let group = &self.groups[group_index];
+                            let arrow = if group.expanded { "⏷" } else { "⏵" };
+                            let mut text = RichText::new(format!(
+                                "{} {}  {}/{}",
+                                arrow,
+                                group.name,
+                                group.done + group.failed,
+                                group.len
+                            ))
+                            .strong();
+                            if group.failed > 0 {
+                                text = text.color(palette.error);
+                            } else if group.done == group.len {
+                                text = text.color(palette.success);
+                            }
+                            if ui.add(egui::Label::new(text).truncate().sense(egui::Sense::click())).clicked() {
+                                toggled = Some(group_index);
+                            }
+                        }
+                        PlanRow::Action(index) => {
+                            let (mark, color) = self.status_mark(index, ui, palette);
+                            let action = &self.actions[index];
+                            ui.horizontal(|ui| {
+                                ui.add_space(14.0);
+                                ui.label(RichText::new(mark).color(color));
+                                ui.add(
+                                    egui::Label::new(format!("{} {}", action.label(), action.path().display()))
+                                        .truncate(),
+                                );
+                            });
+                        }
+                    }
+                }
+            });
+            if let Some(group_index) = toggled {
+                self.groups[group_index].expanded ^= true;
+            }
+        });
+    }
+
+    /// The plan as a tree of the folders it touches, each with its counts, folded until opened.
+    /// Closed folders cost nothing, so only what the user opens is laid out.
+    pub fn show_tree(&self, ui: &mut egui::Ui, palette: &Palette) {
+        for (name, folder) in &self.tree.folders {
+            self.tree_folder(ui, name, folder, name, 1, palette);
+        }
+        if !self.tree.actions.is_empty() {
+            egui::CollapsingHeader::new(format!("{}  {} 项", ROOT_GROUP, self.tree.actions.len()))
+                .id_salt("plan_tree_root")
+                .show(ui, |ui| self.tree_rows(ui, &self.tree.actions, false, palette));
+        }
+    }
+
+    fn tree_folder(&self, ui: &mut egui::Ui, name: &str, folder: &PlanFolder, id: &str, depth: usize, palette: &Palette) {
+        let header = RichText::new(format!("{}  {}", name, folder.counts.summary()));
+        egui::CollapsingHeader::new(header).id_salt(("plan_tree", id)).show(ui, |ui| {
+            if depth >= TREE_MAX_DEPTH {
+                self.tree_rows(ui, &folder.all_actions(), true, palette);
+                return;
+            }
+            for (child_name, child) in &folder.folders {
+                self.tree_folder(ui, child_name, child, &format!("{}/{}", id, child_name), depth + 1, palette);
+            }
+            self.tree_rows(ui, &folder.actions, false, palette);
+        });
+    }
+
+    // The actions of a tree folder, by file name, or by full path when they come from several folders
+    fn tree_rows(&self, ui: &mut egui::Ui, indices: &[usize], full_paths: bool, palette: &Palette) {
+        for &index in indices.iter().take(TREE_ROW_LIMIT) {
+            let (mark, color) = self.status_mark(index, ui, palette);
+            let action = &self.actions[index];
+            let path = action.path();
+            let shown = match path.file_name() {
+                Some(name) if !full_paths => name.to_string_lossy(),
+                _ => path.to_string_lossy(),
+            };
+            ui.horizontal(|ui| {
+                ui.label(RichText::new(mark).color(color));
+                ui.add(egui::Label::new(format!("{} {}", action.label(), shown)).truncate())
+                    .on_hover_text(path.display().to_string());
+            });
+        }
+        if indices.len() > TREE_ROW_LIMIT {
+            ui.label(RichText::new(format!("…还有 {} 项", indices.len() - TREE_ROW_LIMIT)).weak());
+        }
+    }
+
+    fn status_mark(&self, index: usize, ui: &egui::Ui, palette: &Palette) -> (&'static str, Color32) {
+        match self.statuses[index] {
+            None => ("○", ui.visuals().weak_text_color()),
+            Some(ActionStatus::Done) => ("✔", palette.success),
+            Some(ActionStatus::Skipped) => ("–", palette.warning),
+            Some(ActionStatus::Failed) => ("✖", palette.error),
+        }
+    }
+
+    // Which group header or action is shown on `row` of the scrolled list
+    fn row(&self, mut row: usize) -> PlanRow {
+        for (group_index, group) in self.groups.iter().enumerate() {
+            if row == 0 {
+                return PlanRow::Group(group_index);
+            }
+            row -= 1;
+            if group.expanded {
+                if row < group.len {
+                    return PlanRow::Action(self.order[group.start + row]);
+                }
+                row -= group.len;
+            }
+        }
+        PlanRow::Group(self.groups.len() - 1)
+    }
+}
+
+enum PlanRow {
+    Group(usize),
+    Action(usize),
+}
+
+// A top-level folder is listed in its own group; files directly in the sync folder share one
+fn top_level_folder(path: &Path, is_directory: bool) -> String {
+    let mut components = path.components();
+    match (components.next(), components.next()) {
+        (Some(first), Some(_)) => first.as_os_str().to_string_lossy().into_owned(),
+        (Some(first), None) if is_directory => first.as_os_str().to_string_lossy().into_owned(),
+        _ => ROOT_GROUP.to_owned(),
+    }
+}
@@ -0,0 +1,174 @@
+//! A plan folded into the folders it touches, with counts of what changes in and below each one,
+//! so a large reorganization can be read folder by folder instead of as one long list.
+
+use crate::models::SyncAction;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// What a set of actions does, counted by kind.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChangeCounts {
+    pub to_usb: usize,
+    pub to_local: usize,
+    /// Files and folders deleted on either side.
+    pub deleted: usize,
+    pub conflicts: usize,
+    /// Relocations on the USB drive.
+    pub moved: usize,
+    /// Folders created on either side.
+    pub created_folders: usize,
+}
+
+impl ChangeCounts {
+    fn add(&mut self, action: &SyncAction) {
+        match action {
+            SyncAction::LocalToRemote(_) => self.to_usb += 1,
+            SyncAction::RemoteToLocal(_) => self.to_local += 1,
+            SyncAction::DeleteLocal(_) | SyncAction::DeleteRemote(_) | SyncAction::DeleteLocalDir(_) | SyncAction::DeleteRemoteDir(_) => {
+                self.deleted += 1
+            }
+            SyncAction::Conflict { .. } => self.conflicts += 1,
+            SyncAction::MoveRemote { .. } => self.moved += 1,
+            SyncAction::CreateLocalDir(_) | SyncAction::CreateRemoteDir(_) => self.created_folders += 1,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.to_usb + self.to_local + self.deleted + self.conflicts + self.moved + self.created_folders
+    }
+
+    /// The non-zero counts, e.g. "+120 → U盘, −3 删除, 2 冲突".
+    pub fn summary(&self) -> String {
+        let parts = [
+            ("+", self.to_usb, " → U盘"),
+            ("+", self.to_local, " → 本地"),
+            ("−", self.deleted, " 删除"),
+            ("", self.conflicts, " 冲突"),
+            ("", self.moved, " 移动"),
+            ("", self.created_folders, " 新建文件夹"),
+        ];
+        parts
+            .iter()
+            .filter(|(_, count, _)| *count > 0)
+            .map(|(prefix, count, suffix)| format!("{}{}{}", prefix, count, suffix))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// A folder the plan touches. The root stands for the sync folder itself.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlanFolder {
+    /// Everything the plan does in this folder and below.
+    pub counts: ChangeCounts,
+    /// Subfolders with changes, by name.
+    pub folders: BTreeMap<String, PlanFolder>,
+    /// Plan indices of the actions on files directly in this folder and on the folder itself, in plan order.
+    pub actions: Vec<usize>,
+}
+
+impl PlanFolder {
+    /// Plan indices of every action in this folder and below, folder by folder.
+    pub fn all_actions(&self) -> Vec<usize> {
+        let mut indices = self.actions.clone();
+        for folder in self.folders.values() {
+            indices.extend(folder.all_actions());
+        }
+        indices
+    }
+}
+
+/// Groups the actions of a plan by the components of their paths. Folder actions are listed in the folder
+/// they create or delete; every other action in the folder that holds its file.
+pub fn build_plan_tree(actions: &[SyncAction]) -> PlanFolder {
+    let mut root = PlanFolder::default();
+    for (index, action) in actions.iter().enumerate() {
+        let path = action.path();
+        let folder_path = if is_folder_action(action) { Some(path) } else { path.parent() };
+        let mut folder = &mut root;
+        folder.counts.add(action);
+        for component in folder_path.unwrap_or(Path::new("")).components() {
+            let name = component.as_os_str().to_string_lossy().into_owned();
+            folder = folder.folders.entry(name).or_default();
+            folder.counts.add(action);
+        }
+        folder.actions.push(index);
+    }
+    root
+}
+
+/// Whether the action creates or deletes a folder rather than acting on a file.
+pub fn is_folder_action(action: &SyncAction) -> bool {
+    matches!(
+        action,
+        SyncAction::CreateLocalDir(_) | SyncAction::CreateRemoteDir(_) | SyncAction::DeleteLocalDir(_) | SyncAction::DeleteRemoteDir(_)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn path(path: &str) -> PathBuf {
+        PathBuf::from(path)
+    }
+
+    #[test]
+    fn counts_add_up_along_the_path() {
+        let plan = vec![
+            SyncAction::LocalToRemote(path("Photos/2024/a.jpg")),
+            SyncAction::LocalToRemote(path("Photos/2024/b.jpg")),
+            SyncAction::LocalToRemote(path("Photos/c.jpg")),
+            SyncAction::DeleteRemote(path("Photos/old.jpg")),
+            SyncAction::Conflict { path: path("Docs/notes.txt") },
+            SyncAction::RemoteToLocal(path("readme.txt")),
+        ];
+        let tree = build_plan_tree(&plan);
+
+        assert_eq!(tree.counts.total(), 6);
+        assert_eq!(tree.folders.keys().collect::<Vec<_>>(), ["Docs", "Photos"]);
+        let photos = &tree.folders["Photos"];
+        assert_eq!(photos.counts, ChangeCounts { to_usb: 3, deleted: 1, ..Default::default() });
+        assert_eq!(photos.folders["2024"].counts.to_usb, 2);
+        assert_eq!(photos.folders["2024"].actions, [0, 1]);
+        assert_eq!(photos.actions, [2, 3]);
+        assert_eq!(tree.folders["Docs"].counts.conflicts, 1);
+        // Files directly in the sync folder stay at the root
+        assert_eq!(tree.actions, [5]);
+        assert_eq!(photos.all_actions(), [2, 3, 0, 1]);
+    }
+
+    #[test]
+    fn folder_actions_belong_to_their_folder() {
+        let plan = vec![
+            SyncAction::CreateRemoteDir(path("Photos")),
+            SyncAction::CreateRemoteDir(path("Photos/2024")),
+            SyncAction::DeleteLocalDir(path("Old")),
+            SyncAction::MoveRemote { from: path("a.txt"), to: path("Archive/a.txt") },
+        ];
+        let tree = build_plan_tree(&plan);
+
+        assert!(tree.actions.is_empty());
+        assert_eq!(tree.folders["Photos"].actions, [0]);
+        assert_eq!(tree.folders["Photos"].counts.created_folders, 2);
+        assert_eq!(tree.folders["Photos"].folders["2024"].actions, [1]);
+        assert_eq!(tree.folders["Old"].counts.deleted, 1);
+        assert_eq!(tree.folders["Archive"].counts.moved, 1);
+    }
+
+    #[test]
+    fn summaries_list_the_non_zero_counts() {
+        let counts = ChangeCounts { to_usb: 120, deleted: 3, conflicts: 2, ..Default::default() };
+        assert_eq!(counts.summary(), "+120 → U盘, −3 删除, 2 冲突");
+        assert_eq!(ChangeCounts { to_local: 1, ..Default::default() }.summary(), "+1 → 本地");
+        assert_eq!(ChangeCounts::default().summary(), "");
+    }
+
+    #[test]
+    fn an_empty_plan_is_an_empty_tree() {
+        let tree = build_plan_tree(&[]);
+        assert_eq!(tree.counts.total(), 0);
+        assert!(tree.folders.is_empty() && tree.actions.is_empty());
+    }
+}
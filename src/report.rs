@@ -0,0 +1,341 @@
+//! A per-run summary written as a self-contained HTML page next to the sync record, for reading
+//! what a run did in a browser after the fact.
+//!
+//! The report is collected while the run executes and serializes to JSON as well; the page is
+//! rendered from the same structure, with its styles and the table sorting inline.
+
+use crate::error::{IoResultExt, SyncError};
+use crate::models::{ActionStatus, Resolution, SyncAction, SYNCU_VERSION};
+use crate::utils::{format_size, machine_name, BOOKKEEPING_DIR_NAME};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+const REPORT_FILE_PREFIX: &str = "report_";
+const REPORT_FILE_EXTENSION: &str = "html";
+/// Number of reports kept in a sync folder, including the newest one.
+pub const REPORT_KEEP: usize = 20;
+
+/// How long one step of a run took.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct PhaseTiming {
+    pub name: String,
+    pub seconds: f64,
+}
+
+/// A planned action and how it ended. Unset fields are left out of the JSON, which the run history keeps for many runs.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ReportedAction {
+    pub action: SyncAction,
+    /// Size of the file the action copies or deletes, as the scan saw it. None for folders and moves.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// None if the run ended before the action.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<ActionStatus>,
+    /// How a conflict was resolved, whether asked or remembered.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<Resolution>,
+    /// The error of a failed action.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ReportedAction {
+    /// How the action ended, e.g. "完成" or "未执行".
+    pub fn status_label(&self) -> &'static str {
+        status_label(self.status).0
+    }
+
+    /// The path the action works on; both paths for a move, e.g. "a.txt → Archive/a.txt".
+    pub fn path_text(&self) -> String {
+        match &self.action {
+            SyncAction::MoveRemote { from, to } => format!("{} → {}", from.display(), to.display()),
+            other => other.path().display().to_string(),
+        }
+    }
+}
+
+/// Everything the report of a run shows.
+#[derive(Serialize, Clone, Debug)]
+pub struct RunReport {
+    pub version: String,
+    pub machine: String,
+    /// Local time the run started, e.g. "2024-05-01 14:03:12".
+    pub started: String,
+    pub local_folder: Option<PathBuf>,
+    pub usb_sync_path: Option<PathBuf>,
+    /// How the run ended, e.g. "完成"; empty until it has.
+    pub outcome: String,
+    /// The error that ended the run early, if any.
+    pub error: Option<String>,
+    pub phases: Vec<PhaseTiming>,
+    pub actions: Vec<ReportedAction>,
+    #[serde(skip)]
+    current_phase: Option<(String, Instant)>,
+    #[serde(skip)]
+    file_stamp: String,
+}
+
+impl RunReport {
+    /// Starts the report of a run beginning now, timing its first phase.
+    pub fn start() -> Self {
+        let now = Local::now();
+        Self {
+            version: SYNCU_VERSION.to_string(),
+            machine: machine_name(),
+            started: now.format("%Y-%m-%d %H:%M:%S").to_string(),
+            local_folder: None,
+            usb_sync_path: None,
+            outcome: String::new(),
+            error: None,
+            phases: Vec::new(),
+            actions: Vec::new(),
+            current_phase: Some(("准备".to_string(), Instant::now())),
+            file_stamp: now.format("%Y-%m-%d_%H%M%S").to_string(),
+        }
+    }
+
+    /// Ends the phase in progress and starts timing `name`.
+    pub fn start_phase(&mut self, name: &str) {
+        self.end_phase();
+        self.current_phase = Some((name.to_string(), Instant::now()));
+    }
+
+    fn end_phase(&mut self) {
+        if let Some((name, started)) = self.current_phase.take() {
+            self.phases.push(PhaseTiming { name, seconds: started.elapsed().as_secs_f64() });
+        }
+    }
+
+    /// Lists the planned actions with the size of what each one copies or deletes.
+    pub fn set_plan(&mut self, plan: impl IntoIterator<Item = (SyncAction, Option<u64>)>) {
+        self.actions = plan
+            .into_iter()
+            .map(|(action, size)| ReportedAction { action, size, status: None, resolution: None, error: None })
+            .collect();
+    }
+
+    /// Records how the action at `index` in the plan ended.
+    pub fn finish_action(&mut self, index: usize, status: ActionStatus, resolution: Option<Resolution>, error: Option<String>) {
+        if let Some(action) = self.actions.get_mut(index) {
+            action.status = Some(status);
+            action.resolution = resolution;
+            action.error = error;
+        }
+    }
+
+    /// Ends the last phase and records the outcome.
+    pub fn finish(&mut self, outcome: &str) {
+        self.end_phase();
+        self.outcome = outcome.to_string();
+    }
+
+    /// File name of the page, e.g. "report_2024-05-01_140312.html".
+    pub fn file_name(&self) -> String {
+        format!("{}{}.{}", REPORT_FILE_PREFIX, self.file_stamp, REPORT_FILE_EXTENSION)
+    }
+
+    fn count(&self, status: ActionStatus) -> usize {
+        self.actions.iter().filter(|action| action.status == Some(status)).count()
+    }
+}
+
+/// Escapes text for use in HTML element content and quoted attribute values.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn status_label(status: Option<ActionStatus>) -> (&'static str, &'static str) {
+    match status {
+        Some(ActionStatus::Done) => ("完成", "done"),
+        Some(ActionStatus::Skipped) => ("跳过", "skipped"),
+        Some(ActionStatus::Failed) => ("失败", "failed"),
+        None => ("未执行", "pending"),
+    }
+}
+
+fn resolution_label(resolution: Option<&Resolution>) -> &'static str {
+    match resolution {
+        Some(Resolution::KeepLocal) => "采用本地",
+        Some(Resolution::KeepRemote) => "采用U盘",
+        Some(Resolution::Skip) => "跳过",
+        None => "未处理",
+    }
+}
+
+const STYLE: &str = "body{font-family:\"Microsoft YaHei\",\"PingFang SC\",sans-serif;margin:2em;color:#222;background:#fafafa}\
+h1{font-size:1.5em}h2{font-size:1.2em;margin-top:1.5em}\
+table{border-collapse:collapse;background:#fff}th,td{border:1px solid #ddd;padding:4px 8px;text-align:left}\
+th{background:#eee}table.sortable th{cursor:pointer}td.num{text-align:right}\
+tr.failed td{background:#fde8e8}tr.skipped td{color:#777}tr.pending td{color:#999}\
+.error{color:#b00020;font-weight:bold}dl{display:grid;grid-template-columns:max-content auto;gap:2px 12px}dt{font-weight:bold}";
+
+// Sorts a table by the clicked column, by data-sort when present, numbers numerically; a second click reverses
+const SCRIPT: &str = "document.querySelectorAll('table.sortable th').forEach(function(th,col){th.addEventListener('click',function(){\
+var body=th.closest('table').tBodies[0];var rows=Array.from(body.rows);var asc=th.dataset.dir!=='asc';th.dataset.dir=asc?'asc':'desc';\
+var key=function(row){var cell=row.cells[col];var v=cell.dataset.sort!==undefined?cell.dataset.sort:cell.textContent;var n=parseFloat(v);return isNaN(n)?v:n;};\
+rows.sort(function(a,b){var x=key(a),y=key(b);var r=(typeof x==='number'&&typeof y==='number')?x-y:String(x).localeCompare(String(y));return asc?r:-r;});\
+rows.forEach(function(row){body.appendChild(row);});});});";
+
+/// Renders the report as a complete HTML page without external references.
+pub fn render_html(report: &RunReport) -> String {
+    let mut html = String::new();
+    let path_text = |path: &Option<PathBuf>| path.as_ref().map_or_else(|| "-".to_string(), |path| escape_html(&path.display().to_string()));
+    // Writing to a String never fails
+    let _ = writeln!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"zh-CN\">\n<head>\n<meta charset=\"utf-8\">\n<title>SyncU 同步报告 {}</title>\n<style>{}</style>\n</head>\n<body>",
+        escape_html(&report.started),
+        STYLE
+    );
+    html.push_str("<h1>SyncU 同步报告</h1>\n<dl>\n");
+    for (label, value) in [
+        ("开始时间", escape_html(&report.started)),
+        ("本地文件夹", path_text(&report.local_folder)),
+        ("U盘文件夹", path_text(&report.usb_sync_path)),
+        ("计算机", escape_html(&report.machine)),
+        ("版本", escape_html(&report.version)),
+        ("结果", escape_html(&report.outcome)),
+    ] {
+        let _ = writeln!(html, "<dt>{}</dt><dd>{}</dd>", label, value);
+    }
+    html.push_str("</dl>\n");
+    if let Some(error) = &report.error {
+        let _ = writeln!(html, "<p class=\"error\">{}</p>", escape_html(error));
+    }
+    let _ = writeln!(
+        html,
+        "<p>共 {} 个操作: 完成 {} · 跳过 {} · 失败 {} · 未执行 {}</p>",
+        report.actions.len(),
+        report.count(ActionStatus::Done),
+        report.count(ActionStatus::Skipped),
+        report.count(ActionStatus::Failed),
+        report.actions.iter().filter(|action| action.status.is_none()).count()
+    );
+
+    html.push_str("<h2>各阶段用时</h2>\n<table>\n<thead><tr><th>阶段</th><th>用时 (秒)</th></tr></thead>\n<tbody>\n");
+    for phase in &report.phases {
+        let _ = writeln!(html, "<tr><td>{}</td><td class=\"num\">{:.1}</td></tr>", escape_html(&phase.name), phase.seconds);
+    }
+    html.push_str("</tbody>\n</table>\n");
+
+    let failures: Vec<&ReportedAction> = report.actions.iter().filter(|action| action.status == Some(ActionStatus::Failed)).collect();
+    if !failures.is_empty() {
+        html.push_str("<h2 class=\"error\">失败</h2>\n<table>\n<thead><tr><th>路径</th><th>错误</th></tr></thead>\n<tbody>\n");
+        for action in failures {
+            let _ = writeln!(
+                html,
+                "<tr class=\"failed\"><td>{}</td><td>{}</td></tr>",
+                escape_html(&action.action.path().display().to_string()),
+                escape_html(action.error.as_deref().unwrap_or(""))
+            );
+        }
+        html.push_str("</tbody>\n</table>\n");
+    }
+
+    let conflicts: Vec<&ReportedAction> = report.actions.iter().filter(|action| matches!(action.action, SyncAction::Conflict { .. })).collect();
+    if !conflicts.is_empty() {
+        html.push_str("<h2>冲突</h2>\n<table>\n<thead><tr><th>路径</th><th>处理方式</th></tr></thead>\n<tbody>\n");
+        for action in conflicts {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td></tr>",
+                escape_html(&action.action.path().display().to_string()),
+                resolution_label(action.resolution.as_ref())
+            );
+        }
+        html.push_str("</tbody>\n</table>\n");
+    }
+
+    html.push_str("<h2>全部操作</h2>\n");
+    if report.actions.is_empty() {
+        html.push_str("<p>未检测到变化。</p>\n");
+    } else {
+        html.push_str(
+            "<table class=\"sortable\">\n<thead><tr><th>#</th><th>操作</th><th>路径</th><th>大小</th><th>状态</th></tr></thead>\n<tbody>\n",
+        );
+        for (index, action) in report.actions.iter().enumerate() {
+            let (status, class) = status_label(action.status);
+            let path = action.path_text();
+            let _ = writeln!(
+                html,
+                "<tr class=\"{}\"><td class=\"num\">{}</td><td>{}</td><td>{}</td><td class=\"num\" data-sort=\"{}\">{}</td><td>{}</td></tr>",
+                class,
+                index + 1,
+                action.action.label(),
+                escape_html(&path),
+                action.size.unwrap_or(0),
+                action.size.map_or_else(String::new, format_size),
+                status
+            );
+        }
+        html.push_str("</tbody>\n</table>\n");
+    }
+    let _ = writeln!(html, "<script>{}</script>\n</body>\n</html>", SCRIPT);
+    html
+}
+
+fn reports_folder(usb_sync_path: &Path) -> PathBuf {
+    usb_sync_path.join(BOOKKEEPING_DIR_NAME)
+}
+
+fn is_report(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == REPORT_FILE_EXTENSION)
+        && path.file_name().is_some_and(|name| name.to_string_lossy().starts_with(REPORT_FILE_PREFIX))
+}
+
+/// The reports in a sync folder, oldest first. Their names sort by the time of the run.
+pub fn list_reports(usb_sync_path: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(reports_folder(usb_sync_path)) else { return Vec::new() };
+    let mut reports: Vec<PathBuf> = entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).filter(|path| is_report(path)).collect();
+    reports.sort();
+    reports
+}
+
+/// The report of the most recent run in a sync folder, if any.
+pub fn latest_report(usb_sync_path: &Path) -> Option<PathBuf> {
+    list_reports(usb_sync_path).pop()
+}
+
+/// Writes the page into the bookkeeping folder of the sync folder, which must exist, and removes the oldest
+/// reports beyond `REPORT_KEEP`. Returns the path of the page.
+pub fn write_html_report(report: &RunReport, usb_sync_path: &Path) -> Result<PathBuf, SyncError> {
+    let path = reports_folder(usb_sync_path).join(report.file_name());
+    fs::write(&path, render_html(report)).at(&path)?;
+    let reports = list_reports(usb_sync_path);
+    for old in &reports[..reports.len().saturating_sub(REPORT_KEEP)] {
+        let _ = fs::remove_file(old);
+    }
+    Ok(path)
+}
+
+/// A `file://` URL for opening a local file in the browser. Characters that would end or confuse the URL are percent-encoded.
+pub fn file_url(path: &Path) -> String {
+    let text = path.display().to_string().replace('\\', "/");
+    let mut url = String::from(if text.starts_with('/') { "file://" } else { "file:///" });
+    for c in text.chars() {
+        match c {
+            ' ' => url.push_str("%20"),
+            '#' => url.push_str("%23"),
+            '%' => url.push_str("%25"),
+            '?' => url.push_str("%3F"),
+            c => url.push(c),
+        }
+    }
+    url
+}
@@ -0,0 +1,71 @@
+use crate::utils::app_data_dir;
+use chrono::Local;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const SESSION_LOG_FILE_NAME: &str = "last_session.log";
+/// Number of sessions kept, including the current one.
+const SESSION_LOG_KEEP: usize = 5;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+fn session_log_path(generation: usize) -> PathBuf {
+    let dir = app_data_dir();
+    if generation == 0 {
+        dir.join(SESSION_LOG_FILE_NAME)
+    } else {
+        dir.join(format!("last_session.{}.log", generation))
+    }
+}
+
+/// Mirrors the in-app log to a file in the app data directory, so it survives a crash or a quick exit.
+pub struct SessionLog {
+    writer: Option<BufWriter<File>>,
+    last_flush: Instant,
+    dirty: bool,
+}
+
+impl SessionLog {
+    /// Rotates older session logs and starts a new one. Logging is disabled if the file can't be created.
+    pub fn start() -> Self {
+        let writer = (|| -> io::Result<BufWriter<File>> {
+            fs::create_dir_all(app_data_dir())?;
+            for generation in (1..SESSION_LOG_KEEP).rev() {
+                let from = session_log_path(generation - 1);
+                if from.exists() {
+                    fs::rename(&from, session_log_path(generation))?;
+                }
+            }
+            Ok(BufWriter::new(File::create(session_log_path(0))?))
+        })()
+        .ok();
+        Self { writer, last_flush: Instant::now(), dirty: false }
+    }
+
+    /// Buffers a line; it reaches the disk on the next periodic flush.
+    pub fn append(&mut self, line: &str) {
+        if let Some(writer) = &mut self.writer
+            && writeln!(writer, "{} {}", Local::now().format("%Y-%m-%d %H:%M:%S"), line).is_ok()
+        {
+            self.dirty = true;
+        }
+    }
+
+    /// Flushes buffered lines at most once per second. Returns true while lines are still waiting.
+    pub fn flush_if_due(&mut self) -> bool {
+        if self.dirty && self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            if let Some(writer) = &mut self.writer {
+                let _ = writer.flush();
+            }
+            self.last_flush = Instant::now();
+            self.dirty = false;
+        }
+        self.dirty
+    }
+
+    /// Reads the log of the session before this one.
+    pub fn read_previous() -> io::Result<String> {
+        fs::read_to_string(session_log_path(1))
+    }
+}
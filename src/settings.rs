@@ -0,0 +1,634 @@
+use crate::models::{LogLevel, Resolution};
+use crate::utils::{app_data_dir, comparable_path, glob_match, TEXT_EXTENSIONS};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+const SETTINGS_FILE_NAME: &str = "settings.json";
+
+/// Places files matching a glob pattern under a destination prefix on the USB drive.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RoutingRule {
+    pub pattern: String,
+    pub destination: PathBuf,
+}
+
+/// A remembered answer for conflicts on one file, given as its path relative to the local folder.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConflictRule {
+    pub path: PathBuf,
+    pub resolution: Resolution,
+}
+
+/// The answer an answer rule gives to deletion prompts.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeletionAnswer {
+    /// Delete the file or folder.
+    Confirm,
+    /// Keep it this time.
+    Skip,
+    /// Keep it and don't ask again until it changes; folders are kept this time only.
+    Keep,
+    /// Ask, even if a later rule would answer.
+    Ask,
+}
+
+impl DeletionAnswer {
+    pub const ALL: [DeletionAnswer; 4] = [DeletionAnswer::Confirm, DeletionAnswer::Skip, DeletionAnswer::Keep, DeletionAnswer::Ask];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DeletionAnswer::Confirm => "删除",
+            DeletionAnswer::Skip => "本次保留",
+            DeletionAnswer::Keep => "保留且不再询问",
+            DeletionAnswer::Ask => "询问",
+        }
+    }
+}
+
+/// The answer an answer rule gives to conflicts.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictAnswer {
+    Local,
+    Remote,
+    Skip,
+    /// Ask, even if a later rule would answer.
+    Ask,
+}
+
+impl ConflictAnswer {
+    pub const ALL: [ConflictAnswer; 4] = [ConflictAnswer::Local, ConflictAnswer::Remote, ConflictAnswer::Skip, ConflictAnswer::Ask];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ConflictAnswer::Local => "采用本地版本",
+            ConflictAnswer::Remote => "采用U盘版本",
+            ConflictAnswer::Skip => "跳过",
+            ConflictAnswer::Ask => "询问",
+        }
+    }
+
+    /// The resolution it stands for; None for `Ask`.
+    pub fn resolution(&self) -> Option<Resolution> {
+        match self {
+            ConflictAnswer::Local => Some(Resolution::KeepLocal),
+            ConflictAnswer::Remote => Some(Resolution::KeepRemote),
+            ConflictAnswer::Skip => Some(Resolution::Skip),
+            ConflictAnswer::Ask => None,
+        }
+    }
+}
+
+/// Answers deletion prompts and conflicts for the paths matching a glob pattern, relative to the local folder,
+/// e.g. `{"match": "Old Projects/**", "on_delete": "confirm"}`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct AnswerRule {
+    #[serde(rename = "match")]
+    pub pattern: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_delete: Option<DeletionAnswer>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_conflict: Option<ConflictAnswer>,
+}
+
+impl AnswerRule {
+    /// Why the rule can't be used, if it can't.
+    pub fn problem(&self) -> Option<String> {
+        if self.on_delete.is_none() && self.on_conflict.is_none() {
+            return Some("没有指定 on_delete 或 on_conflict".to_owned());
+        }
+        let pattern = self.pattern.trim().replace('\\', "/");
+        if pattern.is_empty() {
+            return Some("匹配模式为空".to_owned());
+        }
+        if pattern.starts_with('/') || pattern.contains(':') {
+            return Some(format!("「{}」不是相对于本地文件夹的路径", self.pattern));
+        }
+        for component in pattern.split('/') {
+            if component == ".." {
+                return Some(format!("「{}」不能包含「..」", self.pattern));
+            }
+            if component.contains("**") && component != "**" {
+                return Some(format!("「{}」中的「**」必须单独作为一级路径，如「a/**/b.txt」", self.pattern));
+            }
+        }
+        None
+    }
+}
+
+/// The rules of a rules file, in order; for each kind of prompt the first matching rule that answers it decides.
+/// Prompts no rule answers are asked as usual, or left alone in unattended runs.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(transparent)]
+pub struct AnswerRules(pub Vec<AnswerRule>);
+
+impl AnswerRules {
+    /// Reads a rules file, a JSON list of rules, and checks every rule.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let rules = Self::read(path)?;
+        rules.check()?;
+        Ok(rules)
+    }
+
+    /// Reads a rules file without checking the rules, e.g. to fix them in the editor.
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| e.to_string())?;
+        serde_json::from_reader(BufReader::new(file)).map_err(|e| format!("格式错误: {}", e))
+    }
+
+    /// Writes the rules as pretty-printed JSON, after checking them.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        self.check()?;
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, json).map_err(|e| e.to_string())
+    }
+
+    /// The first unusable rule, with its number counted from 1.
+    pub fn check(&self) -> Result<(), String> {
+        match self.0.iter().enumerate().find_map(|(index, rule)| rule.problem().map(|problem| (index, problem))) {
+            Some((index, problem)) => Err(format!("规则 #{}: {}", index + 1, problem)),
+            None => Ok(()),
+        }
+    }
+
+    /// The rule answering a deletion of `path`, with its number, unless it says to ask.
+    pub fn deletion_answer(&self, path: &Path) -> Option<(usize, &AnswerRule, DeletionAnswer)> {
+        let (index, rule) = self.0.iter().enumerate().find(|(_, rule)| rule.on_delete.is_some() && glob_match(&rule.pattern, path))?;
+        rule.on_delete.filter(|answer| *answer != DeletionAnswer::Ask).map(|answer| (index + 1, rule, answer))
+    }
+
+    /// The rule answering a conflict on `path`, with its number, unless it says to ask.
+    pub fn conflict_answer(&self, path: &Path) -> Option<(usize, &AnswerRule, Resolution)> {
+        let (index, rule) = self.0.iter().enumerate().find(|(_, rule)| rule.on_conflict.is_some() && glob_match(&rule.pattern, path))?;
+        rule.on_conflict.and_then(|answer| answer.resolution()).map(|resolution| (index + 1, rule, resolution))
+    }
+}
+
+/// What to do with a file that another program has open for writing.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum InUsePolicy {
+    #[default]
+    CopyAndWarn,
+    Skip,
+    Ask,
+}
+
+impl InUsePolicy {
+    pub const ALL: [InUsePolicy; 3] = [InUsePolicy::CopyAndWarn, InUsePolicy::Skip, InUsePolicy::Ask];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            InUsePolicy::CopyAndWarn => "复制并警告",
+            InUsePolicy::Skip => "跳过",
+            InUsePolicy::Ask => "询问",
+        }
+    }
+}
+
+/// What a one-way copy does when the file it would overwrite is newer than its source.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum NewerDestinationPolicy {
+    #[default]
+    SkipAndWarn,
+    Ask,
+    Overwrite,
+}
+
+impl NewerDestinationPolicy {
+    pub const ALL: [NewerDestinationPolicy; 3] =
+        [NewerDestinationPolicy::SkipAndWarn, NewerDestinationPolicy::Ask, NewerDestinationPolicy::Overwrite];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            NewerDestinationPolicy::SkipAndWarn => "跳过并警告",
+            NewerDestinationPolicy::Ask => "询问",
+            NewerDestinationPolicy::Overwrite => "仍然覆盖",
+        }
+    }
+}
+
+/// What a copy does when the file it overwrites has other hard links, e.g. in a snapshot of a backup drive.
+/// Writing into such a file would change every path linked to it.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum HardLinkPolicy {
+    /// Remove the destination first, so the copy gets its own file and the other links keep the old content.
+    #[default]
+    BreakLink,
+    /// Write into the shared file and log a warning.
+    Warn,
+}
+
+impl HardLinkPolicy {
+    pub const ALL: [HardLinkPolicy; 2] = [HardLinkPolicy::BreakLink, HardLinkPolicy::Warn];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            HardLinkPolicy::BreakLink => "先断开链接再写入",
+            HardLinkPolicy::Warn => "直接写入并警告",
+        }
+    }
+}
+
+/// What the log on the USB drive records. The log in the app always shows everything.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum DeviceLogVerbosity {
+    #[default]
+    Full,
+    /// Only decisions, counts, errors and the completion of each run; no file names of synced files.
+    Summary,
+    Off,
+}
+
+impl DeviceLogVerbosity {
+    pub const ALL: [DeviceLogVerbosity; 3] = [DeviceLogVerbosity::Full, DeviceLogVerbosity::Summary, DeviceLogVerbosity::Off];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DeviceLogVerbosity::Full => "完整",
+            DeviceLogVerbosity::Summary => "摘要",
+            DeviceLogVerbosity::Off => "关闭",
+        }
+    }
+
+    /// Whether an entry of the given level is written.
+    pub fn records(&self, level: LogLevel) -> bool {
+        match self {
+            DeviceLogVerbosity::Full => true,
+            DeviceLogVerbosity::Summary => level == LogLevel::Summary,
+            DeviceLogVerbosity::Off => false,
+        }
+    }
+}
+
+/// Which version is kept when a conflict turns out to differ only in line endings.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum LineEndingPolicy {
+    #[default]
+    KeepNewer,
+    KeepLocal,
+    KeepUsb,
+}
+
+impl LineEndingPolicy {
+    pub const ALL: [LineEndingPolicy; 3] = [LineEndingPolicy::KeepNewer, LineEndingPolicy::KeepLocal, LineEndingPolicy::KeepUsb];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LineEndingPolicy::KeepNewer => "保留较新版本",
+            LineEndingPolicy::KeepLocal => "保留本地版本",
+            LineEndingPolicy::KeepUsb => "保留U盘版本",
+        }
+    }
+}
+
+/// Options that apply to a single local folder.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Profile {
+    pub local_folder: PathBuf,
+    /// Volume of the drive the folder was last synced to, which is selected for it under whatever letter it has.
+    pub usb_volume_id: Option<String>,
+    /// Evaluated in order; the first matching rule decides the destination.
+    pub routing_rules: Vec<RoutingRule>,
+    pub in_use_policy: InUsePolicy,
+    /// Ask before propagating deletions when many known files vanished from the USB drive.
+    pub safety_check: bool,
+    /// Percentage of known USB files that may go missing before the safety check triggers.
+    pub safety_threshold_percent: u32,
+    /// Answer applied to every deletion prompt; None asks each time.
+    pub default_deletion_choice: Option<bool>,
+    /// Answer applied to every conflict; None asks each time.
+    pub default_conflict_resolution: Option<Resolution>,
+    /// Answers for conflicts on single files. They take precedence over the default and only apply when the file conflicts.
+    pub conflict_rules: Vec<ConflictRule>,
+    /// A rules file answering deletion prompts and conflicts by path, consulted after `conflict_rules`.
+    /// A file that can't be read or has an unusable rule stops the run before anything changes.
+    pub answer_rules_file: Option<PathBuf>,
+    /// Minutes a deletion or conflict prompt may wait for an answer before it is skipped; None waits indefinitely.
+    pub prompt_timeout_minutes: Option<u32>,
+    /// Restore files that became empty on one side from the intact copy on the other, also when both sides changed.
+    pub repair_truncated_files: bool,
+    /// Optional second folder that receives the same local state after each sync.
+    pub secondary_destination: Option<PathBuf>,
+    /// Applies when the backup copy would overwrite a file that is newer than the local one.
+    pub newer_destination_policy: NewerDestinationPolicy,
+    /// Applies when a copy would overwrite a file that has other hard links, on either side or the backup target.
+    pub hard_link_policy: HardLinkPolicy,
+    /// Write every declined deletion to the log on the USB drive instead of one summary line per group.
+    pub detailed_device_log: bool,
+    /// How much goes into the log on the USB drive, e.g. less for a drive that is handed to others.
+    pub device_log_verbosity: DeviceLogVerbosity,
+    /// Keep metadata, log and trash in a hidden `.syncu` folder instead of the root of the USB sync folder.
+    pub bookkeeping_subfolder: bool,
+    /// Hash only one of several same-size files with identical content; the others reuse its hash after a byte comparison.
+    pub dedupe_scan: bool,
+    /// Highest copy speed in MB/s, e.g. for a backup target on a shared network; None copies at full speed.
+    pub rate_limit_mb_per_sec: Option<f32>,
+    /// Ask before a sync leaves a folder on the USB drive with more entries than this; FAT limits are checked regardless.
+    pub directory_entry_soft_limit: Option<u32>,
+    /// Copy NTFS alternate data streams (Windows) or user extended attributes (Unix) along with file contents.
+    /// FAT and exFAT drives can't store them, so runs against such drives go without.
+    pub copy_extended_attributes: bool,
+    /// After a completed run, compare the saved record with a quick stat of both sides to catch files changed meanwhile.
+    pub check_after_sync: bool,
+    /// Settle conflicts between small text files that only differ in CRLF/LF line endings by copying one side unchanged.
+    pub resolve_line_ending_conflicts: bool,
+    pub line_ending_policy: LineEndingPolicy,
+    /// Larger files are never compared for line endings.
+    pub line_ending_max_kb: u32,
+    /// Lowercase extensions, without the dot, of the files compared for line endings.
+    pub line_ending_extensions: Vec<String>,
+    /// Point out plans that copy many small files to the USB drive; "不再提示" turns it off for the folder.
+    pub small_files_hint: bool,
+    /// After each run, write an HTML report of it into the `.syncu` folder; needs `bookkeeping_subfolder`.
+    pub html_report: bool,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            local_folder: PathBuf::new(),
+            usb_volume_id: None,
+            routing_rules: Vec::new(),
+            in_use_policy: InUsePolicy::default(),
+            safety_check: true,
+            safety_threshold_percent: 20,
+            default_deletion_choice: None,
+            default_conflict_resolution: None,
+            conflict_rules: Vec::new(),
+            answer_rules_file: None,
+            prompt_timeout_minutes: None,
+            repair_truncated_files: true,
+            secondary_destination: None,
+            newer_destination_policy: NewerDestinationPolicy::default(),
+            hard_link_policy: HardLinkPolicy::default(),
+            detailed_device_log: false,
+            device_log_verbosity: DeviceLogVerbosity::default(),
+            bookkeeping_subfolder: false,
+            dedupe_scan: false,
+            rate_limit_mb_per_sec: None,
+            directory_entry_soft_limit: None,
+            copy_extended_attributes: false,
+            check_after_sync: true,
+            resolve_line_ending_conflicts: false,
+            line_ending_policy: LineEndingPolicy::default(),
+            line_ending_max_kb: 1024,
+            line_ending_extensions: TEXT_EXTENSIONS.iter().map(|extension| extension.to_string()).collect(),
+            small_files_hint: true,
+            html_report: false,
+        }
+    }
+}
+
+impl Profile {
+    /// The rate limit in bytes per second, as the sync engine takes it.
+    pub fn rate_limit_bytes(&self) -> Option<u64> {
+        self.rate_limit_mb_per_sec.map(mb_per_sec_to_bytes)
+    }
+
+    /// The remembered answer for a conflict on `path`, if any.
+    pub fn conflict_rule_for(&self, path: &Path) -> Option<&Resolution> {
+        self.conflict_rules.iter().find(|rule| rule.path == path).map(|rule| &rule.resolution)
+    }
+
+    /// Remembers `resolution` for conflicts on `path`, replacing an earlier answer.
+    pub fn set_conflict_rule(&mut self, path: &Path, resolution: Resolution) {
+        self.conflict_rules.retain(|rule| rule.path != path);
+        self.conflict_rules.push(ConflictRule { path: path.to_path_buf(), resolution });
+        self.conflict_rules.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+
+    /// Whether a conflict on `path` of at most `size` bytes may be settled when only line endings differ.
+    pub fn compares_line_endings(&self, path: &Path, size: u64) -> bool {
+        self.resolve_line_ending_conflicts
+            && size <= u64::from(self.line_ending_max_kb) * 1024
+            && path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| self.line_ending_extensions.iter().any(|allowed| allowed.eq_ignore_ascii_case(extension)))
+    }
+}
+
+/// Converts a speed in MB/s, as shown to the user, to bytes per second.
+pub fn mb_per_sec_to_bytes(mb_per_sec: f32) -> u64 {
+    (f64::from(mb_per_sec) * 1024.0 * 1024.0).max(1.0) as u64
+}
+
+/// How another profile's folders overlap those of a local folder about to be synced.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProfileOverlap {
+    /// The other profile's local folder lies inside this one.
+    Contains(PathBuf),
+    /// This local folder lies inside the other profile's.
+    Inside(PathBuf),
+    /// The other local folder has the same name, so both sync into one folder on the USB drive.
+    SameUsbFolder(PathBuf),
+}
+
+impl ProfileOverlap {
+    pub fn local_folder(&self) -> &Path {
+        match self {
+            ProfileOverlap::Contains(path) | ProfileOverlap::Inside(path) | ProfileOverlap::SameUsbFolder(path) => path,
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            ProfileOverlap::Contains(_) => "位于本文件夹内",
+            ProfileOverlap::Inside(_) => "包含本文件夹",
+            ProfileOverlap::SameUsbFolder(_) => "与本文件夹同名，会同步到U盘上的同一文件夹",
+        }
+    }
+}
+
+/// Application settings persisted in the app data directory.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Settings {
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+    /// RGB of the accent used for selections, the progress bar and the sync button; None keeps the theme's own.
+    #[serde(default)]
+    pub accent_color: Option<[u8; 3]>,
+    /// Whether the first-run guide was completed or skipped. Only a missing settings file starts it;
+    /// settings saved before the guide existed count as done.
+    #[serde(default = "guide_done_for_existing_settings")]
+    pub onboarding_done: bool,
+    /// Loopback port streaming the progress of each run to external monitors; None keeps it closed.
+    #[serde(default)]
+    pub monitor_port: Option<u16>,
+    #[serde(default)]
+    pub history_retention: HistoryRetention,
+}
+
+/// How much of the run history in the app data directory is kept. The newest run is kept regardless.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct HistoryRetention {
+    pub max_runs: usize,
+    /// Total size of the history files.
+    pub max_megabytes: u64,
+}
+
+impl Default for HistoryRetention {
+    fn default() -> Self {
+        Self { max_runs: 50, max_megabytes: 200 }
+    }
+}
+
+fn guide_done_for_existing_settings() -> bool {
+    true
+}
+
+impl Settings {
+    /// Loads the settings file, falling back to defaults if it is missing.
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        let path = app_data_dir().join(SETTINGS_FILE_NAME);
+        if !path.exists() {
+            return Ok(Settings::default());
+        }
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Writes the settings file, creating the app data directory if needed.
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let dir = app_data_dir();
+        fs::create_dir_all(&dir)?;
+        let file = File::create(dir.join(SETTINGS_FILE_NAME))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Lists the other profiles whose local folder contains or lies inside `local_folder`, or that share its name
+    /// and so its folder on the USB drive. Names are compared without case, as USB drives usually do.
+    pub fn overlapping_profiles(&self, local_folder: &Path) -> Vec<ProfileOverlap> {
+        let this = comparable_path(local_folder);
+        let name = |path: &Path| path.file_name().map(|name| name.to_string_lossy().to_lowercase());
+        self.profiles
+            .iter()
+            .filter(|profile| !profile.local_folder.as_os_str().is_empty())
+            .filter_map(|profile| {
+                let other = comparable_path(&profile.local_folder);
+                let folder = profile.local_folder.clone();
+                if other == this {
+                    None
+                } else if other.starts_with(&this) {
+                    Some(ProfileOverlap::Contains(folder))
+                } else if this.starts_with(&other) {
+                    Some(ProfileOverlap::Inside(folder))
+                } else if name(&other).is_some() && name(&other) == name(&this) {
+                    Some(ProfileOverlap::SameUsbFolder(folder))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the profile for a local folder, or a default one if none was saved.
+    pub fn profile_for(&self, local_folder: &Path) -> Profile {
+        self.profiles
+            .iter()
+            .find(|p| p.local_folder == local_folder)
+            .cloned()
+            .unwrap_or_else(|| Profile {
+                local_folder: local_folder.to_path_buf(),
+                ..Default::default()
+            })
+    }
+
+    /// Returns a mutable profile for a local folder, creating it if needed.
+    pub fn profile_mut_for(&mut self, local_folder: &Path) -> &mut Profile {
+        let index = match self.profiles.iter().position(|p| p.local_folder == local_folder) {
+            Some(index) => index,
+            None => {
+                self.profiles.push(Profile {
+                    local_folder: local_folder.to_path_buf(),
+                    ..Default::default()
+                });
+                self.profiles.len() - 1
+            }
+        };
+        &mut self.profiles[index]
+    }
+}
+
+/// Extension of the files that open SyncU with a sync pair selected.
+pub const LAUNCH_FILE_EXTENSION: &str = "syncu";
+
+/// A small `.syncu` file that opens SyncU with a sync pair selected, e.g.
+/// `{"local_folder": "D:\\Documents", "usb_drive": "E:\\", "auto": true}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LaunchFile {
+    /// A relative folder is taken from where the launch file lies, so a file next to its folder can move with it.
+    pub local_folder: PathBuf,
+    /// Root of the drive to select; without it the only detected drive is used, as at any start.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usb_drive: Option<PathBuf>,
+    /// Volume of that drive, which finds it again after its letter changed; see `UsbDrive::volume_id`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usb_volume_id: Option<String>,
+    /// Start the sync right away instead of only selecting the pair.
+    #[serde(default)]
+    pub auto: bool,
+}
+
+impl LaunchFile {
+    /// Reads a launch file and resolves its local folder, which must exist.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut launch: LaunchFile = serde_json::from_reader(reader)?;
+        if launch.local_folder.is_relative() {
+            launch.local_folder = path.parent().unwrap_or(Path::new("")).join(&launch.local_folder);
+        }
+        if !launch.local_folder.is_dir() {
+            return Err(format!("本地文件夹不存在: {}", launch.local_folder.display()).into());
+        }
+        Ok(launch)
+    }
+
+    /// Writes the launch file, e.g. for a pair set up in the window.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        serde_json::to_writer_pretty(File::create(path)?, self)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(folders: &[&str]) -> Settings {
+        Settings {
+            profiles: folders.iter().map(|folder| Profile { local_folder: PathBuf::from(folder), ..Default::default() }).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn nested_folders_overlap_either_way() {
+        let settings = settings(&["/data/projects", "/home/user"]);
+
+        assert_eq!(settings.overlapping_profiles(Path::new("/data")), vec![ProfileOverlap::Contains(PathBuf::from("/data/projects"))]);
+        assert_eq!(settings.overlapping_profiles(Path::new("/home/user/docs")), vec![ProfileOverlap::Inside(PathBuf::from("/home/user"))]);
+    }
+
+    #[test]
+    fn the_folder_itself_and_unrelated_ones_do_not_overlap() {
+        let settings = settings(&["/data/projects", "/data/project-archive"]);
+
+        assert!(settings.overlapping_profiles(Path::new("/data/projects/")).is_empty());
+        assert!(settings.overlapping_profiles(Path::new("/data/./projects")).is_empty());
+        assert!(settings.overlapping_profiles(Path::new("/data/other")).is_empty());
+    }
+
+    #[test]
+    fn folders_of_the_same_name_share_a_folder_on_the_drive() {
+        let settings = settings(&["/work/Notes"]);
+
+        assert_eq!(settings.overlapping_profiles(Path::new("/home/notes")), vec![ProfileOverlap::SameUsbFolder(PathBuf::from("/work/Notes"))]);
+    }
+}
@@ -0,0 +1,306 @@
+//! A desktop shortcut and starting with the session, for users who won't go looking for the executable.
+//! Windows gets a .lnk through IShellLink and an HKCU Run entry; Linux gets .desktop files on the desktop
+//! and in the XDG autostart folder. Elsewhere both report that they're unsupported.
+//! Opening `.syncu` launch files by double-click is registered under HKCU on Windows only.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Command-line flag that starts the window minimized, as the startup entry does.
+pub const MINIMIZED_ARG: &str = "--minimized";
+
+#[cfg(any(windows, target_os = "linux"))]
+const APP_NAME: &str = "SyncU";
+
+/// Whether the desktop shortcut exists, wherever its target now points.
+pub fn desktop_shortcut_exists() -> bool {
+    desktop_shortcut_path().is_ok_and(|path| path.is_file())
+}
+
+/// Creates the desktop shortcut to the running executable, replacing an older one.
+pub fn create_desktop_shortcut() -> io::Result<PathBuf> {
+    let path = desktop_shortcut_path()?;
+    platform::write_shortcut(&path, &std::env::current_exe()?, false)?;
+    Ok(path)
+}
+
+pub fn remove_desktop_shortcut() -> io::Result<()> {
+    remove_if_present(&desktop_shortcut_path()?)
+}
+
+/// Whether the session starts the running executable. An entry left by a copy elsewhere doesn't count.
+pub fn autostart_enabled() -> bool {
+    std::env::current_exe().is_ok_and(|exe| platform::autostart_target().is_some_and(|target| target == exe))
+}
+
+/// Registers or removes the startup entry. Started from it, the window opens minimized.
+pub fn set_autostart(enabled: bool) -> io::Result<()> {
+    if enabled {
+        platform::register_autostart(&std::env::current_exe()?)
+    } else {
+        platform::unregister_autostart()
+    }
+}
+
+/// Whether double-clicking a `.syncu` file opens it with the running executable.
+pub fn file_association_enabled() -> bool {
+    std::env::current_exe().is_ok_and(|exe| platform::file_association_target().is_some_and(|target| target == exe))
+}
+
+/// Associates `.syncu` files with the running executable for the current user, or removes the association.
+pub fn set_file_association(enabled: bool) -> io::Result<()> {
+    if enabled {
+        platform::register_file_association(&std::env::current_exe()?)
+    } else {
+        platform::unregister_file_association()
+    }
+}
+
+fn desktop_shortcut_path() -> io::Result<PathBuf> {
+    let extension = if cfg!(windows) { "lnk" } else { "desktop" };
+    Ok(platform::desktop_dir()?.join(format!("SyncU.{}", extension)))
+}
+
+fn remove_if_present(path: &Path) -> io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::{APP_NAME, MINIMIZED_ARG};
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use windows::Win32::Foundation::ERROR_FILE_NOT_FOUND;
+    use windows::Win32::System::Com::{CLSCTX_INPROC_SERVER, COINIT_APARTMENTTHREADED, CoCreateInstance, CoInitializeEx, CoTaskMemFree, IPersistFile};
+    use windows::Win32::System::Registry::{HKEY_CURRENT_USER, REG_SZ, RRF_RT_REG_SZ, RegDeleteKeyValueW, RegDeleteTreeW, RegGetValueW, RegSetKeyValueW};
+    use windows::Win32::UI::Shell::{FOLDERID_Desktop, IShellLinkW, KF_FLAG_DEFAULT, SHGetKnownFolderPath, ShellLink};
+    use windows::core::{HSTRING, Interface};
+
+    const RUN_KEY: &str = r"Software\Microsoft\Windows\CurrentVersion\Run";
+    // The extension points at a file type, whose open command gets the clicked file as its argument
+    const FILE_TYPE: &str = "SyncU.LaunchFile";
+    const EXTENSION_KEY: &str = r"Software\Classes\.syncu";
+    const FILE_TYPE_KEY: &str = r"Software\Classes\SyncU.LaunchFile";
+    const OPEN_COMMAND_KEY: &str = r"Software\Classes\SyncU.LaunchFile\shell\open\command";
+
+    // The known folder follows a desktop redirected elsewhere, e.g. into OneDrive
+    pub fn desktop_dir() -> io::Result<PathBuf> {
+        unsafe {
+            let path = SHGetKnownFolderPath(&FOLDERID_Desktop, KF_FLAG_DEFAULT, None)?;
+            let text = path.to_string();
+            CoTaskMemFree(Some(path.0 as *const _));
+            text.map(PathBuf::from).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        }
+    }
+
+    pub fn write_shortcut(path: &Path, target: &Path, minimized: bool) -> io::Result<()> {
+        unsafe {
+            // The UI thread usually has COM already; a second initialization is harmless
+            let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+            let link: IShellLinkW = CoCreateInstance(&ShellLink, None, CLSCTX_INPROC_SERVER)?;
+            link.SetPath(&HSTRING::from(target))?;
+            if let Some(folder) = target.parent() {
+                link.SetWorkingDirectory(&HSTRING::from(folder))?;
+            }
+            if minimized {
+                link.SetArguments(&HSTRING::from(MINIMIZED_ARG))?;
+            }
+            link.SetDescription(&HSTRING::from(APP_NAME))?;
+            link.cast::<IPersistFile>()?.Save(&HSTRING::from(path), true)?;
+        }
+        Ok(())
+    }
+
+    // Reads a string value under HKCU; an empty name reads the key's default value
+    fn read_string(key: &str, name: &str) -> Option<String> {
+        let mut buffer = vec![0u16; 1024];
+        let mut size = (buffer.len() * 2) as u32;
+        unsafe {
+            RegGetValueW(
+                HKEY_CURRENT_USER,
+                &HSTRING::from(key),
+                &HSTRING::from(name),
+                RRF_RT_REG_SZ,
+                None,
+                Some(buffer.as_mut_ptr().cast()),
+                Some(&mut size),
+            )
+            .ok()
+            .ok()?;
+        }
+        Some(String::from_utf16_lossy(&buffer[..(size as usize / 2).saturating_sub(1)]))
+    }
+
+    // Writes a string value under HKCU, creating the key if needed
+    fn write_string(key: &str, name: &str, value: &str) -> io::Result<()> {
+        let data: Vec<u16> = value.encode_utf16().chain([0]).collect();
+        unsafe {
+            RegSetKeyValueW(
+                HKEY_CURRENT_USER,
+                &HSTRING::from(key),
+                &HSTRING::from(name),
+                REG_SZ.0,
+                Some(data.as_ptr().cast()),
+                (data.len() * 2) as u32,
+            )
+            .ok()?;
+        }
+        Ok(())
+    }
+
+    // The executable of a command written as `"<exe>" <arguments>`
+    fn command_target(command: &str) -> Option<PathBuf> {
+        command.strip_prefix('"')?.split('"').next().map(PathBuf::from)
+    }
+
+    pub fn autostart_target() -> Option<PathBuf> {
+        command_target(&read_string(RUN_KEY, APP_NAME)?)
+    }
+
+    pub fn register_autostart(exe: &Path) -> io::Result<()> {
+        write_string(RUN_KEY, APP_NAME, &format!("\"{}\" {}", exe.display(), MINIMIZED_ARG))
+    }
+
+    pub fn unregister_autostart() -> io::Result<()> {
+        let result = unsafe { RegDeleteKeyValueW(HKEY_CURRENT_USER, &HSTRING::from(RUN_KEY), &HSTRING::from(APP_NAME)) };
+        if result == ERROR_FILE_NOT_FOUND {
+            return Ok(());
+        }
+        result.ok().map_err(io::Error::from)
+    }
+
+    pub fn file_association_target() -> Option<PathBuf> {
+        command_target(&read_string(OPEN_COMMAND_KEY, "")?)
+    }
+
+    pub fn register_file_association(exe: &Path) -> io::Result<()> {
+        write_string(EXTENSION_KEY, "", FILE_TYPE)?;
+        write_string(FILE_TYPE_KEY, "", "SyncU 同步快捷文件")?;
+        write_string(OPEN_COMMAND_KEY, "", &format!("\"{}\" \"%1\"", exe.display()))
+    }
+
+    pub fn unregister_file_association() -> io::Result<()> {
+        for key in [EXTENSION_KEY, FILE_TYPE_KEY] {
+            let result = unsafe { RegDeleteTreeW(HKEY_CURRENT_USER, &HSTRING::from(key)) };
+            if result != ERROR_FILE_NOT_FOUND {
+                result.ok().map_err(io::Error::from)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::{APP_NAME, MINIMIZED_ARG};
+    use std::fs;
+    use std::io;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::{Path, PathBuf};
+
+    fn home() -> io::Result<PathBuf> {
+        std::env::var_os("HOME")
+            .map(PathBuf::from)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "未设置 HOME 环境变量"))
+    }
+
+    pub fn desktop_dir() -> io::Result<PathBuf> {
+        Ok(home()?.join("Desktop"))
+    }
+
+    fn autostart_path() -> io::Result<PathBuf> {
+        let config = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(config) => PathBuf::from(config),
+            None => home()?.join(".config"),
+        };
+        Ok(config.join("autostart").join("syncu.desktop"))
+    }
+
+    pub fn write_shortcut(path: &Path, target: &Path, minimized: bool) -> io::Result<()> {
+        let arguments = if minimized { format!(" {}", MINIMIZED_ARG) } else { String::new() };
+        let entry = format!(
+            "[Desktop Entry]\nType=Application\nName={}\nExec=\"{}\"{}\nTerminal=false\n",
+            APP_NAME,
+            target.display(),
+            arguments
+        );
+        if let Some(folder) = path.parent() {
+            fs::create_dir_all(folder)?;
+        }
+        fs::write(path, entry)?;
+        // Desktops only launch entries that are marked executable
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+    }
+
+    pub fn autostart_target() -> Option<PathBuf> {
+        let entry = fs::read_to_string(autostart_path().ok()?).ok()?;
+        let exec = entry.lines().find_map(|line| line.strip_prefix("Exec="))?;
+        exec.strip_prefix('"')?.split('"').next().map(PathBuf::from)
+    }
+
+    pub fn register_autostart(exe: &Path) -> io::Result<()> {
+        write_shortcut(&autostart_path()?, exe, true)
+    }
+
+    pub fn unregister_autostart() -> io::Result<()> {
+        super::remove_if_present(&autostart_path()?)
+    }
+
+    // Desktops differ in how they learn about new file types, so launch files are opened through the app here
+    pub fn file_association_target() -> Option<PathBuf> {
+        None
+    }
+
+    pub fn register_file_association(_exe: &Path) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "当前系统不支持"))
+    }
+
+    pub fn unregister_file_association() -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+mod platform {
+    use std::io;
+    use std::path::{Path, PathBuf};
+
+    fn unsupported() -> io::Error {
+        io::Error::new(io::ErrorKind::Unsupported, "当前系统不支持")
+    }
+
+    pub fn desktop_dir() -> io::Result<PathBuf> {
+        Err(unsupported())
+    }
+
+    pub fn write_shortcut(_path: &Path, _target: &Path, _minimized: bool) -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    pub fn autostart_target() -> Option<PathBuf> {
+        None
+    }
+
+    pub fn register_autostart(_exe: &Path) -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    pub fn unregister_autostart() -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    pub fn file_association_target() -> Option<PathBuf> {
+        None
+    }
+
+    pub fn register_file_association(_exe: &Path) -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    pub fn unregister_file_association() -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -1,87 +1,861 @@
-use crate::models::{Resolution, SyncAction, SyncData, SyncMessage};
-use crate::utils::{cleanup_empty_dirs, copy_large_file_with_progress, load_sync_data, prune_ancestor_paths, prune_descendant_paths, save_sync_data, scan_directory_with_progress, write_log_entry};
+use crate::error::{IoResultExt, SyncError};
+use crate::models::{ActionStatus, ClockSkewChoice, ConflictSuggestion, ConsistencyReport, CrowdedDirectoryChoice, FileInfo, LogLevel, LongPathChoice, NameCollisionChoice, PlanCheckpoint, PlanItem, RecordedFile, RecordedTarget, RemoteMissingChoice, Resolution, RunOutcome, Side, SkippedConflict, SpaceEstimate, SyncAction, SyncData, SyncStats};
+use crate::observer::{DeletionDecision, SyncObserver, UnattendedObserver};
+use crate::settings::{AnswerRules, DeletionAnswer, DeviceLogVerbosity, HardLinkPolicy, InUsePolicy, LineEndingPolicy, NewerDestinationPolicy, Profile};
+use crate::extended_attributes::{self, copy_extended_attributes};
+use crate::drive_session::DriveSession;
+use crate::report::{write_html_report, RunReport};
+use crate::utils::{classify_io_error, cleanup_empty_dirs, collision_rename, copy_large_file_with_progress, copy_small_file, count_entries, crowded_directories, drops_trailing_dots_and_spaces, ensure_writable, exact_path, infer_mtime_offset, shift_mtimes, name_collisions, detect_clock_skew_from_record, differ_only_in_line_endings, RateLimiter, enclosing_sync_root, find_renamed_sync_folder, format_count, format_size, hard_link_count, is_file_in_use, HashStrategy, machine_name, metadata_path, migrate_bookkeeping, load_plan_checkpoint, load_sync_data, load_sync_data_with_progress, plan_path, prune_ancestor_paths, prune_descendant_paths, remove_dir_all_with_progress, route_path, save_plan_checkpoint, save_sync_data, save_sync_data_with_progress, scan_directory_with_progress, text_diff_preview, trash_path, write_final_log_entry, write_log_entry, IoErrorCategory, BOOKKEEPING_DIR_NAME, TEMP_FILE_SUFFIX};
 use chrono::Local;
-use crossbeam_channel::{Receiver, RecvTimeoutError};
-use std::collections::{BTreeSet, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
-use std::path::{PathBuf};
-use std::time::Duration;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 use walkdir::WalkDir;
 
 const LARGE_FILE_THRESHOLD: u64 = 10 * 1024 * 1024; // 10 MB
+const SAFETY_CHECK_EXAMPLES: usize = 200;
+/// Longest destination path, in UTF-16 units, that FAT32/exFAT drives on Windows reliably accept.
+const MAX_DESTINATION_PATH_LEN: usize = 260;
+/// Example paths recorded with each summarized group in the USB log.
+const LOG_SUMMARY_EXAMPLES: usize = 3;
+/// How much newer a destination must be than its source to count as newer; FAT stores mtimes in 2 second steps.
+const MTIME_TOLERANCE: Duration = Duration::from_secs(2);
+/// Plans with at least this many actions, or this many bytes to copy, are kept on the drive while they run.
+const CHECKPOINT_MIN_ACTIONS: usize = 500;
+const CHECKPOINT_MIN_BYTES: u64 = 1024 * 1024 * 1024;
+/// Least time between two saves of a kept plan; each save rewrites the whole file.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(5);
+/// Orphaned files moved to the trash between progress reports and stop checks.
+const TRASH_BATCH: usize = 256;
+/// Delays before each retry of a copy that failed with a device error; flaky USB controllers often recover within seconds.
+const DEVICE_RETRY_DELAYS: [Duration; 3] = [Duration::from_secs(1), Duration::from_secs(2), Duration::from_secs(4)];
+/// Copies to the USB drive below this size cost more in per-file overhead than in writing data.
+pub const SMALL_FILE_MAX_BYTES: u64 = 64 * 1024;
+/// Plans copying at least this many small files to the USB drive get a hint that the run will be slow.
+pub const SMALL_FILE_HINT_MIN_COUNT: usize = 2000;
 
-/// Helper function to wait for a specific message while also checking for a stop signal.
-fn wait_for_message<F, T>(rx: &Receiver<SyncMessage>, mut condition: F) -> Result<Option<T>, ()>
-where
-    F: FnMut(SyncMessage) -> Option<T>,
-{
+/// The result of executing a single planned action.
+enum ActionOutcome {
+    /// The action was carried out; holds the log message.
+    Done(String),
+    /// The action was deliberately not carried out (declined deletion, skipped conflict).
+    Skipped(String),
+    /// The user stopped the sync while the action was waiting or running.
+    Stopped,
+}
+
+/// Collapses consecutive declined deletions into a single line of the USB log.
+/// The in-app log still shows every item.
+#[derive(Default)]
+struct DeclinedDeletionLog {
+    verbosity: DeviceLogVerbosity,
+    label: Option<&'static str>,
+    count: usize,
+    examples: Vec<PathBuf>,
+}
+
+impl DeclinedDeletionLog {
+    fn push(&mut self, label: &'static str, path: &Path, usb_sync_path: &Path) -> Result<(), SyncError> {
+        if self.label != Some(label) {
+            self.flush(usb_sync_path)?;
+            self.label = Some(label);
+        }
+        self.count += 1;
+        if self.examples.len() < LOG_SUMMARY_EXAMPLES {
+            self.examples.push(path.to_path_buf());
+        }
+        Ok(())
+    }
+
+    /// Writes the pending group, e.g. "取消删除 500 个项目 (例如: a, b, c)".
+    fn flush(&mut self, usb_sync_path: &Path) -> Result<(), SyncError> {
+        if let Some(label) = self.label.take() {
+            let examples: Vec<String> = self.examples.drain(..).map(|path| path.display().to_string()).collect();
+            let message = format!("[{}] {} {} 个项目 (例如: {})", Local::now().format("%H:%M:%S"), label, self.count, examples.join(", "));
+            self.count = 0;
+            write_log_entry(&message, LogLevel::Detail, self.verbosity, usb_sync_path)?;
+        }
+        Ok(())
+    }
+}
+
+/// How a single file copy ended.
+enum CopyOutcome {
+    Copied,
+    /// The source was modified while it was being copied; the copy was discarded and the destination left as it was.
+    ChangedDuringCopy,
+    /// The source is in use and the policy (or the user) chose not to copy it.
+    SkippedInUse,
+    /// The source was deleted or renamed after the sync was planned.
+    SourceMissing,
+    /// The user skipped the file while it was being copied.
+    SkippedByUser,
+    Stopped,
+}
+
+/// Whether `from` is gone while its folder is still there, i.e. the file itself was deleted or renamed.
+/// A missing folder may mean the drive was pulled, which must keep failing the action.
+fn source_vanished(from: &Path) -> bool {
+    !from.exists() && from.parent().is_some_and(Path::exists)
+}
+
+/// Counts and logs a destination that has other hard links. A copy that writes into it in place would change
+/// every linked path, so under `HardLinkPolicy::BreakLink` it is removed first; a copy that replaces it by a rename
+/// leaves the other links alone anyway.
+fn handle_hard_links(
+    to: &Path,
+    in_place: bool,
+    policy: HardLinkPolicy,
+    label: &str,
+    stats: &mut SyncStats,
+    observer: &impl SyncObserver,
+) -> Result<(), SyncError> {
+    if hard_link_count(to).is_none_or(|count| count <= 1) {
+        return Ok(());
+    }
+    stats.hard_linked_destinations += 1;
+    let time = Local::now().format("%H:%M:%S");
+    match (in_place, policy) {
+        (false, _) => observer.on_log(format!("[{}] 目标文件有其他硬链接，以新文件替换，其他链接保留原内容: {}", time, label)),
+        (true, HardLinkPolicy::BreakLink) => {
+            fs::remove_file(to).at(to)?;
+            observer.on_log(format!("[{}] 目标文件有其他硬链接，已先断开链接再写入: {}", time, label));
+        }
+        (true, HardLinkPolicy::Warn) => {
+            observer.on_log(format!("警告: 目标文件有其他硬链接，写入会同时改变链接到它的其他路径: {}", label));
+        }
+    }
+    Ok(())
+}
+
+/// Copies a file for a planned action, retrying as the cause of a failure allows: device errors are retried
+/// with a growing delay, refused access is retried when the user asks to, and anything else fails at once.
+#[allow(clippy::too_many_arguments)]
+fn copy_for_action(
+    from: &Path,
+    to: &Path,
+    file_name_for_ui: &str,
+    profile: &Profile,
+    extended_attributes: bool,
+    observer: &impl SyncObserver,
+    limiter: &RateLimiter,
+    sizes: (u64, u64),
+    stats: &mut SyncStats,
+) -> Result<CopyOutcome, SyncError> {
+    let mut device_retries = 0;
     loop {
-        // Use a timeout to prevent blocking indefinitely.
-        match rx.recv_timeout(Duration::from_millis(100)) {
-            Ok(msg) => {
-                if let SyncMessage::Stop = msg {
-                    return Err(()); // Stop signal received
-                }
-                if let Some(result) = condition(msg) {
-                    return Ok(Some(result)); // Desired message received
+        let (path, source) = match copy_once(from, to, file_name_for_ui, profile, extended_attributes, observer, limiter, sizes, stats) {
+            Err(SyncError::Io { path, source }) => (path, source),
+            result => return result,
+        };
+        match classify_io_error(&source) {
+            IoErrorCategory::Device if device_retries < DEVICE_RETRY_DELAYS.len() => {
+                let delay = DEVICE_RETRY_DELAYS[device_retries];
+                device_retries += 1;
+                observer.on_log(format!(
+                    "警告: 设备读写出错，{} 秒后重试 ({}/{}): {}: {}",
+                    delay.as_secs(),
+                    device_retries,
+                    DEVICE_RETRY_DELAYS.len(),
+                    file_name_for_ui,
+                    source
+                ));
+                if wait_unless_stopped(delay, observer) {
+                    return Ok(CopyOutcome::Stopped);
                 }
             }
-            Err(RecvTimeoutError::Timeout) => {
-                // Timeout is not an error, just continue the loop to check for stop signal again.
+            IoErrorCategory::Permission => match observer.retry_denied_copy(&path, &source.to_string()) {
+                Ok(true) => {}
+                Ok(false) => return Err(SyncError::Io { path, source }),
+                Err(SyncError::Cancelled) => return Ok(CopyOutcome::Stopped),
+                Err(e) => return Err(e),
+            },
+            _ => return Err(SyncError::Io { path, source }),
+        }
+    }
+}
+
+// Sleeps for `delay` in short slices. Returns true if stopped meanwhile.
+fn wait_unless_stopped(delay: Duration, observer: &impl SyncObserver) -> bool {
+    let deadline = Instant::now() + delay;
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()).filter(|d| !d.is_zero()) {
+        if observer.should_stop() {
+            return true;
+        }
+        std::thread::sleep(remaining.min(Duration::from_millis(100)));
+    }
+    false
+}
+
+/// One attempt of `copy_for_action`, honoring the in-use and hard link policies and detecting concurrent modification.
+/// With `extended_attributes`, streams or xattrs follow the contents; failing to copy them only warns.
+#[allow(clippy::too_many_arguments)]
+fn copy_once(
+    from: &Path,
+    to: &Path,
+    file_name_for_ui: &str,
+    profile: &Profile,
+    extended_attributes: bool,
+    observer: &impl SyncObserver,
+    limiter: &RateLimiter,
+    (total_sync_size, processed_size): (u64, u64),
+    stats: &mut SyncStats,
+) -> Result<CopyOutcome, SyncError> {
+    let (from, to) = (&exact_path(from), &exact_path(to));
+    if is_file_in_use(from) {
+        let copy = match profile.in_use_policy {
+            InUsePolicy::CopyAndWarn => {
+                observer.on_log(format!("警告: 文件正在被其他程序使用，复制结果可能不一致: {}", file_name_for_ui));
+                true
             }
-            Err(RecvTimeoutError::Disconnected) => {
-                // Channel disconnected, treat as a stop.
-                return Err(());
+            InUsePolicy::Skip => false,
+            InUsePolicy::Ask => match observer.confirm_copy_in_use(from) {
+                Ok(c) => c,
+                Err(SyncError::Cancelled) => return Ok(CopyOutcome::Stopped),
+                Err(e) => return Err(e),
+            },
+        };
+        if !copy {
+            return Ok(CopyOutcome::SkippedInUse);
+        }
+    }
+
+    let before = match fs::metadata(from) {
+        Ok(metadata) => metadata,
+        Err(_) if source_vanished(from) => return Ok(CopyOutcome::SourceMissing),
+        Err(e) => return Err(SyncError::Io { path: from.to_path_buf(), source: e }),
+    };
+    if let Some(parent) = to.parent() { fs::create_dir_all(parent).at(parent)?; }
+    // A portable heuristic for files being written during the copy. A changed source's copy is discarded, so the
+    // destination keeps what the record says and the next run copies the source without a conflict.
+    let mut changed = false;
+    let keep = || {
+        changed = !fs::metadata(from).is_ok_and(|after| {
+            after.len() == before.len() && after.modified().ok().is_some_and(|modified| before.modified().ok() == Some(modified))
+        });
+        !changed
+    };
+    let copied = if before.len() > LARGE_FILE_THRESHOLD {
+        handle_hard_links(to, false, profile.hard_link_policy, file_name_for_ui, stats, observer)?;
+        copy_large_file_with_progress(from, to, file_name_for_ui, observer, limiter, total_sync_size, processed_size, keep)
+    } else {
+        // Small files go in one piece, so wait for their share of the limit first
+        if limiter.pace(before.len(), observer) {
+            return Ok(CopyOutcome::Stopped);
+        }
+        handle_hard_links(to, true, profile.hard_link_policy, file_name_for_ui, stats, observer)?;
+        // Links the policy left in place are written through
+        let in_place = hard_link_count(to).is_some_and(|count| count > 1);
+        copy_small_file(from, to, in_place, keep).map(|()| false)
+    };
+    match copied {
+        Ok(true) => return Ok(CopyOutcome::Stopped),
+        Ok(false) => {}
+        Err(SyncError::Skipped) => return Ok(CopyOutcome::SkippedByUser),
+        // The source may also vanish while it is being copied
+        Err(_) if source_vanished(from) => return Ok(CopyOutcome::SourceMissing),
+        Err(e) => return Err(e),
+    }
+    if changed {
+        return Ok(if source_vanished(from) { CopyOutcome::SourceMissing } else { CopyOutcome::ChangedDuringCopy });
+    }
+    check_copied_size(to, before.len())?;
+    let attributes = if extended_attributes { copy_extended_attributes(from, to) } else { Ok(0) };
+    if let Err(e) = attributes {
+        observer.on_log(format!("警告: 扩展属性未能复制: {}: {}", file_name_for_ui, e));
+    }
+    Ok(CopyOutcome::Copied)
+}
+
+/// Fails a copy whose destination didn't end up the size of its source, e.g. an empty file left by a full drive.
+fn check_copied_size(to: &Path, expected: u64) -> Result<(), SyncError> {
+    let copied = fs::metadata(to).at(to)?.len();
+    if copied != expected {
+        return Err(SyncError::SizeMismatch { path: to.to_path_buf(), expected, actual: copied });
+    }
+    Ok(())
+}
+
+/// Turns a copy outcome into an action outcome, recording paths whose new state must not be saved.
+fn finish_copy(outcome: CopyOutcome, path: &Path, message: String, retained_paths: &mut HashSet<PathBuf>) -> ActionOutcome {
+    match outcome {
+        CopyOutcome::Copied => ActionOutcome::Done(message),
+        CopyOutcome::ChangedDuringCopy => {
+            retained_paths.insert(path.to_path_buf());
+            ActionOutcome::Skipped(format!("[{}] 复制期间文件被修改，已放弃复制，下次将重新同步: {}", Local::now().format("%H:%M:%S"), path.display()))
+        }
+        CopyOutcome::SkippedInUse => {
+            retained_paths.insert(path.to_path_buf());
+            ActionOutcome::Skipped(format!("[{}] 文件正在使用，已跳过: {}", Local::now().format("%H:%M:%S"), path.display()))
+        }
+        CopyOutcome::SourceMissing => {
+            retained_paths.insert(path.to_path_buf());
+            ActionOutcome::Skipped(format!("[{}] 源文件已不存在，已跳过: {}", Local::now().format("%H:%M:%S"), path.display()))
+        }
+        CopyOutcome::SkippedByUser => {
+            retained_paths.insert(path.to_path_buf());
+            ActionOutcome::Skipped(format!("[{}] 已按要求跳过: {}", Local::now().format("%H:%M:%S"), path.display()))
+        }
+        CopyOutcome::Stopped => ActionOutcome::Stopped,
+    }
+}
+
+/// Decides what becomes of the plan an interrupted run left behind. It is continued if nothing it still has to
+/// copy, overwrite or delete changed since and the user agrees; otherwise it is removed and the run plans from scratch.
+fn resume_checkpoint(
+    checkpoint: PlanCheckpoint,
+    local_path: &Path,
+    usb_sync_path: &Path,
+    plan_path: &Path,
+    log_verbosity: DeviceLogVerbosity,
+    observer: &impl SyncObserver,
+) -> Result<Option<PlanCheckpoint>, SyncError> {
+    // Made for another local folder of the same name
+    if checkpoint.source_folder != local_path {
+        let _ = fs::remove_file(plan_path);
+        return Ok(None);
+    }
+    let remaining = checkpoint.remaining();
+    // Only the final steps were left; what the plan recorded still spares hashing
+    if remaining == 0 {
+        return Ok(Some(checkpoint));
+    }
+    let changed = checkpoint
+        .items
+        .iter()
+        .filter(|item| item.status.is_none() && !unchanged_since_planning(item, local_path, usb_sync_path))
+        .count();
+    let (resume, message) = if changed > 0 {
+        (false, format!("[{}] 上次未完成的同步计划已失效（{} 项在此期间被修改），将重新分析", Local::now().format("%H:%M:%S"), format_count(changed as u64)))
+    } else if observer.confirm_resume_plan(remaining, checkpoint.items.len())? {
+        (true, format!("[{}] 继续上次未完成的同步（还剩 {} 项）", Local::now().format("%H:%M:%S"), format_count(remaining as u64)))
+    } else {
+        (false, format!("[{}] 放弃上次未完成的同步计划，将重新分析", Local::now().format("%H:%M:%S")))
+    };
+    observer.on_log(message.clone());
+    write_log_entry(&message, LogLevel::Summary, log_verbosity, usb_sync_path)?;
+    if !resume {
+        let _ = fs::remove_file(plan_path);
+        return Ok(None);
+    }
+    Ok(Some(checkpoint))
+}
+
+// Whether the file a pending action copies or deletes, and the one it would overwrite, are still as the plan recorded
+fn unchanged_since_planning(item: &PlanItem, local_path: &Path, usb_sync_path: &Path) -> bool {
+    let root = |side: Side| match side {
+        Side::Local => local_path,
+        Side::Usb => usb_sync_path,
+    };
+    let same_file = |path: &Path, info: &FileInfo| {
+        fs::metadata(exact_path(path))
+            .is_ok_and(|metadata| metadata.len() == info.size && metadata.modified().is_ok_and(|modified| modified == info.modified))
+    };
+    let source_unchanged = item.source.as_ref().is_none_or(|recorded| same_file(&root(recorded.side).join(&recorded.info.path), &recorded.info));
+    let target_unchanged = item.target.as_ref().is_none_or(|target| {
+        let path = root(target.side).join(&target.path);
+        match &target.info {
+            Some(info) => same_file(&path, info),
+            None => fs::symlink_metadata(exact_path(&path)).is_err(),
+        }
+    });
+    source_unchanged && target_unchanged
+}
+
+// Answers a deletion prompt for `path` from earlier answers about the folders around it, from the rules file,
+// or by asking `observer`, logging where an answer came from. `side` is the side the deletion happens on.
+#[allow(clippy::too_many_arguments)]
+fn confirm_deletion(
+    rules: &AnswerRules,
+    answers: &mut DeletionAnswers,
+    side: Side,
+    path: &Path,
+    is_folder: bool,
+    absolute_path: &Path,
+    (position, total): (usize, usize),
+    observer: &impl SyncObserver,
+) -> Result<DeletionDecision, SyncError> {
+    if let Some((delete, answered)) = answers.implied(side, path, is_folder) {
+        // One line per answered folder rather than one per entry inside it
+        if answers.mention(side, &answered) {
+            let time = Local::now().format("%H:%M:%S");
+            observer.on_log(if delete {
+                format!("[{}] 目录 {} 已确认删除，其中的项目一并删除，不再逐个询问", time, answered.display())
+            } else if answered.starts_with(path) {
+                format!("[{}] 保留目录 {}: 其中的 {} 已取消删除", time, path.display(), answered.display())
+            } else {
+                format!("[{}] 目录 {} 已取消删除，其中的项目一并保留，不再逐个询问", time, answered.display())
+            });
+        }
+        return Ok(if delete { DeletionDecision::Delete } else { DeletionDecision::Keep });
+    }
+    let decision = match rules.deletion_answer(path) {
+        Some((number, rule, answer)) => {
+            observer.on_log(format!("[{}] 按应答规则 #{} ({}) {}: {}", Local::now().format("%H:%M:%S"), number, rule.pattern, answer.label(), path.display()));
+            match answer {
+                DeletionAnswer::Confirm => DeletionDecision::Delete,
+                DeletionAnswer::Keep => DeletionDecision::KeepPermanently,
+                DeletionAnswer::Skip | DeletionAnswer::Ask => DeletionDecision::Keep,
             }
         }
+        None => observer.confirm_deletion(absolute_path, position, total)?,
+    };
+    answers.record(side, path, is_folder, decision == DeletionDecision::Delete);
+    Ok(decision)
+}
+
+// The record with a constant whole-hour offset of the files under `base_path` compensated, if one is found.
+// Without it a time zone change on FAT defeats the mtime shortcut and every file is hashed again.
+fn compensate_mtime_offset(
+    base_path: &Path,
+    reference: &SyncData,
+    label: &str,
+    log_verbosity: DeviceLogVerbosity,
+    usb_sync_path: &Path,
+    observer: &impl SyncObserver,
+) -> Result<Option<SyncData>, SyncError> {
+    let Some(offset) = infer_mtime_offset(base_path, reference) else { return Ok(None) };
+    let now = SystemTime::now();
+    let ahead = reference.files.values().filter(|info| info.modified > now).count();
+    let mut message = format!(
+        "[{}] {}: 检测到疑似时区偏移 {}，已自动补偿 (抽样 {} 个文件中 {} 个的修改时间与同步记录恰好相差 {} 小时",
+        Local::now().format("%H:%M:%S"),
+        label,
+        offset.label(),
+        offset.sampled,
+        offset.matching,
+        offset.seconds / 3600
+    );
+    if ahead > 0 {
+        message += &format!("，记录中 {} 个文件的修改时间晚于当前时间", ahead);
+    }
+    message += ")";
+    observer.on_log(message.clone());
+    write_log_entry(&message, LogLevel::Summary, log_verbosity, usb_sync_path)?;
+    Ok(Some(shift_mtimes(reference, offset)))
+}
+
+// Saves the kept plan, if the run keeps one. A drive that can't take it only costs the chance to continue later.
+fn save_checkpoint(checkpoint: &mut Option<PlanCheckpoint>, plan_path: &Path, observer: &impl SyncObserver) {
+    let Some(plan) = checkpoint else { return };
+    if let Err(e) = save_plan_checkpoint(plan, plan_path) {
+        observer.on_log(format!("警告: 同步计划无法保存，中断后需重新分析: {}", e));
+        *checkpoint = None;
+        let _ = fs::remove_file(plan_path);
     }
 }
 
+/// Syncs the local folder with its folder on the USB drive, reporting to and asking `observer`.
+/// Unattended runs (scheduled or command line) never wait for an answer; see `UnattendedObserver`.
 pub fn run_sync(
     local_folder: Option<PathBuf>,
     usb_drive: Option<PathBuf>,
-    tx: crossbeam_channel::Sender<SyncMessage>,
-    rx: Receiver<SyncMessage>,
+    profile: Profile,
+    unattended: bool,
+    observer: &impl SyncObserver,
+) {
+    let session = usb_drive.map(DriveSession::single_run);
+    run_sync_in_session(local_folder, session.as_ref(), profile, unattended, observer);
+    if let Some(session) = session {
+        session.finish_action();
+    }
+}
+
+/// Like `run_sync`, for one of several runs against the same drive within one user action.
+/// The caller ends the action with `DriveSession::finish_action` after the last run.
+pub fn run_sync_in_session(
+    local_folder: Option<PathBuf>,
+    session: Option<&DriveSession>,
+    profile: Profile,
+    unattended: bool,
+    observer: &impl SyncObserver,
+) {
+    if unattended {
+        let unattended_observer = UnattendedObserver::new(observer, &profile);
+        sync_folders(local_folder, session, profile, &unattended_observer);
+    } else {
+        sync_folders(local_folder, session, profile, observer);
+    }
+}
+
+fn sync_folders(
+    local_folder: Option<PathBuf>,
+    session: Option<&DriveSession>,
+    profile: Profile,
+    observer: &impl SyncObserver,
 ) {
-    let was_stopped = match (|| -> Result<bool, Box<dyn std::error::Error>> {
-        let local_path = local_folder.as_ref().ok_or("未选择本地文件夹")?;
-        let usb_root_path = usb_drive.as_ref().ok_or("未检测到U盘")?;
+    let mut report = RunReport::start();
+    let result = (|| -> Result<bool, SyncError> {
+        let local_path = local_folder.as_ref().ok_or(SyncError::InvalidSelection("未选择本地文件夹"))?;
+        // Rules meant to make a run predictable must be usable before it changes anything
+        let answer_rules = match &profile.answer_rules_file {
+            Some(path) => AnswerRules::load(path).map_err(|message| SyncError::InvalidRules { path: path.clone(), message })?,
+            None => AnswerRules::default(),
+        };
+        let session = session.ok_or(SyncError::InvalidSelection("未检测到U盘"))?;
+        let usb_root_path = session.root();
+        if !usb_root_path.exists() {
+            return Err(SyncError::DeviceMissing(usb_root_path.to_path_buf()));
+        }
+        // Each step up to the scans can take long on a slow drive, so a stop is honored between them
+        if observer.should_stop() { return Ok(true); }
+        session.check_writable()?;
 
-        let sync_folder_name = local_path.file_name().ok_or("无效的本地文件夹名称")?;
+        let sync_folder_name = local_path.file_name().ok_or(SyncError::InvalidSelection("无效的本地文件夹名称"))?;
         let usb_sync_path = usb_root_path.join(sync_folder_name);
-        fs::create_dir_all(&usb_sync_path)?;
+        report.local_folder = Some(local_path.clone());
+        report.usb_sync_path = Some(usb_sync_path.clone());
+        // A missing target folder may just mean the local folder was renamed since the last sync
+        if observer.should_stop() { return Ok(true); }
+        if !usb_sync_path.exists()
+            && let Some(old_sync_path) = find_renamed_sync_folder(usb_root_path, local_path)
+        {
+            let old_name = old_sync_path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+            let new_name = sync_folder_name.to_string_lossy();
+            if observer.confirm_relink(&old_name, &new_name)? {
+                fs::rename(&old_sync_path, &usb_sync_path).at(&old_sync_path)?;
+                session.record_written(&usb_sync_path);
+                let message = format!("[{}] U盘文件夹 '{}' 已重命名为 '{}'，沿用其同步记录", Local::now().format("%H:%M:%S"), old_name, new_name);
+                observer.on_log(message.clone());
+                write_log_entry(&message, LogLevel::Summary, profile.device_log_verbosity, &usb_sync_path)?;
+            }
+        }
+        if observer.should_stop() { return Ok(true); }
+        // The drive is only flushed after runs that wrote to it, so a run with nothing to do leaves it alone
+        if !usb_sync_path.exists() {
+            fs::create_dir_all(&usb_sync_path).at(&usb_sync_path)?;
+            session.record_written(&usb_sync_path);
+        }
+
+        if observer.should_stop() { return Ok(true); }
+
+        // Bring the bookkeeping files to where the profile wants them before anything reads them
+        let moved = migrate_bookkeeping(&usb_sync_path, profile.bookkeeping_subfolder)?;
+        if moved > 0 {
+            session.record_written(&usb_sync_path);
+            let message = if profile.bookkeeping_subfolder {
+                format!("[{}] 已将 {} 项同步记录移入 {} 文件夹", Local::now().format("%H:%M:%S"), moved, BOOKKEEPING_DIR_NAME)
+            } else {
+                format!("[{}] 已将 {} 项同步记录移回同步文件夹根目录", Local::now().format("%H:%M:%S"), moved)
+            };
+            observer.on_log(message.clone());
+            write_log_entry(&message, LogLevel::Summary, profile.device_log_verbosity, &usb_sync_path)?;
+        }
 
-        let metadata_path = usb_sync_path.join(".syncu_metadata.json");
+        let metadata_path = metadata_path(&usb_sync_path);
+        if let Some(outer_root) = enclosing_sync_root(&usb_sync_path) {
+            observer.on_log(format!("警告: 目标文件夹位于另一个 SyncU 同步目录内: {}", outer_root.display()));
+        }
 
-        tx.send(SyncMessage::Progress(
+        if observer.should_stop() { return Ok(true); }
+        observer.on_progress(
             0.0,
             "正在加载上次同步记录...".to_string(),
-        ))?;
-        let last_sync_data = load_sync_data(&metadata_path)?;
+        );
+        let Some(mut last_sync_data) = load_sync_data_with_progress(&metadata_path, observer)? else {
+            return Ok(true);
+        };
+        // A newer version's record may hold what this one would drop on saving; the files can still be synced if it's left alone
+        let save_record = match last_sync_data.written_by_newer_version() {
+            Some(version) if observer.confirm_newer_metadata(version)? => {
+                let message = format!("警告: 同步记录由较新版本的 SyncU ({}) 写入，本次同步不更新记录: {}", version, metadata_path.display());
+                observer.on_log(message.clone());
+                write_log_entry(&message, LogLevel::Summary, profile.device_log_verbosity, &usb_sync_path)?;
+                false
+            }
+            _ => {
+                ensure_writable(&last_sync_data, &metadata_path)?;
+                true
+            }
+        };
+        // Set once the loaded record is edited in memory, after which it no longer matches the file.
+        // A record in an older format counts as edited from the start, since only a save migrates it.
+        let mut last_sync_data_edited = last_sync_data.needs_migration();
+
+        // An unreliable clock breaks the mtime shortcut, so let the user decide how to proceed.
+        let mut full_rehash = false;
+        if let Some(description) = detect_clock_skew_from_record(&last_sync_data, SystemTime::now()) {
+            let choice = observer.resolve_clock_skew(description)?;
+            let message = match choice {
+                ClockSkewChoice::Continue => format!("[{}] 系统时间异常，用户选择继续同步", Local::now().format("%H:%M:%S")),
+                ClockSkewChoice::FullRehash => {
+                    full_rehash = true;
+                    format!("[{}] 系统时间异常，用户选择完整校验所有文件", Local::now().format("%H:%M:%S"))
+                }
+                ClockSkewChoice::Abort => format!("[{}] 系统时间异常，用户取消同步", Local::now().format("%H:%M:%S")),
+            };
+            observer.on_log(message.clone());
+            write_log_entry(&message, LogLevel::Summary, profile.device_log_verbosity, &usb_sync_path)?;
+            if choice == ClockSkewChoice::Abort {
+                return Ok(true);
+            }
+        }
+        // An interrupted long run left its plan behind; what it already transferred needn't be hashed again
+        if observer.should_stop() { return Ok(true); }
+        let plan_path = plan_path(&usb_sync_path);
+        let resumed = match load_plan_checkpoint(&plan_path) {
+            Ok(Some(checkpoint)) => resume_checkpoint(checkpoint, local_path, &usb_sync_path, &plan_path, profile.device_log_verbosity, observer)?,
+            Ok(None) => None,
+            Err(e) => {
+                observer.on_log(format!("警告: 上次未完成的同步计划无法读取，将重新分析: {}", e));
+                let _ = fs::remove_file(&plan_path);
+                None
+            }
+        };
 
-        if rx.try_recv() == Ok(SyncMessage::Stop) { return Ok(true); }
-        tx.send(SyncMessage::Progress(0.0, "正在统计本地文件...".to_string()))?;
-        let local_total = WalkDir::new(local_path).into_iter().filter_map(Result::ok).count();
+        // When the clock can't be trusted, compare against an empty record so every file is hashed.
+        let empty_sync_data = SyncData::default();
+        let hash_reference = if full_rehash { &empty_sync_data } else { &last_sync_data };
+        // Another machine's mtimes say nothing about this machine's copies
+        let machine = machine_name();
+        let own_reference = last_sync_data.local_reference(&machine);
+        let local_hash_reference = if full_rehash { &empty_sync_data } else { own_reference.as_ref().unwrap_or(&last_sync_data) };
+        // A whole-hour shift of every mtime is a time zone change, not edits; compare against the shifted times
+        let local_offset_reference =
+            compensate_mtime_offset(local_path, local_hash_reference, "扫描本地", profile.device_log_verbosity, &usb_sync_path, observer)?;
+        let local_hash_reference = local_offset_reference.as_ref().unwrap_or(local_hash_reference);
+        // The recorded states of a continued plan are newer than either record; like them they rely on mtimes
+        let with_recorded = |reference: &SyncData, side: Side| {
+            resumed.as_ref().filter(|_| !full_rehash).map(|checkpoint| {
+                let mut files = reference.files.clone();
+                files.extend(checkpoint.recorded_files(side));
+                SyncData { files, ..Default::default() }
+            })
+        };
+        let resumed_local_reference = with_recorded(local_hash_reference, Side::Local);
+        let local_hash_reference = resumed_local_reference.as_ref().unwrap_or(local_hash_reference);
+
+        // FAT and exFAT drop streams and xattrs, so don't try for every file
+        let extended_attributes = profile.copy_extended_attributes
+            && match session.file_system() {
+                Some(file_system) if !extended_attributes::supported_on(file_system) => {
+                    observer.on_log(format!(
+                        "[{}] U盘文件系统 ({}) 无法保存扩展属性，本次同步不复制扩展属性",
+                        Local::now().format("%H:%M:%S"),
+                        file_system
+                    ));
+                    false
+                }
+                _ => true,
+            };
+        let limiter = RateLimiter::default();
+        let hashing = if profile.dedupe_scan { HashStrategy::Deduplicated } else { HashStrategy::Full };
+
+        if observer.should_stop() { return Ok(true); }
+        report.start_phase("扫描");
+        observer.on_progress(0.0, "正在统计本地文件...".to_string());
+        let Some(local_total) = count_entries(local_path, observer) else { return Ok(true) };
         let local_sync_data =
-            match scan_directory_with_progress(local_path, &tx, &rx, local_total, "扫描本地", &last_sync_data)? {
+            match scan_directory_with_progress(local_path, observer, Some(local_total), "扫描本地", local_hash_reference, hashing)? {
                 Some(data) => data,
                 None => return Ok(true), // Stopped
             };
 
-        if rx.try_recv() == Ok(SyncMessage::Stop) { return Ok(true); }
-        tx.send(SyncMessage::Progress(0.0, "正在统计U盘文件...".to_string()))?;
-        let remote_total = WalkDir::new(&usb_sync_path).into_iter().filter_map(Result::ok).count();
+        if observer.should_stop() { return Ok(true); }
+        observer.on_progress(0.0, "正在统计U盘文件...".to_string());
+        let Some(remote_total) = count_entries(&usb_sync_path, observer) else { return Ok(true) };
+        // Routed files are recorded under their local paths, so look them up by their USB location instead.
+        let remote_hash_reference = if last_sync_data.routes.is_empty() {
+            None
+        } else {
+            Some(SyncData {
+                files: hash_reference
+                    .files
+                    .iter()
+                    .map(|(path, info)| (last_sync_data.routes.get(path).cloned().unwrap_or_else(|| path.clone()), info.clone()))
+                    .collect(),
+                ..Default::default()
+            })
+        };
+        let remote_hash_reference = remote_hash_reference.as_ref().unwrap_or(hash_reference);
+        let remote_offset_reference =
+            compensate_mtime_offset(&usb_sync_path, remote_hash_reference, "扫描U盘", profile.device_log_verbosity, &usb_sync_path, observer)?;
+        let remote_hash_reference = remote_offset_reference.as_ref().unwrap_or(remote_hash_reference);
+        let resumed_remote_reference = with_recorded(remote_hash_reference, Side::Usb);
+        let remote_hash_reference = resumed_remote_reference.as_ref().unwrap_or(remote_hash_reference);
         let remote_sync_data =
-            match scan_directory_with_progress(&usb_sync_path, &tx, &rx, remote_total, "扫描U盘", &last_sync_data)?
+            match scan_directory_with_progress(&usb_sync_path, observer, Some(remote_total), "扫描U盘", remote_hash_reference, hashing)?
             {
                 Some(data) => data,
                 None => return Ok(true), // Stopped
             };
 
-        tx.send(SyncMessage::Progress(0.0, "正在分析文件差异...".to_string()))?;
+        // --- Routing: translate the USB scan into local path space ---
+        // remote_locations maps every local relative path to where the file currently lives on the USB drive.
+        let reverse_routes: HashMap<&PathBuf, &PathBuf> =
+            last_sync_data.routes.iter().map(|(local, remote)| (remote, local)).collect();
+        let mut remote_locations = HashMap::new();
+        let mut remote_files = HashMap::new();
+        for (remote_rel, info) in remote_sync_data.files {
+            let key = reverse_routes.get(&remote_rel).map(|p| (*p).clone()).unwrap_or_else(|| remote_rel.clone());
+            remote_locations.entry(key.clone()).or_insert(remote_rel);
+            remote_files.entry(key).or_insert(info);
+        }
+        let remote_directories = {
+            let routed_locations: Vec<(&PathBuf, &PathBuf)> =
+                remote_locations.iter().filter(|(key, remote)| key != remote).collect();
+            // Folders that only exist on the USB drive because routing put files there
+            let is_routing_dir = |dir: &Path| {
+                profile.routing_rules.iter().any(|rule| {
+                    !rule.destination.as_os_str().is_empty()
+                        && (dir.starts_with(&rule.destination) || rule.destination.starts_with(dir))
+                }) || routed_locations.iter().any(|(_, remote)| remote.starts_with(dir))
+            };
+            let mut directories: HashSet<PathBuf> = remote_sync_data
+                .directories
+                .into_iter()
+                .filter(|dir| {
+                    local_sync_data.directories.contains(dir)
+                        || last_sync_data.directories.contains(dir)
+                        || !is_routing_dir(dir)
+                })
+                .collect();
+            // Local folders whose files were routed elsewhere still count as present on the USB drive
+            for (key, _) in &routed_locations {
+                directories.extend(
+                    key.ancestors()
+                        .skip(1)
+                        .filter(|ancestor| !ancestor.as_os_str().is_empty())
+                        .map(Path::to_path_buf),
+                );
+            }
+            directories
+        };
+        let remote_sync_data = SyncData {
+            files: remote_files,
+            directories: remote_directories,
+            ..Default::default()
+        };
+
+        // --- Safety check: files vanishing from the USB drive outside SyncU ---
+        if profile.safety_check && !last_sync_data.files.is_empty() {
+            let mut missing: Vec<PathBuf> = last_sync_data
+                .files
+                .keys()
+                .filter(|path| !remote_sync_data.files.contains_key(*path) && local_sync_data.files.contains_key(*path))
+                .cloned()
+                .collect();
+            let known = last_sync_data.files.len();
+            if missing.len() * 100 > known * profile.safety_threshold_percent as usize {
+                missing.sort();
+                let choice = observer.resolve_remote_missing(
+                    missing.len(),
+                    known,
+                    missing.iter().take(SAFETY_CHECK_EXAMPLES).cloned().collect(),
+                )?;
+                let message = match choice {
+                    RemoteMissingChoice::Recopy => {
+                        // Forgetting the missing files turns them into first-time copies to the USB drive
+                        for path in &missing {
+                            last_sync_data.files.remove(path);
+                        }
+                        last_sync_data_edited = true;
+                        let missing_dirs: Vec<PathBuf> = last_sync_data
+                            .directories
+                            .iter()
+                            .filter(|dir| !remote_sync_data.directories.contains(*dir) && local_sync_data.directories.contains(*dir))
+                            .cloned()
+                            .collect();
+                        for dir in &missing_dirs {
+                            last_sync_data.directories.remove(dir);
+                        }
+                        format!("[{}] 安全检查: U盘缺少 {} 个文件，将重新复制到U盘", Local::now().format("%H:%M:%S"), missing.len())
+                    }
+                    RemoteMissingChoice::DeleteLocal => format!("[{}] 安全检查: U盘缺少 {} 个文件，用户确认删除本地副本", Local::now().format("%H:%M:%S"), missing.len()),
+                    RemoteMissingChoice::Abort => format!("[{}] 安全检查: 用户取消同步", Local::now().format("%H:%M:%S")),
+                };
+                observer.on_log(message.clone());
+                write_log_entry(&message, LogLevel::Summary, profile.device_log_verbosity, &usb_sync_path)?;
+                if choice == RemoteMissingChoice::Abort {
+                    return Ok(true);
+                }
+            }
+        }
+
+        // --- Names the USB drive can't tell apart ---
+        // Local files whose names differ only by trailing dots or spaces would overwrite each other there
+        let mut collision_renames = HashMap::new();
+        let mut collision_skipped = HashSet::new();
+        if drops_trailing_dots_and_spaces(session.file_system()) {
+            let collisions = name_collisions(local_sync_data.files.keys(), |path| route_path(&profile.routing_rules, path));
+            if !collisions.is_empty() {
+                let renames: HashMap<PathBuf, PathBuf> = collisions
+                    .iter()
+                    .flat_map(|group| {
+                        group.iter().enumerate().skip(1).map(|(n, path)| {
+                            (path.clone(), collision_rename(&route_path(&profile.routing_rules, path), n))
+                        })
+                    })
+                    .collect();
+                // An earlier run already stored them under the numbered names
+                let settled = renames.iter().all(|(path, renamed)| remote_locations.get(path) == Some(renamed));
+                let choice = if settled {
+                    NameCollisionChoice::Rename
+                } else {
+                    observer.resolve_name_collisions(collisions.len(), collisions.iter().take(SAFETY_CHECK_EXAMPLES).cloned().collect())?
+                };
+                let message = match choice {
+                    NameCollisionChoice::Skip => {
+                        collision_skipped.extend(renames.into_keys());
+                        format!("[{}] 名称冲突: 跳过 {} 个文件", Local::now().format("%H:%M:%S"), collision_skipped.len())
+                    }
+                    NameCollisionChoice::Rename => {
+                        collision_renames = renames;
+                        format!("[{}] 名称冲突: {} 个文件在U盘上以编号名称保存", Local::now().format("%H:%M:%S"), collision_renames.len())
+                    }
+                    NameCollisionChoice::Abort => format!("[{}] 名称冲突: 用户取消同步", Local::now().format("%H:%M:%S")),
+                };
+                observer.on_log(message.clone());
+                write_log_entry(&message, LogLevel::Summary, profile.device_log_verbosity, &usb_sync_path)?;
+                if choice == NameCollisionChoice::Abort {
+                    return Ok(true);
+                }
+            }
+        }
+        // Where a local relative path belongs on the USB drive
+        let desired_location = |path: &Path| collision_renames.get(path).cloned().unwrap_or_else(|| route_path(&profile.routing_rules, path));
+
+        // Routing can aim a file at a place another one already takes, e.g. "x.jpg" routed into "Photos" next to a
+        // local "Photos/x.jpg". The file already there, or else the one not moved by routing, keeps the place; the
+        // others are left out so neither overwrites the other.
+        if !profile.routing_rules.is_empty() {
+            let mut by_location: HashMap<PathBuf, Vec<&PathBuf>> = HashMap::new();
+            for path in local_sync_data.files.keys().chain(remote_sync_data.files.keys().filter(|path| !local_sync_data.files.contains_key(*path))) {
+                by_location.entry(desired_location(path)).or_default().push(path);
+            }
+            let mut refused: Vec<PathBuf> = Vec::new();
+            for (location, mut group) in by_location.into_iter().filter(|(_, group)| group.len() > 1) {
+                group.sort();
+                let keeper = group
+                    .iter()
+                    .position(|path| remote_locations.get(*path) == Some(&location))
+                    .or_else(|| group.iter().position(|path| **path == location))
+                    .unwrap_or(0);
+                refused.extend(group.iter().enumerate().filter(|(n, _)| *n != keeper).map(|(_, path)| (*path).clone()));
+            }
+            if !refused.is_empty() {
+                refused.sort();
+                let message = format!(
+                    "[{}] 警告: {} 个文件按路由规则会与其他文件存放在同一位置，已跳过: {}",
+                    Local::now().format("%H:%M:%S"),
+                    refused.len(),
+                    refused.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(", ")
+                );
+                observer.on_log(message.clone());
+                write_log_entry(&message, LogLevel::Summary, profile.device_log_verbosity, &usb_sync_path)?;
+                collision_skipped.extend(refused);
+            }
+        }
+
+        report.start_phase("分析差异");
+        observer.on_progress(0.0, "正在分析文件差异...".to_string());
 
         // Use BTreeSet to ensure that operations are ordered correctly (parents before children)
         let mut sync_plan = BTreeSet::new();
+        // Paths whose entry from the last sync is carried over instead of their current state
+        let mut retained_paths = HashSet::new();
 
         // --- Directory Synchronization Logic ---
         let mut all_dirs = HashSet::new();
@@ -114,6 +888,11 @@ pub fn run_sync(
             };
         }
 
+        // Never delete a USB folder that still holds routed files belonging elsewhere
+        dirs_to_delete_remote.retain(|dir: &PathBuf| {
+            !remote_locations.iter().any(|(key, remote)| remote.starts_with(dir) && !key.starts_with(dir))
+        });
+
         // Prune directory lists
         let final_dirs_to_create_local = prune_ancestor_paths(&dirs_to_create_local);
         let final_dirs_to_create_remote = prune_ancestor_paths(&dirs_to_create_remote);
@@ -140,20 +919,75 @@ pub fn run_sync(
         all_files.extend(local_sync_data.files.keys().cloned());
         all_files.extend(remote_sync_data.files.keys().cloned());
 
+        // Tombstones go away once the path is gone from both sides
+        let mut tombstones = last_sync_data.tombstones.clone();
+        tombstones.retain(|path, _| local_sync_data.files.contains_key(path) || remote_sync_data.files.contains_key(path));
+        // A skipped conflict needs both versions to still exist
+        let mut skipped_conflicts = last_sync_data.skipped_conflicts.clone();
+        skipped_conflicts.retain(|path, _| local_sync_data.files.contains_key(path) && remote_sync_data.files.contains_key(path));
+
         for path in all_files {
-            if rx.try_recv() == Ok(SyncMessage::Stop) {
+            if observer.should_stop() {
                 return Ok(true);
             }
 
+            // Left out after a name or routing collision; its record from the last sync stays as it was
+            if collision_skipped.contains(&path) {
+                retained_paths.insert(path.clone());
+                continue;
+            }
+
             let last_info = last_sync_data.files.get(&path);
             let local_info = local_sync_data.files.get(&path);
             let remote_info = remote_sync_data.files.get(&path);
 
+            // A kept file stays out of the plan until its content changes
+            if let Some(kept_hash) = tombstones.get(&path) {
+                match (local_info, remote_info, last_info) {
+                    (Some(kept), None, Some(_)) | (None, Some(kept), Some(_)) if kept.hash == *kept_hash => {
+                        retained_paths.insert(path.clone());
+                        continue;
+                    }
+                    _ => {
+                        tombstones.remove(&path);
+                    }
+                }
+            }
+
+            // A skipped conflict stays skipped, and out of the record, until either version changes
+            if let Some(skipped) = skipped_conflicts.get(&path) {
+                match (local_info, remote_info) {
+                    (Some(local), Some(remote)) if local.hash == skipped.local_hash && remote.hash == skipped.remote_hash => {
+                        observer.on_log(format!("[{}] 冲突先前已跳过，双方均未再修改: {}", Local::now().format("%H:%M:%S"), path.display()));
+                        retained_paths.insert(path.clone());
+                        continue;
+                    }
+                    _ => {
+                        skipped_conflicts.remove(&path);
+                    }
+                }
+            }
+
             let action = match (local_info, remote_info, last_info) {
                 (Some(local), Some(remote), Some(last)) => {
                     let local_changed = local.hash != last.hash;
                     let remote_changed = remote.hash != last.hash;
-                    if local_changed && remote_changed { Some(SyncAction::Conflict { path: path.clone() }) }
+                    // Programs sometimes truncate a file for a moment, and an interrupted copy can leave an empty one.
+                    // A version that became empty is never spread over an intact copy, even if that one changed too.
+                    let emptied = |info: &FileInfo| info.size == 0 && last.size > 0;
+                    let truncated_local = local_changed && emptied(local) && remote.size > 0;
+                    let truncated_remote = remote_changed && emptied(remote) && local.size > 0;
+                    if profile.repair_truncated_files && (truncated_local || truncated_remote) {
+                        let side = if truncated_local { "本地" } else { "U盘" };
+                        observer.on_log(format!("警告: {}文件疑似损坏（变为 0 字节），已用另一侧的版本修复: {}", side, path.display()));
+                        if truncated_local { Some(SyncAction::RemoteToLocal(path.clone())) } else { Some(SyncAction::LocalToRemote(path.clone())) }
+                    }
+                    // The same edit made on both sides needs nothing; the final scan records the new hash
+                    else if local_changed && remote_changed && local.hash == remote.hash {
+                        observer.on_log(format!("[{}] 双方修改一致，无需操作: {}", Local::now().format("%H:%M:%S"), path.display()));
+                        None
+                    }
+                    else if local_changed && remote_changed { Some(SyncAction::Conflict { path: path.clone() }) }
                     else if local_changed { Some(SyncAction::LocalToRemote(path.clone())) }
                     else if remote_changed { Some(SyncAction::RemoteToLocal(path.clone())) }
                     else { None }
@@ -169,38 +1003,325 @@ pub fn run_sync(
                 _ => None,
             };
 
+            // Editors converting between CRLF and LF cause conflicts with nothing to decide; one side is still copied verbatim
+            let action = match (action, local_info, remote_info) {
+                (Some(SyncAction::Conflict { path }), Some(local), Some(remote))
+                    if profile.compares_line_endings(&path, local.size.max(remote.size)) =>
+                {
+                    let remote_file = usb_sync_path.join(remote_locations.get(&path).cloned().unwrap_or_else(|| desired_location(&path)));
+                    let keep = if differ_only_in_line_endings(&local_path.join(&path), &remote_file) {
+                        line_ending_resolution(profile.line_ending_policy, local, remote)
+                    } else {
+                        None
+                    };
+                    match keep {
+                        Some(side) => {
+                            let kept = if side == Side::Local { "本地" } else { "U盘" };
+                            observer.on_log(format!("[{}] 仅行尾不同，已自动处理（保留{}版本）: {}", Local::now().format("%H:%M:%S"), kept, path.display()));
+                            if side == Side::Local { Some(SyncAction::LocalToRemote(path)) } else { Some(SyncAction::RemoteToLocal(path)) }
+                        }
+                        None => Some(SyncAction::Conflict { path }),
+                    }
+                }
+                (action, _, _) => action,
+            };
+
             if let Some(action) = action {
                 sync_plan.insert(action);
             }
+
+            // Files whose routing changed since the last sync are moved on the USB drive instead of copied again
+            if local_info.is_some()
+                && let Some(current) = remote_locations.get(&path)
+            {
+                let desired = desired_location(&path);
+                if *current != desired {
+                    sync_plan.insert(SyncAction::MoveRemote { from: current.clone(), to: desired.clone() });
+                    // Moves run first, so later actions find the file at its new location
+                    remote_locations.insert(path.clone(), desired);
+                }
+            }
         }
-        
+
+        // Resolves where a local relative path lives (or will live) on the USB drive.
+        let remote_path = |path: &Path| {
+            usb_sync_path.join(
+                remote_locations
+                    .get(path)
+                    .cloned()
+                    .unwrap_or_else(|| desired_location(path)),
+            )
+        };
+
+        // Destinations deeper than the drive allows would otherwise fail one by one during copying
+        let mut skipped_files = HashSet::new();
+        let mut too_long: Vec<PathBuf> = sync_plan
+            .iter()
+            .filter_map(|action| match action {
+                SyncAction::LocalToRemote(path) | SyncAction::Conflict { path } => Some(path),
+                _ => None,
+            })
+            .filter(|path| remote_path(path).as_os_str().to_string_lossy().encode_utf16().count() > MAX_DESTINATION_PATH_LEN)
+            .cloned()
+            .collect();
+        if !too_long.is_empty() {
+            too_long.sort();
+            let choice = observer.resolve_long_paths(
+                MAX_DESTINATION_PATH_LEN,
+                too_long.len(),
+                too_long.iter().take(SAFETY_CHECK_EXAMPLES).map(|path| remote_path(path)).collect(),
+            )?;
+            let message = match choice {
+                LongPathChoice::Skip => {
+                    for path in &too_long {
+                        sync_plan.remove(&SyncAction::LocalToRemote(path.clone()));
+                        sync_plan.remove(&SyncAction::Conflict { path: path.clone() });
+                        skipped_files.insert(path.clone());
+                    }
+                    format!("[{}] 路径过长: 跳过 {} 个文件", Local::now().format("%H:%M:%S"), too_long.len())
+                }
+                LongPathChoice::Attempt => format!("[{}] 路径过长: 用户选择仍然尝试复制 {} 个文件", Local::now().format("%H:%M:%S"), too_long.len()),
+                LongPathChoice::Abort => format!("[{}] 路径过长: 用户取消同步", Local::now().format("%H:%M:%S")),
+            };
+            observer.on_log(message.clone());
+            write_log_entry(&message, LogLevel::Summary, profile.device_log_verbosity, &usb_sync_path)?;
+            if choice == LongPathChoice::Abort {
+                return Ok(true);
+            }
+        }
+
+        // A folder with more entries than a FAT drive allows fails partway through, so it is raised before copying.
+        // The USB side after the sync follows from the scan and the plan.
+        let mut remote_entries: HashSet<PathBuf> = remote_sync_data.directories.clone();
+        remote_entries.extend(remote_locations.values().cloned());
+        for action in &sync_plan {
+            match action {
+                SyncAction::LocalToRemote(path) if !remote_locations.contains_key(path) => {
+                    remote_entries.insert(desired_location(path));
+                }
+                SyncAction::CreateRemoteDir(path) => {
+                    remote_entries.insert(path.clone());
+                }
+                SyncAction::DeleteRemote(path) => {
+                    if let Some(location) = remote_locations.get(path) {
+                        remote_entries.remove(location);
+                    }
+                }
+                SyncAction::DeleteRemoteDir(path) => {
+                    remote_entries.remove(path);
+                }
+                _ => {}
+            }
+        }
+        let soft_limit = profile.directory_entry_soft_limit.map(|limit| limit as usize);
+        let crowded = crowded_directories(remote_entries.iter().map(PathBuf::as_path), session.file_system(), soft_limit);
+        if !crowded.is_empty() {
+            let crowded_paths: HashSet<PathBuf> = crowded.iter().map(|directory| directory.path.clone()).collect();
+            let choice = observer.resolve_crowded_directories(crowded)?;
+            let message = match choice {
+                CrowdedDirectoryChoice::Skip => {
+                    let added: Vec<PathBuf> = sync_plan
+                        .iter()
+                        .filter_map(|action| match action {
+                            SyncAction::LocalToRemote(path) if !remote_locations.contains_key(path) => Some(path),
+                            _ => None,
+                        })
+                        .filter(|path| desired_location(path).parent().is_some_and(|parent| crowded_paths.contains(parent)))
+                        .cloned()
+                        .collect();
+                    for path in &added {
+                        sync_plan.remove(&SyncAction::LocalToRemote(path.clone()));
+                        skipped_files.insert(path.clone());
+                    }
+                    format!("[{}] 文件夹项目过多: 跳过 {} 个新增文件", Local::now().format("%H:%M:%S"), added.len())
+                }
+                CrowdedDirectoryChoice::Attempt => format!("[{}] 文件夹项目过多: 用户选择仍然复制", Local::now().format("%H:%M:%S")),
+                CrowdedDirectoryChoice::Abort => format!("[{}] 文件夹项目过多: 用户取消同步", Local::now().format("%H:%M:%S")),
+            };
+            observer.on_log(message.clone());
+            write_log_entry(&message, LogLevel::Summary, profile.device_log_verbosity, &usb_sync_path)?;
+            if choice == CrowdedDirectoryChoice::Abort {
+                return Ok(true);
+            }
+        }
+
         // Convert BTreeSet to Vec for processing
         let sync_plan: Vec<_> = sync_plan.into_iter().collect();
 
-        let total_sync_size = sync_plan.iter().try_fold(0u64, |acc, action| -> Result<u64, Box<dyn std::error::Error>> {
-            Ok(acc + match action {
-                SyncAction::LocalToRemote(path) => fs::metadata(local_path.join(path))?.len(),
-                SyncAction::RemoteToLocal(path) => fs::metadata(usb_sync_path.join(path))?.len(),
-                SyncAction::Conflict { path, .. } => fs::metadata(local_path.join(path))?.len(),
-                _ => 0,
-            })
-        })?;
+        // The file whose size an action transfers
+        let size_source = |action: &SyncAction| match action {
+            SyncAction::LocalToRemote(path) | SyncAction::Conflict { path, .. } => Some(local_path.join(path)),
+            SyncAction::RemoteToLocal(path) => Some(remote_path(path)),
+            _ => None,
+        };
+        // Sizes that can't be read count as zero; a flaky drive shouldn't keep the user from seeing the plan.
+        // The plan indices of those files are tried once more when their turn comes.
+        let (total_sync_size, unreadable_sizes) = sync_plan.iter().enumerate().fold((0u64, BTreeMap::new()), |(total, mut unreadable), (index, action)| {
+            let Some(full_path) = size_source(action) else { return (total, unreadable) };
+            match fs::metadata(&full_path) {
+                Ok(metadata) => (total + metadata.len(), unreadable),
+                // A file deleted since the scan is skipped when it comes up
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    observer.on_log(format!("警告: 源文件在扫描后已不存在: {}", full_path.display()));
+                    (total, unreadable)
+                }
+                Err(e) => {
+                    unreadable.insert(index, e);
+                    (total, unreadable)
+                }
+            }
+        });
+        if let Some((&first, first_error)) = unreadable_sizes.iter().next() {
+            let count = unreadable_sizes.len();
+            let examples = unreadable_sizes.keys().take(SAFETY_CHECK_EXAMPLES).map(|&index| sync_plan[index].path().to_path_buf()).collect();
+            let proceed = observer.confirm_unreadable_sizes(count, examples)?;
+            let message = if proceed {
+                format!(
+                    "[{}] 无法读取 {} 个文件的大小 (例如 {}: {}): 继续同步，执行时仍无法读取的文件将被跳过",
+                    Local::now().format("%H:%M:%S"),
+                    count,
+                    sync_plan[first].path().display(),
+                    first_error
+                )
+            } else {
+                format!("[{}] 无法读取 {} 个文件的大小: 用户取消同步", Local::now().format("%H:%M:%S"), count)
+            };
+            observer.on_log(message.clone());
+            write_log_entry(&message, LogLevel::Summary, profile.device_log_verbosity, &usb_sync_path)?;
+            if !proceed {
+                return Ok(true);
+            }
+        }
 
-        let mut skipped_files = HashSet::new();
         let mut processed_size = 0u64;
         let sync_plan_len = sync_plan.len();
+        let mut stats = SyncStats { remaining: sync_plan_len, total_bytes: total_sync_size, ..Default::default() };
+        // Lets each deletion prompt say how many more a "全部…" answer would cover
+        let is_deletion = |action: &SyncAction| {
+            matches!(action, SyncAction::DeleteLocal(_) | SyncAction::DeleteRemote(_) | SyncAction::DeleteLocalDir(_) | SyncAction::DeleteRemoteDir(_))
+        };
+        let deletion_total = sync_plan.iter().filter(|action| is_deletion(action)).count();
+        let mut deletion_position = 0;
+        // Answers already given, so entries of a folder the user decided on aren't asked about again
+        let mut deletion_answers = DeletionAnswers::default();
 
+        // Long runs keep their plan on the drive, so an interruption doesn't cost hashing what was already transferred
+        let planned_source = |action: &SyncAction| {
+            let (side, info) = match action {
+                SyncAction::LocalToRemote(path) | SyncAction::Conflict { path } | SyncAction::DeleteLocal(path) => {
+                    (Side::Local, local_sync_data.files.get(path)?)
+                }
+                SyncAction::RemoteToLocal(path) | SyncAction::DeleteRemote(path) => (Side::Usb, remote_sync_data.files.get(path)?),
+                _ => return None,
+            };
+            Some(RecordedFile { side, info: info.clone() })
+        };
+        // The file a copy would overwrite, or its absence, so a resumed plan doesn't clobber what was written there since
+        let planned_target = |action: &SyncAction| {
+            let (side, root, destination, scanned) = match action {
+                SyncAction::LocalToRemote(path) | SyncAction::Conflict { path } => {
+                    (Side::Usb, &usb_sync_path, remote_path(path), remote_sync_data.files.get(path))
+                }
+                SyncAction::RemoteToLocal(path) => (Side::Local, local_path, local_path.join(path), local_sync_data.files.get(path)),
+                _ => return None,
+            };
+            Some(RecordedTarget { side, path: destination.strip_prefix(root).ok()?.to_path_buf(), info: scanned.cloned() })
+        };
+        let mut checkpoint = (sync_plan_len >= CHECKPOINT_MIN_ACTIONS || total_sync_size >= CHECKPOINT_MIN_BYTES).then(|| PlanCheckpoint {
+            source_folder: local_path.clone(),
+            items: sync_plan
+                .iter()
+                .map(|action| PlanItem {
+                    action: action.clone(),
+                    source: planned_source(action),
+                    target: planned_target(action),
+                    written: None,
+                    status: None,
+                })
+                .collect(),
+        });
+        match checkpoint {
+            Some(_) => save_checkpoint(&mut checkpoint, &plan_path, observer),
+            // A continued plan that is now small enough isn't kept any longer
+            None => {
+                let _ = fs::remove_file(&plan_path);
+            }
+        }
+        let mut checkpoint_saved = Instant::now();
+        // What a finished copy left at its destination. Its content is the source's, which the scan hashed.
+        let written_file = |action: &SyncAction| {
+            let (side, root, destination, source) = match action {
+                SyncAction::LocalToRemote(path) => (Side::Usb, &usb_sync_path, remote_path(path), local_sync_data.files.get(path)?),
+                SyncAction::RemoteToLocal(path) => (Side::Local, local_path, local_path.join(path), remote_sync_data.files.get(path)?),
+                _ => return None,
+            };
+            let metadata = fs::metadata(exact_path(&destination)).ok()?;
+            let info = FileInfo {
+                path: destination.strip_prefix(root).ok()?.to_path_buf(),
+                hash: source.hash.clone(),
+                modified: metadata.modified().ok()?,
+                size: metadata.len(),
+                verified: false,
+            };
+            Some(RecordedFile { side, info })
+        };
+
+        observer.on_plan(&sync_plan);
+        report.set_plan(sync_plan.iter().map(|action| (action.clone(), planned_source(action).map(|source| source.info.size))));
+        report.start_phase("执行计划");
         if sync_plan.is_empty() {
-            tx.send(SyncMessage::Log("未检测到变化.".to_owned()))?;
+            observer.on_log("未检测到变化.".to_owned());
         } else {
-            tx.send(SyncMessage::Log(format!("计划执行 {} 个同步操作...", sync_plan_len)))?;
+            observer.on_log(format!("计划执行 {} 个同步操作...", sync_plan_len));
+            observer.on_stats(stats.clone());
+
+            // Net change on the USB drive: overwrites count the size difference, deletions free the whole file.
+            // Conflicts are left out since their direction isn't known yet.
+            let remote_size = |path: &PathBuf| remote_sync_data.files.get(path).map_or(0, |info| info.size as i64);
+            let usb_delta: i64 = sync_plan
+                .iter()
+                .map(|action| match action {
+                    SyncAction::LocalToRemote(path) => {
+                        local_sync_data.files.get(path).map_or(0, |info| info.size as i64) - remote_size(path)
+                    }
+                    SyncAction::DeleteRemote(path) => -remote_size(path),
+                    _ => 0,
+                })
+                .sum();
+            if let Some(available) = session.available_space() {
+                let estimate = SpaceEstimate { available, after: available as i64 - usb_delta };
+                observer.on_log(estimate.summary());
+                if let Some(shortfall) = estimate.shortfall() {
+                    observer.on_log(format!("警告: U盘空间可能不足，约缺少 {}", format_size(shortfall)));
+                }
+                observer.on_space_estimate(estimate);
+            }
+            // Later runs on the drive estimate from here instead of reading the free space again
+            session.record_space_used(usb_delta);
+
+            if profile.small_files_hint {
+                let small_files = sync_plan
+                    .iter()
+                    .filter(|action| match action {
+                        SyncAction::LocalToRemote(path) => local_sync_data.files.get(path).is_some_and(|info| info.size < SMALL_FILE_MAX_BYTES),
+                        _ => false,
+                    })
+                    .count();
+                if small_files >= SMALL_FILE_HINT_MIN_COUNT {
+                    observer.on_many_small_files(small_files);
+                }
+            }
         }
 
         const BATCH_SIZE: usize = 16;
+        let mut declined_log = DeclinedDeletionLog { verbosity: profile.device_log_verbosity, ..Default::default() };
+        // Files this run copied to the local folder, which the final scan reads back
+        let mut copied_to_local = HashSet::new();
         let mut batch_start = 0;
         
         while batch_start < sync_plan.len() {
-            if rx.try_recv() == Ok(SyncMessage::Stop) {
+            if observer.should_stop() {
+                save_checkpoint(&mut checkpoint, &plan_path, observer);
                 return Ok(true);
             }
             
@@ -210,222 +1331,1073 @@ pub fn run_sync(
             for (i, action) in batch.iter().enumerate() {
                 let index = batch_start + i;
 
-                if rx.try_recv() == Ok(SyncMessage::Stop) {
+                if observer.should_stop() {
+                    save_checkpoint(&mut checkpoint, &plan_path, observer);
                     return Ok(true);
                 }
 
                 let (file_size, current_file_name) = match action {
+                    SyncAction::MoveRemote { from, .. } => {
+                        (0, format!("移动: {}", from.display()))
+                    }
                     SyncAction::LocalToRemote(path) | SyncAction::RemoteToLocal(path) | SyncAction::Conflict { path, .. } => {
-                        let full_path = if matches!(action, SyncAction::RemoteToLocal(_)) { usb_sync_path.join(path) } else { local_path.join(path) };
-                        (fs::metadata(&full_path).map(|m| m.len()).unwrap_or(0), path.to_str().unwrap_or("").to_string())
+                        let full_path = if matches!(action, SyncAction::RemoteToLocal(_)) { remote_path(path) } else { local_path.join(path) };
+                        (fs::metadata(&full_path).map(|m| m.len()).unwrap_or(0), path.display().to_string())
                     }
                     SyncAction::DeleteLocal(path) | SyncAction::DeleteRemote(path) => {
-                        (0, format!("删除: {}", path.to_str().unwrap_or("")))
+                        (0, format!("删除: {}", path.display()))
                     }
                     SyncAction::CreateLocalDir(path) | SyncAction::CreateRemoteDir(path) => {
-                        (0, format!("创建目录: {}", path.to_str().unwrap_or("")))
+                        (0, format!("创建目录: {}", path.display()))
                     }
                     SyncAction::DeleteLocalDir(path) | SyncAction::DeleteRemoteDir(path) => {
-                        (0, format!("删除目录: {}", path.to_str().unwrap_or("")))
+                        (0, format!("删除目录: {}", path.display()))
                     }
                 };
 
                 let progress = if total_sync_size > 0 { processed_size as f32 / total_sync_size as f32 } else { 0.0 };
-                tx.send(SyncMessage::Progress(progress, format!("({}/{})正在处理: {}", index + 1, sync_plan_len, current_file_name)))?;
+                observer.on_action_started(index, action);
+                observer.on_progress(progress, format!("({}/{})正在处理: {}", index + 1, sync_plan_len, current_file_name));
+                if is_deletion(action) {
+                    deletion_position += 1;
+                }
 
-                let message = match action {
+                // How a conflict was resolved, for the report
+                let mut resolved = None;
+                // A file whose size couldn't be read while planning gets this one more try. A vanished one is left to the copy.
+                let still_unreadable = unreadable_sizes
+                    .contains_key(&index)
+                    .then(|| size_source(action).and_then(|full_path| fs::metadata(full_path).err()))
+                    .flatten()
+                    .filter(|e| e.kind() != io::ErrorKind::NotFound);
+                let outcome = (|| -> Result<ActionOutcome, SyncError> { Ok(match action {
+                    SyncAction::LocalToRemote(path) | SyncAction::RemoteToLocal(path) | SyncAction::Conflict { path } if still_unreadable.is_some() => {
+                        retained_paths.insert(path.clone());
+                        let error = still_unreadable.as_ref().map(io::Error::to_string).unwrap_or_default();
+                        ActionOutcome::Skipped(format!("[{}] 仍无法读取，已跳过: {}: {}", Local::now().format("%H:%M:%S"), path.display(), error))
+                    }
+                    SyncAction::MoveRemote { from, to } => {
+                        let from_path = usb_sync_path.join(from);
+                        let to_path = usb_sync_path.join(to);
+                        if let Some(parent) = to_path.parent() { fs::create_dir_all(parent).at(parent)?; }
+                        fs::rename(&from_path, &to_path).at(&from_path)?;
+                        cleanup_empty_dirs(&from_path, &usb_sync_path)?;
+                        ActionOutcome::Done(format!("[{}] 移动U盘文件: {} -> {}", Local::now().format("%H:%M:%S"), from.display(), to.display()))
+                    }
                     SyncAction::LocalToRemote(path) => {
                         let from = local_path.join(path);
-                        let to = usb_sync_path.join(path);
-                        if let Some(parent) = to.parent() { fs::create_dir_all(parent)?; }
-                        if fs::metadata(&from)?.len() > LARGE_FILE_THRESHOLD {
-                            if copy_large_file_with_progress(&from, &to, &current_file_name, &tx, &rx, total_sync_size, processed_size)? {
-                                return Ok(true); // Stopped
-                            }
-                        } else { fs::copy(&from, &to)?; }
-                        format!("[{}] 本地 -> U盘: {}", Local::now().format("%H:%M:%S"), from.strip_prefix(local_path)?.display())
+                        let to = remote_path(path);
+                        let outcome = copy_for_action(&from, &to, &current_file_name, &profile, extended_attributes, observer, &limiter, (total_sync_size, processed_size), &mut stats)?;
+                        let message = format!("[{}] 本地 -> U盘: {}", Local::now().format("%H:%M:%S"), path.display());
+                        finish_copy(outcome, path, message, &mut retained_paths)
                     }
                     SyncAction::RemoteToLocal(path) => {
-                        let from = usb_sync_path.join(path);
+                        let from = remote_path(path);
                         let to = local_path.join(path);
-                        if let Some(parent) = to.parent() { fs::create_dir_all(parent)?; }
-                        if fs::metadata(&from)?.len() > LARGE_FILE_THRESHOLD {
-                            if copy_large_file_with_progress(&from, &to, &current_file_name, &tx, &rx, total_sync_size, processed_size)? {
-                                return Ok(true); // Stopped
-                            }
-                        } else { fs::copy(&from, &to)?; }
-                        format!("[{}] U盘 -> 本地: {}", Local::now().format("%H:%M:%S"), from.strip_prefix(&usb_sync_path)?.display())
+                        let outcome = copy_for_action(&from, &to, &current_file_name, &profile, extended_attributes, observer, &limiter, (total_sync_size, processed_size), &mut stats)?;
+                        let message = format!("[{}] U盘 -> 本地: {}", Local::now().format("%H:%M:%S"), path.display());
+                        if matches!(outcome, CopyOutcome::Copied) {
+                            copied_to_local.insert(path.clone());
+                        }
+                        finish_copy(outcome, path, message, &mut retained_paths)
                     }
                     SyncAction::DeleteRemote(path) => {
-                        let absolute_path = usb_sync_path.join(path);
-                        tx.send(SyncMessage::ConfirmDeletion(absolute_path.clone()))?;
-                        let confirmed = match wait_for_message(&rx, |msg| match msg {
-                            SyncMessage::DeletionConfirmed(c) => Some(c),
-                            _ => None,
-                        }) {
-                            Ok(Some(c)) => c,
-                            _ => return Ok(true), // Stopped or disconnected
+                        let absolute_path = remote_path(path);
+                        let decision = match confirm_deletion(
+                            &answer_rules,
+                            &mut deletion_answers,
+                            Side::Usb,
+                            path,
+                            false,
+                            &absolute_path,
+                            (deletion_position, deletion_total),
+                            observer,
+                        ) {
+                            Ok(decision) => decision,
+                            Err(SyncError::Cancelled) => return Ok(ActionOutcome::Stopped),
+                            Err(e) => return Err(e),
                         };
-                        if confirmed {
+                        if decision == DeletionDecision::KeepPermanently {
+                            if let Some(info) = remote_sync_data.files.get(path) {
+                                tombstones.insert(path.clone(), info.hash.clone());
+                            }
+                            retained_paths.insert(path.clone());
+                            return Ok(ActionOutcome::Skipped(format!("[{}] 保留且不再询问: {}", Local::now().format("%H:%M:%S"), path.display())));
+                        }
+                        if decision == DeletionDecision::Delete {
                             if absolute_path.exists() { 
-                                fs::remove_file(&absolute_path)?; 
+                                fs::remove_file(&absolute_path).at(&absolute_path)?;
                                 cleanup_empty_dirs(&absolute_path, &usb_sync_path)?;
                             }
-                            format!("[{}] 删除U盘文件: {}", Local::now().format("%H:%M:%S"), path.display())
+                            ActionOutcome::Done(format!("[{}] 删除U盘文件: {}", Local::now().format("%H:%M:%S"), path.display()))
                         } else {
-                            format!("[{}] 取消删除: {}", Local::now().format("%H:%M:%S"), path.display())
+                            ActionOutcome::Skipped(format!("[{}] 取消删除: {}", Local::now().format("%H:%M:%S"), path.display()))
                         }
                     }
                     SyncAction::DeleteLocal(path) => {
                         let absolute_path = local_path.join(path);
-                        tx.send(SyncMessage::ConfirmDeletion(absolute_path.clone()))?;
-                        let confirmed = match wait_for_message(&rx, |msg| match msg {
-                            SyncMessage::DeletionConfirmed(c) => Some(c),
-                            _ => None,
-                        }) {
-                            Ok(Some(c)) => c,
-                            _ => return Ok(true), // Stopped or disconnected
+                        let decision = match confirm_deletion(
+                            &answer_rules,
+                            &mut deletion_answers,
+                            Side::Local,
+                            path,
+                            false,
+                            &absolute_path,
+                            (deletion_position, deletion_total),
+                            observer,
+                        ) {
+                            Ok(decision) => decision,
+                            Err(SyncError::Cancelled) => return Ok(ActionOutcome::Stopped),
+                            Err(e) => return Err(e),
                         };
-                        if confirmed {
+                        if decision == DeletionDecision::KeepPermanently {
+                            if let Some(info) = local_sync_data.files.get(path) {
+                                tombstones.insert(path.clone(), info.hash.clone());
+                            }
+                            retained_paths.insert(path.clone());
+                            return Ok(ActionOutcome::Skipped(format!("[{}] 保留且不再询问: {}", Local::now().format("%H:%M:%S"), path.display())));
+                        }
+                        if decision == DeletionDecision::Delete {
                             if absolute_path.exists() { 
-                                fs::remove_file(&absolute_path)?; 
+                                fs::remove_file(&absolute_path).at(&absolute_path)?;
                                 cleanup_empty_dirs(&absolute_path, local_path)?;
                             }
-                            format!("[{}] 删除本地文件: {}", Local::now().format("%H:%M:%S"), path.display())
+                            ActionOutcome::Done(format!("[{}] 删除本地文件: {}", Local::now().format("%H:%M:%S"), path.display()))
                         } else {
-                            format!("[{}] 取消删除: {}", Local::now().format("%H:%M:%S"), path.display())
+                            ActionOutcome::Skipped(format!("[{}] 取消删除: {}", Local::now().format("%H:%M:%S"), path.display()))
                         }
                     }
                     SyncAction::Conflict { path } => {
-                        tx.send(SyncMessage::AskForConflictResolution { path: path.clone() })?;
-                        let resolution = match wait_for_message(&rx, |msg| match msg {
-                            SyncMessage::ConflictResolved(r) => Some(r),
-                            _ => None,
-                        }) {
-                            Ok(Some(r)) => r,
-                            _ => return Ok(true), // Stopped or disconnected
+                        // A file the user answered for once resolves the same way without asking
+                        let resolution = if let Some(remembered) = profile.conflict_rule_for(path) {
+                            observer.on_log(format!("[{}] 按记住的选择处理冲突: {}", Local::now().format("%H:%M:%S"), path.display()));
+                            remembered.clone()
+                        } else if let Some((number, rule, resolution)) = answer_rules.conflict_answer(path) {
+                            observer.on_log(format!(
+                                "[{}] 按应答规则 #{} ({}) 处理冲突 ({}): {}",
+                                Local::now().format("%H:%M:%S"),
+                                number,
+                                rule.pattern,
+                                rule.on_conflict.map_or("", |answer| answer.label()),
+                                path.display()
+                            ));
+                            resolution
+                        } else {
+                            let diff = text_diff_preview(&local_path.join(path), &remote_path(path));
+                            let state_of = |path: &Path| fs::metadata(path).map_or((None, 0), |metadata| (metadata.modified().ok(), metadata.len()));
+                            let ((local_modified, local_size), (remote_modified, remote_size)) = (state_of(&local_path.join(path)), state_of(&remote_path(path)));
+                            let suggestion = suggest_conflict_resolution(local_modified, local_size, remote_modified, remote_size);
+                            match observer.resolve_conflict(path, diff, suggestion) {
+                                Ok(r) => r,
+                                Err(SyncError::Cancelled) => return Ok(ActionOutcome::Stopped),
+                                Err(e) => return Err(e),
+                            }
                         };
+                        resolved = Some(resolution.clone());
 
                         match resolution {
                             Resolution::KeepLocal => {
                                 let from = local_path.join(path);
-                                let to = usb_sync_path.join(path);
-                                if let Some(parent) = to.parent() { fs::create_dir_all(parent)?; }
-                                if fs::metadata(&from)?.len() > LARGE_FILE_THRESHOLD {
-                                    if copy_large_file_with_progress(&from, &to, &current_file_name, &tx, &rx, total_sync_size, processed_size)? {
-                                        return Ok(true); // Stopped
-                                    }
-                                } else { fs::copy(&from, &to)?; }
-                                format!("[{}] 冲突解决 (采用本地): {}", Local::now().format("%H:%M:%S"), from.strip_prefix(local_path)?.display())
+                                let to = remote_path(path);
+                                let outcome = copy_for_action(&from, &to, &current_file_name, &profile, extended_attributes, observer, &limiter, (total_sync_size, processed_size), &mut stats)?;
+                                let message = format!("[{}] 冲突解决 (采用本地): {}", Local::now().format("%H:%M:%S"), path.display());
+                                finish_copy(outcome, path, message, &mut retained_paths)
                             }
                             Resolution::KeepRemote => {
-                                let from = usb_sync_path.join(path);
+                                let from = remote_path(path);
                                 let to = local_path.join(path);
-                                if let Some(parent) = to.parent() { fs::create_dir_all(parent)?; }
-                                if fs::metadata(&from)?.len() > LARGE_FILE_THRESHOLD {
-                                    if copy_large_file_with_progress(&from, &to, &current_file_name, &tx, &rx, total_sync_size, processed_size)? {
-                                        return Ok(true); // Stopped
-                                    }
-                                } else { fs::copy(&from, &to)?; }
-                                format!("[{}] 冲突解决 (采用U盘): {}", Local::now().format("%H:%M:%S"), from.strip_prefix(&usb_sync_path)?.display())
+                                let outcome = copy_for_action(&from, &to, &current_file_name, &profile, extended_attributes, observer, &limiter, (total_sync_size, processed_size), &mut stats)?;
+                                let message = format!("[{}] 冲突解决 (采用U盘): {}", Local::now().format("%H:%M:%S"), path.display());
+                                if matches!(outcome, CopyOutcome::Copied) {
+                                    copied_to_local.insert(path.clone());
+                                }
+                                finish_copy(outcome, path, message, &mut retained_paths)
                             }
                             Resolution::Skip => {
                                 skipped_files.insert(path.clone());
-                                format!("[{}] 跳过冲突文件: {}", Local::now().format("%H:%M:%S"), path.display())
+                                if let (Some(local), Some(remote)) = (local_sync_data.files.get(path), remote_sync_data.files.get(path)) {
+                                    skipped_conflicts.insert(
+                                        path.clone(),
+                                        SkippedConflict { local_hash: local.hash.clone(), remote_hash: remote.hash.clone() },
+                                    );
+                                }
+                                ActionOutcome::Skipped(format!("[{}] 跳过冲突文件: {}", Local::now().format("%H:%M:%S"), path.display()))
                             }
                         }
                     }
                     SyncAction::CreateLocalDir(path) => {
-                        fs::create_dir_all(local_path.join(path))?;
-                        format!("[{}] 创建本地目录: {}", Local::now().format("%H:%M:%S"), path.display())
+                        let dir_to_create = local_path.join(path);
+                        fs::create_dir_all(&dir_to_create).at(&dir_to_create)?;
+                        ActionOutcome::Done(format!("[{}] 创建本地目录: {}", Local::now().format("%H:%M:%S"), path.display()))
                     }
                     SyncAction::CreateRemoteDir(path) => {
-                        fs::create_dir_all(usb_sync_path.join(path))?;
-                        format!("[{}] 创建U盘目录: {}", Local::now().format("%H:%M:%S"), path.display())
+                        let dir_to_create = usb_sync_path.join(path);
+                        fs::create_dir_all(&dir_to_create).at(&dir_to_create)?;
+                        ActionOutcome::Done(format!("[{}] 创建U盘目录: {}", Local::now().format("%H:%M:%S"), path.display()))
                     }
                     SyncAction::DeleteLocalDir(path) => {
                         let dir_to_delete = local_path.join(path);
-                        tx.send(SyncMessage::ConfirmDeletion(dir_to_delete.clone()))?;
-                        let confirmed = match wait_for_message(&rx, |msg| match msg {
-                            SyncMessage::DeletionConfirmed(c) => Some(c),
-                            _ => None,
-                        }) {
-                            Ok(Some(c)) => c,
-                            _ => return Ok(true), // Stopped or disconnected
+                        // Directories can't be kept permanently, so that answer just keeps them this time
+                        let decision = match confirm_deletion(
+                            &answer_rules,
+                            &mut deletion_answers,
+                            Side::Local,
+                            path,
+                            true,
+                            &dir_to_delete,
+                            (deletion_position, deletion_total),
+                            observer,
+                        ) {
+                            Ok(decision) => decision,
+                            Err(SyncError::Cancelled) => return Ok(ActionOutcome::Stopped),
+                            Err(e) => return Err(e),
                         };
 
-                        if confirmed {
-                            if dir_to_delete.exists() {
-                                fs::remove_dir_all(&dir_to_delete)?;
+                        if decision == DeletionDecision::Delete {
+                            if dir_to_delete.exists() && remove_dir_all_with_progress(&dir_to_delete, &path.display().to_string(), progress, observer)? {
+                                return Ok(ActionOutcome::Stopped);
                             }
-                            format!("[{}] 删除本地目录: {}", Local::now().format("%H:%M:%S"), path.display())
+                            ActionOutcome::Done(format!("[{}] 删除本地目录: {}", Local::now().format("%H:%M:%S"), path.display()))
                         } else {
-                            format!("[{}] 取消删除目录: {}", Local::now().format("%H:%M:%S"), path.display())
+                            ActionOutcome::Skipped(format!("[{}] 取消删除目录: {}", Local::now().format("%H:%M:%S"), path.display()))
                         }
                     }
                     SyncAction::DeleteRemoteDir(path) => {
                         let dir_to_delete = usb_sync_path.join(path);
-                        tx.send(SyncMessage::ConfirmDeletion(dir_to_delete.clone()))?;
-                        let confirmed = match wait_for_message(&rx, |msg| match msg {
-                            SyncMessage::DeletionConfirmed(c) => Some(c),
-                            _ => None,
-                        }) {
-                            Ok(Some(c)) => c,
-                            _ => return Ok(true), // Stopped or disconnected
+                        // Directories can't be kept permanently, so that answer just keeps them this time
+                        let decision = match confirm_deletion(
+                            &answer_rules,
+                            &mut deletion_answers,
+                            Side::Usb,
+                            path,
+                            true,
+                            &dir_to_delete,
+                            (deletion_position, deletion_total),
+                            observer,
+                        ) {
+                            Ok(decision) => decision,
+                            Err(SyncError::Cancelled) => return Ok(ActionOutcome::Stopped),
+                            Err(e) => return Err(e),
                         };
 
-                        if confirmed {
-                            if dir_to_delete.exists() {
-                                fs::remove_dir_all(&dir_to_delete)?;
+                        if decision == DeletionDecision::Delete {
+                            if dir_to_delete.exists() && remove_dir_all_with_progress(&dir_to_delete, &path.display().to_string(), progress, observer)? {
+                                return Ok(ActionOutcome::Stopped);
                             }
-                            format!("[{}] 删除U盘目录: {}", Local::now().format("%H:%M:%S"), path.display())
+                            ActionOutcome::Done(format!("[{}] 删除U盘目录: {}", Local::now().format("%H:%M:%S"), path.display()))
                         } else {
-                            format!("[{}] 取消删除目录: {}", Local::now().format("%H:%M:%S"), path.display())
+                            ActionOutcome::Skipped(format!("[{}] 取消删除目录: {}", Local::now().format("%H:%M:%S"), path.display()))
+                        }
+                    }
+                }) })();
+
+                // Set for declined deletions, which the USB log may summarize instead of listing
+                let mut declined = None;
+                let (message, status) = match outcome {
+                    Ok(ActionOutcome::Done(message)) => {
+                        stats.completed += 1;
+                        (message, ActionStatus::Done)
+                    }
+                    Ok(ActionOutcome::Skipped(message)) => {
+                        stats.skipped += 1;
+                        match action {
+                            SyncAction::Conflict { .. } => stats.skipped_conflicts += 1,
+                            SyncAction::DeleteLocal(path) | SyncAction::DeleteRemote(path) => {
+                                stats.declined_deletions += 1;
+                                declined = Some(("取消删除", path));
+                            }
+                            SyncAction::DeleteLocalDir(path) | SyncAction::DeleteRemoteDir(path) => {
+                                stats.declined_deletions += 1;
+                                declined = Some(("取消删除目录", path));
+                            }
+                            _ => {}
                         }
+                        (message, ActionStatus::Skipped)
+                    }
+                    Ok(ActionOutcome::Stopped) | Err(SyncError::Cancelled) => {
+                        save_checkpoint(&mut checkpoint, &plan_path, observer);
+                        declined_log.flush(&usb_sync_path)?;
+                        return Ok(true);
+                    }
+                    // Pulling the drive makes every remaining action fail, so end the run instead
+                    Err(SyncError::Io { .. }) if !usb_root_path.exists() => {
+                        return Err(SyncError::DeviceMissing(usb_root_path.to_path_buf()));
+                    }
+                    // So does a full volume; the kept plan lets the next run pick up after space is freed
+                    Err(SyncError::Io { path, source }) if classify_io_error(&source) == IoErrorCategory::DiskFull => {
+                        save_checkpoint(&mut checkpoint, &plan_path, observer);
+                        declined_log.flush(&usb_sync_path)?;
+                        return Err(SyncError::DiskFull(path));
+                    }
+                    Err(e) => {
+                        // A failed action doesn't abort the run; the next sync re-evaluates the path
+                        stats.failed += 1;
+                        let category = match &e {
+                            SyncError::Io { source, .. } => classify_io_error(source),
+                            _ => IoErrorCategory::Other,
+                        };
+                        match category {
+                            IoErrorCategory::Permission => stats.permission_failures += 1,
+                            IoErrorCategory::Path => stats.path_failures += 1,
+                            IoErrorCategory::Device => stats.device_failures += 1,
+                            IoErrorCategory::DiskFull | IoErrorCategory::Other => {}
+                        }
+                        match action {
+                            SyncAction::DeleteRemote(path) | SyncAction::DeleteRemoteDir(path) => {
+                                retained_paths.insert(path.clone());
+                            }
+                            SyncAction::LocalToRemote(path) | SyncAction::RemoteToLocal(path) | SyncAction::Conflict { path } => {
+                                skipped_files.insert(path.clone());
+                            }
+                            _ => {}
+                        }
+                        // Retrying can't shorten a path, so point at what can
+                        let hint = if category == IoErrorCategory::Path { " (路径过长或名称无效，可缩短路径或用路由规则改变U盘上的位置)" } else { "" };
+                        (format!("错误: {}: {}{}", current_file_name, e, hint), ActionStatus::Failed)
                     }
                 };
+                stats.remaining -= 1;
                 processed_size += file_size;
-                tx.send(SyncMessage::Log(message.clone()))?;
-                write_log_entry(&message, &usb_sync_path)?;
+                observer.on_log(message.clone());
+                observer.on_action_finished(index, status);
+                report.finish_action(index, status, resolved, (status == ActionStatus::Failed).then(|| message.clone()));
+                if let Some(plan) = &mut checkpoint {
+                    let item = &mut plan.items[index];
+                    item.status = Some(status);
+                    // A file changed during its copy is synced again next time, so its state isn't worth keeping
+                    let changed_during_copy = matches!(action, SyncAction::LocalToRemote(path) | SyncAction::RemoteToLocal(path) if retained_paths.contains(path));
+                    if status == ActionStatus::Done && !changed_during_copy {
+                        item.written = written_file(action);
+                    }
+                }
+                observer.on_stats(stats.clone());
+                match declined {
+                    Some((label, path)) if !profile.detailed_device_log => declined_log.push(label, path, &usb_sync_path)?,
+                    _ => {
+                        declined_log.flush(&usb_sync_path)?;
+                        let level = if status == ActionStatus::Failed { LogLevel::Summary } else { LogLevel::Detail };
+                        write_log_entry(&message, level, profile.device_log_verbosity, &usb_sync_path)?;
+                    }
+                }
             }
-            
+            if checkpoint_saved.elapsed() >= CHECKPOINT_INTERVAL {
+                save_checkpoint(&mut checkpoint, &plan_path, observer);
+                checkpoint_saved = Instant::now();
+            }
+
             batch_start = batch_end;
         }
+        declined_log.flush(&usb_sync_path)?;
+        // Every action has run; if the final steps are interrupted, the next run still finds what they wrote
+        save_checkpoint(&mut checkpoint, &plan_path, observer);
+
+        if sync_plan_len > 0 {
+            session.record_written(&usb_sync_path);
+            let mut summary = format!("[{}] 同步统计: {}", Local::now().format("%H:%M:%S"), stats.summary());
+            if stats.hard_linked_destinations > 0 {
+                summary += &format!(" · 有其他硬链接的目标文件 {} 个 ({})", stats.hard_linked_destinations, profile.hard_link_policy.label());
+            }
+            observer.on_log(summary.clone());
+            write_log_entry(&summary, LogLevel::Summary, profile.device_log_verbosity, &usb_sync_path)?;
+        }
 
-        if rx.try_recv() == Ok(SyncMessage::Stop) { return Ok(true); }
-        tx.send(SyncMessage::Progress(0.99, "正在生成新的同步记录...".to_string()))?;
+        if observer.should_stop() { return Ok(true); }
+        report.start_phase("更新同步记录");
+        observer.on_progress(0.99, "正在生成新的同步记录...".to_string());
+        // Files this run didn't touch still carry the size and mtime of the first scan, so only written files are hashed again.
+        // Without a trustworthy clock that shortcut is unsafe, so everything is hashed as before.
+        let final_hash_reference = if full_rehash { &empty_sync_data } else { &local_sync_data };
         let final_scan_result =
-            scan_directory_with_progress(local_path, &tx, &rx, local_total, "更新本地元数据", &SyncData::default())?;
+            scan_directory_with_progress(local_path, observer, Some(local_total), "更新本地元数据", final_hash_reference, hashing)?;
 
+        let record_unchanged;
         if let Some(mut final_sync_data) = final_scan_result {
+            // The final scan read back every copy to this machine; matching the scanned USB version verifies it
+            for path in &copied_to_local {
+                if let (Some(info), Some(source)) = (final_sync_data.files.get_mut(path), remote_sync_data.files.get(path)) {
+                    info.verified = info.hash == source.hash;
+                }
+            }
+            // The final scan is exactly what this machine sees now, before any entries are carried over
+            let own_observations = final_sync_data.files.clone();
             final_sync_data.files.retain(|path, _| !skipped_files.contains(path));
-            save_sync_data(&final_sync_data, &metadata_path)?;
+            for path in &retained_paths {
+                match last_sync_data.files.get(path) {
+                    Some(info) => final_sync_data.files.insert(path.clone(), info.clone()),
+                    None => final_sync_data.files.remove(path),
+                };
+                if last_sync_data.directories.contains(path) {
+                    final_sync_data.directories.insert(path.clone());
+                }
+            }
+            final_sync_data.last_sync_time = Some(SystemTime::now());
+            final_sync_data.tombstones = tombstones;
+            final_sync_data.skipped_conflicts = skipped_conflicts;
+            final_sync_data.source_folder = Some(local_path.clone());
+            final_sync_data.volume_id = session.volume_id().map(str::to_owned);
+            final_sync_data.record_observations(&machine, &own_observations, &last_sync_data);
+            final_sync_data.routes = final_sync_data
+                .files
+                .keys()
+                .filter_map(|path| {
+                    let routed = desired_location(path);
+                    (routed != *path).then(|| (path.clone(), routed))
+                })
+                .collect();
+            // Carried-over entries keep their recorded location, e.g. a file renamed for a name collision
+            for path in retained_paths.iter().filter(|path| final_sync_data.files.contains_key(*path)) {
+                if let Some(route) = last_sync_data.routes.get(path) {
+                    final_sync_data.routes.entry(path.clone()).or_insert_with(|| route.clone());
+                }
+            }
+            // Rewriting an identical record only wears the drive; it keeps the time of the last run that changed something
+            record_unchanged = !last_sync_data_edited && final_sync_data.same_state(&last_sync_data);
+            if !save_record {
+                observer.on_log(format!("[{}] 同步记录由较新版本写入，按要求未更新", Local::now().format("%H:%M:%S")));
+                let _ = fs::remove_file(&plan_path);
+            } else if record_unchanged {
+                observer.on_log(format!("[{}] 同步记录未变化，跳过写入", Local::now().format("%H:%M:%S")));
+                let _ = fs::remove_file(&plan_path);
+            } else {
+                session.record_written(&usb_sync_path);
+                let save_started = Instant::now();
+                // The files are already synced at this point, so a failure here only affects the recorded state
+                match save_sync_data_with_progress(&final_sync_data, &metadata_path, observer)
+                    .map_err(|e| SyncError::StateNotPersisted(Box::new(e)))?
+                {
+                    Some(size) => {
+                        observer.on_log(format!(
+                            "[{}] 同步记录已写入: {}，用时 {:.1} 秒",
+                            Local::now().format("%H:%M:%S"),
+                            format_size(size),
+                            save_started.elapsed().as_secs_f64()
+                        ));
+                        // The record now covers everything the plan did
+                        let _ = fs::remove_file(&plan_path);
+                    }
+                    None => return Ok(true),
+                }
+            }
+
+            if let Some(secondary_root) = &profile.secondary_destination {
+                // The backup is best effort: its failures never fail the primary sync
+                report.start_phase("备份");
+                let secondary_path = secondary_root.join(sync_folder_name);
+                let mirrored = mirror_to_secondary(local_path, &final_sync_data, &secondary_path, &profile, &mut stats, observer, &limiter);
+                observer.on_stats(stats.clone());
+                match mirrored {
+                    Ok(true) | Err(SyncError::Cancelled) => return Ok(true),
+                    Ok(false) => {}
+                    Err(e) => observer.on_log(format!("警告: [备份] 同步到备份目标失败: {}", e)),
+                }
+            }
+
+            if profile.check_after_sync {
+                report.start_phase("核对");
+                observer.on_progress(1.0, "正在核对两侧...".to_string());
+                let Some(report) = check_consistency(local_path, &usb_sync_path, &final_sync_data, &retained_paths, observer) else {
+                    return Ok(true);
+                };
+                if report.mismatched.is_empty() {
+                    observer.on_log(format!("[{}] 核对完成: 两侧一致 ({} 个文件)", Local::now().format("%H:%M:%S"), format_count(report.checked as u64)));
+                } else {
+                    observer.on_log(format!(
+                        "[{}] 核对发现 {} 个文件在同步期间被修改，下次同步会处理它们:",
+                        Local::now().format("%H:%M:%S"),
+                        format_count(report.mismatched.len() as u64)
+                    ));
+                    for path in &report.mismatched {
+                        observer.on_log(format!("  {}", path.display()));
+                    }
+                }
+                observer.on_consistency_checked(&report);
+            }
         } else {
             return Ok(true); // Stopped during final scan
         }
 
-        tx.send(SyncMessage::Progress(1.0, "同步完成!".to_string()))?;
+        // Completion is only reported once the log, like the metadata above, has reached the disk.
+        // A run that found nothing to do and left the record alone doesn't add to the log either.
+        if !(record_unchanged && sync_plan_len == 0) {
+            session.record_written(&usb_sync_path);
+            let message = format!("[{}] 同步完成", Local::now().format("%H:%M:%S"));
+            write_final_log_entry(&message, LogLevel::Summary, profile.device_log_verbosity, &usb_sync_path)
+                .map_err(|e| SyncError::StateNotPersisted(Box::new(e)))?;
+        }
+        observer.on_progress(1.0, "同步完成!".to_string());
         Ok(false)
-    })() {
-        Ok(stopped) => stopped,
+    })();
+    let outcome = match result {
+        Ok(false) => RunOutcome::Completed,
+        Ok(true) | Err(SyncError::Cancelled) => RunOutcome::Stopped,
+        Err(SyncError::DeviceMissing(path)) => {
+            observer.on_log(format!("错误: {}", SyncError::DeviceMissing(path.clone())));
+            observer.on_device_removed(&path);
+            RunOutcome::Completed
+        }
+        Err(SyncError::StateNotPersisted(details)) => {
+            let details = details.to_string();
+            observer.on_log(format!("错误: 完成但写入状态失败: {}", details));
+            RunOutcome::StateNotPersisted(details)
+        }
         Err(e) => {
             let msg = format!("错误: {}", e);
-            let _ = tx.send(SyncMessage::Log(msg.clone()));
-            if let (Some(local_folder), Some(usb_drive)) = (local_folder, usb_drive) {
-                let sync_folder_name = local_folder.file_name().unwrap();
-                let usb_sync_path = usb_drive.join(sync_folder_name);
-                let _ = write_log_entry(&msg, &usb_sync_path);
+            observer.on_log(msg.clone());
+            report.error = Some(msg.clone());
+            if let (Some(local_folder), Some(session)) = (local_folder, session)
+                && let Some(sync_folder_name) = local_folder.file_name()
+            {
+                // A run that failed early may not have created the folder; don't leave a stray log behind
+                let usb_sync_path = session.root().join(sync_folder_name);
+                if usb_sync_path.is_dir() {
+                    let _ = write_log_entry(&msg, LogLevel::Summary, profile.device_log_verbosity, &usb_sync_path);
+                }
             }
-            false
+            RunOutcome::Completed
         }
     };
 
-    if was_stopped {
+    report.finish(match &outcome {
+        RunOutcome::Completed if report.error.is_some() => "出错",
+        RunOutcome::Completed => "完成",
+        RunOutcome::Stopped => "已停止",
+        RunOutcome::StateNotPersisted(_) => "完成但写入状态失败",
+    });
+    if profile.html_report {
+        write_run_report(&report, session, observer);
+    }
+
+    if outcome == RunOutcome::Stopped {
         let msg = format!("[{}] 同步已由用户停止。", Local::now().format("%H:%M:%S"));
-        let _ = tx.send(SyncMessage::Log(msg));
-        let _ = tx.send(SyncMessage::Stopped);
+        observer.on_log(msg);
+    }
+    if !report.actions.is_empty() {
+        observer.on_run_recorded(&report);
+    }
+    observer.on_finished(outcome);
+}
+
+/// Writes the page of a run that got as far as its sync folder. The page lives in the bookkeeping folder, which
+/// isn't created for it: its presence decides where the record is looked for.
+fn write_run_report(report: &RunReport, session: Option<&DriveSession>, observer: &impl SyncObserver) {
+    let (Some(usb_sync_path), Some(session)) = (&report.usb_sync_path, session) else { return };
+    if !usb_sync_path.is_dir() {
+        return;
+    }
+    if !usb_sync_path.join(BOOKKEEPING_DIR_NAME).is_dir() {
+        observer.on_log(format!("警告: 同步报告需要将同步记录存放在 {} 文件夹中，本次未生成", BOOKKEEPING_DIR_NAME));
+        return;
+    }
+    session.record_written(usb_sync_path);
+    match write_html_report(report, usb_sync_path) {
+        Ok(path) => observer.on_log(format!("[{}] 同步报告已生成: {}", Local::now().format("%H:%M:%S"), path.display())),
+        Err(e) => observer.on_log(format!("警告: 无法生成同步报告: {}", e)),
+    }
+}
+
+
+/// Compares the record a run just saved with a fresh look at both sides, without reading any content.
+/// Local files must keep their recorded size and time; on the USB drive, where times are not exact,
+/// the size alone is compared. Paths in `exclude` kept an older entry on purpose and are left out.
+/// Returns None if stopped.
+pub fn check_consistency(
+    local_path: &Path,
+    usb_sync_path: &Path,
+    record: &SyncData,
+    exclude: &HashSet<PathBuf>,
+    observer: &impl SyncObserver,
+) -> Option<ConsistencyReport> {
+    let mut report = ConsistencyReport::default();
+    for (index, (path, info)) in record.files.iter().filter(|(path, _)| !exclude.contains(*path)).enumerate() {
+        if index % 256 == 0 && observer.should_stop() {
+            return None;
+        }
+        report.checked += 1;
+        let local_matches = fs::metadata(exact_path(&local_path.join(path)))
+            .is_ok_and(|metadata| metadata.is_file() && metadata.len() == info.size && metadata.modified().is_ok_and(|modified| modified == info.modified));
+        let remote = usb_sync_path.join(record.routes.get(path).unwrap_or(path));
+        let remote_matches = fs::metadata(exact_path(&remote)).is_ok_and(|metadata| metadata.is_file() && metadata.len() == info.size);
+        if !(local_matches && remote_matches) {
+            report.mismatched.push(path.clone());
+        }
+    }
+    report.mismatched.sort();
+    Some(report)
+}
+
+/// Brings a secondary backup folder in line with the local state after the primary sync.
+/// Copies new and changed files, and deletes files it mirrored before that no longer exist locally.
+/// Backup files newer than their local source are handled per the profile's `newer_destination_policy` and counted
+/// in `stats`, as are backup files with other hard links.
+/// The secondary keeps its own metadata file. Returns Ok(true) if stopped.
+fn mirror_to_secondary(
+    local_path: &Path,
+    local_sync_data: &SyncData,
+    secondary_path: &Path,
+    profile: &Profile,
+    stats: &mut SyncStats,
+    observer: &impl SyncObserver,
+    limiter: &RateLimiter,
+) -> Result<bool, SyncError> {
+    fs::create_dir_all(secondary_path).at(secondary_path)?;
+    let metadata_path = metadata_path(secondary_path);
+    let last_sync_data = load_sync_data(&metadata_path)?;
+    ensure_writable(&last_sync_data, &metadata_path)?;
+    observer.on_log(format!("[{}] [备份] 正在同步到 {}", Local::now().format("%H:%M:%S"), secondary_path.display()));
+
+    // Entries carried over from the last record, e.g. files kept on the USB drive, may name files this machine doesn't have
+    let local_sync_data = SyncData {
+        files: local_sync_data
+            .files
+            .iter()
+            .filter(|(path, _)| local_path.join(path).is_file())
+            .map(|(path, info)| (path.clone(), info.clone()))
+            .collect(),
+        directories: local_sync_data.directories.iter().filter(|path| local_path.join(path).is_dir()).cloned().collect(),
+        ..Default::default()
+    };
+
+    let Some(secondary_sync_data) =
+        scan_directory_with_progress(secondary_path, observer, None, "[备份] 扫描", &last_sync_data, HashStrategy::Full)?
+    else {
+        return Ok(true);
+    };
+
+    let to_copy: Vec<&PathBuf> = local_sync_data
+        .files
+        .iter()
+        .filter(|(path, info)| secondary_sync_data.files.get(*path).is_none_or(|existing| existing.hash != info.hash))
+        .map(|(path, _)| path)
+        .collect();
+    let to_delete: Vec<&PathBuf> = last_sync_data
+        .files
+        .keys()
+        .filter(|path| !local_sync_data.files.contains_key(*path) && secondary_sync_data.files.contains_key(*path))
+        .collect();
+
+    let total = to_copy.len() + to_delete.len();
+    let mut failed = HashSet::new();
+    let mut kept_newer = HashSet::new();
+    let mut newer_overwritten = 0;
+    for (index, path) in to_copy.iter().chain(to_delete.iter()).enumerate() {
+        if observer.should_stop() {
+            return Ok(true);
+        }
+        observer.on_progress(
+            (index + 1) as f32 / total as f32,
+            format!("[备份] ({}/{}) 正在处理: {}", index + 1, total, path.display()),
+        );
+        let target = secondary_path.join(path);
+        // Someone changed the backup copy after the local file was last written; a blind copy would lose that
+        let newer = index < to_copy.len() && local_sync_data.files.get(*path).is_some_and(|info| is_newer_than(&target, info.modified));
+        if newer {
+            let overwrite = match profile.newer_destination_policy {
+                NewerDestinationPolicy::SkipAndWarn => false,
+                NewerDestinationPolicy::Overwrite => true,
+                NewerDestinationPolicy::Ask => match observer.confirm_overwrite_newer(&target) {
+                    Ok(overwrite) => overwrite,
+                    Err(SyncError::Cancelled) => return Ok(true),
+                    Err(e) => return Err(e),
+                },
+            };
+            if !overwrite {
+                kept_newer.insert((*path).clone());
+                observer.on_log(format!("警告: [备份] 目标文件比本地文件新，已跳过: {}", path.display()));
+                continue;
+            }
+            newer_overwritten += 1;
+            observer.on_log(format!("警告: [备份] 目标文件比本地文件新，已覆盖: {}", path.display()));
+        }
+        let size = local_sync_data.files.get(*path).map_or(0, |info| info.size);
+        if index < to_copy.len() && limiter.pace(size, observer) {
+            return Ok(true);
+        }
+        let result = if index < to_copy.len() {
+            target
+                .parent()
+                .map_or(Ok(()), |parent| fs::create_dir_all(parent).at(parent))
+                .and_then(|_| handle_hard_links(&target, true, profile.hard_link_policy, &format!("[备份] {}", path.display()), stats, observer))
+                .and_then(|_| fs::copy(local_path.join(path), &target).at(&target))
+                .map(|_| format!("[{}] [备份] 复制: {}", Local::now().format("%H:%M:%S"), path.display()))
+        } else {
+            fs::remove_file(&target)
+                .at(&target)
+                .and_then(|_| cleanup_empty_dirs(&target, secondary_path))
+                .map(|_| format!("[{}] [备份] 删除: {}", Local::now().format("%H:%M:%S"), path.display()))
+        };
+        let message = match result {
+            Ok(message) => message,
+            Err(e) => {
+                failed.insert((*path).clone());
+                format!("警告: [备份] {}: {}", path.display(), e)
+            }
+        };
+        observer.on_log(message);
+    }
+
+    // Failed and kept paths keep what the secondary had before, so the next run looks at them again
+    let unmirrored: HashSet<&PathBuf> = failed.iter().chain(kept_newer.iter()).collect();
+    let mut mirrored = SyncData {
+        files: local_sync_data
+            .files
+            .iter()
+            .filter(|(path, _)| !unmirrored.contains(path))
+            .map(|(path, info)| (path.clone(), info.clone()))
+            .collect(),
+        directories: local_sync_data.directories.clone(),
+        last_sync_time: Some(SystemTime::now()),
+        ..Default::default()
+    };
+    for path in unmirrored {
+        if let Some(info) = last_sync_data.files.get(path) {
+            mirrored.files.insert(path.clone(), info.clone());
+        }
+    }
+    if mirrored.same_state(&last_sync_data) {
+        observer.on_log(format!("[{}] [备份] 同步记录未变化，跳过写入", Local::now().format("%H:%M:%S")));
     } else {
-        let _ = tx.send(SyncMessage::Complete);
+        save_sync_data(&mirrored, &metadata_path)?;
+    }
+    stats.newer_destinations_skipped += kept_newer.len();
+    observer.on_log(format!(
+        "[{}] [备份] 完成: {} 个复制, {} 个删除, {} 个失败, {} 个目标文件较新 (跳过 {}, 覆盖 {})",
+        Local::now().format("%H:%M:%S"),
+        to_copy.len() - kept_newer.len(),
+        to_delete.len(),
+        failed.len(),
+        kept_newer.len() + newer_overwritten,
+        kept_newer.len(),
+        newer_overwritten
+    ));
+    Ok(false)
+}
+
+/// Which version of a conflicting file looks newer, from each side's modification time and size.
+/// Times within the FAT tolerance of each other can't be told apart, and neither can a missing time.
+/// An empty version is never suggested over a non-empty one, since programs briefly truncate files while saving.
+fn suggest_conflict_resolution(
+    local: Option<SystemTime>,
+    local_size: u64,
+    remote: Option<SystemTime>,
+    remote_size: u64,
+) -> ConflictSuggestion {
+    let (Some(local), Some(remote)) = (local, remote) else { return ConflictSuggestion::Undecided };
+    let suggestion = match local.duration_since(remote) {
+        Ok(ahead) if ahead > MTIME_TOLERANCE => ConflictSuggestion::LocalNewer,
+        Ok(_) => ConflictSuggestion::Undecided,
+        Err(behind) if behind.duration() > MTIME_TOLERANCE => ConflictSuggestion::RemoteNewer,
+        Err(_) => ConflictSuggestion::Undecided,
+    };
+    match suggestion {
+        ConflictSuggestion::LocalNewer if local_size == 0 && remote_size > 0 => ConflictSuggestion::Undecided,
+        ConflictSuggestion::RemoteNewer if remote_size == 0 && local_size > 0 => ConflictSuggestion::Undecided,
+        other => other,
+    }
+}
+
+/// The side whose version is kept for a conflict that only differs in line endings.
+/// Keeping the newer one gives None when the times can't tell, so the conflict is asked as usual.
+fn line_ending_resolution(policy: LineEndingPolicy, local: &FileInfo, remote: &FileInfo) -> Option<Side> {
+    match policy {
+        LineEndingPolicy::KeepLocal => Some(Side::Local),
+        LineEndingPolicy::KeepUsb => Some(Side::Usb),
+        LineEndingPolicy::KeepNewer => match suggest_conflict_resolution(Some(local.modified), local.size, Some(remote.modified), remote.size) {
+            ConflictSuggestion::LocalNewer => Some(Side::Local),
+            ConflictSuggestion::RemoteNewer => Some(Side::Usb),
+            ConflictSuggestion::Undecided => None,
+        },
     }
 }
 
+/// Whether the file at `path` was modified noticeably later than `source_modified`.
+fn is_newer_than(path: &Path, source_modified: SystemTime) -> bool {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|modified| modified.duration_since(source_modified).is_ok_and(|ahead| ahead > MTIME_TOLERANCE))
+}
+
+/// Roughly counts the file changes the next sync would make, comparing sizes and modification times only.
+/// Returns None if the observer asks to stop before the scans finish. Progress is reported through it as usual.
+pub fn estimate_change_count(local_folder: &Path, usb_drive: &Path, observer: &impl SyncObserver) -> Result<Option<usize>, SyncError> {
+    let sync_folder_name = local_folder.file_name().ok_or(SyncError::InvalidSelection("无效的本地文件夹名称"))?;
+    let usb_sync_path = usb_drive.join(sync_folder_name);
+    let last_sync_data = load_sync_data(&metadata_path(&usb_sync_path))?;
+    let own_reference = last_sync_data.local_reference(&machine_name());
+    let Some(local_sync_data) =
+        scan_directory_with_progress(local_folder, observer, None, "估算本地", own_reference.as_ref().unwrap_or(&last_sync_data), HashStrategy::Skip)?
+    else {
+        return Ok(None);
+    };
+    let remote_files: HashMap<PathBuf, FileInfo> = if usb_sync_path.exists() {
+        let remote_reference = SyncData {
+            files: last_sync_data
+                .files
+                .iter()
+                .map(|(path, info)| (last_sync_data.routes.get(path).cloned().unwrap_or_else(|| path.clone()), info.clone()))
+                .collect(),
+            ..Default::default()
+        };
+        let Some(remote_sync_data) = scan_directory_with_progress(&usb_sync_path, observer, None, "估算U盘", &remote_reference, HashStrategy::Skip)? else {
+            return Ok(None);
+        };
+        let reverse_routes: HashMap<&PathBuf, &PathBuf> = last_sync_data.routes.iter().map(|(local, remote)| (remote, local)).collect();
+        remote_sync_data
+            .files
+            .into_iter()
+            .map(|(path, info)| (reverse_routes.get(&path).map(|local| (*local).clone()).unwrap_or(path), info))
+            .collect()
+    } else {
+        HashMap::new()
+    };
+
+    let mut all_files: HashSet<&PathBuf> = HashSet::new();
+    all_files.extend(last_sync_data.files.keys());
+    all_files.extend(local_sync_data.files.keys());
+    all_files.extend(remote_files.keys());
+    Ok(Some(
+        all_files
+            .into_iter()
+            .filter(|path| !last_sync_data.tombstones.contains_key(*path))
+            .filter(|path| {
+                match (local_sync_data.files.get(*path), remote_files.get(*path), last_sync_data.files.get(*path)) {
+                    (Some(local), Some(remote), Some(last)) => local.hash != last.hash || remote.hash != last.hash,
+                    // Unhashed new files on both sides can only be told apart by size
+                    (Some(local), Some(remote), None) => local.size != remote.size,
+                    (None, None, _) => false,
+                    _ => true,
+                }
+            })
+            .count(),
+    ))
+}
+
+/// Why a file on the USB drive is no longer part of the sync.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OrphanKind {
+    /// Deleted locally, but the user chose to keep the USB copy and not be asked again.
+    KeptAfterDeletion,
+    /// A temporary file left behind by an interrupted copy; syncs ignore it.
+    LeftoverTemp,
+}
+
+/// The deletion answers given so far in a run, by side. An entry inside a folder whose deletion was declined
+/// is kept without asking, and so is a folder holding a declined entry, since removing the folder would take
+/// the entry along. An entry inside a folder whose deletion was approved is deleted without asking.
+#[derive(Debug, Default)]
+struct DeletionAnswers {
+    declined: Vec<(Side, PathBuf)>,
+    approved_folders: Vec<(Side, PathBuf)>,
+    mentioned: HashSet<(Side, PathBuf)>,
+}
+
+impl DeletionAnswers {
+    /// Records the answer for deleting `path` on `side`.
+    fn record(&mut self, side: Side, path: &Path, is_folder: bool, delete: bool) {
+        if !delete {
+            self.declined.push((side, path.to_path_buf()));
+        } else if is_folder {
+            self.approved_folders.push((side, path.to_path_buf()));
+        }
+    }
+
+    /// Whether earlier answers already decide deleting `path` on `side`, and the path answered for.
+    /// A declined answer wins over an approved one.
+    fn implied(&self, side: Side, path: &Path, is_folder: bool) -> Option<(bool, PathBuf)> {
+        let declined = self
+            .declined
+            .iter()
+            .find(|(declined_side, declined)| *declined_side == side && (path.starts_with(declined) || (is_folder && declined.starts_with(path))));
+        if let Some((_, declined)) = declined {
+            return Some((false, declined.clone()));
+        }
+        self.approved_folders
+            .iter()
+            .find(|(folder_side, folder)| *folder_side == side && path.starts_with(folder))
+            .map(|(_, folder)| (true, folder.clone()))
+    }
+
+    // Whether `answered` hasn't been logged as the reason for a decision yet
+    fn mention(&mut self, side: Side, answered: &Path) -> bool {
+        self.mentioned.insert((side, answered.to_path_buf()))
+    }
+}
+
+/// A file on the USB drive that syncing will never update or delete.
+#[derive(Clone, Debug)]
+pub struct OrphanFile {
+    /// Relative to the sync folder on the USB drive.
+    pub path: PathBuf,
+    pub size: u64,
+    pub kind: OrphanKind,
+}
+
+/// Lists files in the USB sync folder that syncing leaves alone for good: copies kept after a local deletion and
+/// temporary files of interrupted copies. Nothing is modified.
+pub fn find_orphan_files(local_folder: &Path, usb_drive: &Path) -> Result<Vec<OrphanFile>, SyncError> {
+    let sync_folder_name = local_folder.file_name().ok_or(SyncError::InvalidSelection("无效的本地文件夹名称"))?;
+    let usb_sync_path = usb_drive.join(sync_folder_name);
+    let last_sync_data = load_sync_data(&metadata_path(&usb_sync_path))?;
+    // Kept files are recorded under their local paths, which routing may have changed on the USB drive
+    let kept_locations: HashMap<PathBuf, &PathBuf> = last_sync_data
+        .tombstones
+        .keys()
+        .map(|path| (last_sync_data.routes.get(path).cloned().unwrap_or_else(|| path.clone()), path))
+        .collect();
+
+    let trash = trash_path(&usb_sync_path);
+    let mut orphans = Vec::new();
+    for entry in WalkDir::new(&usb_sync_path)
+        .into_iter()
+        .filter_entry(|e| e.path() != trash)
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file())
+    {
+        let relative = entry.path().strip_prefix(&usb_sync_path).unwrap_or(entry.path()).to_path_buf();
+        let kind = if entry.file_name().to_string_lossy().ends_with(TEMP_FILE_SUFFIX) {
+            OrphanKind::LeftoverTemp
+        } else if kept_locations.get(&relative).is_some_and(|local| !local_folder.join(local).exists()) {
+            OrphanKind::KeptAfterDeletion
+        } else {
+            continue;
+        };
+        let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+        orphans.push(OrphanFile { path: relative, size, kind });
+    }
+    orphans.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(orphans)
+}
+
+/// Moves orphaned files into a dated folder in the trash of the USB sync folder, keeping their relative paths.
+/// A stop leaves the files not yet moved in place. Returns how many were moved.
+pub fn move_orphans_to_trash(
+    local_folder: &Path,
+    usb_drive: &Path,
+    orphans: &[OrphanFile],
+    log_verbosity: DeviceLogVerbosity,
+    observer: &impl SyncObserver,
+) -> Result<usize, SyncError> {
+    let sync_folder_name = local_folder.file_name().ok_or(SyncError::InvalidSelection("无效的本地文件夹名称"))?;
+    let usb_sync_path = usb_drive.join(sync_folder_name);
+    let trash_path = trash_path(&usb_sync_path).join(Local::now().format("%Y%m%d-%H%M%S").to_string());
+    let mut moved = 0;
+    for orphan in orphans {
+        // Each file is moved whole, so a stop between them leaves both folders consistent
+        if moved % TRASH_BATCH == 0 {
+            observer.on_progress(
+                moved as f32 / orphans.len() as f32,
+                format!("正在移至回收文件夹 ({}/{})", format_count(moved as u64), format_count(orphans.len() as u64)),
+            );
+            if observer.should_stop() {
+                break;
+            }
+        }
+        let from = usb_sync_path.join(&orphan.path);
+        let to = trash_path.join(&orphan.path);
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent).at(parent)?;
+        }
+        fs::rename(&from, &to).at(&from)?;
+        cleanup_empty_dirs(&from, &usb_sync_path)?;
+        moved += 1;
+    }
+    let mut message = format!("[{}] 孤立文件检查: {} 个文件已移至 {}", Local::now().format("%H:%M:%S"), moved, trash_path.display());
+    if moved < orphans.len() {
+        message += &format!("，已停止，其余 {} 个未移动", orphans.len() - moved);
+    }
+    write_log_entry(&message, LogLevel::Summary, log_verbosity, &usb_sync_path)?;
+    Ok(moved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_copy_of_the_wrong_size_fails() {
+        let dir = std::env::temp_dir().join(format!("syncu-copied-size-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let to = dir.join("a.txt");
+        fs::write(&to, b"").unwrap();
+        let short = check_copied_size(&to, 6);
+        let exact = check_copied_size(&to, 0);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(short, Err(SyncError::SizeMismatch { expected: 6, actual: 0, .. })), "{:?}", short);
+        assert!(exact.is_ok());
+    }
+
+    fn at(seconds: u64) -> Option<SystemTime> {
+        Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000 + seconds))
+    }
+
+    #[test]
+    fn the_clearly_later_side_is_suggested() {
+        assert_eq!(suggest_conflict_resolution(at(60), 10, at(0), 10), ConflictSuggestion::LocalNewer);
+        assert_eq!(suggest_conflict_resolution(at(0), 10, at(60), 10), ConflictSuggestion::RemoteNewer);
+        assert_eq!(ConflictSuggestion::LocalNewer.resolution(), Some(Resolution::KeepLocal));
+        assert_eq!(ConflictSuggestion::RemoteNewer.resolution(), Some(Resolution::KeepRemote));
+    }
+
+    #[test]
+    fn equal_times_are_undecided() {
+        assert_eq!(suggest_conflict_resolution(at(5), 10, at(5), 20), ConflictSuggestion::Undecided);
+        assert_eq!(ConflictSuggestion::Undecided.resolution(), None);
+    }
+
+    #[test]
+    fn a_missing_time_is_undecided() {
+        assert_eq!(suggest_conflict_resolution(None, 10, at(5), 10), ConflictSuggestion::Undecided);
+        assert_eq!(suggest_conflict_resolution(at(5), 10, None, 10), ConflictSuggestion::Undecided);
+        assert_eq!(suggest_conflict_resolution(None, 10, None, 10), ConflictSuggestion::Undecided);
+    }
+
+    #[test]
+    fn times_within_the_fat_tolerance_are_undecided() {
+        // FAT stores mtimes in 2 second steps
+        assert_eq!(suggest_conflict_resolution(at(2), 10, at(0), 10), ConflictSuggestion::Undecided);
+        assert_eq!(suggest_conflict_resolution(at(0), 10, at(2), 10), ConflictSuggestion::Undecided);
+        assert_eq!(suggest_conflict_resolution(at(3), 10, at(0), 10), ConflictSuggestion::LocalNewer);
+        assert_eq!(suggest_conflict_resolution(at(0), 10, at(3), 10), ConflictSuggestion::RemoteNewer);
+    }
+
+    #[test]
+    fn an_empty_newer_version_is_not_suggested() {
+        assert_eq!(suggest_conflict_resolution(at(60), 0, at(0), 10), ConflictSuggestion::Undecided);
+        assert_eq!(suggest_conflict_resolution(at(0), 10, at(60), 0), ConflictSuggestion::Undecided);
+        assert_eq!(suggest_conflict_resolution(at(60), 0, at(0), 0), ConflictSuggestion::LocalNewer);
+    }
+
+    #[test]
+    fn entries_of_an_answered_folder_follow_the_answer() {
+        let mut answers = DeletionAnswers::default();
+        answers.record(Side::Usb, Path::new("Old Projects"), true, false);
+        answers.record(Side::Usb, Path::new("Drafts"), true, true);
+
+        assert_eq!(answers.implied(Side::Usb, Path::new("Old Projects/a/b.txt"), false), Some((false, PathBuf::from("Old Projects"))));
+        assert_eq!(answers.implied(Side::Usb, Path::new("Drafts/x.txt"), false), Some((true, PathBuf::from("Drafts"))));
+        // Only whole components match, and only on the side that was answered for
+        assert_eq!(answers.implied(Side::Usb, Path::new("Old Projects 2/a.txt"), false), None);
+        assert_eq!(answers.implied(Side::Local, Path::new("Old Projects/a.txt"), false), None);
+    }
+
+    #[test]
+    fn a_folder_holding_a_declined_entry_is_kept() {
+        let mut answers = DeletionAnswers::default();
+        answers.record(Side::Local, Path::new("Old Projects/a.txt"), false, false);
+        answers.record(Side::Local, Path::new("Old Projects/b.txt"), false, true);
+
+        assert_eq!(answers.implied(Side::Local, Path::new("Old Projects"), true), Some((false, PathBuf::from("Old Projects/a.txt"))));
+        // Approving a file says nothing about its siblings
+        assert_eq!(answers.implied(Side::Local, Path::new("Old Projects/c.txt"), false), None);
+    }
+
+    #[test]
+    fn a_decline_inside_an_approved_folder_wins() {
+        let mut answers = DeletionAnswers::default();
+        answers.record(Side::Usb, Path::new("Old Projects"), true, true);
+        answers.record(Side::Usb, Path::new("Old Projects/keep"), true, false);
+
+        assert_eq!(answers.implied(Side::Usb, Path::new("Old Projects/keep/a.txt"), false), Some((false, PathBuf::from("Old Projects/keep"))));
+        assert_eq!(answers.implied(Side::Usb, Path::new("Old Projects/b.txt"), false), Some((true, PathBuf::from("Old Projects"))));
+    }
+}
@@ -0,0 +1,89 @@
+//! Progress on the app's taskbar button, through ITaskbarList3 on Windows.
+//! Other platforms have no equivalent here, so every call compiles to nothing.
+
+/// What the taskbar button shows. Fractions are clamped to 0.0..=1.0.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TaskbarState {
+    #[default]
+    Hidden,
+    Normal(f32),
+    /// Shown in yellow, e.g. while the run waits for an answer or is stopping.
+    Paused(f32),
+    /// Shown in red, e.g. once a run had failures.
+    Error(f32),
+}
+
+impl TaskbarState {
+    // Steps of 0.1% are finer than the button can show, and avoid a COM call every frame
+    fn quantized(self) -> Self {
+        let step = |fraction: f32| (fraction.clamp(0.0, 1.0) * 1000.0).round() / 1000.0;
+        match self {
+            TaskbarState::Hidden => TaskbarState::Hidden,
+            TaskbarState::Normal(fraction) => TaskbarState::Normal(step(fraction)),
+            TaskbarState::Paused(fraction) => TaskbarState::Paused(step(fraction)),
+            TaskbarState::Error(fraction) => TaskbarState::Error(step(fraction)),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct TaskbarProgress {
+    shown: TaskbarState,
+    #[cfg(windows)]
+    taskbar: Option<windows::Win32::UI::Shell::ITaskbarList3>,
+    #[cfg(windows)]
+    unavailable: bool,
+}
+
+impl TaskbarProgress {
+    /// Shows `state` on the taskbar button of `frame`'s window; unchanged states cost nothing.
+    pub fn set(&mut self, frame: &eframe::Frame, state: TaskbarState) {
+        let state = state.quantized();
+        if state == self.shown {
+            return;
+        }
+        self.shown = state;
+        #[cfg(windows)]
+        self.apply(frame, state);
+        #[cfg(not(windows))]
+        let _ = frame;
+    }
+
+    #[cfg(windows)]
+    fn apply(&mut self, frame: &eframe::Frame, state: TaskbarState) {
+        use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+        use windows::Win32::Foundation::HWND;
+        use windows::Win32::System::Com::{CLSCTX_INPROC_SERVER, COINIT_APARTMENTTHREADED, CoCreateInstance, CoInitializeEx};
+        use windows::Win32::UI::Shell::{ITaskbarList3, TBPF_ERROR, TBPF_NOPROGRESS, TBPF_NORMAL, TBPF_PAUSED, TaskbarList};
+
+        let Ok(handle) = frame.window_handle() else { return };
+        let RawWindowHandle::Win32(handle) = handle.as_raw() else { return };
+        let hwnd = HWND(handle.hwnd.get() as *mut _);
+
+        if self.taskbar.is_none() && !self.unavailable {
+            // The UI thread usually has COM already; a second initialization is harmless
+            let taskbar = unsafe {
+                let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+                CoCreateInstance::<_, ITaskbarList3>(&TaskbarList, None, CLSCTX_INPROC_SERVER)
+                    .and_then(|taskbar| taskbar.HrInit().map(|_| taskbar))
+            };
+            // Without a taskbar (e.g. some shells), stop trying instead of failing every frame
+            self.unavailable = taskbar.is_err();
+            self.taskbar = taskbar.ok();
+        }
+        let Some(taskbar) = &self.taskbar else { return };
+
+        let (flag, fraction) = match state {
+            TaskbarState::Hidden => (TBPF_NOPROGRESS, None),
+            TaskbarState::Normal(fraction) => (TBPF_NORMAL, Some(fraction)),
+            TaskbarState::Paused(fraction) => (TBPF_PAUSED, Some(fraction)),
+            TaskbarState::Error(fraction) => (TBPF_ERROR, Some(fraction)),
+        };
+        unsafe {
+            let _ = taskbar.SetProgressState(hwnd, flag);
+            if let Some(fraction) = fraction {
+                let _ = taskbar.SetProgressValue(hwnd, (fraction * 1000.0) as u64, 1000);
+            }
+        }
+    }
+}
@@ -1,296 +1,1929 @@
-use crate::models::{FileInfo, SyncData, SyncMessage};
-use crossbeam_channel::Receiver;
+use crate::error::{IoResultExt, SyncError};
+use crate::models::{CrowdedDirectory, DiffLine, DriveUnavailable, FileInfo, LogLevel, PlanCheckpoint, SyncData, UsbDrive};
+use crate::observer::SyncObserver;
+use crate::settings::{DeviceLogVerbosity, RoutingRule};
 use dashmap::{DashMap, DashSet};
 use rayon::prelude::*;
 use sha2::{Digest, Sha256};
+use similar::{ChangeTag, TextDiff};
 use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
-use std::io::{self, BufReader, Read, Write};
-use std::path::{Path, PathBuf};
-use std::sync::{Arc, atomic::{AtomicBool, AtomicUsize, Ordering}};
-use std::time::Instant;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, atomic::{AtomicBool, AtomicUsize, Ordering}};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use sysinfo::{System, Disks};
 use walkdir::WalkDir;
 
-/// Finds all removable drives connected to the system.
-pub fn find_usb_drives() -> Vec<PathBuf> {
-    let mut sys = System::new();
-    sys.refresh_all();
+/// Name of the metadata file kept in the root of every sync folder.
+pub const METADATA_FILE_NAME: &str = ".syncu_metadata.json";
+/// Name of the log file kept in the root of every sync folder.
+pub const LOG_FILE_NAME: &str = ".syncu_log.txt";
+/// Name of the file that holds the plan of a long run until it finishes, kept in the root of the sync folder.
+pub const PLAN_FILE_NAME: &str = ".syncu_plan.json";
+/// Suffix of the file a copy is written to before it replaces the destination.
+pub const TEMP_FILE_SUFFIX: &str = ".syncu_tmp";
+/// Folder in the root of a USB sync folder that receives cleaned-up files; syncs never look inside it.
+pub const TRASH_DIR_NAME: &str = ".syncu_trash";
+/// Hidden folder in the root of a sync folder that holds SyncU's own files instead, when a profile asks for it.
+pub const BOOKKEEPING_DIR_NAME: &str = ".syncu";
+/// SyncU's own entries in the root of a sync folder, with the names they get inside the bookkeeping folder.
+const BOOKKEEPING_ENTRIES: &[(&str, &str)] = &[
+    (METADATA_FILE_NAME, "metadata.json"),
+    (LOG_FILE_NAME, "log.txt"),
+    (TRASH_DIR_NAME, "trash"),
+    (PLAN_FILE_NAME, "plan.json"),
+];
+/// Folder next to a cloud-synced folder that receives copies into it until they are complete.
+pub const STAGING_DIR_NAME: &str = ".syncu_staging";
+/// Names of the folders well-known cloud clients sync, as created in the user's profile.
+/// "OneDrive - <organization>" is matched by its prefix.
+const CLOUD_FOLDER_NAMES: &[&str] = &["OneDrive", "Dropbox", "Google Drive", "iCloudDrive", "iCloud Drive", "Nutstore", "我的坚果云"];
+/// Longest file name component most file systems accept, in their own encoding units.
+const MAX_NAME_COMPONENT_LEN: usize = 255;
+
+/// Finds all removable drives connected to the system, including those that can't be used right now
+/// (e.g. locked by BitLocker), so the UI can say why instead of leaving them out.
+pub fn find_usb_drives() -> Vec<UsbDrive> {
     let disks = Disks::new_with_refreshed_list();
-    disks
+    #[allow(unused_mut)]
+    let mut drives: Vec<UsbDrive> = disks
         .iter()
         .filter(|d| d.is_removable())
-        .map(|d| d.mount_point().to_path_buf())
+        .map(|d| {
+            let mount_point = d.mount_point().to_path_buf();
+            UsbDrive { unavailable: drive_unavailable(&mount_point), volume_id: disk_volume_id(d), mount_point }
+        })
+        .collect();
+    // Locked volumes have no readable file system, so the disk list above never contains them
+    #[cfg(windows)]
+    for root in windows_drives::removable_roots() {
+        if drives.iter().any(|drive| drive.mount_point == root) {
+            continue;
+        }
+        if let Some(reason) = windows_drives::volume_problem(&root) {
+            drives.push(UsbDrive { volume_id: windows_drives::volume_serial(&root), mount_point: root, unavailable: Some(reason) });
+        }
+    }
+    #[cfg(windows)]
+    let is_volume_root = windows_drives::is_volume_root;
+    #[cfg(not(windows))]
+    let is_volume_root = |_: &Path| true;
+    dedupe_drives(drives, is_volume_root)
+}
+
+/// Lists each volume once when it is reachable under several mount points, e.g. a second drive letter or a
+/// `subst` drive. The volume's own mount point is kept where `is_volume_root` tells it apart, else the first by name.
+fn dedupe_drives(mut drives: Vec<UsbDrive>, is_volume_root: impl Fn(&Path) -> bool) -> Vec<UsbDrive> {
+    drives.sort_by_key(|drive| (!is_volume_root(&drive.mount_point), drive.mount_point.clone()));
+    let mut seen = HashSet::new();
+    drives.retain(|drive| drive.volume_id.as_ref().is_none_or(|id| seen.insert(id.clone())));
+    drives.sort_by(|a, b| a.mount_point.cmp(&b.mount_point));
+    drives
+}
+
+/// Identifies the volume holding `path` whatever it is mounted as: the serial number its file system was
+/// formatted with, so the same drive gets the same one on every machine. None where it can't be read.
+pub fn volume_id(path: &Path) -> Option<String> {
+    with_disk_holding(path, disk_volume_id).flatten()
+}
+
+// The file system's serial number, e.g. "1A2B-3C4D". Windows' volume GUIDs would differ from machine to machine.
+#[cfg(windows)]
+fn disk_volume_id(disk: &sysinfo::Disk) -> Option<String> {
+    windows_drives::volume_serial(disk.mount_point())
+}
+
+// The file system UUID udev links the device under; for FAT drives it is the serial number Windows shows.
+#[cfg(target_os = "linux")]
+fn disk_volume_id(disk: &sysinfo::Disk) -> Option<String> {
+    let device = fs::canonicalize(disk.name()).ok()?;
+    fs::read_dir("/dev/disk/by-uuid")
+        .ok()?
+        .filter_map(Result::ok)
+        .find(|entry| fs::canonicalize(entry.path()).is_ok_and(|target| target == device))
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+fn disk_volume_id(_disk: &sysinfo::Disk) -> Option<String> {
+    None
+}
+
+// A listed drive can still refuse to be read, e.g. while it is being mounted or if the stick is failing
+fn drive_unavailable(mount_point: &Path) -> Option<DriveUnavailable> {
+    #[cfg(windows)]
+    if let Some(reason) = windows_drives::volume_problem(mount_point) {
+        return Some(reason);
+    }
+    fs::read_dir(mount_point).is_err().then_some(DriveUnavailable::Inaccessible)
+}
+
+#[cfg(windows)]
+mod windows_drives {
+    use crate::models::DriveUnavailable;
+    use std::path::{Path, PathBuf};
+    use windows::Win32::Foundation::ERROR_NOT_READY;
+    use windows::Win32::Storage::FileSystem::{GetDriveTypeW, GetLogicalDrives, GetVolumeInformationW, GetVolumeNameForVolumeMountPointW};
+    use windows::core::{HRESULT, HSTRING};
+
+    /// What GetDriveTypeW returns for drives with removable media.
+    const DRIVE_REMOVABLE: u32 = 2;
+    /// FVE_E_LOCKED_VOLUME: "This drive is locked by BitLocker Drive Encryption."
+    const FVE_E_LOCKED_VOLUME: HRESULT = HRESULT(0x8031_0000_u32 as i32);
+
+    /// Roots of all drive letters assigned to removable drives, e.g. `E:\`.
+    pub fn removable_roots() -> Vec<PathBuf> {
+        let mask = unsafe { GetLogicalDrives() };
+        (0..26u8)
+            .filter(|letter| mask & (1 << letter) != 0)
+            .map(|letter| PathBuf::from(format!("{}:\\", (b'A' + letter) as char)))
+            .filter(|root| unsafe { GetDriveTypeW(&HSTRING::from(root.as_path())) } == DRIVE_REMOVABLE)
+            .collect()
+    }
+
+    /// Why the volume at `root` can't be read, or None if it can or if the slot holds no medium (e.g. an empty card reader).
+    pub fn volume_problem(root: &Path) -> Option<DriveUnavailable> {
+        match unsafe { GetVolumeInformationW(&HSTRING::from(root), None, None, None, None, None) } {
+            Ok(()) => None,
+            Err(e) if e.code() == FVE_E_LOCKED_VOLUME => Some(DriveUnavailable::BitLockerLocked),
+            Err(e) if e.code() == ERROR_NOT_READY.to_hresult() => None,
+            Err(_) => Some(DriveUnavailable::Inaccessible),
+        }
+    }
+
+    /// Serial number of the file system on the volume holding `root`, as `dir` shows it, e.g. "1A2B-3C4D".
+    /// A `subst` drive reports the one of the volume it points into.
+    pub fn volume_serial(root: &Path) -> Option<String> {
+        let mut serial = 0u32;
+        unsafe { GetVolumeInformationW(&HSTRING::from(root), None, Some(&mut serial), None, None, None) }.ok()?;
+        Some(format!("{:04X}-{:04X}", serial >> 16, serial & 0xFFFF))
+    }
+
+    /// Whether `root` is where a volume is mounted, as opposed to a `subst` drive pointing into one.
+    pub fn is_volume_root(root: &Path) -> bool {
+        // A volume GUID path, e.g. `\\?\Volume{…}\`, is 49 characters plus the terminating null
+        let mut name = [0u16; 50];
+        unsafe { GetVolumeNameForVolumeMountPointW(&HSTRING::from(root), &mut name) }.is_ok()
+    }
+}
+
+/// Name of this machine, used to keep its observations apart in shared metadata.
+pub fn machine_name() -> String {
+    System::host_name().unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Returns the free space on the disk holding `path`, if it can be determined.
+pub fn available_space(path: &Path) -> Option<u64> {
+    with_disk_holding(path, |d| d.available_space())
+}
+
+/// Returns the name of the file system holding `path` as the OS reports it, e.g. "NTFS" or "vfat".
+pub fn file_system_name(path: &Path) -> Option<String> {
+    with_disk_holding(path, |d| d.file_system().to_string_lossy().into_owned())
+}
+
+// The innermost mount point containing `path` decides
+fn with_disk_holding<T>(path: &Path, f: impl FnOnce(&sysinfo::Disk) -> T) -> Option<T> {
+    let disks = Disks::new_with_refreshed_list();
+    disks
+        .iter()
+        .filter(|d| path.starts_with(d.mount_point()))
+        .max_by_key(|d| d.mount_point().components().count())
+        .map(f)
+}
+
+/// Formats a byte count for display, e.g. "14.2 GB".
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 { format!("{} B", bytes) } else { format!("{:.1} {}", size, UNITS[unit]) }
+}
+
+/// Formats a count with thousands separators, e.g. "18,204".
+pub fn format_count(count: u64) -> String {
+    let digits = count.to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// Cleans up a selected local folder: drops trailing separators and `.` components and resolves `..` lexically.
+/// Rejects paths without a final folder name, such as drive roots and bare UNC shares, since the USB folder is named after it.
+pub fn normalize_local_folder(path: &Path) -> Result<PathBuf, &'static str> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if matches!(normalized.components().next_back(), Some(Component::Normal(_))) {
+                    normalized.pop();
+                }
+            }
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    if normalized.file_name().is_none() {
+        return Err("不能选择磁盘根目录作为本地文件夹，请选择其中的一个文件夹。");
+    }
+    Ok(normalized)
+}
+
+/// A folder path in a form that compares equal for the same folder: cleaned up like a selected local folder,
+/// and lowercased on Windows, where paths ignore case.
+pub fn comparable_path(path: &Path) -> PathBuf {
+    let normalized = normalize_local_folder(path).unwrap_or_else(|_| path.to_path_buf());
+    if cfg!(windows) {
+        PathBuf::from(normalized.to_string_lossy().to_lowercase())
+    } else {
+        normalized
+    }
+}
+
+/// Returns the directory where SyncU keeps its own settings and state.
+pub fn app_data_dir() -> PathBuf {
+    let base = std::env::var_os("APPDATA")
+        .or_else(|| std::env::var_os("XDG_CONFIG_HOME"))
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_else(|| PathBuf::from("."));
+    base.join("SyncU")
+}
+
+/// Matches a relative path against a glob pattern.
+/// `*` and `?` never cross a path separator, `**` matches any number of directories.
+/// Patterns without a `/` are matched against the file name only.
+pub fn glob_match(pattern: &str, path: &Path) -> bool {
+    let path_str = path.to_string_lossy().replace('\\', "/");
+    let pattern = pattern.trim().replace('\\', "/");
+    let target = if pattern.contains('/') {
+        path_str.as_str()
+    } else {
+        path_str.rsplit('/').next().unwrap_or_default()
+    };
+    let pattern: Vec<char> = pattern.chars().collect();
+    let target: Vec<char> = target.chars().collect();
+    glob_match_chars(&pattern, &target)
+}
+
+fn glob_match_chars(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', rest @ ..] => {
+            // `**/` may also match zero directories
+            if let ['/', after_slash @ ..] = rest
+                && glob_match_chars(after_slash, text)
+            {
+                return true;
+            }
+            (0..=text.len()).any(|i| glob_match_chars(rest, &text[i..]))
+        }
+        ['*', rest @ ..] => {
+            for i in 0..=text.len() {
+                if glob_match_chars(rest, &text[i..]) {
+                    return true;
+                }
+                if text.get(i) == Some(&'/') {
+                    break;
+                }
+            }
+            false
+        }
+        ['?', rest @ ..] => match text {
+            [c, text_rest @ ..] if *c != '/' => glob_match_chars(rest, text_rest),
+            _ => false,
+        },
+        [p, rest @ ..] => match text {
+            [c, text_rest @ ..] if p.eq_ignore_ascii_case(c) => glob_match_chars(rest, text_rest),
+            _ => false,
+        },
+    }
+}
+
+/// Computes where a local relative path is placed on the USB drive.
+/// The first matching rule wins; unmatched paths keep their local layout, and so do paths already under the
+/// rule's destination, such as a file made there on the USB drive and copied back as is.
+pub fn route_path(rules: &[RoutingRule], path: &Path) -> PathBuf {
+    rules
+        .iter()
+        .find(|rule| !rule.pattern.trim().is_empty() && glob_match(&rule.pattern, path))
+        .map(|rule| if path.starts_with(&rule.destination) { path.to_path_buf() } else { rule.destination.join(path) })
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Whether the destination drops trailing dots and spaces from every name component: always through the
+/// Windows API, and on FAT-family drives elsewhere. Such a drive stores "report." and "report" as one file.
+pub fn drops_trailing_dots_and_spaces(file_system: Option<&str>) -> bool {
+    cfg!(windows) || file_system.is_some_and(|name| !crate::extended_attributes::supported_on(name))
+}
+
+/// `path` as such a drive stores it. Components made only of dots and spaces are left alone.
+pub fn strip_trailing_dots_and_spaces(path: &Path) -> PathBuf {
+    path.components()
+        .map(|component| match component {
+            Component::Normal(name) => {
+                let name = name.to_string_lossy();
+                let stripped = name.trim_end_matches(['.', ' ']);
+                PathBuf::from(if stripped.is_empty() { name.as_ref() } else { stripped })
+            }
+            other => PathBuf::from(other.as_os_str()),
+        })
         .collect()
 }
 
-/// Calculates the SHA256 hash of a file.
+/// `path` in a form that reaches names ending in dots or spaces, which Windows otherwise trims on the way to the
+/// file system. Such names only come from tools using extended-length paths; elsewhere `path` is returned as is.
+pub fn exact_path(path: &Path) -> PathBuf {
+    #[cfg(windows)]
+    {
+        let trimmed = path.components().any(|c| matches!(c, Component::Normal(name) if name.to_string_lossy().ends_with(['.', ' '])));
+        if trimmed && path.is_absolute() && !path.as_os_str().to_string_lossy().starts_with(r"\\") {
+            return PathBuf::from(format!(r"\\?\{}", path.display()));
+        }
+    }
+    path.to_path_buf()
+}
+
+/// Groups local paths whose destinations (as given by `destination`) become one once trailing dots and
+/// spaces are dropped. Within a group, the path that keeps the plain name comes first; groups are sorted.
+pub fn name_collisions<'a>(paths: impl Iterator<Item = &'a PathBuf>, destination: impl Fn(&Path) -> PathBuf) -> Vec<Vec<PathBuf>> {
+    let mut by_name: HashMap<PathBuf, Vec<(bool, PathBuf)>> = HashMap::new();
+    for path in paths {
+        let destination = destination(path);
+        let stored = strip_trailing_dots_and_spaces(&destination);
+        by_name.entry(stored.clone()).or_default().push((stored != destination, path.clone()));
+    }
+    let mut groups: Vec<Vec<PathBuf>> = by_name
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .map(|mut group| {
+            group.sort();
+            group.into_iter().map(|(_, path)| path).collect()
+        })
+        .collect();
+    groups.sort();
+    groups
+}
+
+/// Where the `n`th extra file of a collision group is stored instead, e.g. "report (重名 1).txt".
+pub fn collision_rename(destination: &Path, n: usize) -> PathBuf {
+    suffixed_path(&strip_trailing_dots_and_spaces(destination), &format!(" (重名 {})", n), true)
+}
+
+/// Bytes hashed between checks of the stop flag.
+const HASH_STOP_CHECK_INTERVAL: u64 = 8 * 1024 * 1024;
+/// Bytes hashed between progress reports for a single file; smaller files never report.
+const HASH_PROGRESS_INTERVAL: u64 = 64 * 1024 * 1024;
+
+/// Calculates the SHA256 hash of a file. Returns None if stopped.
+/// `progress` is called with (bytes hashed, file size) every `HASH_PROGRESS_INTERVAL` bytes.
 fn calculate_hash(
     path: &Path,
     stop_flag: &AtomicBool,
-) -> Result<Option<String>, Box<dyn std::error::Error>> {
-    let mut file = File::open(path)?;
+    progress: Option<&dyn Fn(u64, u64)>,
+) -> Result<Option<String>, SyncError> {
+    let mut file = File::open(path).at(path)?;
+    let size = file.metadata().at(path)?.len();
     let mut hasher = Sha256::new();
-    let mut buffer = vec![0; 8192]; // 8KB buffer
+    let mut buffer = vec![0; 64 * 1024]; // 64KB buffer
+    let mut hashed = 0;
+    let mut next_stop_check = 0;
+    let mut next_report = HASH_PROGRESS_INTERVAL;
     loop {
         // Check for stop signal periodically to avoid blocking
-        if stop_flag.load(Ordering::Relaxed) {
-            return Ok(None);
+        if hashed >= next_stop_check {
+            if stop_flag.load(Ordering::Relaxed) {
+                return Ok(None);
+            }
+            next_stop_check = hashed + HASH_STOP_CHECK_INTERVAL;
         }
-        let bytes_read = file.read(&mut buffer)?;
+        let bytes_read = file.read(&mut buffer).at(path)?;
         if bytes_read == 0 {
             break;
         }
         hasher.update(&buffer[..bytes_read]);
+        hashed += bytes_read as u64;
+        if hashed >= next_report {
+            if let Some(progress) = progress {
+                progress(hashed, size.max(hashed));
+            }
+            next_report = hashed + HASH_PROGRESS_INTERVAL;
+        }
     }
     Ok(Some(format!("{:x}", hasher.finalize())))
 }
 
+/// How a scan computes hashes for files it can't take from the last sync's records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashStrategy {
+    /// Leave the hash empty, so the file compares as changed.
+    Skip,
+    /// Hash every file from disk.
+    Full,
+    /// Hash one file per distinct content among files of the same size, reusing it for verified duplicates.
+    Deduplicated,
+}
+
+/// A file whose hash is computed after the walk, together with the other files of its size.
+struct PendingHash {
+    relative_path: PathBuf,
+    modified: SystemTime,
+    size: u64,
+    recorded_hash: Option<String>,
+}
+
+/// Bytes read from each end of a file to tell same-size files apart without reading them fully.
+const SAMPLE_LEN: u64 = 4096;
+
+/// Hashes files grouped by size. A file whose head and tail match an earlier file of its group is compared
+/// with it byte for byte and reuses its hash if identical; every other file is hashed as usual.
+/// Unreadable files are left out, as in the walk. Returns the hashes and how many were reused, or None if stopped.
+fn hash_by_size_group(base_path: &Path, pending: Vec<PendingHash>, stop_flag: &AtomicBool) -> Option<(Vec<(PendingHash, String)>, usize)> {
+    let mut groups: HashMap<u64, Vec<PendingHash>> = HashMap::new();
+    for file in pending {
+        groups.entry(file.size).or_default().push(file);
+    }
+    let reused = AtomicUsize::new(0);
+    let hashed: Vec<_> = groups
+        .into_par_iter()
+        .flat_map_iter(|(size, group)| {
+            let mut results = Vec::with_capacity(group.len());
+            // Distinct contents seen so far, keyed by a digest of their sample: a file to compare against and its hash
+            let mut distinct: HashMap<Vec<u8>, (PathBuf, String)> = HashMap::new();
+            let single = group.len() == 1;
+            for file in group {
+                let path = base_path.join(&file.relative_path);
+                let sample = if single {
+                    None
+                } else {
+                    match read_sample(&path, size) {
+                        Ok(sample) => Some(Sha256::digest(&sample).to_vec()),
+                        Err(_) => continue,
+                    }
+                };
+                // Small files are sampled whole, so a matching sample already proves they are identical
+                let duplicate = sample.as_ref().and_then(|key| distinct.get(key)).filter(|(other, _)| {
+                    size <= 2 * SAMPLE_LEN || files_identical(&path, other, stop_flag).unwrap_or(false)
+                });
+                let hash = match duplicate {
+                    Some((_, hash)) => {
+                        reused.fetch_add(1, Ordering::Relaxed);
+                        hash.clone()
+                    }
+                    None => match calculate_hash(&path, stop_flag, None) {
+                        Ok(Some(hash)) => {
+                            if let Some(key) = sample {
+                                distinct.entry(key).or_insert_with(|| (path, hash.clone()));
+                            }
+                            hash
+                        }
+                        Ok(None) | Err(_) => continue,
+                    },
+                };
+                results.push((file, hash));
+            }
+            results
+        })
+        .collect();
+    if stop_flag.load(Ordering::Relaxed) {
+        return None;
+    }
+    Some((hashed, reused.into_inner()))
+}
+
+/// Reads the first and last `SAMPLE_LEN` bytes of a file of `size` bytes, or all of it if that is shorter.
+fn read_sample(path: &Path, size: u64) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    if size <= 2 * SAMPLE_LEN {
+        let mut sample = Vec::with_capacity(size as usize);
+        file.read_to_end(&mut sample)?;
+        return Ok(sample);
+    }
+    let mut sample = vec![0; 2 * SAMPLE_LEN as usize];
+    let (head, tail) = sample.split_at_mut(SAMPLE_LEN as usize);
+    file.read_exact(head)?;
+    file.seek(SeekFrom::End(-(SAMPLE_LEN as i64)))?;
+    file.read_exact(tail)?;
+    Ok(sample)
+}
+
+/// Compares two files byte for byte. A stop request counts as a difference.
+fn files_identical(a: &Path, b: &Path, stop_flag: &AtomicBool) -> io::Result<bool> {
+    let mut a = File::open(a)?;
+    let mut b = File::open(b)?;
+    let mut buffer_a = vec![0; 64 * 1024];
+    let mut buffer_b = vec![0; 64 * 1024];
+    loop {
+        if stop_flag.load(Ordering::Relaxed) {
+            return Ok(false);
+        }
+        let read_a = read_full(&mut a, &mut buffer_a)?;
+        let read_b = read_full(&mut b, &mut buffer_b)?;
+        if read_a != read_b || buffer_a[..read_a] != buffer_b[..read_b] {
+            return Ok(false);
+        }
+        if read_a == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Fills `buffer` from `reader` unless the end comes first. Returns how many bytes were read.
+fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..])? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
+
+/// Number of files and their combined size in a sync folder.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FolderTotals {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// Adds up file sizes below `base_path` without hashing anything, skipping the same entries as a scan.
+/// A folder that doesn't exist yet counts as empty. Returns None if stopped.
+pub fn folder_totals(base_path: &Path, observer: &impl SyncObserver) -> Option<FolderTotals> {
+    let mut totals = FolderTotals::default();
+    if !base_path.exists() {
+        return Some(totals);
+    }
+    let mut visited = VisitedDirectories::default();
+    let walker = WalkDir::new(base_path).follow_links(false).into_iter().filter_entry(|e| {
+        let repeated = e.file_type().is_dir() && visited.revisit(e.path());
+        let nested = e.depth() > 0 && e.file_type().is_dir() && is_sync_root(e.path());
+        !repeated && !nested && !is_bookkeeping_entry(e)
+    });
+    for entry in walker.filter_map(|e| e.ok()) {
+        if observer.should_stop() {
+            return None;
+        }
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        if entry.file_name().to_string_lossy().ends_with(TEMP_FILE_SUFFIX) {
+            continue;
+        }
+        // Entries that vanish or can't be read mid-walk are left out, as in a scan
+        if let Ok(metadata) = entry.metadata() {
+            totals.files += 1;
+            totals.bytes += metadata.len();
+        }
+    }
+    Some(totals)
+}
+
+/// Identifies a folder independently of the path it was reached through: device and inode on Unix,
+/// volume serial number and file index on Windows. None if the folder can't be opened or the platform has neither.
+pub fn directory_identity(path: &Path) -> Option<(u64, u64)> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        fs::metadata(path).ok().map(|metadata| (metadata.dev(), metadata.ino()))
+    }
+    #[cfg(windows)]
+    {
+        let info = file_information(path)?;
+        Some((u64::from(info.dwVolumeSerialNumber), (u64::from(info.nFileIndexHigh) << 32) | u64::from(info.nFileIndexLow)))
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+/// How many names the file at `path` has, counting `path` itself; more than one means it is hard linked elsewhere.
+/// None if it can't be read or the platform doesn't tell.
+pub fn hard_link_count(path: &Path) -> Option<u64> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        fs::symlink_metadata(path).ok().filter(|metadata| metadata.is_file()).map(|metadata| metadata.nlink())
+    }
+    #[cfg(windows)]
+    {
+        file_information(path).map(|info| u64::from(info.nNumberOfLinks))
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+#[cfg(windows)]
+fn file_information(path: &Path) -> Option<windows::Win32::Storage::FileSystem::BY_HANDLE_FILE_INFORMATION> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::io::AsRawHandle;
+    use windows::Win32::Foundation::HANDLE;
+    use windows::Win32::Storage::FileSystem::{GetFileInformationByHandle, BY_HANDLE_FILE_INFORMATION};
+    // Required to open a folder rather than a file
+    const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+    let handle = fs::OpenOptions::new().access_mode(0).custom_flags(FILE_FLAG_BACKUP_SEMANTICS).open(path).ok()?;
+    let mut info = BY_HANDLE_FILE_INFORMATION::default();
+    unsafe { GetFileInformationByHandle(HANDLE(handle.as_raw_handle()), &mut info) }.ok()?;
+    Some(info)
+}
+
+/// The folders a walk has entered, by identity, so that one reached again through a junction, link or bind mount
+/// is skipped instead of walked over and over.
+#[derive(Debug, Default)]
+pub struct VisitedDirectories(HashSet<(u64, u64)>);
+
+impl VisitedDirectories {
+    /// Records the folder at `path` and tells whether it was entered before. Folders without an identity never repeat.
+    pub fn revisit(&mut self, path: &Path) -> bool {
+        directory_identity(path).is_some_and(|identity| !self.0.insert(identity))
+    }
+}
+
+/// Counts the entries below `base_path` to size the progress of a scan, without following links
+/// or entering a folder twice. Returns None if stopped.
+pub fn count_entries(base_path: &Path, observer: &impl SyncObserver) -> Option<usize> {
+    let mut visited = VisitedDirectories::default();
+    let walker = WalkDir::new(base_path)
+        .follow_links(false)
+        .into_iter()
+        .filter_entry(|e| !(e.file_type().is_dir() && visited.revisit(e.path())));
+    let mut count = 0;
+    for _ in walker.filter_map(Result::ok) {
+        count += 1;
+        if count % 1000 == 0 && observer.should_stop() {
+            return None;
+        }
+    }
+    Some(count)
+}
+
+/// At most this many first-level folders are opened when probing a picked folder, so huge folders stay quick.
+const PERMISSION_PROBE_SAMPLE: usize = 200;
+/// The probe gives up sampling after this long, e.g. on a slow network share.
+const PERMISSION_PROBE_BUDGET: Duration = Duration::from_millis(500);
+
+/// Which folders a quick look at a picked local folder could not open.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PermissionProbe {
+    /// Folders tried, the picked folder included.
+    pub probed: usize,
+    pub inaccessible: Vec<PathBuf>,
+}
+
+impl PermissionProbe {
+    /// Whether enough is unreadable to ask before using the folder: the folder itself, or a tenth of the sample.
+    pub fn is_significant(&self) -> bool {
+        !self.inaccessible.is_empty() && (self.probed <= 1 || self.inaccessible.len() * 10 >= self.probed)
+    }
+}
+
+/// Tries to list `path` and a capped sample of its first-level folders, in well under a second.
+pub fn probe_folder_permissions(path: &Path) -> PermissionProbe {
+    let started = Instant::now();
+    let mut probe = PermissionProbe { probed: 1, inaccessible: Vec::new() };
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(_) => {
+            probe.inaccessible.push(path.to_path_buf());
+            return probe;
+        }
+    };
+    let folders = entries.filter_map(Result::ok).filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()));
+    for entry in folders.take(PERMISSION_PROBE_SAMPLE) {
+        if started.elapsed() > PERMISSION_PROBE_BUDGET {
+            break;
+        }
+        probe.probed += 1;
+        // Some systems only refuse once the listing is read
+        if fs::read_dir(entry.path()).and_then(|mut listing| listing.next().transpose()).is_err() {
+            probe.inaccessible.push(entry.path());
+        }
+    }
+    probe.inaccessible.sort();
+    probe
+}
+
 /// Scans a directory, calculates file hashes incrementally, and sends progress updates.
 /// Skips hashing for files whose size and modification date haven't changed since the last sync.
+/// Entries are streamed from the directory walk, so memory stays proportional to the result rather than the tree.
+/// When `total_entries` is None, progress reports a running count instead of a fraction.
+/// With `HashStrategy::Skip`, files that can't reuse a recorded hash get an empty one, so they compare as changed.
 pub fn scan_directory_with_progress(
     base_path: &Path,
-    tx: &crossbeam_channel::Sender<SyncMessage>,
-    rx: &Receiver<SyncMessage>,
-    total_entries: usize,
+    observer: &impl SyncObserver,
+    total_entries: Option<usize>,
     ui_message_prefix: &str,
     last_sync_data: &SyncData,
-) -> Result<Option<SyncData>, Box<dyn std::error::Error>> {
+    hashing: HashStrategy,
+) -> Result<Option<SyncData>, SyncError> {
     let files = DashMap::new();
+    let pending = Mutex::new(Vec::new());
     let directories = DashSet::new();
     let processed_entries = AtomicUsize::new(0);
+    // Files whose timestamp or size changed but whose content hashed the same as before
+    let unchanged_after_rehash = AtomicUsize::new(0);
     let stop_flag = Arc::new(AtomicBool::new(false));
+    // A file large enough to report its own hashing progress, and its name with the percentage.
+    // While set, it stands in for the per-entry file name, so the two don't take turns in the status text.
+    let large_file: Mutex<Option<(PathBuf, String)>> = Mutex::new(None);
+    let report_progress = |processed: usize, file_name: &str| {
+        let (progress, counter) = match total_entries {
+            Some(total) if total > 0 => (processed as f32 / total as f32, format!("{}/{}", processed, total)),
+            Some(_) => (1.0, processed.to_string()),
+            None => (0.0, format!("已处理 {}", processed)),
+        };
+        let message = match &*large_file.lock().unwrap() {
+            Some((_, status)) => format!("{} ({}) - {}", ui_message_prefix, counter, status),
+            None => format!("{} ({}) - {}", ui_message_prefix, counter, file_name),
+        };
+        observer.on_progress(progress, message);
+    };
 
-    // Collect all entries first
-    let entries: Vec<_> = WalkDir::new(base_path)
+    // Walk the tree without descending into other sync folders nested inside this one, or into a folder twice
+    let mut nested_roots = Vec::new();
+    let mut loops = Vec::new();
+    let mut visited = VisitedDirectories::default();
+    WalkDir::new(base_path)
+        .follow_links(false)
         .into_iter()
+        .filter_entry(|e| {
+            if e.file_type().is_dir() && visited.revisit(e.path()) {
+                loops.push(e.path().to_path_buf());
+                return false;
+            }
+            let nested = e.depth() > 0 && e.file_type().is_dir() && is_sync_root(e.path());
+            if nested {
+                nested_roots.push(e.path().to_path_buf());
+            }
+            !nested && !is_bookkeeping_entry(e)
+        })
         .filter_map(|e| e.ok())
-        .collect();
-
-    // Process entries in parallel
-    let results: Vec<_> = entries
-        .par_iter()
-        .map(|entry| {
+        .par_bridge()
+        .for_each(|entry| {
             // Check for stop signal from the UI thread
-            if let Ok(SyncMessage::Stop) = rx.try_recv() {
+            if observer.should_stop() {
                 stop_flag.store(true, Ordering::Relaxed);
             }
             if stop_flag.load(Ordering::Relaxed) {
-                return None;
+                return;
             }
 
             let path = entry.path();
-            let file_name = path.file_name().unwrap_or_default().to_str().unwrap_or_default();
+            // Names that aren't valid Unicode still show up and are still recognized as temporary files
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy();
 
-            // Ignore metadata and log files
-            if file_name == ".syncu_metadata.json" || file_name == ".syncu_log.txt" {
-                return None;
+            // Ignore leftover temporary files
+            if file_name.ends_with(TEMP_FILE_SUFFIX) {
+                return;
             }
 
             let relative_path = match path.strip_prefix(base_path) {
                 Ok(p) => p.to_path_buf(),
-                Err(_) => return None,
+                Err(_) => return,
             };
             
             if relative_path.as_os_str().is_empty() {
-                return None; // Skip the root directory itself
+                return; // Skip the root directory itself
             }
 
             // Update progress counter
             let current_processed = processed_entries.fetch_add(1, Ordering::Relaxed) + 1;
             
             if current_processed % 10 == 1 {
-                let progress = if total_entries > 0 {
-                    current_processed as f32 / total_entries as f32
-                } else {
-                    1.0
-                };
-                
-                let _ = tx.send(SyncMessage::Progress(
-                    progress,
-                    format!(
-                        "{} ({}/{}) - {}",
-                        ui_message_prefix, current_processed, total_entries, file_name
-                    ),
-                ));
+                report_progress(current_processed, &file_name);
             }
 
             if entry.file_type().is_dir() {
                 directories.insert(relative_path);
-                return None; // Return None for directories as they don't need further processing in this map
+                return; // Directories don't need hashing
             }
 
             // From here, we are dealing with a file
-            let metadata = match fs::metadata(path) {
+            let exact = exact_path(path);
+            let metadata = match fs::metadata(&exact) {
                 Ok(m) => m,
-                Err(_) => return None,
+                Err(_) => return,
             };
 
             let modified = match metadata.modified() {
                 Ok(m) => m,
-                Err(_) => return None,
+                Err(_) => return,
             };
 
             let size = metadata.len();
 
-            let hash = if let Some(last_file_info) = last_sync_data.files.get(&relative_path) {
-                if last_file_info.modified == modified && last_file_info.size == size {
+            let recorded = last_sync_data.files.get(&relative_path);
+            let hash = match (recorded, hashing) {
+                (Some(last_file_info), _) if last_file_info.modified == modified && last_file_info.size == size => {
                     last_file_info.hash.clone()
-                } else {
-                    match calculate_hash(path, &stop_flag) {
-                        Ok(Some(h)) => h,
-                        Ok(None) => return None,
-                        Err(_) => return None,
-                    }
                 }
-            } else {
-                match calculate_hash(path, &stop_flag) {
-                    Ok(Some(h)) => h,
-                    Ok(None) => return None,
-                    Err(_) => return None,
+                (_, HashStrategy::Skip) => String::new(),
+                (_, HashStrategy::Deduplicated) => {
+                    // Hashed after the walk, once every file of the same size is known
+                    let recorded_hash = recorded.map(|info| info.hash.clone());
+                    pending.lock().unwrap().push(PendingHash { relative_path, modified, size, recorded_hash });
+                    return;
+                }
+                (_, HashStrategy::Full) => {
+                    let on_hash_progress = |hashed: u64, total: u64| {
+                        let status = format!("{} ({}%)", file_name, hashed * 100 / total);
+                        *large_file.lock().unwrap() = Some((relative_path.clone(), status));
+                        report_progress(processed_entries.load(Ordering::Relaxed), &file_name);
+                    };
+                    let hashed = calculate_hash(&exact, &stop_flag, Some(&on_hash_progress));
+                    // Give the status text back, unless another large file has taken it over
+                    let mut shown = large_file.lock().unwrap();
+                    if shown.as_ref().is_some_and(|(shown_path, _)| *shown_path == relative_path) {
+                        *shown = None;
+                    }
+                    drop(shown);
+                    match hashed {
+                        Ok(Some(h)) => {
+                            if recorded.is_some_and(|info| info.hash == h) {
+                                unchanged_after_rehash.fetch_add(1, Ordering::Relaxed);
+                            }
+                            h
+                        }
+                        Ok(None) => return,
+                        Err(_) => return,
+                    }
                 }
             };
 
-            Some((
+            // An untouched file keeps what its last write established
+            let verified = recorded.is_some_and(|info| info.verified && info.hash == hash && info.modified == modified && info.size == size);
+            files.insert(
                 relative_path.clone(),
                 FileInfo {
                     path: relative_path,
                     hash,
                     modified,
                     size,
+                    verified,
                 },
-            ))
-        })
-        .collect();
+            );
+        });
+    for root in &nested_roots {
+        observer.on_log(format!("警告: 已跳过嵌套的 SyncU 同步目录: {}", root.display()));
+    }
+    for path in &loops {
+        observer.on_log(format!("警告: 检测到循环引用，已跳过: {}", path.display()));
+    }
 
     if stop_flag.load(Ordering::Relaxed) {
         return Ok(None);
     }
 
-    for result in results {
-        if let Some((path, info)) = result {
-            files.insert(path, info);
+    let pending = pending.into_inner().unwrap();
+    if !pending.is_empty() {
+        observer.on_progress(1.0, format!("{} - 正在比较大小相同的文件...", ui_message_prefix));
+        let Some((hashed, reused)) = hash_by_size_group(base_path, pending, &stop_flag) else {
+            return Ok(None);
+        };
+        for (file, hash) in hashed {
+            if file.recorded_hash.as_ref() == Some(&hash) {
+                unchanged_after_rehash.fetch_add(1, Ordering::Relaxed);
+            }
+            let PendingHash { relative_path, modified, size, .. } = file;
+            files.insert(relative_path.clone(), FileInfo { path: relative_path, hash, modified, size, verified: false });
+        }
+        if reused > 0 {
+            observer.on_log(format!(
+                "[{}] {}: {} 个重复文件复用了校验值",
+                chrono::Local::now().format("%H:%M:%S"),
+                ui_message_prefix,
+                reused
+            ));
         }
     }
 
+    // Many of these mean something touched the tree, which explains a slow sync that copied nothing
+    let unchanged_after_rehash = unchanged_after_rehash.into_inner();
+    if unchanged_after_rehash > 0 {
+        observer.on_log(format!(
+            "[{}] {}: 重新校验 {} 个文件，内容未变化",
+            chrono::Local::now().format("%H:%M:%S"),
+            ui_message_prefix,
+            unchanged_after_rehash
+        ));
+    }
+
     let files_map: HashMap<PathBuf, FileInfo> = files.into_iter().collect();
     let directories_set: HashSet<PathBuf> = directories.into_iter().collect();
     
     Ok(Some(SyncData {
         files: files_map,
         directories: directories_set,
+        last_sync_time: None,
+        routes: HashMap::new(),
+        tombstones: HashMap::new(),
+        skipped_conflicts: HashMap::new(),
+        source_folder: None,
+        volume_id: None,
+        observations: HashMap::new(),
+        written_by: None,
+        backslash_separators: false,
     }))
 }
 
-/// Saves the synchronization metadata to a JSON file.
-pub fn save_sync_data(sync_data: &SyncData, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
-    let file = File::create(path)?;
-    serde_json::to_writer_pretty(file, sync_data)?;
+/// Maximum distance into the future a recorded timestamp may lie before the clock is considered wrong.
+const CLOCK_SKEW_TOLERANCE: Duration = Duration::from_secs(24 * 60 * 60);
+/// How far the clock may be behind the last sync before it is considered wrong: two machines' clocks drift apart
+/// by this much without anything being amiss, and FAT rounds times to two seconds anyway.
+const LAST_SYNC_TOLERANCE: Duration = Duration::from_secs(5 * 60);
+
+/// Checks the current system time against the last sync's record: when that sync ran and the newest file mtime
+/// it holds. The local folder's own mtimes aren't consulted, since it is only scanned once this check has decided
+/// whether the scan may trust them. Returns a description of the problem if the clock looks wrong, or None if it
+/// looks sane.
+pub fn detect_clock_skew_from_record(last_sync_data: &SyncData, now: SystemTime) -> Option<String> {
+    if let Some(last_sync_time) = last_sync_data.last_sync_time {
+        let behind = last_sync_time.duration_since(now).unwrap_or_default();
+        if behind > LAST_SYNC_TOLERANCE {
+            return Some(format!(
+                "当前系统时间早于上次同步时间约 {} 分钟。",
+                behind.as_secs() / 60
+            ));
+        }
+    }
+
+    let newest_modified = last_sync_data.files.values().map(|f| f.modified).max()?;
+    match newest_modified.duration_since(now) {
+        Ok(ahead) if ahead > CLOCK_SKEW_TOLERANCE => Some(format!(
+            "上次同步记录中有文件的修改时间比当前系统时间晚约 {} 小时。",
+            ahead.as_secs() / 3600
+        )),
+        _ => None,
+    }
+}
+
+/// Recorded files whose on-disk mtime is compared with the record when looking for a constant offset.
+const MTIME_OFFSET_SAMPLE: usize = 200;
+/// Fewer comparable files than this say too little to infer an offset from.
+const MTIME_OFFSET_MIN_SAMPLES: usize = 10;
+/// Time zones lie within this many hours of UTC.
+const MTIME_OFFSET_MAX_HOURS: i64 = 14;
+
+/// A whole-hour shift between the mtimes on disk and those in the last sync record, as left by a time zone
+/// change on a file system that stores local time (FAT) or by a machine whose clock was hours off.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MtimeOffset {
+    /// On-disk mtime minus recorded mtime, in seconds.
+    pub seconds: i64,
+    /// Sampled files that differ by exactly this offset.
+    pub matching: usize,
+    /// Recorded files found on disk with their recorded size.
+    pub sampled: usize,
+}
+
+impl MtimeOffset {
+    /// The offset as a signed number of hours, e.g. "+8:00".
+    pub fn label(&self) -> String {
+        format!("{}{}:00", if self.seconds < 0 { '-' } else { '+' }, self.seconds.abs() / 3600)
+    }
+}
+
+/// Compares the mtimes of up to `MTIME_OFFSET_SAMPLE` recorded files under `base_path` with the record.
+/// Returns an offset only if it is a non-zero whole number of hours, at most `MTIME_OFFSET_MAX_HOURS`, and more
+/// than 90% of the sampled files differ by exactly that much. Files whose size changed aren't sampled.
+pub fn infer_mtime_offset(base_path: &Path, reference: &SyncData) -> Option<MtimeOffset> {
+    const HOUR_NANOS: i128 = 3_600_000_000_000;
+    let nanos = |time: SystemTime| match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => after.as_nanos() as i128,
+        Err(before) => -(before.duration().as_nanos() as i128),
+    };
+    let mut differences: HashMap<i128, usize> = HashMap::new();
+    let mut sampled = 0;
+    for (path, info) in &reference.files {
+        if sampled == MTIME_OFFSET_SAMPLE {
+            break;
+        }
+        let Ok(metadata) = fs::metadata(exact_path(&base_path.join(path))) else { continue };
+        let Ok(modified) = metadata.modified() else { continue };
+        if !metadata.is_file() || metadata.len() != info.size {
+            continue;
+        }
+        sampled += 1;
+        *differences.entry(nanos(modified) - nanos(info.modified)).or_default() += 1;
+    }
+    if sampled < MTIME_OFFSET_MIN_SAMPLES {
+        return None;
+    }
+    let (&difference, &matching) = differences.iter().max_by_key(|(_, count)| **count)?;
+    let hours = difference / HOUR_NANOS;
+    let whole_hours = difference != 0 && difference % HOUR_NANOS == 0 && hours.abs() <= MTIME_OFFSET_MAX_HOURS as i128;
+    (whole_hours && matching * 10 > sampled * 9).then_some(MtimeOffset { seconds: hours as i64 * 3600, matching, sampled })
+}
+
+/// A copy of the files in `reference` with their mtimes moved by `offset`, so they compare equal to the files on disk.
+pub fn shift_mtimes(reference: &SyncData, offset: MtimeOffset) -> SyncData {
+    let shift = Duration::from_secs(offset.seconds.unsigned_abs());
+    let files = reference
+        .files
+        .iter()
+        .map(|(path, info)| {
+            let modified = if offset.seconds < 0 { info.modified.checked_sub(shift) } else { info.modified.checked_add(shift) };
+            (path.clone(), FileInfo { modified: modified.unwrap_or(info.modified), ..info.clone() })
+        })
+        .collect();
+    SyncData { files, ..Default::default() }
+}
+
+/// Flushes a directory entry to disk, so a rename inside it survives a crash or unplug.
+/// Windows can't open directories as files, and commits renames without this.
+fn sync_directory(path: &Path) -> Result<(), SyncError> {
+    #[cfg(unix)]
+    File::open(path).and_then(|dir| dir.sync_all()).at(path)?;
+    #[cfg(not(unix))]
+    let _ = path;
     Ok(())
 }
 
+// Moves a fully written temporary metadata file into place and makes the rename durable.
+fn commit_temp_file(temp: &Path, path: &Path) -> Result<(), SyncError> {
+    fs::rename(temp, path).at(path)?;
+    match path.parent() {
+        Some(parent) => sync_directory(parent),
+        None => Ok(()),
+    }
+}
+
+/// Saves the synchronization metadata to a JSON file.
+/// Written to a temporary file first, so an interrupted write leaves the previous metadata intact.
+pub fn save_sync_data(sync_data: &SyncData, path: &Path) -> Result<(), SyncError> {
+    write_sync_data(sync_data, path, None).map(|_| ())
+}
+
+/// Saves the plan of a running sync, replacing the previous checkpoint only once the new one is complete.
+/// Written without indentation, since large plans are saved again and again while they run.
+pub fn save_plan_checkpoint(checkpoint: &PlanCheckpoint, path: &Path) -> Result<(), SyncError> {
+    let temp = suffixed_path(path, TEMP_FILE_SUFFIX, false);
+    let mut writer = BufWriter::new(File::create(&temp).at(&temp)?);
+    let result = serde_json::to_writer(&mut writer, checkpoint)
+        .map_err(io::Error::from)
+        .and_then(|_| writer.flush())
+        .and_then(|_| writer.get_ref().sync_all());
+    drop(writer);
+    if let Err(source) = result {
+        let _ = fs::remove_file(&temp);
+        return Err(SyncError::Io { path: temp, source });
+    }
+    commit_temp_file(&temp, path)
+}
+
+/// Loads the plan an unfinished run left behind, or None if the last run finished.
+pub fn load_plan_checkpoint(path: &Path) -> Result<Option<PlanCheckpoint>, SyncError> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let reader = BufReader::new(File::open(path).at(path)?);
+    let checkpoint = serde_json::from_reader(reader).map_err(|source| SyncError::MetadataParse {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    Ok(Some(checkpoint))
+}
+
+/// Counts the bytes written through it, reports them as progress and aborts the write when asked to stop.
+/// Without an observer it only counts.
+struct ProgressWriter<'a, W: Write> {
+    inner: W,
+    written: u64,
+    estimated: u64,
+    observer: Option<&'a dyn SyncObserver>,
+    last_update: Instant,
+    stopped: bool,
+}
+
+impl<W: Write> Write for ProgressWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.observer.is_some_and(|observer| observer.should_stop()) {
+            self.stopped = true;
+            return Err(io::Error::other("stopped"));
+        }
+        let count = self.inner.write(buf)?;
+        self.written += count as u64;
+        if let Some(observer) = self.observer.filter(|_| self.last_update.elapsed() > Duration::from_millis(50)) {
+            // The estimate can be exceeded when the record grew, so never claim to be done early
+            let percent = (self.written * 100 / self.estimated.max(1)).min(99);
+            observer.on_progress(0.99, format!("正在写入同步记录 {}%", percent));
+            self.last_update = Instant::now();
+        }
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Like `save_sync_data`, but reports progress and can be stopped, which leaves the previous metadata in place.
+/// Returns the size written, or None if stopped.
+pub fn save_sync_data_with_progress(sync_data: &SyncData, path: &Path, observer: &impl SyncObserver) -> Result<Option<u64>, SyncError> {
+    write_sync_data(sync_data, path, Some(observer))
+}
+
+fn write_sync_data(sync_data: &SyncData, path: &Path, observer: Option<&dyn SyncObserver>) -> Result<Option<u64>, SyncError> {
+    // The previous file is usually close in size; a first save falls back to a rough per-file guess
+    let estimated = fs::metadata(path).map(|m| m.len()).unwrap_or(0).max(sync_data.files.len() as u64 * 200);
+    let temp = suffixed_path(path, TEMP_FILE_SUFFIX, false);
+    let mut writer = ProgressWriter {
+        inner: BufWriter::with_capacity(256 * 1024, File::create(&temp).at(&temp)?),
+        written: 0,
+        estimated,
+        observer,
+        last_update: Instant::now(),
+        stopped: false,
+    };
+    let result = serde_json::to_writer_pretty(&mut writer, sync_data)
+        .map_err(io::Error::from)
+        .and_then(|_| writer.flush())
+        .and_then(|_| writer.inner.get_ref().sync_all());
+    let (stopped, written) = (writer.stopped, writer.written);
+    drop(writer);
+    if stopped {
+        let _ = fs::remove_file(&temp);
+        return Ok(None);
+    }
+    if let Err(source) = result {
+        let _ = fs::remove_file(&temp);
+        return Err(SyncError::Io { path: temp, source });
+    }
+    commit_temp_file(&temp, path)?;
+    Ok(Some(written))
+}
+
 /// Loads synchronization metadata from a JSON file.
-pub fn load_sync_data(path: &Path) -> Result<SyncData, Box<dyn std::error::Error>> {
+pub fn load_sync_data(path: &Path) -> Result<SyncData, SyncError> {
     if !path.exists() {
         return Ok(SyncData::default());
     }
-    let file = File::open(path)?;
+    let file = File::open(path).at(path)?;
     let reader = BufReader::new(file);
-    let sync_data = serde_json::from_reader(reader)?;
+    let sync_data = SyncData::from_reader(reader).map_err(|source| SyncError::MetadataParse {
+        path: path.to_path_buf(),
+        source,
+    })?;
     Ok(sync_data)
 }
 
+/// Refuses a record written by a newer SyncU, since saving it again could drop what this version doesn't know.
+/// Callers check before changing anything that they would have to record.
+pub fn ensure_writable(sync_data: &SyncData, path: &Path) -> Result<(), SyncError> {
+    match sync_data.written_by_newer_version() {
+        Some(version) => Err(SyncError::NewerMetadata { path: path.to_path_buf(), version: version.to_owned() }),
+        None => Ok(()),
+    }
+}
+
+/// Counts the bytes read through it, reports them as progress and aborts the read when asked to stop.
+struct ProgressReader<'a, R: Read, O: SyncObserver> {
+    inner: R,
+    read: u64,
+    total: u64,
+    observer: &'a O,
+    last_update: Instant,
+    stopped: bool,
+}
+
+impl<R: Read, O: SyncObserver> Read for ProgressReader<'_, R, O> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.observer.should_stop() {
+            self.stopped = true;
+            return Err(io::Error::other("stopped"));
+        }
+        let count = self.inner.read(buf)?;
+        self.read += count as u64;
+        if self.last_update.elapsed() > Duration::from_millis(50) {
+            let percent = (self.read * 100 / self.total.max(1)).min(99);
+            self.observer.on_progress(0.0, format!("正在加载上次同步记录 {}%", percent));
+            self.last_update = Instant::now();
+        }
+        Ok(count)
+    }
+}
+
+/// Like `load_sync_data`, but reports progress and can be stopped between chunks, e.g. for a large record on a slow drive.
+/// Returns None if stopped.
+pub fn load_sync_data_with_progress(path: &Path, observer: &impl SyncObserver) -> Result<Option<SyncData>, SyncError> {
+    if !path.exists() {
+        return Ok(Some(SyncData::default()));
+    }
+    let file = File::open(path).at(path)?;
+    let total = file.metadata().at(path)?.len();
+    let mut reader = ProgressReader { inner: file, read: 0, total, observer, last_update: Instant::now(), stopped: false };
+    let result = SyncData::from_reader(BufReader::with_capacity(256 * 1024, &mut reader));
+    if reader.stopped {
+        return Ok(None);
+    }
+    result.map(Some).map_err(|source| SyncError::MetadataParse { path: path.to_path_buf(), source })
+}
+
+/// Longest burst a rate limit lets through at full speed, as a fraction of one second's allowance.
+const RATE_LIMIT_BURST_SECONDS: f64 = 0.5;
+
+/// Token bucket that paces copies to the observer's current rate limit, which may change during a run.
+/// Does nothing while there is no limit.
+#[derive(Default)]
+pub struct RateLimiter {
+    // When the bucket was last topped up, and how many bytes may still go out without waiting
+    bucket: Mutex<Option<(Instant, f64)>>,
+}
+
+impl RateLimiter {
+    /// Accounts for `bytes` just copied and sleeps as long as the limit requires. Returns true if stopped while waiting.
+    pub fn pace(&self, bytes: u64, observer: &impl SyncObserver) -> bool {
+        let Some(limit) = observer.rate_limit() else {
+            *self.bucket.lock().unwrap() = None;
+            return false;
+        };
+        let rate = limit.max(1) as f64;
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+            let (last, tokens) = bucket.unwrap_or((now, rate * RATE_LIMIT_BURST_SECONDS));
+            let tokens = (tokens + now.duration_since(last).as_secs_f64() * rate).min(rate * RATE_LIMIT_BURST_SECONDS) - bytes as f64;
+            *bucket = Some((now, tokens));
+            Duration::from_secs_f64((-tokens).max(0.0) / rate)
+        };
+        // Sleep in slices so a stop request or a new limit is noticed quickly
+        let deadline = Instant::now() + wait;
+        while let Some(remaining) = deadline.checked_duration_since(Instant::now()).filter(|d| !d.is_zero()) {
+            if observer.should_stop() {
+                return true;
+            }
+            std::thread::sleep(remaining.min(Duration::from_millis(100)));
+        }
+        false
+    }
+}
+
 /// Copies a large file with progress reporting, allowing for cancellation.
+/// A skip request abandons the copy with `SyncError::Skipped`; the destination is left as it was.
+/// Chunks are paced by `limiter`; time spent waiting doesn't count as progress.
+/// The finished copy replaces `to` only if `keep` agrees, e.g. because the source didn't change meanwhile.
+#[allow(clippy::too_many_arguments)]
 pub fn copy_large_file_with_progress(
     from: &Path,
     to: &Path,
     file_name_for_ui: &str,
-    tx: &crossbeam_channel::Sender<SyncMessage>,
-    rx: &Receiver<SyncMessage>,
+    observer: &impl SyncObserver,
+    limiter: &RateLimiter,
     total_sync_size: u64,
     processed_size_before: u64,
-) -> Result<bool, io::Error> {
-    let file_size = fs::metadata(from)?.len();
-    let mut source = File::open(from)?;
-    let mut dest = File::create(to)?;
+    keep: impl FnOnce() -> bool,
+) -> Result<bool, SyncError> {
+    // Copy to a temporary file first, so an interrupted copy never leaves a partial file under the real name
+    let temp = temp_path_for(to);
+    let result = copy_to_temp_with_progress(from, &temp, file_name_for_ui, observer, limiter, total_sync_size, processed_size_before);
+    let result = match result {
+        Ok(false) if !keep() => {
+            let _ = fs::remove_file(&temp);
+            Ok(false)
+        }
+        Ok(false) => fs::rename(&temp, to).at(to).map(|_| false),
+        _ => {
+            let _ = fs::remove_file(&temp);
+            result
+        }
+    };
+    remove_staging_dir(&temp);
+    result
+}
+
+/// Copies a file small enough to go in one piece. It too is written to a temporary file first, which replaces
+/// `to` only if `keep` agrees once it is complete. With `in_place` its contents are written into `to` instead,
+/// so other hard links to it see them as well.
+pub fn copy_small_file(from: &Path, to: &Path, in_place: bool, keep: impl FnOnce() -> bool) -> Result<(), SyncError> {
+    let temp = temp_path_for(to);
+    let result = copy_to_temp(from, &temp).and_then(|()| match keep() {
+        false => Ok(()),
+        true if in_place => fs::copy(&temp, to).at(to).map(|_| ()),
+        true => fs::rename(&temp, to).at(to),
+    });
+    let _ = fs::remove_file(&temp);
+    remove_staging_dir(&temp);
+    result
+}
+
+fn copy_to_temp(from: &Path, temp: &Path) -> Result<(), SyncError> {
+    let mut source = File::open(from).at(from)?;
+    let mut dest = create_temp_file(temp).at(temp)?;
+    io::copy(&mut source, &mut dest).at(temp)?;
+    // As `fs::copy` would, so e.g. an executable stays one
+    fs::set_permissions(temp, source.metadata().at(from)?.permissions()).at(temp)
+}
+
+// A staging folder is only kept while a copy is in it
+fn remove_staging_dir(temp: &Path) {
+    if let Some(staging) = temp.parent().filter(|parent| parent.file_name() == Some(STAGING_DIR_NAME.as_ref())) {
+        let _ = fs::remove_dir(staging);
+    }
+}
+
+/// The cloud-synced folder that `path` lies in, if it is one of the well-known clients' folders.
+/// Used only to keep SyncU's temporary files out of it, never to refuse a folder.
+pub fn cloud_sync_root(path: &Path) -> Option<PathBuf> {
+    let configured: Vec<PathBuf> = ["OneDrive", "OneDriveConsumer", "OneDriveCommercial"]
+        .iter()
+        .filter_map(std::env::var_os)
+        .map(PathBuf::from)
+        .collect();
+    path.ancestors()
+        .find(|ancestor| {
+            let name = ancestor.file_name().unwrap_or_default().to_string_lossy();
+            configured.iter().any(|root| root == ancestor)
+                || CLOUD_FOLDER_NAMES.contains(&name.as_ref())
+                || name.starts_with("OneDrive - ")
+        })
+        .map(Path::to_path_buf)
+}
+
+/// Where a copy to `destination` is written before it replaces it: next to the destination, or in a staging
+/// folder beside the cloud-synced folder that holds it, so the cloud client never uploads a partial file.
+/// The staging folder is on the same volume, so the finished copy is still moved into place by a rename.
+pub fn temp_path_for(destination: &Path) -> PathBuf {
+    let Some(staging) = cloud_sync_root(destination).and_then(|root| root.parent().map(|parent| parent.join(STAGING_DIR_NAME))) else {
+        return suffixed_path(destination, TEMP_FILE_SUFFIX, false);
+    };
+    // Names are taken from the whole destination path, so files of the same name in different folders don't meet
+    let digest = Sha256::digest(destination.as_os_str().as_encoded_bytes());
+    let name: String = digest[..8].iter().map(|byte| format!("{:02x}", byte)).collect();
+    staging.join(format!("{}{}", name, TEMP_FILE_SUFFIX))
+}
+
+// Creates a temporary copy target, marked as short-lived for the OS cache and as ignored for Dropbox where the
+// file system allows. Other clients have no such marker; the staging folder keeps them away from the file instead.
+fn create_temp_file(path: &Path) -> io::Result<File> {
+    if let Some(parent) = path.parent().filter(|parent| parent.file_name() == Some(STAGING_DIR_NAME.as_ref())) {
+        fs::create_dir_all(parent)?;
+    }
+    #[cfg(windows)]
+    let file = {
+        use std::os::windows::fs::OpenOptionsExt;
+        const FILE_ATTRIBUTE_TEMPORARY: u32 = 0x100;
+        let file = fs::OpenOptions::new().write(true).create(true).truncate(true).attributes(FILE_ATTRIBUTE_TEMPORARY).open(path)?;
+        let mut marker = path.as_os_str().to_owned();
+        marker.push(":com.dropbox.ignored");
+        let _ = fs::write(marker, "1");
+        file
+    };
+    #[cfg(not(windows))]
+    let file = {
+        let file = File::create(path)?;
+        #[cfg(unix)]
+        {
+            let name = if cfg!(target_os = "linux") { "user.com.dropbox.ignored" } else { "com.dropbox.ignored" };
+            let _ = xattr::set(path, name, b"1");
+        }
+        file
+    };
+    Ok(file)
+}
+
+fn copy_to_temp_with_progress(
+    from: &Path,
+    to: &Path,
+    file_name_for_ui: &str,
+    observer: &impl SyncObserver,
+    limiter: &RateLimiter,
+    total_sync_size: u64,
+    processed_size_before: u64,
+) -> Result<bool, SyncError> {
+    let file_size = fs::metadata(from).at(from)?.len();
+    let mut source = File::open(from).at(from)?;
+    let mut dest = create_temp_file(to).at(to)?;
     let mut buffer = vec![0; 64 * 1024]; // 64KB buffer
     let mut copied_size = 0;
     let mut last_update = Instant::now();
 
     loop {
-        match rx.try_recv() {
-            Ok(SyncMessage::Stop) => {
-                // Clean up the partially copied file on cancellation
-                drop(dest);
-                let _ = fs::remove_file(to);
-                return Ok(true);
-            }
-            _ => {}
+        if observer.should_stop() {
+            return Ok(true);
+        }
+        if observer.should_skip_current() {
+            return Err(SyncError::Skipped);
         }
 
-        let bytes_read = source.read(&mut buffer)?;
+        let bytes_read = source.read(&mut buffer).at(from)?;
         if bytes_read == 0 {
             break;
         }
-        dest.write_all(&buffer[..bytes_read])?;
+        dest.write_all(&buffer[..bytes_read]).at(to)?;
         copied_size += bytes_read as u64;
+        if limiter.pace(bytes_read as u64, observer) {
+            return Ok(true);
+        }
 
         // Throttle progress updates to avoid overwhelming the UI thread
         if total_sync_size > 0 && (last_update.elapsed().as_millis() > 50 || copied_size == file_size) {
             let progress = (processed_size_before + copied_size) as f32 / total_sync_size as f32;
             let file_progress = copied_size as f32 / file_size as f32;
-            tx.send(SyncMessage::Progress(
+            observer.on_progress(
                 progress,
                 format!(
                     "正在处理: {} ({:.0}%)",
                     file_name_for_ui,
                     file_progress * 100.0
                 ),
-            ))
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "Failed to send progress"))?;
+            );
             last_update = Instant::now();
         }
     }
     Ok(false)
 }
 
-/// Writes a log message to the .syncu_log.txt file in the sync directory.
-pub fn write_log_entry(message: &str, usb_sync_path: &Path) -> Result<(), io::Error> {
-    let log_path = usb_sync_path.join(".syncu_log.txt");
+/// Directory entry slots a FAT directory has; a long name takes one slot per 13 UTF-16 units besides its own.
+/// Every folder below the drive root also spends two on "." and "..".
+const FAT_DIRECTORY_SLOTS: usize = 65_536 - 2;
+
+/// Finds the folders that would hold more entries than a FAT file system allows or than `soft_limit`.
+/// `entries` are all files and folders in the USB sync folder after the sync, relative to it. The sync folder
+/// is itself a folder on the drive, so the small fixed root directory of FAT12/16 never applies.
+pub fn crowded_directories<'a>(
+    entries: impl IntoIterator<Item = &'a Path>,
+    file_system: Option<&str>,
+    soft_limit: Option<usize>,
+) -> Vec<CrowdedDirectory> {
+    let fat = file_system.is_some_and(|name| {
+        matches!(name.to_ascii_lowercase().as_str(), "fat" | "fat12" | "fat16" | "fat32" | "vfat" | "msdos")
+    });
+    // Entries and FAT slots per folder
+    let mut folders: HashMap<PathBuf, (usize, usize)> = HashMap::new();
+    for entry in entries {
+        let (Some(parent), Some(name)) = (entry.parent(), entry.file_name()) else { continue };
+        let slots = 1 + name.to_string_lossy().encode_utf16().count().div_ceil(13);
+        let folder = folders.entry(parent.to_path_buf()).or_default();
+        folder.0 += 1;
+        folder.1 += slots;
+    }
+    let mut crowded: Vec<CrowdedDirectory> = folders
+        .into_iter()
+        .filter_map(|(path, (entries, slots))| {
+            if fat && slots > FAT_DIRECTORY_SLOTS {
+                let limit = FAT_DIRECTORY_SLOTS * entries / slots;
+                return Some(CrowdedDirectory { path, entries, limit, exceeds_file_system: true });
+            }
+            match soft_limit {
+                Some(limit) if entries > limit => Some(CrowdedDirectory { path, entries, limit, exceeds_file_system: false }),
+                _ => None,
+            }
+        })
+        .collect();
+    crowded.sort_by(|a, b| a.path.cmp(&b.path));
+    crowded
+}
+
+// Length of a name component in the units the destination file system limits.
+// NTFS, exFAT and FAT32 count UTF-16 units; most other file systems count UTF-8 bytes.
+fn name_component_len(name: &str) -> usize {
+    if cfg!(windows) { name.encode_utf16().count() } else { name.len() }
+}
+
+/// Returns `path` with `suffix` added to its file name, keeping the name within the component length limit.
+/// With `before_extension` the suffix goes between stem and extension ("report (冲突).docx"), otherwise after the
+/// whole name ("report.docx.syncu_tmp"). Too long stems are cut at a char boundary; when that happens a short
+/// counter is added if the shortened name is already taken.
+pub fn suffixed_path(path: &Path, suffix: &str, before_extension: bool) -> PathBuf {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let (stem, extension) = match file_name.rfind('.') {
+        Some(dot) if before_extension && dot > 0 => file_name.split_at(dot),
+        _ => (file_name.as_ref(), ""),
+    };
+    let fits = |stem: &str, counter: &str| {
+        name_component_len(stem) + name_component_len(counter) + name_component_len(suffix) + name_component_len(extension)
+            <= MAX_NAME_COMPONENT_LEN
+    };
+    let truncate = |counter: &str| {
+        let mut end = stem.len();
+        while end > 0 && !fits(&stem[..end], counter) {
+            end = stem[..end].char_indices().next_back().map_or(0, |(index, _)| index);
+        }
+        &stem[..end]
+    };
+
+    if fits(stem, "") {
+        return path.with_file_name(format!("{}{}{}", stem, suffix, extension));
+    }
+    let candidate = path.with_file_name(format!("{}{}{}", truncate(""), suffix, extension));
+    if !candidate.exists() {
+        return candidate;
+    }
+    (1..)
+        .map(|n| {
+            let counter = format!("~{}", n);
+            path.with_file_name(format!("{}{}{}{}", truncate(&counter), counter, suffix, extension))
+        })
+        .find(|candidate| !candidate.exists())
+        .unwrap_or(candidate)
+}
+
+/// Returns true if another process appears to have the file open for writing.
+/// Only detectable on Windows; elsewhere the copy relies on comparing mtimes before and after.
+pub fn is_file_in_use(path: &Path) -> bool {
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        const FILE_SHARE_READ: u32 = 0x1;
+        const ERROR_SHARING_VIOLATION: i32 = 32;
+        // Refusing to share write access fails while another process holds a write handle
+        match fs::OpenOptions::new().read(true).share_mode(FILE_SHARE_READ).open(path) {
+            Err(e) => e.raw_os_error() == Some(ERROR_SHARING_VIOLATION),
+            Ok(_) => false,
+        }
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+/// What an I/O error says about the chances of trying again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IoErrorCategory {
+    /// Access was refused; trying again fails the same way until the user changes something.
+    Permission,
+    /// The path is too long or names something the file system can't hold; trying again can't help.
+    Path,
+    /// The device didn't respond or reported a fault, as flaky USB controllers do; trying again often works.
+    Device,
+    /// The destination volume is full.
+    DiskFull,
+    Other,
+}
+
+impl IoErrorCategory {
+    /// Short tag for the summary, e.g. "权限不足".
+    pub fn label(self) -> &'static str {
+        match self {
+            IoErrorCategory::Permission => "权限不足",
+            IoErrorCategory::Path => "路径问题",
+            IoErrorCategory::Device => "设备错误",
+            IoErrorCategory::DiskFull => "磁盘已满",
+            IoErrorCategory::Other => "其他",
+        }
+    }
+}
+
+// Raw OS error codes by category: Win32 error codes on Windows, errno values elsewhere
+#[cfg(windows)]
+const IO_ERROR_CODES: &[(i32, IoErrorCategory)] = &[
+    (5, IoErrorCategory::Permission),   // ERROR_ACCESS_DENIED
+    (3, IoErrorCategory::Path),         // ERROR_PATH_NOT_FOUND
+    (123, IoErrorCategory::Path),       // ERROR_INVALID_NAME
+    (206, IoErrorCategory::Path),       // ERROR_FILENAME_EXCED_RANGE
+    (121, IoErrorCategory::Device),     // ERROR_SEM_TIMEOUT
+    (1117, IoErrorCategory::Device),    // ERROR_IO_DEVICE
+    (39, IoErrorCategory::DiskFull),    // ERROR_HANDLE_DISK_FULL
+    (112, IoErrorCategory::DiskFull),   // ERROR_DISK_FULL
+];
+#[cfg(not(windows))]
+const IO_ERROR_CODES: &[(i32, IoErrorCategory)] = &[
+    (1, IoErrorCategory::Permission),   // EPERM
+    (13, IoErrorCategory::Permission),  // EACCES
+    (36, IoErrorCategory::Path),        // ENAMETOOLONG
+    (5, IoErrorCategory::Device),       // EIO
+    (110, IoErrorCategory::Device),     // ETIMEDOUT
+    (28, IoErrorCategory::DiskFull),    // ENOSPC
+    (122, IoErrorCategory::DiskFull),   // EDQUOT
+];
+
+/// Sorts an I/O error by whether retrying can help, from its raw OS error code or else its kind.
+pub fn classify_io_error(error: &io::Error) -> IoErrorCategory {
+    if let Some(code) = error.raw_os_error() {
+        return IO_ERROR_CODES.iter().find(|(known, _)| *known == code).map_or(IoErrorCategory::Other, |(_, category)| *category);
+    }
+    match error.kind() {
+        io::ErrorKind::PermissionDenied => IoErrorCategory::Permission,
+        io::ErrorKind::TimedOut => IoErrorCategory::Device,
+        io::ErrorKind::StorageFull => IoErrorCategory::DiskFull,
+        _ => IoErrorCategory::Other,
+    }
+}
+
+/// Extensions of files that get a diff preview when they conflict.
+pub const TEXT_EXTENSIONS: &[&str] = &[
+    "txt", "md", "json", "toml", "yaml", "yml", "ini", "cfg", "conf", "xml", "csv", "log",
+    "html", "css", "js", "ts", "py", "rs", "c", "h", "cpp", "java", "sh", "bat", "ps1",
+];
+/// Files larger than this are not previewed.
+const DIFF_PREVIEW_MAX_SIZE: u64 = 200 * 1024;
+/// Maximum number of changed lines included in a preview.
+const DIFF_PREVIEW_MAX_LINES: usize = 100;
+
+/// Computes a line diff between the USB and local versions of a small text file.
+/// Returns None for binary, oversized, or unreadable files.
+pub fn text_diff_preview(local: &Path, remote: &Path) -> Option<Vec<DiffLine>> {
+    let extension = local.extension()?.to_str()?.to_lowercase();
+    if !TEXT_EXTENSIONS.contains(&extension.as_str()) {
+        return None;
+    }
+    if fs::metadata(local).ok()?.len() > DIFF_PREVIEW_MAX_SIZE
+        || fs::metadata(remote).ok()?.len() > DIFF_PREVIEW_MAX_SIZE
+    {
+        return None;
+    }
+    let local_text = fs::read_to_string(local).ok()?;
+    let remote_text = fs::read_to_string(remote).ok()?;
+
+    let diff = TextDiff::from_lines(&remote_text, &local_text);
+    let lines = diff
+        .iter_all_changes()
+        .filter_map(|change| {
+            let text = change.value().trim_end_matches(['\r', '\n']).to_string();
+            match change.tag() {
+                ChangeTag::Insert => Some(DiffLine::Added(text)),
+                ChangeTag::Delete => Some(DiffLine::Removed(text)),
+                ChangeTag::Equal => None,
+            }
+        })
+        .take(DIFF_PREVIEW_MAX_LINES)
+        .collect();
+    Some(lines)
+}
+
+/// Whether two text files hold the same text once CRLF and lone CR line endings are read as LF.
+/// Files with a NUL byte are taken for binary and, like unreadable ones, count as different.
+pub fn differ_only_in_line_endings(a: &Path, b: &Path) -> bool {
+    fn normalized(path: &Path) -> Option<Vec<u8>> {
+        let bytes = fs::read(path).ok()?;
+        if bytes.contains(&0) {
+            return None;
+        }
+        let mut text = Vec::with_capacity(bytes.len());
+        let mut iter = bytes.iter().peekable();
+        while let Some(&byte) = iter.next() {
+            if byte == b'\r' {
+                iter.next_if_eq(&&b'\n');
+                text.push(b'\n');
+            } else {
+                text.push(byte);
+            }
+        }
+        Some(text)
+    }
+    matches!((normalized(a), normalized(b)), (Some(a), Some(b)) if a == b)
+}
+
+/// Whether a walk entry below a sync root is one of SyncU's own entries: the bookkeeping folder,
+/// or the metadata file, log or trash folder from before it existed.
+fn is_bookkeeping_entry(entry: &walkdir::DirEntry) -> bool {
+    // A staging folder sits beside a cloud folder, which may itself be inside the synced folder
+    (entry.file_name() == STAGING_DIR_NAME && entry.file_type().is_dir())
+        || entry.depth() == 1
+            && (entry.file_name() == BOOKKEEPING_DIR_NAME
+                || BOOKKEEPING_ENTRIES.iter().any(|(legacy, _)| entry.file_name() == *legacy))
+}
+
+/// Where the entry known by `legacy_name` lives in a sync folder. Folders that have a bookkeeping folder
+/// keep it there; all others still use the old name in the root, so their records load as before.
+fn bookkeeping_path(sync_root: &Path, legacy_name: &str) -> PathBuf {
+    let folder = sync_root.join(BOOKKEEPING_DIR_NAME);
+    match BOOKKEEPING_ENTRIES.iter().find(|(legacy, _)| *legacy == legacy_name) {
+        Some((_, name)) if folder.is_dir() => folder.join(name),
+        _ => sync_root.join(legacy_name),
+    }
+}
+
+/// Path of the metadata file of a sync folder.
+pub fn metadata_path(sync_root: &Path) -> PathBuf {
+    bookkeeping_path(sync_root, METADATA_FILE_NAME)
+}
+
+/// Path of the log file of a sync folder.
+pub fn log_path(sync_root: &Path) -> PathBuf {
+    bookkeeping_path(sync_root, LOG_FILE_NAME)
+}
+
+/// Path of the checkpointed plan of an unfinished run in a sync folder.
+pub fn plan_path(sync_root: &Path) -> PathBuf {
+    bookkeeping_path(sync_root, PLAN_FILE_NAME)
+}
+
+/// Path of the folder that receives cleaned-up files in a sync folder.
+pub fn trash_path(sync_root: &Path) -> PathBuf {
+    bookkeeping_path(sync_root, TRASH_DIR_NAME)
+}
+
+/// Whether `path` is a SyncU sync folder, in either layout.
+pub fn is_sync_root(path: &Path) -> bool {
+    metadata_path(path).is_file()
+}
+
+/// Moves the metadata, log and trash of a sync folder into its bookkeeping folder, or back out to the root.
+/// Entries already in place are left alone, and an emptied bookkeeping folder is removed.
+/// Returns how many entries were moved.
+pub fn migrate_bookkeeping(sync_root: &Path, into_subfolder: bool) -> Result<usize, SyncError> {
+    let folder = sync_root.join(BOOKKEEPING_DIR_NAME);
+    if into_subfolder {
+        fs::create_dir_all(&folder).at(&folder)?;
+    } else if !folder.is_dir() {
+        return Ok(0);
+    }
+    let mut moved = 0;
+    for (legacy, name) in BOOKKEEPING_ENTRIES {
+        let (from, to) = if into_subfolder {
+            (sync_root.join(legacy), folder.join(name))
+        } else {
+            (folder.join(name), sync_root.join(legacy))
+        };
+        // Never replace an entry at the destination; the next run moves what is left
+        if from.exists() && !to.exists() {
+            fs::rename(&from, &to).at(&from)?;
+            moved += 1;
+        }
+    }
+    if !into_subfolder {
+        // Only succeeds once the folder is empty
+        let _ = fs::remove_dir(&folder);
+    }
+    Ok(moved)
+}
+
+/// Writes a log message to the log file of the sync directory.
+pub fn write_log_entry(message: &str, level: LogLevel, verbosity: DeviceLogVerbosity, usb_sync_path: &Path) -> Result<(), SyncError> {
+    if !verbosity.records(level) {
+        return Ok(());
+    }
+    let log_path = log_path(usb_sync_path);
     let mut file = fs::OpenOptions::new()
         .create(true)
         .append(true)
-        .open(log_path)?;
-    writeln!(file, "{}", message)?;
+        .open(&log_path)
+        .at(&log_path)?;
+    writeln!(file, "{}", message).at(&log_path)?;
     Ok(())
 }
 
+/// Appends a final message to the sync folder's log and waits until the log has reached the disk.
+pub fn write_final_log_entry(message: &str, level: LogLevel, verbosity: DeviceLogVerbosity, usb_sync_path: &Path) -> Result<(), SyncError> {
+    if !verbosity.records(level) {
+        return Ok(());
+    }
+    let log_path = log_path(usb_sync_path);
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .at(&log_path)?;
+    writeln!(file, "{}", message).at(&log_path)?;
+    file.sync_all().at(&log_path)
+}
+
+/// Returns the closest ancestor of `path` that is itself a SyncU sync folder, if any.
+pub fn enclosing_sync_root(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .skip(1)
+        .find(|ancestor| is_sync_root(ancestor))
+        .map(Path::to_path_buf)
+}
+
+/// Finds a sync folder on the USB drive that was last synced from a sibling of `local_folder` which no longer exists,
+/// i.e. the folder the local one was most likely renamed from.
+pub fn find_renamed_sync_folder(usb_root: &Path, local_folder: &Path) -> Option<PathBuf> {
+    let local_parent = local_folder.parent()?;
+    fs::read_dir(usb_root)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| is_sync_root(path))
+        .find(|path| {
+            let Ok(data) = load_sync_data(&metadata_path(path)) else { return false };
+            data.source_folder.is_some_and(|source| {
+                source != local_folder && source.parent() == Some(local_parent) && !source.exists()
+            })
+        })
+}
+
+/// Shortens text to fit `max_width` by replacing its middle with "…".
+/// `measure` returns the rendered width of a string; text is only cut on char boundaries.
+pub fn elide_middle(text: &str, max_width: f32, measure: impl Fn(&str) -> f32) -> String {
+    if measure(text) <= max_width {
+        return text.to_string();
+    }
+    let chars: Vec<char> = text.chars().collect();
+    let build = |keep: usize| {
+        let head = keep.div_ceil(2);
+        let tail = keep / 2;
+        let mut elided: String = chars[..head].iter().collect();
+        elided.push('…');
+        elided.extend(&chars[chars.len() - tail..]);
+        elided
+    };
+    // Binary search for the largest number of kept chars that still fits
+    let (mut low, mut high) = (0, chars.len().saturating_sub(1));
+    while low < high {
+        let mid = (low + high).div_ceil(2);
+        if measure(&build(mid)) <= max_width {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    build(low)
+}
+
 /// Helper function to remove ancestor paths.
 /// If we have {"a", "a/b"}, it returns {"a/b"}.
 pub fn prune_ancestor_paths(paths: &HashSet<PathBuf>) -> HashSet<PathBuf> {
-    let mut pruned = paths.clone();
-    pruned.retain(|p1| !paths.iter().any(|p2| p1 != p2 && p2.starts_with(p1)));
-    pruned
+    let sorted = sorted_by_components(paths);
+    // A path with any descendant is directly followed by one
+    sorted
+        .iter()
+        .enumerate()
+        .filter(|(index, path)| sorted.get(index + 1).is_none_or(|next| !next.starts_with(path)))
+        .map(|(_, path)| (*path).clone())
+        .collect()
 }
 
 /// Helper function to remove descendant paths.
 /// If we have {"a", "a/b"}, it returns {"a"}.
 pub fn prune_descendant_paths(paths: &HashSet<PathBuf>) -> HashSet<PathBuf> {
-    let mut pruned = paths.clone();
-    pruned.retain(|p1| !paths.iter().any(|p2| p1 != p2 && p1.starts_with(p2)));
+    let mut pruned = HashSet::new();
+    let mut last_kept: Option<&PathBuf> = None;
+    for path in sorted_by_components(paths) {
+        if last_kept.is_none_or(|kept| !path.starts_with(kept)) {
+            pruned.insert(path.clone());
+            last_kept = Some(path);
+        }
+    }
     pruned
 }
 
+// Sorting compares paths component by component, so every path is directly followed by its descendants
+// ("a", "a/b", "a/b/c", "ab"), unlike a string sort that would put "a/b" after "a-b".
+fn sorted_by_components(paths: &HashSet<PathBuf>) -> Vec<&PathBuf> {
+    let mut sorted: Vec<&PathBuf> = paths.iter().collect();
+    sorted.sort_unstable();
+    sorted
+}
+
+/// Entries removed between progress reports and stop checks when deleting a folder tree.
+const REMOVE_BATCH: usize = 1000;
+
+/// Deletes the folder at `path` with everything in it, deepest entries first, reporting
+/// "删除目录 {label} (done/total)" at `progress` and checking for a stop every `REMOVE_BATCH` entries.
+/// A stop leaves the rest in place, still a complete tree that the next run proposes deleting again, and logs
+/// how much is left. Links are removed, never followed. Returns true if stopped.
+pub fn remove_dir_all_with_progress(path: &Path, label: &str, progress: f32, observer: &impl SyncObserver) -> Result<bool, SyncError> {
+    observer.on_progress(progress, format!("删除目录 {} (正在统计...)", label));
+    let Some(total) = count_entries(path, observer) else { return Ok(true) };
+    let mut removed = 0;
+    for entry in WalkDir::new(path).follow_links(false).contents_first(true) {
+        let entry = entry.map_err(|e| SyncError::Io { path: e.path().unwrap_or(path).to_path_buf(), source: e.into() })?;
+        let entry_path = entry.path();
+        if entry.file_type().is_dir() {
+            fs::remove_dir(entry_path).at(entry_path)?;
+        } else {
+            // A link to a folder is removed as a folder on Windows
+            fs::remove_file(entry_path)
+                .or_else(|e| if cfg!(windows) && entry_path.is_dir() { fs::remove_dir(entry_path) } else { Err(e) })
+                .at(entry_path)?;
+        }
+        removed += 1;
+        if removed % REMOVE_BATCH == 0 {
+            observer.on_progress(progress, format!("删除目录 {} ({}/{})", label, format_count(removed as u64), format_count(total as u64)));
+            if observer.should_stop() && path.exists() {
+                observer.on_log(format!(
+                    "[{}] 已停止删除目录 {}: 已删除 {} 项，剩余约 {} 项，下次同步时会再次询问",
+                    chrono::Local::now().format("%H:%M:%S"),
+                    label,
+                    format_count(removed as u64),
+                    format_count(total.saturating_sub(removed) as u64)
+                ));
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
 /// Recursively cleans up empty parent directories.
-pub fn cleanup_empty_dirs(start_path: &Path, base_path: &Path) -> Result<(), std::io::Error> {
+pub fn cleanup_empty_dirs(start_path: &Path, base_path: &Path) -> Result<(), SyncError> {
     let mut current = start_path.parent();
     while let Some(dir) = current {
         if !dir.starts_with(base_path) || dir == base_path {
             break;
         }
         // Check if the directory is empty
-        if dir.read_dir()?.next().is_none() {
-            fs::remove_dir(dir)?;
+        if dir.read_dir().at(dir)?.next().is_none() {
+            fs::remove_dir(dir).at(dir)?;
         } else {
             // Stop if we find a non-empty directory
             break;
@@ -299,3 +1932,268 @@ pub fn cleanup_empty_dirs(start_path: &Path, base_path: &Path) -> Result<(), std
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_without_a_slash_match_the_file_name() {
+        assert!(glob_match("*.jpg", Path::new("Photos/2024/x.jpg")));
+        assert!(glob_match("*.JPG", Path::new("x.jpg")));
+        assert!(glob_match("报告?.docx", Path::new("文档/报告1.docx")));
+        assert!(!glob_match("*.jpg", Path::new("x.jpeg")));
+        assert!(!glob_match("", Path::new("x.jpg")));
+    }
+
+    #[test]
+    fn stars_stay_within_a_folder_unless_doubled() {
+        assert!(glob_match("Photos/*.jpg", Path::new("Photos/x.jpg")));
+        assert!(!glob_match("Photos/*.jpg", Path::new("Photos/2024/x.jpg")));
+        assert!(glob_match("Photos/**/*.jpg", Path::new("Photos/2024/06/x.jpg")));
+        // `**/` also stands for no folder at all
+        assert!(glob_match("Photos/**/*.jpg", Path::new("Photos/x.jpg")));
+        assert!(glob_match("Photos\\*.jpg", Path::new("Photos/x.jpg")));
+    }
+
+    fn category_of(code: i32) -> IoErrorCategory {
+        classify_io_error(&io::Error::from_raw_os_error(code))
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn win32_error_codes_are_classified() {
+        assert_eq!(category_of(5), IoErrorCategory::Permission);
+        assert_eq!(category_of(3), IoErrorCategory::Path);
+        assert_eq!(category_of(206), IoErrorCategory::Path);
+        assert_eq!(category_of(123), IoErrorCategory::Path);
+        assert_eq!(category_of(1117), IoErrorCategory::Device);
+        assert_eq!(category_of(121), IoErrorCategory::Device);
+        assert_eq!(category_of(112), IoErrorCategory::DiskFull);
+        // ERROR_SHARING_VIOLATION is left to the in-use handling
+        assert_eq!(category_of(32), IoErrorCategory::Other);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn errno_values_are_classified() {
+        assert_eq!(category_of(13), IoErrorCategory::Permission);
+        assert_eq!(category_of(1), IoErrorCategory::Permission);
+        assert_eq!(category_of(36), IoErrorCategory::Path);
+        assert_eq!(category_of(5), IoErrorCategory::Device);
+        assert_eq!(category_of(28), IoErrorCategory::DiskFull);
+        // ENOENT usually means the source vanished, which is handled before any retry
+        assert_eq!(category_of(2), IoErrorCategory::Other);
+    }
+
+    #[test]
+    fn errors_without_a_code_are_classified_by_kind() {
+        assert_eq!(classify_io_error(&io::Error::from(io::ErrorKind::PermissionDenied)), IoErrorCategory::Permission);
+        assert_eq!(classify_io_error(&io::Error::new(io::ErrorKind::TimedOut, "no answer")), IoErrorCategory::Device);
+        assert_eq!(classify_io_error(&io::Error::from(io::ErrorKind::StorageFull)), IoErrorCategory::DiskFull);
+        assert_eq!(classify_io_error(&io::Error::other("something else")), IoErrorCategory::Other);
+    }
+
+    fn naive_prune_ancestors(paths: &HashSet<PathBuf>) -> HashSet<PathBuf> {
+        paths.iter().filter(|p1| !paths.iter().any(|p2| p1 != &p2 && p2.starts_with(p1))).cloned().collect()
+    }
+
+    fn naive_prune_descendants(paths: &HashSet<PathBuf>) -> HashSet<PathBuf> {
+        paths.iter().filter(|p1| !paths.iter().any(|p2| p1 != &p2 && p1.starts_with(p2))).cloned().collect()
+    }
+
+    fn set(paths: &[&str]) -> HashSet<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    // Small deterministic generator, so failures can be reproduced from the seed.
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self, bound: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % bound as u64) as usize
+        }
+    }
+
+    // Components chosen so that many paths are string prefixes of each other without being path prefixes.
+    const COMPONENTS: &[&str] = &["a", "ab", "a-b", "a.b", "b", "名", "名字"];
+
+    fn random_path_set(rng: &mut XorShift) -> HashSet<PathBuf> {
+        (0..rng.next(40))
+            .map(|_| (0..1 + rng.next(4)).map(|_| COMPONENTS[rng.next(COMPONENTS.len())]).collect::<PathBuf>())
+            .collect()
+    }
+
+    #[test]
+    fn pruning_matches_naive_implementation_on_random_sets() {
+        for seed in 1..=500u64 {
+            let mut rng = XorShift(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            let paths = random_path_set(&mut rng);
+            assert_eq!(prune_ancestor_paths(&paths), naive_prune_ancestors(&paths), "ancestors, seed {}: {:?}", seed, paths);
+            assert_eq!(prune_descendant_paths(&paths), naive_prune_descendants(&paths), "descendants, seed {}: {:?}", seed, paths);
+        }
+    }
+
+    #[test]
+    fn string_prefixes_are_not_path_prefixes() {
+        let paths = set(&["ab", "abc", "ab/c", "a-b", "a/b"]);
+        assert_eq!(prune_ancestor_paths(&paths), set(&["abc", "ab/c", "a-b", "a/b"]));
+        assert_eq!(prune_descendant_paths(&paths), set(&["ab", "abc", "a-b", "a/b"]));
+    }
+
+    #[test]
+    fn pruning_handles_empty_and_single_sets() {
+        assert!(prune_ancestor_paths(&HashSet::new()).is_empty());
+        assert!(prune_descendant_paths(&HashSet::new()).is_empty());
+        assert_eq!(prune_ancestor_paths(&set(&["a"])), set(&["a"]));
+        assert_eq!(prune_descendant_paths(&set(&["a"])), set(&["a"]));
+    }
+
+    #[test]
+    fn pruning_keeps_only_the_ends_of_deep_chains() {
+        let paths = set(&["node_modules", "node_modules/x", "node_modules/x/lib", "node_modules/y", "src"]);
+        assert_eq!(prune_ancestor_paths(&paths), set(&["node_modules/x/lib", "node_modules/y", "src"]));
+        assert_eq!(prune_descendant_paths(&paths), set(&["node_modules", "src"]));
+    }
+
+    fn synced_at(time: SystemTime) -> SyncData {
+        SyncData { last_sync_time: Some(time), ..Default::default() }
+    }
+
+    fn name_of(path: &Path) -> String {
+        path.file_name().unwrap().to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn a_collision_rename_goes_before_the_extension() {
+        assert_eq!(collision_rename(Path::new("照片/报告.txt"), 1), Path::new("照片/报告 (重名 1).txt"));
+        assert_eq!(collision_rename(Path::new("notes."), 2), Path::new("notes (重名 2)"));
+    }
+
+    #[test]
+    fn long_cjk_names_are_cut_at_a_char_boundary() {
+        // Three bytes but one UTF-16 unit per char, so too long by either measure; the "a" shifts the boundaries
+        let stem = format!("a{}", "报".repeat(300));
+        let renamed = name_of(&collision_rename(&Path::new("照片").join(format!("{}.txt", stem)), 1));
+        assert!(name_component_len(&renamed) <= MAX_NAME_COMPONENT_LEN, "{}", name_component_len(&renamed));
+        let kept = renamed.strip_suffix(" (重名 1).txt").unwrap();
+        assert!(kept.len() > 1 && stem.starts_with(kept), "{}", renamed);
+        // As long as the limit allows, give or take the one char that didn't fit
+        assert!(name_component_len(&renamed) + name_component_len("报") > MAX_NAME_COMPONENT_LEN);
+    }
+
+    #[test]
+    fn a_cut_name_already_taken_gets_a_counter() {
+        let dir = std::env::temp_dir().join(format!("syncu-suffixed-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.txt", "报".repeat(300)));
+        let first = suffixed_path(&path, " (冲突)", true);
+        fs::write(&first, b"").unwrap();
+        let second = suffixed_path(&path, " (冲突)", true);
+        fs::write(&second, b"").unwrap();
+        let third = suffixed_path(&path, " (冲突)", true);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(!name_of(&first).contains('~'));
+        assert!(name_of(&second).ends_with("~1 (冲突).txt"), "{}", name_of(&second));
+        assert!(name_of(&third).ends_with("~2 (冲突).txt"), "{}", name_of(&third));
+        for path in [&first, &second, &third] {
+            assert!(name_component_len(&name_of(path)) <= MAX_NAME_COMPONENT_LEN);
+        }
+    }
+
+    // A stand-in for the font: CJK chars take two columns, everything else one
+    fn columns(text: &str) -> f32 {
+        text.chars().map(|c| if c as u32 >= 0x2E80 { 2.0 } else { 1.0 }).sum()
+    }
+
+    #[test]
+    fn text_that_fits_is_left_alone() {
+        assert_eq!(elide_middle("report.txt", 10.0, columns), "report.txt");
+        assert_eq!(elide_middle("", 0.0, columns), "");
+    }
+
+    #[test]
+    fn the_middle_is_replaced_to_fit() {
+        assert_eq!(elide_middle("abcdefghij", 5.0, columns), "ab…ij");
+        assert_eq!(elide_middle("abcdefghij", 6.0, columns), "abc…ij");
+    }
+
+    #[test]
+    fn no_room_leaves_only_the_ellipsis() {
+        assert_eq!(elide_middle("abcdefghij", 0.0, columns), "…");
+        // Narrower than the ellipsis itself
+        assert_eq!(elide_middle("abcdefghij", 0.5, columns), "…");
+        assert_eq!(elide_middle("照片", 1.0, columns), "…");
+    }
+
+    #[test]
+    fn multi_byte_text_is_cut_between_chars() {
+        // 25 columns, so a few chars must go from the middle
+        let text = "照片/2024年/旅行/北京.jpg";
+        let elided = elide_middle(text, 16.0, columns);
+        assert!(columns(&elided) <= 16.0, "{}", elided);
+        assert!(columns(&elided) >= 14.0, "{}", elided);
+        let (head, tail) = elided.split_once('…').unwrap();
+        assert!(text.starts_with(head) && text.ends_with(tail), "{}", elided);
+        assert_eq!(elide_middle("😀😀😀😀", 3.0, |text| text.chars().count() as f32), "😀…😀");
+    }
+
+    #[test]
+    fn redundant_components_of_a_local_folder_are_resolved() {
+        assert_eq!(normalize_local_folder(Path::new("/home/me/docs/../work/./notes/")), Ok(PathBuf::from("/home/me/work/notes")));
+        assert_eq!(normalize_local_folder(Path::new("/../docs")), Ok(PathBuf::from("/docs")));
+        assert!(normalize_local_folder(Path::new("/")).is_err());
+        assert!(normalize_local_folder(Path::new("/docs/..")).is_err());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn drive_and_share_roots_are_rejected_but_their_folders_kept() {
+        assert!(normalize_local_folder(Path::new(r"D:\")).is_err());
+        assert_eq!(normalize_local_folder(Path::new(r"D:\Docs\")), Ok(PathBuf::from(r"D:\Docs")));
+        assert_eq!(normalize_local_folder(Path::new(r"D:\Docs\..\Work\.\Notes")), Ok(PathBuf::from(r"D:\Work\Notes")));
+        assert!(normalize_local_folder(Path::new(r"\\server\share")).is_err());
+        assert!(normalize_local_folder(Path::new(r"\\server\share\")).is_err());
+        let share_folder = normalize_local_folder(Path::new(r"\\server\share\Docs")).unwrap();
+        assert_eq!(share_folder, PathBuf::from(r"\\server\share\Docs"));
+        assert_eq!(share_folder.file_name(), Some("Docs".as_ref()));
+    }
+
+    #[test]
+    fn a_clock_slightly_behind_the_last_sync_is_tolerated() {
+        let last_sync = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(detect_clock_skew_from_record(&synced_at(last_sync), last_sync - Duration::from_secs(2)), None);
+        assert_eq!(detect_clock_skew_from_record(&synced_at(last_sync), last_sync - LAST_SYNC_TOLERANCE), None);
+        assert!(detect_clock_skew_from_record(&synced_at(last_sync), last_sync - Duration::from_secs(60 * 60)).is_some());
+    }
+
+    fn drive(mount_point: &str, volume_id: Option<&str>) -> UsbDrive {
+        UsbDrive { mount_point: PathBuf::from(mount_point), volume_id: volume_id.map(str::to_owned), unavailable: None }
+    }
+
+    fn mount_points(drives: &[UsbDrive]) -> Vec<&Path> {
+        drives.iter().map(|drive| drive.mount_point.as_path()).collect()
+    }
+
+    #[test]
+    fn a_volume_under_several_letters_is_listed_once() {
+        // S: is a `subst` drive into the stick mounted at E:; drives without a known volume are never merged
+        let drives = vec![
+            drive("S:\\", Some("1A2B-3C4D")),
+            drive("E:\\", Some("1A2B-3C4D")),
+            drive("F:\\", Some("5E6F-7A8B")),
+            drive("G:\\", None),
+            drive("H:\\", None),
+        ];
+        let listed = dedupe_drives(drives, |mount_point| mount_point != Path::new("S:\\"));
+        assert_eq!(mount_points(&listed), [Path::new("E:\\"), Path::new("F:\\"), Path::new("G:\\"), Path::new("H:\\")]);
+
+        // Where no mount point stands out, the first in letter order stays
+        let listed = dedupe_drives(vec![drive("E:\\", Some("1A2B-3C4D")), drive("D:\\", Some("1A2B-3C4D"))], |_| true);
+        assert_eq!(mount_points(&listed), [Path::new("D:\\")]);
+    }
+}
@@ -0,0 +1,126 @@
+//! Rules files that answer deletion prompts and conflicts by path.
+
+mod common;
+
+use common::{write_file, write_tree, Fixture, ScriptedObserver, TempDir};
+use std::fs;
+use std::path::{Path, PathBuf};
+use syncu::models::Resolution;
+use syncu::settings::{AnswerRule, AnswerRules, ConflictAnswer, DeletionAnswer, Profile};
+
+fn with_rules(fixture: &Fixture, dir: &TempDir, json: &str) -> Profile {
+    let path = dir.path().join("rules.json");
+    fs::write(&path, json).unwrap();
+    Profile { answer_rules_file: Some(path), ..fixture.profile() }
+}
+
+#[test]
+fn rules_answer_deletions_in_order() {
+    let fixture = Fixture::new();
+    let rules = TempDir::new();
+    write_tree(&fixture.local, &[("Old Projects/a.txt", b"a\n"), ("notes.txt", b"notes\n"), ("keep.txt", b"keep\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    for path in ["Old Projects/a.txt", "notes.txt", "keep.txt"] {
+        fs::remove_file(fixture.local.join(path)).unwrap();
+    }
+    let profile = with_rules(
+        &fixture,
+        &rules,
+        r#"[
+            { "match": "Old Projects/**", "on_delete": "confirm" },
+            { "match": "keep.txt", "on_delete": "ask" },
+            { "match": "**", "on_delete": "skip", "on_conflict": "local" }
+        ]"#,
+    );
+
+    let observer = ScriptedObserver::new();
+    fixture.run_with_profile(&observer, profile);
+
+    assert!(!fixture.remote().join("Old Projects/a.txt").exists());
+    assert!(fixture.remote().join("notes.txt").exists());
+    // "ask" falls through to the prompt, which the scripted observer answers by deleting
+    assert_eq!(observer.deletions_asked(), 1);
+    assert!(!fixture.remote().join("keep.txt").exists());
+    let logs = observer.logs();
+    assert!(logs.iter().any(|line| line.contains("按应答规则 #1 (Old Projects/**) 删除: Old Projects/a.txt")), "{:#?}", logs);
+    assert!(logs.iter().any(|line| line.contains("按应答规则 #3 (**) 本次保留: notes.txt")), "{:#?}", logs);
+}
+
+#[test]
+fn rules_answer_conflicts() {
+    let fixture = Fixture::new();
+    let rules = TempDir::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    write_file(&fixture.local, "a.txt", b"local\n");
+    write_file(&fixture.remote(), "a.txt", b"remote!\n");
+    let profile = with_rules(&fixture, &rules, r#"[{ "match": "*.txt", "on_conflict": "local" }]"#);
+
+    let observer = ScriptedObserver::new().with_conflict_resolution(Resolution::KeepRemote);
+    fixture.run_with_profile(&observer, profile);
+
+    assert_eq!(observer.conflicts_asked(), 0);
+    assert_eq!(fs::read(fixture.remote().join("a.txt")).unwrap(), b"local\n");
+    assert!(observer.logs().iter().any(|line| line.contains("按应答规则 #1 (*.txt) 处理冲突 (采用本地版本): a.txt")), "{:#?}", observer.logs());
+}
+
+#[test]
+fn an_unusable_rules_file_stops_the_run_before_anything_changes() {
+    let fixture = Fixture::new();
+    let rules = TempDir::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n")]);
+    let profile = with_rules(&fixture, &rules, r#"[{ "match": "src/**.rs", "on_delete": "confirm" }]"#);
+
+    let observer = ScriptedObserver::new();
+    fixture.run_with_profile(&observer, profile);
+
+    let logs = observer.logs();
+    assert!(logs.iter().any(|line| line.starts_with("错误") && line.contains("应答规则文件无效") && line.contains("规则 #1")), "{:#?}", logs);
+    assert!(!fixture.remote().exists());
+}
+
+#[test]
+fn rules_are_checked() {
+    let rule = |pattern: &str| AnswerRule { pattern: pattern.to_owned(), on_delete: Some(DeletionAnswer::Skip), on_conflict: None };
+    assert_eq!(rule("Old Projects/**").problem(), None);
+    assert_eq!(rule("**/*.tmp").problem(), None);
+    assert!(rule("").problem().is_some());
+    assert!(rule("a**b").problem().is_some());
+    assert!(rule("../outside/**").problem().is_some());
+    assert!(rule("/etc/**").problem().is_some());
+    assert!(rule(r"C:\docs\**").problem().is_some());
+    assert!(AnswerRule { pattern: "**".to_owned(), ..Default::default() }.problem().is_some());
+
+    let rules = AnswerRules(vec![rule("**"), rule("x/***")]);
+    assert!(rules.check().unwrap_err().starts_with("规则 #2"));
+}
+
+#[test]
+fn files_round_trip_and_bad_values_are_reported() {
+    let dir = TempDir::new();
+    let path = dir.path().join("rules.json");
+    let rules = AnswerRules(vec![
+        AnswerRule { pattern: "Old Projects/**".to_owned(), on_delete: Some(DeletionAnswer::Confirm), on_conflict: None },
+        AnswerRule { pattern: "**".to_owned(), on_delete: Some(DeletionAnswer::Keep), on_conflict: Some(ConflictAnswer::Remote) },
+    ]);
+    rules.save(&path).unwrap();
+    assert!(fs::read_to_string(&path).unwrap().contains(r#""match": "Old Projects/**""#));
+    assert_eq!(AnswerRules::load(&path).unwrap(), rules);
+
+    fs::write(&path, r#"[{ "match": "**", "on_delete": "maybe" }]"#).unwrap();
+    assert!(AnswerRules::load(&path).unwrap_err().starts_with("格式错误"));
+    assert!(AnswerRules::load(&dir.path().join("missing.json")).is_err());
+}
+
+#[test]
+fn the_first_rule_that_answers_decides() {
+    let rules = AnswerRules(vec![
+        AnswerRule { pattern: "docs/**".to_owned(), on_delete: None, on_conflict: Some(ConflictAnswer::Skip) },
+        AnswerRule { pattern: "**".to_owned(), on_delete: Some(DeletionAnswer::Confirm), on_conflict: Some(ConflictAnswer::Ask) },
+    ]);
+    let path = PathBuf::from("docs/a.txt");
+
+    assert_eq!(rules.deletion_answer(&path).map(|(number, _, answer)| (number, answer)), Some((2, DeletionAnswer::Confirm)));
+    assert_eq!(rules.conflict_answer(&path).map(|(number, _, resolution)| (number, resolution)), Some((1, Resolution::Skip)));
+    assert_eq!(rules.conflict_answer(Path::new("b.txt")), None);
+}
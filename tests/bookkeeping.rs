@@ -0,0 +1,77 @@
+//! Keeping SyncU's metadata, log and trash in a `.syncu` folder instead of the root of the USB sync folder.
+
+mod common;
+
+use common::{assert_in_sync, write_tree, Fixture, ScriptedObserver};
+use std::fs;
+use syncu::models::RunOutcome;
+use syncu::settings::Profile;
+use syncu::utils::{folder_totals, log_path, metadata_path, migrate_bookkeeping, FolderTotals, LOG_FILE_NAME, METADATA_FILE_NAME};
+
+fn subfolder_profile(fixture: &Fixture) -> Profile {
+    Profile { bookkeeping_subfolder: true, ..fixture.profile() }
+}
+
+#[test]
+fn existing_folder_is_migrated_and_keeps_its_records() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n"), ("notes/b.md", b"# bravo\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    assert!(fixture.remote().join(METADATA_FILE_NAME).is_file());
+    let before = fixture.metadata();
+
+    let observer = ScriptedObserver::new();
+    assert_eq!(fixture.run_with_profile(&observer, subfolder_profile(&fixture)), RunOutcome::Completed);
+
+    let remote = fixture.remote();
+    assert!(!remote.join(METADATA_FILE_NAME).exists());
+    assert!(!remote.join(LOG_FILE_NAME).exists());
+    assert_eq!(metadata_path(&remote), remote.join(".syncu/metadata.json"));
+    assert_eq!(log_path(&remote), remote.join(".syncu/log.txt"));
+    assert!(fs::read_to_string(log_path(&remote)).unwrap().contains("同步完成"));
+    assert!(observer.logs().iter().any(|line| line.contains("移入 .syncu")));
+    // The old records were found, so nothing looked new or deleted
+    assert_eq!(observer.deletions_asked(), 0);
+    assert_eq!(fixture.metadata().files.len(), before.files.len());
+    assert!(!fixture.metadata().directories.iter().any(|dir| dir.starts_with(".syncu")));
+    assert_in_sync(&fixture);
+}
+
+#[test]
+fn bookkeeping_folder_is_never_synced_or_counted() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"12345")]);
+    assert_eq!(fixture.run_with_profile(&ScriptedObserver::new(), subfolder_profile(&fixture)), RunOutcome::Completed);
+    write_tree(&fixture.remote(), &[(".syncu/trash/20240101-000000/old.txt", b"trashed")]);
+
+    assert!(!fixture.local.join(".syncu").exists());
+    assert_eq!(folder_totals(&fixture.remote(), &ScriptedObserver::new()), Some(FolderTotals { files: 1, bytes: 5 }));
+    let rerun = ScriptedObserver::new();
+    assert_eq!(fixture.run_with_profile(&rerun, subfolder_profile(&fixture)), RunOutcome::Completed);
+    assert!(!fixture.local.join(".syncu").exists());
+    assert!(!fixture.metadata().files.keys().any(|path| path.starts_with(".syncu")));
+}
+
+#[test]
+fn turning_the_option_off_moves_everything_back() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n")]);
+    assert_eq!(fixture.run_with_profile(&ScriptedObserver::new(), subfolder_profile(&fixture)), RunOutcome::Completed);
+
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    let remote = fixture.remote();
+    assert!(!remote.join(".syncu").exists());
+    assert!(remote.join(METADATA_FILE_NAME).is_file());
+    assert!(remote.join(LOG_FILE_NAME).is_file());
+    assert_eq!(fixture.metadata().files.len(), 1);
+}
+
+#[test]
+fn migration_never_replaces_existing_entries() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[(METADATA_FILE_NAME, b"old"), (".syncu/metadata.json", b"new")]);
+
+    assert_eq!(migrate_bookkeeping(&fixture.local, true).unwrap(), 0);
+    assert_eq!(fs::read(fixture.local.join(".syncu/metadata.json")).unwrap(), b"new");
+    assert_eq!(fs::read(fixture.local.join(METADATA_FILE_NAME)).unwrap(), b"old");
+}
@@ -0,0 +1,36 @@
+//! Files modified while they are being copied are left for the next run, without touching the destination.
+
+mod common;
+
+use common::{content, write_file, Fixture, ScriptedObserver};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use syncu::models::{ActionStatus, SyncAction};
+
+// Above the size copied in one piece, so the copy reports progress while it runs
+const BIG: usize = 10 * 1024 * 1024 + 1;
+
+#[test]
+fn a_copy_whose_source_changed_is_discarded_and_copied_next_time() {
+    let fixture = Fixture::new();
+    write_file(&fixture.local, "big.bin", &content(1, BIG));
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+
+    write_file(&fixture.local, "big.bin", &content(2, BIG));
+    let source = fixture.local.join("big.bin");
+    let observer = ScriptedObserver::new().doing_on_progress("正在处理: big.bin (", move || {
+        OpenOptions::new().append(true).open(source).unwrap().write_all(b"still being written").unwrap();
+    });
+    assert!(!fixture.sync(&observer));
+    assert!(observer.logs().iter().any(|line| line.contains("复制期间文件被修改")), "{:#?}", observer.logs());
+    assert_eq!(observer.finished_actions(), vec![(0, ActionStatus::Skipped)]);
+    assert_eq!(fs::read(fixture.remote().join("big.bin")).unwrap(), content(1, BIG));
+
+    // The USB copy is still what the record says, so only the local side counts as changed
+    let next = ScriptedObserver::new();
+    assert!(!fixture.sync(&next));
+    assert_eq!(next.plan(), Some(vec![SyncAction::LocalToRemote(PathBuf::from("big.bin"))]));
+    assert_eq!(next.conflicts_asked(), 0);
+    assert_eq!(fs::read(fixture.remote().join("big.bin")).unwrap(), fs::read(fixture.local.join("big.bin")).unwrap());
+}
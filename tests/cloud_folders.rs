@@ -0,0 +1,56 @@
+//! Keeping temporary copies out of folders that a cloud client syncs.
+
+mod common;
+
+use common::{content, write_file, ScriptedObserver, TempDir};
+use std::fs;
+use std::path::Path;
+use syncu::utils::{cloud_sync_root, copy_large_file_with_progress, temp_path_for, RateLimiter, STAGING_DIR_NAME, TEMP_FILE_SUFFIX};
+
+#[test]
+fn well_known_cloud_folders_are_recognized() {
+    let home = Path::new("/home/user");
+    assert_eq!(cloud_sync_root(&home.join("Dropbox/docs/a.txt")), Some(home.join("Dropbox")));
+    assert_eq!(cloud_sync_root(&home.join("OneDrive - Contoso/docs")), Some(home.join("OneDrive - Contoso")));
+    assert_eq!(cloud_sync_root(&home.join("Documents/docs/a.txt")), None);
+}
+
+#[test]
+fn copies_into_a_cloud_folder_are_staged_beside_it() {
+    let home = Path::new("/home/user");
+    let temp = temp_path_for(&home.join("Dropbox/docs/a.txt"));
+    assert_eq!(temp.parent(), Some(home.join(STAGING_DIR_NAME).as_path()));
+    assert!(temp.to_string_lossy().ends_with(TEMP_FILE_SUFFIX));
+    // Files of the same name in different folders get their own temporary file
+    assert_ne!(temp, temp_path_for(&home.join("Dropbox/other/a.txt")));
+
+    let elsewhere = home.join("Documents/a.txt");
+    assert_eq!(temp_path_for(&elsewhere).parent(), elsewhere.parent());
+}
+
+#[test]
+fn a_staged_copy_lands_in_place_and_leaves_nothing_behind() {
+    let root = TempDir::new();
+    let source = root.path().join("usb");
+    write_file(&source, "big.bin", &content(7, 64 * 1024 + 100));
+    let destination = root.path().join("Dropbox").join("docs").join("big.bin");
+    fs::create_dir_all(destination.parent().unwrap()).unwrap();
+
+    let stopped = copy_large_file_with_progress(
+        &source.join("big.bin"),
+        &destination,
+        "big.bin",
+        &ScriptedObserver::new(),
+        &RateLimiter::default(),
+        0,
+        0,
+        || true,
+    )
+    .unwrap();
+
+    assert!(!stopped);
+    assert_eq!(fs::read(&destination).unwrap(), content(7, 64 * 1024 + 100));
+    assert!(!root.path().join(STAGING_DIR_NAME).exists());
+    let leftovers: Vec<_> = fs::read_dir(destination.parent().unwrap()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    assert_eq!(leftovers.len(), 1, "{:?}", leftovers);
+}
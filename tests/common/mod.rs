@@ -0,0 +1,554 @@
+//! Helpers for driving `run_sync` against temporary folders.
+// Each test binary uses only some of these
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use syncu::error::SyncError;
+use syncu::models::{ActionStatus, ClockSkewChoice, ConflictSuggestion, ConsistencyReport, CrowdedDirectory, CrowdedDirectoryChoice, DiffLine, LongPathChoice, NameCollisionChoice, RemoteMissingChoice, Resolution, RunOutcome, SpaceEstimate, SyncAction, SyncData, SyncStats};
+use syncu::observer::{DeletionDecision, SyncObserver};
+use syncu::report::RunReport;
+use syncu::settings::Profile;
+use syncu::sync::run_sync;
+use syncu::utils::{load_sync_data, metadata_path, BOOKKEEPING_DIR_NAME};
+use walkdir::WalkDir;
+
+/// Name of the local folder, and so of the sync folder on the fake USB drive.
+pub const FOLDER_NAME: &str = "docs";
+
+/// A directory under the system temp dir that is removed when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new() -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().subsec_nanos();
+        let path = std::env::temp_dir().join(format!(
+            "syncu-test-{}-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+            nanos
+        ));
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A local folder and a fake USB drive root, side by side in one temp dir.
+pub struct Fixture {
+    _root: TempDir,
+    pub local: PathBuf,
+    pub usb: PathBuf,
+}
+
+impl Fixture {
+    pub fn new() -> Self {
+        let root = TempDir::new();
+        let local = root.path().join("local").join(FOLDER_NAME);
+        let usb = root.path().join("usb");
+        fs::create_dir_all(&local).unwrap();
+        fs::create_dir_all(&usb).unwrap();
+        Self { _root: root, local, usb }
+    }
+
+    /// A fixture whose local folder holds `files` and has been synced once.
+    pub fn synced(files: &[(&str, &[u8])]) -> Self {
+        let fixture = Self::new();
+        write_tree(&fixture.local, files);
+        assert!(!fixture.sync(&ScriptedObserver::new()));
+        assert_in_sync(&fixture);
+        fixture
+    }
+
+    /// The sync folder on the fake USB drive.
+    pub fn remote(&self) -> PathBuf {
+        self.usb.join(FOLDER_NAME)
+    }
+
+    /// The default profile for the local folder.
+    pub fn profile(&self) -> Profile {
+        Profile { local_folder: self.local.clone(), ..Default::default() }
+    }
+
+    /// Runs one sync and returns how it ended, without checking the log.
+    pub fn run(&self, observer: &ScriptedObserver) -> RunOutcome {
+        self.run_with_profile(observer, self.profile())
+    }
+
+    pub fn run_with_profile(&self, observer: &ScriptedObserver, profile: Profile) -> RunOutcome {
+        self.run_as(observer, profile, false)
+    }
+
+    /// Runs one sync as a scheduled or command line run would.
+    pub fn run_unattended(&self, observer: &ScriptedObserver, profile: Profile) -> RunOutcome {
+        self.run_as(observer, profile, true)
+    }
+
+    fn run_as(&self, observer: &ScriptedObserver, profile: Profile, unattended: bool) -> RunOutcome {
+        run_sync(Some(self.local.clone()), Some(self.usb.clone()), profile, unattended, observer);
+        observer.finished.lock().unwrap().take().expect("run_sync did not report the end of the run")
+    }
+
+    /// Runs one sync that must not log errors and returns whether it was stopped.
+    pub fn sync(&self, observer: &ScriptedObserver) -> bool {
+        let stopped = self.run(observer) == RunOutcome::Stopped;
+        assert!(
+            !observer.logs().iter().any(|line| line.starts_with("错误")),
+            "sync logged errors: {:#?}",
+            observer.logs()
+        );
+        stopped
+    }
+
+    pub fn metadata(&self) -> SyncData {
+        load_sync_data(&metadata_path(&self.remote())).unwrap()
+    }
+}
+
+/// Writes files given as (relative path, contents), creating parent folders.
+pub fn write_tree(root: &Path, files: &[(&str, &[u8])]) {
+    for (path, contents) in files {
+        write_file(root, path, contents);
+    }
+}
+
+pub fn write_file(root: &Path, path: &str, contents: &[u8]) {
+    let path = root.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+/// Every file (with contents) and directory below `root`, leaving out SyncU's own files.
+#[derive(Debug, PartialEq)]
+pub struct Tree {
+    pub files: BTreeMap<PathBuf, Vec<u8>>,
+    pub directories: Vec<PathBuf>,
+}
+
+pub fn read_tree(root: &Path) -> Tree {
+    let mut files = BTreeMap::new();
+    let mut directories = Vec::new();
+    let walker = WalkDir::new(root).min_depth(1).sort_by_file_name().into_iter();
+    for entry in walker.filter_entry(|e| !(e.depth() == 1 && e.file_name() == BOOKKEEPING_DIR_NAME)) {
+        let entry = entry.unwrap();
+        if entry.file_name().to_string_lossy().starts_with(".syncu_") {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(root).unwrap().to_path_buf();
+        if entry.file_type().is_dir() {
+            directories.push(relative);
+        } else {
+            files.insert(relative, fs::read(entry.path()).unwrap());
+        }
+    }
+    Tree { files, directories }
+}
+
+/// Asserts that both sides hold the same files and folders.
+pub fn assert_in_sync(fixture: &Fixture) {
+    assert_eq!(read_tree(&fixture.local), read_tree(&fixture.remote()), "local folder and USB folder differ");
+}
+
+/// Content of `len` bytes that differs for every seed, so size and hash both change.
+pub fn content(seed: u8, len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed)).collect()
+}
+
+// Prefix of the progress message that triggers the action, and the action to run once
+type ProgressHook = (String, Box<dyn FnOnce() + Send>);
+
+/// Answers every question with a fixed choice, and can stop the run after a number of actions.
+pub struct ScriptedObserver {
+    deletion: DeletionDecision,
+    conflict: Resolution,
+    name_collision: NameCollisionChoice,
+    crowded_directory: CrowdedDirectoryChoice,
+    crowded_directories: Mutex<Vec<CrowdedDirectory>>,
+    unreadable_sizes_answer: bool,
+    unreadable_sizes: Mutex<Option<Vec<PathBuf>>>,
+    resume_plan: bool,
+    resumes_asked: AtomicUsize,
+    remote_missing: RemoteMissingChoice,
+    newer_metadata_answer: bool,
+    // (missing, known) of every safety check prompt
+    remote_missing_asked: Mutex<Vec<(usize, usize)>>,
+    stop_after_actions: Option<usize>,
+    actions_started: AtomicUsize,
+    // Prefix of the progress message that requests a stop, and whether it has been seen
+    stop_on_progress: Option<String>,
+    stop_requested: AtomicBool,
+    // Plan index of the action to skip while it copies, taken when the skip is requested
+    skip_action: Mutex<Option<usize>>,
+    current_action: Mutex<Option<usize>>,
+    conflicts_asked: AtomicUsize,
+    deletions_asked: AtomicUsize,
+    logs: Mutex<Vec<String>>,
+    progress_messages: Mutex<Vec<String>>,
+    plan: Mutex<Option<Vec<SyncAction>>>,
+    initial_stats: Mutex<Option<SyncStats>>,
+    finished_actions: Mutex<Vec<(usize, ActionStatus)>>,
+    consistency: Mutex<Option<ConsistencyReport>>,
+    small_files: Mutex<Option<usize>>,
+    space_estimate: Mutex<Option<SpaceEstimate>>,
+    finish_check: Option<Box<dyn Fn() + Sync>>,
+    finished: Mutex<Option<RunOutcome>>,
+    recorded_run: Mutex<Option<RunReport>>,
+    rate_limit: Option<u64>,
+    progress_hook: Mutex<Option<ProgressHook>>,
+}
+
+impl ScriptedObserver {
+    /// Confirms deletions, keeps the local side of conflicts, renames colliding names and continues interrupted plans.
+    pub fn new() -> Self {
+        Self {
+            deletion: DeletionDecision::Delete,
+            conflict: Resolution::KeepLocal,
+            name_collision: NameCollisionChoice::Rename,
+            crowded_directory: CrowdedDirectoryChoice::Attempt,
+            crowded_directories: Mutex::new(Vec::new()),
+            unreadable_sizes_answer: true,
+            unreadable_sizes: Mutex::new(None),
+            resume_plan: true,
+            resumes_asked: AtomicUsize::new(0),
+            remote_missing: RemoteMissingChoice::DeleteLocal,
+            newer_metadata_answer: false,
+            remote_missing_asked: Mutex::new(Vec::new()),
+            stop_after_actions: None,
+            actions_started: AtomicUsize::new(0),
+            stop_on_progress: None,
+            stop_requested: AtomicBool::new(false),
+            skip_action: Mutex::new(None),
+            current_action: Mutex::new(None),
+            conflicts_asked: AtomicUsize::new(0),
+            deletions_asked: AtomicUsize::new(0),
+            logs: Mutex::new(Vec::new()),
+            progress_messages: Mutex::new(Vec::new()),
+            plan: Mutex::new(None),
+            initial_stats: Mutex::new(None),
+            finished_actions: Mutex::new(Vec::new()),
+            consistency: Mutex::new(None),
+            small_files: Mutex::new(None),
+            space_estimate: Mutex::new(None),
+            finish_check: None,
+            finished: Mutex::new(None),
+            recorded_run: Mutex::new(None),
+            rate_limit: None,
+            progress_hook: Mutex::new(None),
+        }
+    }
+
+    pub fn with_conflict_resolution(mut self, resolution: Resolution) -> Self {
+        self.conflict = resolution;
+        self
+    }
+
+    pub fn with_deletion_decision(mut self, decision: DeletionDecision) -> Self {
+        self.deletion = decision;
+        self
+    }
+
+    pub fn with_crowded_directory_choice(mut self, choice: CrowdedDirectoryChoice) -> Self {
+        self.crowded_directory = choice;
+        self
+    }
+
+    /// Cancels the run when it asks about files whose size couldn't be read, instead of continuing.
+    pub fn cancelling_on_unreadable_sizes(mut self) -> Self {
+        self.unreadable_sizes_answer = false;
+        self
+    }
+
+    /// The example paths of files whose size couldn't be read, if the run asked.
+    pub fn unreadable_sizes(&self) -> Option<Vec<PathBuf>> {
+        self.unreadable_sizes.lock().unwrap().clone()
+    }
+
+    /// The folders reported as holding too many entries, if the run asked.
+    pub fn crowded_directories(&self) -> Vec<CrowdedDirectory> {
+        self.crowded_directories.lock().unwrap().clone()
+    }
+
+    pub fn with_name_collision_choice(mut self, choice: NameCollisionChoice) -> Self {
+        self.name_collision = choice;
+        self
+    }
+
+    pub fn with_resume_plan(mut self, resume: bool) -> Self {
+        self.resume_plan = resume;
+        self
+    }
+
+    /// Syncs past a record written by a newer version instead of stopping.
+    pub fn syncing_past_newer_metadata(mut self) -> Self {
+        self.newer_metadata_answer = true;
+        self
+    }
+
+    pub fn with_remote_missing_choice(mut self, choice: RemoteMissingChoice) -> Self {
+        self.remote_missing = choice;
+        self
+    }
+
+    /// (missing, known) file counts of each time the run asked about files gone from the USB drive.
+    pub fn remote_missing_asked(&self) -> Vec<(usize, usize)> {
+        self.remote_missing_asked.lock().unwrap().clone()
+    }
+
+    /// Asks the run to stop once `count` planned actions have started.
+    pub fn stopping_after(mut self, count: usize) -> Self {
+        self.stop_after_actions = Some(count);
+        self
+    }
+
+    /// Asks the run to stop once a progress message starts with `prefix`, e.g. in the middle of a long step.
+    pub fn stopping_on_progress(mut self, prefix: &str) -> Self {
+        self.stop_on_progress = Some(prefix.to_owned());
+        self
+    }
+
+    /// Asks to skip the action at `index` in the plan once its copy is under way, as 跳过此项 would.
+    pub fn skipping_action(self, index: usize) -> Self {
+        *self.skip_action.lock().unwrap() = Some(index);
+        self
+    }
+
+    /// Limits copies to `bytes_per_sec`, as the UI would.
+    pub fn with_rate_limit(mut self, bytes_per_sec: u64) -> Self {
+        self.rate_limit = Some(bytes_per_sec);
+        self
+    }
+
+    /// Runs `action` once, the first time a progress message starts with `prefix`,
+    /// e.g. "(1/" for the first planned action or "扫描U盘" for the USB scan.
+    pub fn doing_on_progress(self, prefix: &str, action: impl FnOnce() + Send + 'static) -> Self {
+        *self.progress_hook.lock().unwrap() = Some((prefix.to_owned(), Box::new(action)));
+        self
+    }
+
+    /// Runs `check` when the run reports its end, e.g. to look at what is on disk at that moment.
+    pub fn checking_on_finish(mut self, check: impl Fn() + Sync + 'static) -> Self {
+        self.finish_check = Some(Box::new(check));
+        self
+    }
+
+    /// How many conflict prompts the run raised.
+    pub fn conflicts_asked(&self) -> usize {
+        self.conflicts_asked.load(Ordering::Relaxed)
+    }
+
+    /// How many times the run asked whether to continue an interrupted plan.
+    pub fn resumes_asked(&self) -> usize {
+        self.resumes_asked.load(Ordering::Relaxed)
+    }
+
+    /// How many deletion prompts the run raised.
+    pub fn deletions_asked(&self) -> usize {
+        self.deletions_asked.load(Ordering::Relaxed)
+    }
+
+    pub fn logs(&self) -> Vec<String> {
+        self.logs.lock().unwrap().clone()
+    }
+
+    /// Every status text the run reported, in order.
+    pub fn progress_messages(&self) -> Vec<String> {
+        self.progress_messages.lock().unwrap().clone()
+    }
+
+    /// The plan the run reported, if it got that far.
+    pub fn plan(&self) -> Option<Vec<SyncAction>> {
+        self.plan.lock().unwrap().clone()
+    }
+
+    /// The counts sent before the first action, if the run got that far.
+    pub fn initial_stats(&self) -> Option<SyncStats> {
+        self.initial_stats.lock().unwrap().clone()
+    }
+
+    /// The check of both sides after the run, if it was made.
+    pub fn consistency(&self) -> Option<ConsistencyReport> {
+        self.consistency.lock().unwrap().clone()
+    }
+
+    /// How many small files the run warned about copying, if it did.
+    pub fn small_files(&self) -> Option<usize> {
+        *self.small_files.lock().unwrap()
+    }
+
+    /// The plan's estimated effect on the drive's free space, if the free space could be read.
+    pub fn space_estimate(&self) -> Option<SpaceEstimate> {
+        *self.space_estimate.lock().unwrap()
+    }
+
+    /// Plan indices and outcomes of the finished actions, in the order they were reported.
+    pub fn finished_actions(&self) -> Vec<(usize, ActionStatus)> {
+        self.finished_actions.lock().unwrap().clone()
+    }
+
+    /// The report handed over for the run history, if the run planned any actions.
+    pub fn recorded_run(&self) -> Option<RunReport> {
+        self.recorded_run.lock().unwrap().clone()
+    }
+}
+
+impl SyncObserver for ScriptedObserver {
+    fn on_progress(&self, _progress: f32, message: String) {
+        let mut hook = self.progress_hook.lock().unwrap();
+        if hook.as_ref().is_some_and(|(prefix, _)| message.starts_with(prefix.as_str())) {
+            let (_, action) = hook.take().unwrap();
+            action();
+        }
+        drop(hook);
+        if self.stop_on_progress.as_ref().is_some_and(|prefix| message.starts_with(prefix.as_str())) {
+            self.stop_requested.store(true, Ordering::Relaxed);
+        }
+        self.progress_messages.lock().unwrap().push(message.clone());
+        // Each planned action reports "(i/n)正在处理: ..." once before it runs
+        if message.starts_with('(') {
+            self.actions_started.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn on_log(&self, message: String) {
+        self.logs.lock().unwrap().push(message);
+    }
+
+    fn on_stats(&self, stats: SyncStats) {
+        self.initial_stats.lock().unwrap().get_or_insert(stats);
+    }
+
+    fn on_plan(&self, plan: &[SyncAction]) {
+        *self.plan.lock().unwrap() = Some(plan.to_vec());
+    }
+
+    fn on_action_started(&self, index: usize, _action: &SyncAction) {
+        *self.current_action.lock().unwrap() = Some(index);
+    }
+
+    fn on_action_finished(&self, index: usize, status: ActionStatus) {
+        *self.current_action.lock().unwrap() = None;
+        self.finished_actions.lock().unwrap().push((index, status));
+    }
+
+    fn on_consistency_checked(&self, report: &ConsistencyReport) {
+        *self.consistency.lock().unwrap() = Some(report.clone());
+    }
+
+    fn on_many_small_files(&self, count: usize) {
+        *self.small_files.lock().unwrap() = Some(count);
+    }
+
+    fn on_space_estimate(&self, estimate: SpaceEstimate) {
+        *self.space_estimate.lock().unwrap() = Some(estimate);
+    }
+
+    fn on_device_removed(&self, usb_drive: &Path) {
+        panic!("fake USB drive reported as removed: {}", usb_drive.display());
+    }
+
+    fn on_run_recorded(&self, report: &RunReport) {
+        *self.recorded_run.lock().unwrap() = Some(report.clone());
+    }
+
+    fn on_finished(&self, outcome: RunOutcome) {
+        if let Some(check) = &self.finish_check {
+            check();
+        }
+        *self.finished.lock().unwrap() = Some(outcome);
+    }
+
+    fn should_stop(&self) -> bool {
+        self.stop_requested.load(Ordering::Relaxed)
+            || self.stop_after_actions.is_some_and(|count| self.actions_started.load(Ordering::Relaxed) >= count)
+    }
+
+    fn should_skip_current(&self) -> bool {
+        let current = *self.current_action.lock().unwrap();
+        let mut skip = self.skip_action.lock().unwrap();
+        if current.is_some() && *skip == current {
+            *skip = None;
+            return true;
+        }
+        false
+    }
+
+    fn rate_limit(&self) -> Option<u64> {
+        self.rate_limit
+    }
+
+    fn confirm_deletion(&self, _path: &Path, _position: usize, _total: usize) -> Result<DeletionDecision, SyncError> {
+        self.deletions_asked.fetch_add(1, Ordering::Relaxed);
+        Ok(self.deletion)
+    }
+
+    fn resolve_conflict(&self, _path: &Path, _diff: Option<Vec<DiffLine>>, _suggestion: ConflictSuggestion) -> Result<Resolution, SyncError> {
+        self.conflicts_asked.fetch_add(1, Ordering::Relaxed);
+        Ok(self.conflict.clone())
+    }
+
+    fn resolve_clock_skew(&self, _description: String) -> Result<ClockSkewChoice, SyncError> {
+        Ok(ClockSkewChoice::Continue)
+    }
+
+    fn confirm_copy_in_use(&self, _path: &Path) -> Result<bool, SyncError> {
+        Ok(true)
+    }
+
+    fn resolve_remote_missing(&self, missing: usize, known: usize, _examples: Vec<PathBuf>) -> Result<RemoteMissingChoice, SyncError> {
+        self.remote_missing_asked.lock().unwrap().push((missing, known));
+        Ok(self.remote_missing.clone())
+    }
+
+    fn resolve_long_paths(&self, _limit: usize, _count: usize, _examples: Vec<PathBuf>) -> Result<LongPathChoice, SyncError> {
+        Ok(LongPathChoice::Attempt)
+    }
+
+    fn confirm_unreadable_sizes(&self, _count: usize, examples: Vec<PathBuf>) -> Result<bool, SyncError> {
+        *self.unreadable_sizes.lock().unwrap() = Some(examples);
+        Ok(self.unreadable_sizes_answer)
+    }
+
+    fn resolve_crowded_directories(&self, directories: Vec<CrowdedDirectory>) -> Result<CrowdedDirectoryChoice, SyncError> {
+        *self.crowded_directories.lock().unwrap() = directories;
+        Ok(self.crowded_directory.clone())
+    }
+
+    fn resolve_name_collisions(&self, _count: usize, _examples: Vec<Vec<PathBuf>>) -> Result<NameCollisionChoice, SyncError> {
+        Ok(self.name_collision.clone())
+    }
+
+    fn confirm_relink(&self, _old_name: &str, _new_name: &str) -> Result<bool, SyncError> {
+        Ok(false)
+    }
+
+    fn confirm_overwrite_newer(&self, _path: &Path) -> Result<bool, SyncError> {
+        Ok(false)
+    }
+
+    fn retry_denied_copy(&self, _path: &Path, _error: &str) -> Result<bool, SyncError> {
+        Ok(false)
+    }
+
+    fn confirm_newer_metadata(&self, _version: &str) -> Result<bool, SyncError> {
+        Ok(self.newer_metadata_answer)
+    }
+
+    fn confirm_resume_plan(&self, _remaining: usize, _total: usize) -> Result<bool, SyncError> {
+        self.resumes_asked.fetch_add(1, Ordering::Relaxed);
+        Ok(self.resume_plan)
+    }
+}
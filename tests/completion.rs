@@ -0,0 +1,44 @@
+//! The end of a run: metadata and log must be on disk before completion is reported.
+
+mod common;
+
+use common::{write_file, write_tree, Fixture, ScriptedObserver};
+use std::fs;
+use std::path::PathBuf;
+use syncu::models::RunOutcome;
+use syncu::utils::{load_sync_data, log_path, metadata_path, suffixed_path, TEMP_FILE_SUFFIX};
+
+#[test]
+fn metadata_and_log_are_written_before_completion_is_reported() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n"), ("notes/b.md", b"# bravo\n")]);
+    let remote = fixture.remote();
+    let observer = ScriptedObserver::new().checking_on_finish(move || {
+        let metadata = load_sync_data(&metadata_path(&remote)).unwrap();
+        assert!(metadata.files.contains_key(&PathBuf::from("a.txt")));
+        assert!(metadata.files.contains_key(&PathBuf::from("notes/b.md")));
+        let log = fs::read_to_string(log_path(&remote)).unwrap();
+        assert!(log.lines().last().is_some_and(|line| line.ends_with("同步完成")), "last log line: {:?}", log.lines().last());
+    });
+
+    assert_eq!(fixture.run(&observer), RunOutcome::Completed);
+}
+
+#[test]
+fn failing_to_save_metadata_downgrades_the_result() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n"), ("b.txt", b"bravo\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    let before = fixture.metadata();
+
+    // A folder where the temporary metadata file goes makes the save fail after the files are copied
+    fs::create_dir(suffixed_path(&metadata_path(&fixture.remote()), TEMP_FILE_SUFFIX, false)).unwrap();
+    write_file(&fixture.local, "c.txt", b"charlie\n");
+    let observer = ScriptedObserver::new();
+    let outcome = fixture.run(&observer);
+
+    assert!(matches!(outcome, RunOutcome::StateNotPersisted(_)), "{:?}", outcome);
+    assert!(observer.logs().iter().any(|line| line.starts_with("错误: 完成但写入状态失败")));
+    assert_eq!(fs::read(fixture.remote().join("c.txt")).unwrap(), b"charlie\n");
+    assert_eq!(fixture.metadata().files.len(), before.files.len());
+}
@@ -0,0 +1,58 @@
+//! Answers remembered for conflicts on single files.
+
+mod common;
+
+use common::{write_file, write_tree, Fixture, ScriptedObserver};
+use std::fs;
+use std::path::Path;
+use syncu::models::Resolution;
+use syncu::settings::Profile;
+
+fn remembering(fixture: &Fixture, resolution: Resolution) -> Profile {
+    let mut profile = fixture.profile();
+    profile.set_conflict_rule(Path::new("settings.json"), resolution);
+    profile
+}
+
+#[test]
+fn a_remembered_answer_resolves_the_conflict_without_asking() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("settings.json", b"{}\n"), ("other.txt", b"other\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+
+    write_file(&fixture.local, "settings.json", b"{\"office\": true}\n");
+    write_file(&fixture.remote(), "settings.json", b"{\"home\": true}\n");
+    write_file(&fixture.local, "other.txt", b"other, local\n");
+    write_file(&fixture.remote(), "other.txt", b"other, usb\n");
+    let observer = ScriptedObserver::new();
+    fixture.run_with_profile(&observer, remembering(&fixture, Resolution::KeepLocal));
+
+    // Only the file without a rule is asked about
+    assert_eq!(observer.conflicts_asked(), 1);
+    assert!(observer.logs().iter().any(|line| line.contains("按记住的选择处理冲突")));
+    assert_eq!(fs::read(fixture.remote().join("settings.json")).unwrap(), b"{\"office\": true}\n");
+}
+
+#[test]
+fn a_one_sided_change_flows_despite_a_rule() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("settings.json", b"{}\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+
+    write_file(&fixture.remote(), "settings.json", b"{\"home\": true}\n");
+    let observer = ScriptedObserver::new();
+    fixture.run_with_profile(&observer, remembering(&fixture, Resolution::KeepLocal));
+
+    assert_eq!(fs::read(fixture.local.join("settings.json")).unwrap(), b"{\"home\": true}\n");
+}
+
+#[test]
+fn a_new_answer_replaces_the_old_one() {
+    let mut profile = Profile::default();
+    profile.set_conflict_rule(Path::new("a.json"), Resolution::KeepLocal);
+    profile.set_conflict_rule(Path::new("a.json"), Resolution::KeepRemote);
+
+    assert_eq!(profile.conflict_rules.len(), 1);
+    assert_eq!(profile.conflict_rule_for(Path::new("a.json")), Some(&Resolution::KeepRemote));
+    assert_eq!(profile.conflict_rule_for(Path::new("b.json")), None);
+}
@@ -0,0 +1,46 @@
+//! The quick look at both sides after a completed run, which catches files changed while it ran.
+
+mod common;
+
+use common::{write_file, write_tree, Fixture, ScriptedObserver};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use syncu::sync::check_consistency;
+
+#[test]
+fn a_completed_run_reports_both_sides_consistent() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n"), ("notes/b.md", b"# bravo\n")]);
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+
+    let report = observer.consistency().expect("checked after the run");
+    assert_eq!(report.checked, 2);
+    assert!(report.mismatched.is_empty());
+    assert!(observer.logs().iter().any(|line| line.contains("两侧一致")));
+}
+
+#[test]
+fn files_changed_after_the_record_was_taken_are_listed() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n"), ("b.txt", b"bravo\n"), ("c.txt", b"charlie\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+
+    write_file(&fixture.local, "b.txt", b"bravo, edited meanwhile\n");
+    std::fs::remove_file(fixture.remote().join("c.txt")).unwrap();
+    let report = check_consistency(&fixture.local, &fixture.remote(), &fixture.metadata(), &HashSet::new(), &ScriptedObserver::new()).unwrap();
+
+    assert_eq!(report.checked, 3);
+    assert_eq!(report.mismatched, vec![PathBuf::from("b.txt"), PathBuf::from("c.txt")]);
+}
+
+#[test]
+fn the_check_can_be_turned_off() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n")]);
+    let profile = syncu::settings::Profile { check_after_sync: false, ..fixture.profile() };
+    let observer = ScriptedObserver::new();
+    fixture.run_with_profile(&observer, profile);
+
+    assert!(observer.consistency().is_none());
+}
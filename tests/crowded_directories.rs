@@ -0,0 +1,64 @@
+//! Folders on the USB drive that would hold more entries than the drive allows or the profile warns about.
+
+mod common;
+
+use common::{write_tree, Fixture, ScriptedObserver};
+use std::path::{Path, PathBuf};
+use syncu::models::CrowdedDirectoryChoice;
+use syncu::settings::Profile;
+use syncu::utils::crowded_directories;
+
+fn many_files(fixture: &Fixture, count: usize) {
+    let names: Vec<String> = (0..count).map(|index| format!("photos/{:03}.jpg", index)).collect();
+    let files: Vec<(&str, &[u8])> = names.iter().map(|name| (name.as_str(), b"jpeg".as_slice())).collect();
+    write_tree(&fixture.local, &files);
+}
+
+#[test]
+fn fat_limits_count_long_names_as_several_slots() {
+    let long_names: Vec<PathBuf> = (0..30_000).map(|index| PathBuf::from(format!("big/a rather long file name {:05}.txt", index))).collect();
+    let entries = long_names.iter().map(PathBuf::as_path);
+    let crowded = crowded_directories(entries, Some("FAT32"), None);
+
+    assert_eq!(crowded.len(), 1);
+    assert_eq!(crowded[0].path, Path::new("big"));
+    assert!(crowded[0].exceeds_file_system);
+    assert!(crowded[0].limit < 30_000);
+    // Other file systems have no such limit
+    assert!(crowded_directories(long_names.iter().map(PathBuf::as_path), Some("NTFS"), None).is_empty());
+}
+
+#[test]
+fn a_soft_limit_warns_and_skipping_leaves_new_files_out() {
+    let fixture = Fixture::new();
+    many_files(&fixture, 12);
+    let observer = ScriptedObserver::new().with_crowded_directory_choice(CrowdedDirectoryChoice::Skip);
+    fixture.run_with_profile(&observer, Profile { directory_entry_soft_limit: Some(10), ..fixture.profile() });
+
+    let crowded = observer.crowded_directories();
+    assert_eq!(crowded.len(), 1);
+    assert_eq!((crowded[0].path.as_path(), crowded[0].entries, crowded[0].limit), (Path::new("photos"), 12, 10));
+    assert!(!fixture.remote().join("photos/000.jpg").exists());
+    assert!(!fixture.metadata().files.contains_key(Path::new("photos/000.jpg")));
+}
+
+#[test]
+fn copying_anyway_goes_ahead() {
+    let fixture = Fixture::new();
+    many_files(&fixture, 12);
+    let observer = ScriptedObserver::new();
+    fixture.run_with_profile(&observer, Profile { directory_entry_soft_limit: Some(10), ..fixture.profile() });
+
+    assert_eq!(observer.crowded_directories().len(), 1);
+    assert!(fixture.remote().join("photos/011.jpg").exists());
+}
+
+#[test]
+fn folders_within_the_limit_are_not_reported() {
+    let fixture = Fixture::new();
+    many_files(&fixture, 5);
+    let observer = ScriptedObserver::new();
+    fixture.run_with_profile(&observer, Profile { directory_entry_soft_limit: Some(10), ..fixture.profile() });
+
+    assert!(observer.crowded_directories().is_empty());
+}
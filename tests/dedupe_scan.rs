@@ -0,0 +1,91 @@
+//! The deduplicating hash strategy must produce the same hashes as hashing every file.
+
+mod common;
+
+use common::{content, write_file, Fixture, ScriptedObserver};
+use std::path::PathBuf;
+use std::time::Instant;
+use syncu::models::SyncData;
+use syncu::utils::{scan_directory_with_progress, HashStrategy};
+
+fn scan(fixture: &Fixture, hashing: HashStrategy, observer: &ScriptedObserver) -> SyncData {
+    scan_directory_with_progress(&fixture.local, observer, None, "扫描本地", &SyncData::default(), hashing).unwrap().unwrap()
+}
+
+fn assert_same_hashes(full: &SyncData, deduplicated: &SyncData) {
+    assert_eq!(full.files.len(), deduplicated.files.len());
+    for (path, info) in &full.files {
+        assert_eq!(deduplicated.files[path].hash, info.hash, "hash of {}", path.display());
+    }
+}
+
+#[test]
+fn duplicates_reuse_hashes_and_near_duplicates_do_not() {
+    let fixture = Fixture::new();
+    let small = content(1, 300);
+    let large = content(2, 64 * 1024);
+    // Same size, head and tail as `large`, but one byte in the middle differs
+    let mut near = large.clone();
+    near[32 * 1024] ^= 0xff;
+    for album in ["a", "b", "c"] {
+        write_file(&fixture.local, &format!("{}/small.txt", album), &small);
+        write_file(&fixture.local, &format!("{}/large.jpg", album), &large);
+    }
+    write_file(&fixture.local, "d/near.jpg", &near);
+    write_file(&fixture.local, "d/other.txt", &content(3, 300));
+    write_file(&fixture.local, "d/unique.bin", &content(4, 1000));
+
+    let full = scan(&fixture, HashStrategy::Full, &ScriptedObserver::new());
+    let observer = ScriptedObserver::new();
+    let deduplicated = scan(&fixture, HashStrategy::Deduplicated, &observer);
+
+    assert_same_hashes(&full, &deduplicated);
+    assert_ne!(deduplicated.files[&PathBuf::from("d/near.jpg")].hash, deduplicated.files[&PathBuf::from("a/large.jpg")].hash);
+    // Two extra copies each of the small and the large file
+    assert!(observer.logs().iter().any(|line| line.ends_with("扫描本地: 4 个重复文件复用了校验值")), "{:#?}", observer.logs());
+}
+
+#[test]
+fn recorded_hashes_are_still_reused_first() {
+    let fixture = Fixture::new();
+    write_file(&fixture.local, "a.txt", b"alpha\n");
+    write_file(&fixture.local, "b.txt", b"alpha\n");
+    let recorded = scan(&fixture, HashStrategy::Full, &ScriptedObserver::new());
+
+    let observer = ScriptedObserver::new();
+    let rescanned = scan_directory_with_progress(&fixture.local, &observer, None, "扫描本地", &recorded, HashStrategy::Deduplicated)
+        .unwrap()
+        .unwrap();
+    assert_same_hashes(&recorded, &rescanned);
+    assert!(observer.logs().is_empty(), "{:#?}", observer.logs());
+}
+
+/// Compares both strategies on a folder of burst shots copied into several albums.
+/// Run with `cargo test --release --test dedupe_scan -- --ignored --nocapture` to see the timings.
+#[test]
+#[ignore = "benchmark"]
+fn benchmark_synthetic_duplicates() {
+    let fixture = Fixture::new();
+    for shot in 0..20u8 {
+        let photo = content(shot, 2 * 1024 * 1024);
+        for album in 0..8 {
+            write_file(&fixture.local, &format!("album{}/IMG_{:04}.jpg", album, shot), &photo);
+        }
+    }
+
+    let started = Instant::now();
+    let full = scan(&fixture, HashStrategy::Full, &ScriptedObserver::new());
+    let full_time = started.elapsed();
+    let started = Instant::now();
+    let deduplicated = scan(&fixture, HashStrategy::Deduplicated, &ScriptedObserver::new());
+    let deduplicated_time = started.elapsed();
+
+    assert_same_hashes(&full, &deduplicated);
+    eprintln!(
+        "{} files: full {:?}, deduplicated {:?} ({:.1}x)",
+        full.files.len(),
+        full_time,
+        deduplicated_time,
+        full_time.as_secs_f64() / deduplicated_time.as_secs_f64()
+    );
+}
@@ -0,0 +1,24 @@
+//! Deletions inside or around a folder the user already answered for aren't asked about again.
+
+mod common;
+
+use common::{write_tree, Fixture, ScriptedObserver};
+use std::fs;
+use syncu::observer::DeletionDecision;
+
+#[test]
+fn declining_the_files_of_a_folder_keeps_the_folder_without_asking() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("Old Projects/a.txt", b"a\n"), ("Old Projects/b.txt", b"b\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    fs::remove_dir_all(fixture.local.join("Old Projects")).unwrap();
+
+    let observer = ScriptedObserver::new().with_deletion_decision(DeletionDecision::Keep);
+    fixture.run(&observer);
+
+    // The two files are asked about; the folder holding them isn't
+    assert_eq!(observer.deletions_asked(), 2);
+    assert!(fixture.remote().join("Old Projects/a.txt").exists());
+    let logs = observer.logs();
+    assert!(logs.iter().any(|line| line.contains("保留目录 Old Projects: 其中的 Old Projects/a.txt 已取消删除")), "{:#?}", logs);
+}
@@ -0,0 +1,47 @@
+//! How much the log on the USB drive records, per folder.
+
+mod common;
+
+use common::{write_tree, Fixture, ScriptedObserver};
+use std::fs;
+use syncu::settings::{DeviceLogVerbosity, Profile};
+use syncu::utils::log_path;
+
+fn run(fixture: &Fixture, verbosity: DeviceLogVerbosity) -> ScriptedObserver {
+    write_tree(&fixture.local, &[("private-letter.txt", b"dear\n"), ("notes/b.md", b"# bravo\n")]);
+    let observer = ScriptedObserver::new();
+    fixture.run_with_profile(&observer, Profile { device_log_verbosity: verbosity, ..fixture.profile() });
+    observer
+}
+
+#[test]
+fn the_full_log_names_every_copied_file() {
+    let fixture = Fixture::new();
+    run(&fixture, DeviceLogVerbosity::Full);
+
+    let log = fs::read_to_string(log_path(&fixture.remote())).unwrap();
+    assert!(log.contains("private-letter.txt"));
+    assert!(log.contains("同步完成"));
+}
+
+#[test]
+fn a_summary_log_leaves_out_file_names() {
+    let fixture = Fixture::new();
+    let observer = run(&fixture, DeviceLogVerbosity::Summary);
+
+    let log = fs::read_to_string(log_path(&fixture.remote())).unwrap();
+    assert!(!log.contains("private-letter.txt"), "{}", log);
+    assert!(log.contains("同步统计"));
+    assert!(log.contains("同步完成"));
+    // The log in the app is unaffected
+    assert!(observer.logs().iter().any(|line| line.contains("private-letter.txt")));
+}
+
+#[test]
+fn a_disabled_log_is_never_written() {
+    let fixture = Fixture::new();
+    run(&fixture, DeviceLogVerbosity::Off);
+
+    assert!(fixture.remote().join("private-letter.txt").exists());
+    assert!(!log_path(&fixture.remote()).exists());
+}
@@ -0,0 +1,48 @@
+//! Walks over trees where a link leads back to one of its own ancestors.
+
+mod common;
+
+use common::{write_tree, Fixture, ScriptedObserver, TempDir};
+use syncu::models::RunOutcome;
+use syncu::utils::{count_entries, VisitedDirectories};
+
+#[test]
+fn a_folder_is_entered_only_once() {
+    let dir = TempDir::new();
+    write_tree(dir.path(), &[("a/1.txt", b"1")]);
+    let mut visited = VisitedDirectories::default();
+
+    assert!(!visited.revisit(dir.path()));
+    assert!(!visited.revisit(&dir.path().join("a")));
+    assert!(visited.revisit(&dir.path().join("a").join("..")));
+}
+
+#[cfg(unix)]
+#[test]
+fn a_link_back_to_an_ancestor_is_recognised_as_the_same_folder() {
+    let dir = TempDir::new();
+    write_tree(dir.path(), &[("a/b/1.txt", b"1")]);
+    let link = dir.path().join("a/b/back");
+    std::os::unix::fs::symlink(dir.path(), &link).unwrap();
+    let mut visited = VisitedDirectories::default();
+
+    assert!(!visited.revisit(dir.path()));
+    assert!(visited.revisit(&link));
+}
+
+#[cfg(unix)]
+#[test]
+fn counting_and_syncing_a_tree_with_a_link_loop_terminate() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n"), ("sub/deeper/b.txt", b"bravo\n")]);
+    std::os::unix::fs::symlink(&fixture.local, fixture.local.join("sub/deeper/back")).unwrap();
+
+    // The root, a.txt, sub, deeper, b.txt and the link itself
+    assert_eq!(count_entries(&fixture.local, &ScriptedObserver::new()), Some(6));
+
+    let observer = ScriptedObserver::new();
+    assert_eq!(fixture.run(&observer), RunOutcome::Completed);
+    assert!(fixture.remote().join("a.txt").is_file());
+    assert!(fixture.remote().join("sub/deeper/b.txt").is_file());
+    assert!(!fixture.remote().join("sub/deeper/back/a.txt").exists());
+}
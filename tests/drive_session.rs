@@ -0,0 +1,57 @@
+//! Several runs against the same drive within one user action, sharing what is known about the drive.
+
+mod common;
+
+use common::{assert_in_sync, write_tree, Fixture, ScriptedObserver};
+use std::fs;
+use syncu::drive_session::DriveSession;
+use syncu::settings::Profile;
+use syncu::sync::run_sync_in_session;
+
+#[test]
+fn consecutive_profiles_share_one_session() {
+    let fixture = Fixture::new();
+    let photos = fixture.local.parent().unwrap().join("photos");
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n")]);
+    write_tree(&photos, &[("b.jpg", b"not really a photo\n")]);
+    let session = DriveSession::new(fixture.usb.clone(), vec![fixture.usb.clone()]);
+
+    for local in [&fixture.local, &photos] {
+        let observer = ScriptedObserver::new();
+        run_sync_in_session(Some(local.clone()), Some(&session), Profile { local_folder: local.clone(), ..Default::default() }, false, &observer);
+        assert!(!observer.logs().iter().any(|line| line.starts_with("错误")), "{:#?}", observer.logs());
+    }
+    session.finish_action();
+
+    assert_in_sync(&fixture);
+    assert_eq!(fs::read(fixture.usb.join("photos/b.jpg")).unwrap(), b"not really a photo\n");
+    // The write probe leaves nothing behind on the drive
+    let mut entries: Vec<_> = fs::read_dir(&fixture.usb).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    entries.sort();
+    assert_eq!(entries, ["docs", "photos"]);
+}
+
+#[test]
+fn free_space_estimate_carries_over_until_the_action_ends() {
+    let fixture = Fixture::new();
+    let session = DriveSession::single_run(fixture.usb.clone());
+    let Some(before) = session.available_space() else {
+        return; // The temp dir's disk isn't listed on this system
+    };
+
+    session.record_space_used(4096);
+    assert_eq!(session.available_space(), Some(before.saturating_sub(4096)));
+    session.finish_action();
+    assert!(session.available_space().is_some());
+}
+
+#[test]
+fn session_only_matches_the_drive_list_it_was_created_with() {
+    let fixture = Fixture::new();
+    let other = fixture.usb.with_file_name("other");
+    let session = DriveSession::new(fixture.usb.clone(), vec![fixture.usb.clone()]);
+
+    assert!(session.matches(&fixture.usb, std::slice::from_ref(&fixture.usb)));
+    assert!(!session.matches(&fixture.usb, &[fixture.usb.clone(), other.clone()]));
+    assert!(!session.matches(&other, std::slice::from_ref(&fixture.usb)));
+}
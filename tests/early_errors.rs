@@ -0,0 +1,31 @@
+//! Runs that fail before the scans still tell the UI what went wrong and that they ended.
+
+mod common;
+
+use common::TempDir;
+use crossbeam_channel::unbounded;
+use std::path::PathBuf;
+use syncu::models::SyncMessage;
+use syncu::observer::ChannelObserver;
+use syncu::settings::Profile;
+use syncu::sync::run_sync;
+
+#[test]
+fn selecting_a_drive_root_reports_an_error_and_finishes() {
+    let usb = TempDir::new();
+    let root = std::env::temp_dir().ancestors().last().map(PathBuf::from).unwrap();
+    let (tx, rx_ui) = unbounded();
+    // Kept alive, as the UI does: a closed channel reads as a stop request
+    let (_tx_ui, rx) = unbounded();
+
+    run_sync(Some(root), Some(usb.path().to_path_buf()), Profile::default(), false, &ChannelObserver::new(tx, rx));
+
+    let messages: Vec<SyncMessage> = rx_ui.try_iter().collect();
+    assert!(
+        matches!(messages.as_slice(), [.., SyncMessage::Log(line), SyncMessage::Complete] if line.starts_with("错误")),
+        "{:#?}",
+        messages
+    );
+    // Nothing was created for the run on the drive
+    assert_eq!(std::fs::read_dir(usb.path()).unwrap().count(), 0);
+}
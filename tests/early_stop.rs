@@ -0,0 +1,50 @@
+//! Stopping a run before it reaches the scans: nothing is created and the stop is still reported.
+
+mod common;
+
+use common::{write_tree, Fixture, ScriptedObserver};
+use std::fs;
+use syncu::models::RunOutcome;
+use syncu::utils::{load_sync_data, load_sync_data_with_progress, metadata_path, save_sync_data_with_progress, TEMP_FILE_SUFFIX};
+
+#[test]
+fn stop_before_the_first_step_leaves_the_drive_untouched() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n")]);
+    let observer = ScriptedObserver::new().stopping_after(0);
+
+    assert_eq!(fixture.run(&observer), RunOutcome::Stopped);
+    assert!(!fixture.remote().exists());
+    assert!(observer.logs().iter().any(|line| line.ends_with("同步已由用户停止。")), "{:#?}", observer.logs());
+    assert_eq!(fs::read_dir(&fixture.usb).unwrap().count(), 0);
+}
+
+#[test]
+fn loading_the_record_can_be_stopped() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n"), ("b.txt", b"bravo\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    let path = metadata_path(&fixture.remote());
+
+    assert!(load_sync_data_with_progress(&path, &ScriptedObserver::new().stopping_after(0)).unwrap().is_none());
+    let loaded = load_sync_data_with_progress(&path, &ScriptedObserver::new()).unwrap().unwrap();
+    assert_eq!(loaded.files.len(), 2);
+}
+
+#[test]
+fn saving_the_record_can_be_stopped() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n"), ("b.txt", b"bravo\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    let path = metadata_path(&fixture.remote());
+    let previous = fs::read(&path).unwrap();
+    let mut changed = load_sync_data(&path).unwrap();
+    changed.files.clear();
+
+    assert_eq!(save_sync_data_with_progress(&changed, &path, &ScriptedObserver::new().stopping_after(0)).unwrap(), None);
+    assert_eq!(fs::read(&path).unwrap(), previous);
+    let folder = path.parent().unwrap();
+    let leftovers: Vec<_> =
+        fs::read_dir(folder).unwrap().map(|entry| entry.unwrap().file_name()).filter(|name| name.to_string_lossy().ends_with(TEMP_FILE_SUFFIX)).collect();
+    assert!(leftovers.is_empty(), "{:?}", leftovers);
+}
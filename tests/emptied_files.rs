@@ -0,0 +1,75 @@
+//! Files that became empty since the last sync, e.g. after an interrupted copy, are treated as damaged.
+
+mod common;
+
+use common::{write_file, write_tree, Fixture, ScriptedObserver};
+use std::fs;
+
+#[test]
+fn an_emptied_side_of_a_conflict_is_repaired_from_the_intact_one() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+
+    write_file(&fixture.remote(), "a.txt", b"");
+    write_file(&fixture.local, "a.txt", b"alpha, edited\n");
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+
+    assert_eq!(observer.conflicts_asked(), 0);
+    assert!(observer.logs().iter().any(|line| line.contains("疑似损坏")));
+    assert_eq!(fs::read(fixture.remote().join("a.txt")).unwrap(), b"alpha, edited\n");
+    assert_eq!(fs::read(fixture.local.join("a.txt")).unwrap(), b"alpha, edited\n");
+}
+
+#[test]
+fn without_repair_the_conflict_is_asked() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+
+    write_file(&fixture.local, "a.txt", b"");
+    write_file(&fixture.remote(), "a.txt", b"alpha, edited\n");
+    let profile = syncu::settings::Profile { repair_truncated_files: false, ..fixture.profile() };
+    let observer = ScriptedObserver::new();
+    fixture.run_with_profile(&observer, profile);
+
+    assert_eq!(observer.conflicts_asked(), 1);
+}
+
+#[test]
+fn a_file_that_was_empty_before_is_synced_as_usual() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+
+    write_file(&fixture.remote(), "a.txt", b"now with content\n");
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+
+    assert!(!observer.logs().iter().any(|line| line.contains("疑似损坏")));
+    assert_eq!(fs::read(fixture.local.join("a.txt")).unwrap(), b"now with content\n");
+}
+
+#[test]
+fn a_truncated_local_file_is_restored_and_then_left_alone() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+
+    // An application empties the file for a moment while a sync runs
+    write_file(&fixture.local, "a.txt", b"");
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+    assert_eq!(observer.conflicts_asked(), 0);
+    assert!(observer.logs().iter().any(|line| line.contains("本地文件疑似损坏")), "{:#?}", observer.logs());
+    assert_eq!(fs::read(fixture.local.join("a.txt")).unwrap(), b"alpha\n");
+    assert_eq!(fs::read(fixture.remote().join("a.txt")).unwrap(), b"alpha\n");
+
+    // The restored file matches the record, so the next sync has nothing to do
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+    assert_eq!(observer.conflicts_asked(), 0);
+    assert!(observer.plan().unwrap_or_default().is_empty(), "{:#?}", observer.plan());
+    assert_eq!(fs::read(fixture.remote().join("a.txt")).unwrap(), b"alpha\n");
+}
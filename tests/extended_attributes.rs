@@ -0,0 +1,64 @@
+//! Copying user extended attributes along with file contents, when the profile asks for it.
+
+mod common;
+
+use common::{content, write_file, write_tree, Fixture, ScriptedObserver};
+use std::path::Path;
+use syncu::extended_attributes::supported_on;
+
+#[test]
+fn fat_family_file_systems_are_recognised() {
+    for name in ["FAT", "FAT32", "exFAT", "vfat", "msdos"] {
+        assert!(!supported_on(name), "{}", name);
+    }
+    for name in ["NTFS", "ext4", "apfs", "btrfs", "unknown"] {
+        assert!(supported_on(name), "{}", name);
+    }
+}
+
+// Temp folders on some systems (e.g. older tmpfs) can't hold user xattrs; those runs have nothing to check
+#[cfg(unix)]
+fn tag(path: &Path, value: &[u8]) -> bool {
+    xattr::set(path, "user.syncu.test", value).is_ok()
+}
+
+#[cfg(unix)]
+fn read_tag(path: &Path) -> Option<Vec<u8>> {
+    xattr::get(path, "user.syncu.test").unwrap()
+}
+
+#[cfg(unix)]
+#[test]
+fn attributes_follow_small_and_large_copies_when_enabled() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n")]);
+    // Above the large file threshold, so the chunked copy path is used
+    write_file(&fixture.local, "large.bin", &content(3, 11 * 1024 * 1024));
+    if !tag(&fixture.local.join("a.txt"), b"small") || !tag(&fixture.local.join("large.bin"), b"large") {
+        return;
+    }
+
+    let profile = syncu::settings::Profile { copy_extended_attributes: true, ..fixture.profile() };
+    let observer = ScriptedObserver::new();
+    fixture.run_with_profile(&observer, profile);
+
+    if observer.logs().iter().any(|line| line.contains("无法保存扩展属性")) {
+        return;
+    }
+    assert!(!observer.logs().iter().any(|line| line.starts_with("警告: 扩展属性未能复制")), "{:#?}", observer.logs());
+    assert_eq!(read_tag(&fixture.remote().join("a.txt")).as_deref(), Some(&b"small"[..]));
+    assert_eq!(read_tag(&fixture.remote().join("large.bin")).as_deref(), Some(&b"large"[..]));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn attributes_are_left_behind_by_default() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n")]);
+    if !tag(&fixture.local.join("a.txt"), b"small") {
+        return;
+    }
+
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    assert_eq!(read_tag(&fixture.remote().join("a.txt")), None);
+}
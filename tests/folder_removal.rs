@@ -0,0 +1,78 @@
+//! Deleting large folder trees and moving many files to the trash, with progress and stop support.
+
+mod common;
+
+use common::{write_file, write_tree, Fixture, ScriptedObserver, TempDir};
+use std::fs;
+use syncu::settings::DeviceLogVerbosity;
+use syncu::sync::{find_orphan_files, move_orphans_to_trash};
+use syncu::utils::{remove_dir_all_with_progress, TEMP_FILE_SUFFIX};
+use walkdir::WalkDir;
+
+// 2500 files spread over nested folders
+fn write_large_tree(root: &std::path::Path) {
+    for i in 0..2500 {
+        write_file(root, &format!("{}/{}/{}.txt", i % 5, i % 7, i), b"x");
+    }
+}
+
+#[test]
+fn a_large_tree_is_deleted_with_progress() {
+    let dir = TempDir::new();
+    let big = dir.path().join("big");
+    write_large_tree(&big);
+    let observer = ScriptedObserver::new();
+
+    assert!(!remove_dir_all_with_progress(&big, "big", 0.5, &observer).unwrap());
+    assert!(!big.exists());
+    let messages = observer.progress_messages();
+    assert!(messages.iter().any(|message| message.starts_with("删除目录 big (1,000/")), "{:#?}", messages);
+}
+
+#[cfg(unix)]
+#[test]
+fn links_inside_the_tree_are_removed_not_followed() {
+    let dir = TempDir::new();
+    let big = dir.path().join("big");
+    write_tree(dir.path(), &[("outside/keep.txt", b"keep"), ("big/a.txt", b"a")]);
+    std::os::unix::fs::symlink(dir.path().join("outside"), big.join("link")).unwrap();
+
+    assert!(!remove_dir_all_with_progress(&big, "big", 0.0, &ScriptedObserver::new()).unwrap());
+    assert!(!big.exists());
+    assert!(dir.path().join("outside/keep.txt").is_file());
+}
+
+#[test]
+fn a_stop_leaves_the_rest_for_the_next_attempt() {
+    let dir = TempDir::new();
+    let big = dir.path().join("big");
+    write_large_tree(&big);
+    let total = WalkDir::new(&big).into_iter().count();
+    let observer = ScriptedObserver::new().stopping_on_progress("删除目录 big (1,000/");
+
+    assert!(remove_dir_all_with_progress(&big, "big", 0.0, &observer).unwrap());
+    assert_eq!(WalkDir::new(&big).into_iter().count(), total - 1000);
+    assert!(observer.logs().iter().any(|line| line.contains("已停止删除目录 big")), "{:#?}", observer.logs());
+
+    assert!(!remove_dir_all_with_progress(&big, "big", 0.0, &ScriptedObserver::new()).unwrap());
+    assert!(!big.exists());
+}
+
+#[test]
+fn moving_orphans_to_the_trash_can_be_stopped() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    for i in 0..300 {
+        write_file(&fixture.remote(), &format!("left/{}.txt{}", i, TEMP_FILE_SUFFIX), b"partial");
+    }
+    let orphans = find_orphan_files(&fixture.local, &fixture.usb).unwrap();
+    assert_eq!(orphans.len(), 300);
+
+    let observer = ScriptedObserver::new().stopping_on_progress("正在移至回收文件夹 (256/");
+    let moved = move_orphans_to_trash(&fixture.local, &fixture.usb, &orphans, DeviceLogVerbosity::Full, &observer).unwrap();
+
+    assert_eq!(moved, 256);
+    assert_eq!(fs::read_dir(fixture.remote().join("left")).unwrap().count(), 44);
+    assert_eq!(find_orphan_files(&fixture.local, &fixture.usb).unwrap().len(), 44);
+}
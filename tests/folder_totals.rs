@@ -0,0 +1,46 @@
+//! Counting files and bytes of a sync folder for the totals shown next to each side.
+
+mod common;
+
+use common::{write_tree, Fixture, ScriptedObserver};
+use syncu::utils::{folder_totals, format_count, FolderTotals, METADATA_FILE_NAME, TRASH_DIR_NAME};
+
+#[test]
+fn counts_files_and_bytes_but_not_syncu_files() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"12345"), ("nested/b.txt", b"123"), ("nested/deeper/c.bin", &[0u8; 1000])]);
+    write_tree(
+        &fixture.local,
+        &[
+            (METADATA_FILE_NAME, b"{}"),
+            ("d.txt.syncu_tmp", b"partial"),
+            (&format!("{}/old.txt", TRASH_DIR_NAME), b"trashed"),
+        ],
+    );
+
+    let totals = folder_totals(&fixture.local, &ScriptedObserver::new()).unwrap();
+    assert_eq!(totals, FolderTotals { files: 3, bytes: 1008 });
+}
+
+#[test]
+fn missing_folder_counts_as_empty() {
+    let fixture = Fixture::new();
+    assert!(!fixture.remote().exists());
+    assert_eq!(folder_totals(&fixture.remote(), &ScriptedObserver::new()), Some(FolderTotals::default()));
+}
+
+#[test]
+fn stopping_abandons_the_count() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"a")]);
+    assert_eq!(folder_totals(&fixture.local, &ScriptedObserver::new().stopping_after(0)), None);
+}
+
+#[test]
+fn formats_counts_with_thousands_separators() {
+    assert_eq!(format_count(0), "0");
+    assert_eq!(format_count(999), "999");
+    assert_eq!(format_count(1000), "1,000");
+    assert_eq!(format_count(18204), "18,204");
+    assert_eq!(format_count(1234567), "1,234,567");
+}
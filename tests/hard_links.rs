@@ -0,0 +1,73 @@
+//! Overwriting destination files that share their content with other paths through hard links.
+
+mod common;
+
+use common::{write_file, write_tree, Fixture, ScriptedObserver, TempDir};
+use std::fs;
+use std::path::PathBuf;
+use syncu::settings::{HardLinkPolicy, Profile};
+use syncu::utils::hard_link_count;
+
+// Syncs a.txt, links the USB copy into a snapshot folder outside the sync folder, then edits a.txt locally.
+fn fixture_with_snapshot() -> (Fixture, PathBuf) {
+    let fixture = Fixture::synced(&[("a.txt", b"alpha\n")]);
+    let snapshot = fixture.usb.join("snapshot-1");
+    fs::create_dir_all(&snapshot).unwrap();
+    fs::hard_link(fixture.remote().join("a.txt"), snapshot.join("a.txt")).unwrap();
+    write_file(&fixture.local, "a.txt", b"alpha, edited\n");
+    (fixture, snapshot.join("a.txt"))
+}
+
+#[test]
+fn link_counts_are_read() {
+    let dir = TempDir::new();
+    write_file(dir.path(), "a.txt", b"alpha\n");
+    assert_eq!(hard_link_count(&dir.path().join("a.txt")), Some(1));
+    fs::hard_link(dir.path().join("a.txt"), dir.path().join("b.txt")).unwrap();
+    assert_eq!(hard_link_count(&dir.path().join("a.txt")), Some(2));
+    assert_eq!(hard_link_count(&dir.path().join("missing.txt")), None);
+}
+
+#[test]
+fn by_default_the_link_is_broken_before_writing() {
+    let (fixture, snapshot) = fixture_with_snapshot();
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+
+    assert_eq!(fs::read(fixture.remote().join("a.txt")).unwrap(), b"alpha, edited\n");
+    assert_eq!(fs::read(&snapshot).unwrap(), b"alpha\n");
+    assert_eq!(hard_link_count(&fixture.remote().join("a.txt")), Some(1));
+    let logs = observer.logs();
+    assert!(logs.iter().any(|line| line.contains("已先断开链接再写入")), "{:#?}", logs);
+    assert!(logs.iter().any(|line| line.contains("同步统计") && line.contains("有其他硬链接的目标文件 1 个")), "{:#?}", logs);
+}
+
+#[test]
+fn warning_writes_through_the_link() {
+    let (fixture, snapshot) = fixture_with_snapshot();
+    let observer = ScriptedObserver::new();
+    let profile = Profile { hard_link_policy: HardLinkPolicy::Warn, ..fixture.profile() };
+    fixture.run_with_profile(&observer, profile);
+
+    assert_eq!(fs::read(fixture.remote().join("a.txt")).unwrap(), b"alpha, edited\n");
+    assert_eq!(fs::read(&snapshot).unwrap(), b"alpha, edited\n");
+    assert!(observer.logs().iter().any(|line| line.starts_with("警告: 目标文件有其他硬链接")), "{:#?}", observer.logs());
+}
+
+#[test]
+fn backup_copies_break_links_too() {
+    let backup = TempDir::new();
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n")]);
+    let profile = Profile { secondary_destination: Some(backup.path().to_path_buf()), ..fixture.profile() };
+    fixture.run_with_profile(&ScriptedObserver::new(), profile.clone());
+    let backed_up = backup.path().join(common::FOLDER_NAME).join("a.txt");
+    let snapshot = backup.path().join("a.snapshot");
+    fs::hard_link(&backed_up, &snapshot).unwrap();
+    write_file(&fixture.local, "a.txt", b"alpha, edited\n");
+
+    fixture.run_with_profile(&ScriptedObserver::new(), profile);
+
+    assert_eq!(fs::read(&backed_up).unwrap(), b"alpha, edited\n");
+    assert_eq!(fs::read(&snapshot).unwrap(), b"alpha\n");
+}
@@ -0,0 +1,42 @@
+//! Status text while a single huge file is hashed during a scan.
+
+mod common;
+
+use common::{content, write_file, write_tree, Fixture, ScriptedObserver};
+use syncu::models::SyncData;
+use syncu::utils::{scan_directory_with_progress, HashStrategy};
+
+#[test]
+fn huge_file_reports_its_own_percentage_next_to_the_scan_counter() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n")]);
+    // Three progress intervals, so at least two reports before the end
+    write_file(&fixture.local, "big.iso", &content(4, 3 * 64 * 1024 * 1024));
+    let observer = ScriptedObserver::new();
+
+    let scanned = scan_directory_with_progress(&fixture.local, &observer, Some(3), "扫描本地", &SyncData::default(), HashStrategy::Full)
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(scanned.files.len(), 2);
+    let reports: Vec<String> = observer.progress_messages().into_iter().filter(|message| message.contains("big.iso (")).collect();
+    assert!(reports.len() >= 2, "{:#?}", observer.progress_messages());
+    for report in &reports {
+        assert!(report.starts_with("扫描本地 ("), "{}", report);
+        assert!(report.ends_with("%)"), "{}", report);
+    }
+    assert!(reports.iter().any(|report| report.ends_with(" - big.iso (33%)")), "{:#?}", reports);
+}
+
+#[test]
+fn small_files_keep_the_plain_status_text() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n"), ("b.txt", b"bravo\n")]);
+    let observer = ScriptedObserver::new();
+
+    scan_directory_with_progress(&fixture.local, &observer, None, "扫描本地", &SyncData::default(), HashStrategy::Full)
+        .unwrap()
+        .unwrap();
+
+    assert!(!observer.progress_messages().iter().any(|message| message.ends_with("%)")), "{:#?}", observer.progress_messages());
+}
@@ -0,0 +1,52 @@
+//! The `.syncu` files that open SyncU with a sync pair selected.
+
+mod common;
+
+use common::TempDir;
+use std::fs;
+use std::path::PathBuf;
+use syncu::settings::LaunchFile;
+
+#[test]
+fn a_saved_launch_file_loads_back() {
+    let dir = TempDir::new();
+    let local = dir.path().join("Documents");
+    fs::create_dir(&local).unwrap();
+    let path = dir.path().join("Documents.syncu");
+    let launch = LaunchFile {
+        local_folder: local,
+        usb_drive: Some(PathBuf::from("E:\\")),
+        usb_volume_id: Some("1A2B-3C4D".to_owned()),
+        auto: true,
+    };
+    launch.save(&path).unwrap();
+
+    assert_eq!(LaunchFile::load(&path).unwrap(), launch);
+}
+
+#[test]
+fn a_relative_folder_is_found_next_to_the_file() {
+    let dir = TempDir::new();
+    fs::create_dir(dir.path().join("Documents")).unwrap();
+    let path = dir.path().join("Documents.syncu");
+    fs::write(&path, r#"{ "local_folder": "Documents" }"#).unwrap();
+    let launch = LaunchFile::load(&path).unwrap();
+
+    assert_eq!(launch.local_folder, dir.path().join("Documents"));
+    assert_eq!(launch.usb_drive, None);
+    assert_eq!(launch.usb_volume_id, None);
+    assert!(!launch.auto);
+}
+
+#[test]
+fn malformed_files_and_missing_folders_are_rejected() {
+    let dir = TempDir::new();
+    let malformed = dir.path().join("broken.syncu");
+    fs::write(&malformed, "{ local_folder: ").unwrap();
+    assert!(LaunchFile::load(&malformed).is_err());
+
+    let missing = dir.path().join("missing.syncu");
+    fs::write(&missing, r#"{ "local_folder": "gone" }"#).unwrap();
+    let error = LaunchFile::load(&missing).unwrap_err().to_string();
+    assert!(error.contains("本地文件夹不存在"), "{}", error);
+}
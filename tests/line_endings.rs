@@ -0,0 +1,85 @@
+//! Conflicts between text files that only differ in CRLF/LF line endings, settled when the folder opts in.
+
+mod common;
+
+use common::{write_file, write_tree, Fixture, ScriptedObserver, TempDir};
+use std::fs;
+use std::time::{Duration, SystemTime};
+use syncu::settings::{LineEndingPolicy, Profile};
+use syncu::utils::differ_only_in_line_endings;
+
+fn opted_in(fixture: &Fixture, policy: LineEndingPolicy) -> Profile {
+    Profile { resolve_line_ending_conflicts: true, line_ending_policy: policy, ..fixture.profile() }
+}
+
+// Both sides changed since the last sync: LF locally, CRLF on the drive
+fn converted_on_both_sides(fixture: &Fixture) {
+    write_tree(&fixture.local, &[("notes.txt", b"one\ntwo\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    write_file(&fixture.local, "notes.txt", b"one\ntwo\nthree\n");
+    write_file(&fixture.remote(), "notes.txt", b"one\r\ntwo\r\nthree\r\n");
+}
+
+#[test]
+fn line_endings_are_compared_as_text() {
+    let dir = TempDir::new();
+    write_file(dir.path(), "lf.txt", b"a\nb\n");
+    write_file(dir.path(), "crlf.txt", b"a\r\nb\r\n");
+    write_file(dir.path(), "cr.txt", b"a\rb\r");
+    write_file(dir.path(), "other.txt", b"a\nc\n");
+    write_file(dir.path(), "binary.txt", b"a\0\r\n");
+    write_file(dir.path(), "binary_lf.txt", b"a\0\n");
+    assert!(differ_only_in_line_endings(&dir.path().join("lf.txt"), &dir.path().join("crlf.txt")));
+    assert!(differ_only_in_line_endings(&dir.path().join("lf.txt"), &dir.path().join("cr.txt")));
+    assert!(!differ_only_in_line_endings(&dir.path().join("lf.txt"), &dir.path().join("other.txt")));
+    assert!(!differ_only_in_line_endings(&dir.path().join("binary.txt"), &dir.path().join("binary_lf.txt")));
+}
+
+#[test]
+fn a_line_ending_conflict_keeps_the_chosen_side_verbatim() {
+    let fixture = Fixture::new();
+    converted_on_both_sides(&fixture);
+    let observer = ScriptedObserver::new();
+    fixture.run_with_profile(&observer, opted_in(&fixture, LineEndingPolicy::KeepUsb));
+
+    assert_eq!(observer.conflicts_asked(), 0);
+    assert!(observer.logs().iter().any(|line| line.contains("仅行尾不同，已自动处理")));
+    assert_eq!(fs::read(fixture.local.join("notes.txt")).unwrap(), b"one\r\ntwo\r\nthree\r\n");
+    assert_eq!(fs::read(fixture.remote().join("notes.txt")).unwrap(), b"one\r\ntwo\r\nthree\r\n");
+}
+
+#[test]
+fn keeping_the_newer_side_follows_the_modification_times() {
+    let fixture = Fixture::new();
+    converted_on_both_sides(&fixture);
+    let older = SystemTime::now() - Duration::from_secs(3600);
+    fs::File::options().write(true).open(fixture.remote().join("notes.txt")).unwrap().set_modified(older).unwrap();
+    let observer = ScriptedObserver::new();
+    fixture.run_with_profile(&observer, opted_in(&fixture, LineEndingPolicy::KeepNewer));
+
+    assert_eq!(observer.conflicts_asked(), 0);
+    assert_eq!(fs::read(fixture.remote().join("notes.txt")).unwrap(), b"one\ntwo\nthree\n");
+}
+
+#[test]
+fn without_opting_in_the_conflict_is_asked() {
+    let fixture = Fixture::new();
+    converted_on_both_sides(&fixture);
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+
+    assert_eq!(observer.conflicts_asked(), 1);
+}
+
+#[test]
+fn files_outside_the_allowlist_are_asked() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("data.bin", b"one\ntwo\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    write_file(&fixture.local, "data.bin", b"one\ntwo\nthree\n");
+    write_file(&fixture.remote(), "data.bin", b"one\r\ntwo\r\nthree\r\n");
+    let observer = ScriptedObserver::new();
+    fixture.run_with_profile(&observer, opted_in(&fixture, LineEndingPolicy::KeepLocal));
+
+    assert_eq!(observer.conflicts_asked(), 1);
+}
@@ -0,0 +1,38 @@
+//! One stick synced against several machines: each keeps the mtimes of its own copies for the mtime shortcut.
+
+mod common;
+
+use common::{write_tree, Fixture, ScriptedObserver};
+use std::collections::HashMap;
+use std::time::Duration;
+use syncu::models::Observation;
+use syncu::utils::{metadata_path, save_sync_data};
+
+#[test]
+fn another_machines_observations_survive_and_this_one_hashes_nothing() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n"), ("notes/b.md", b"# bravo\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+
+    // A laptop synced the same stick; its copies were written an hour later
+    let mut record = fixture.metadata();
+    let laptop: HashMap<_, _> = record
+        .files
+        .iter()
+        .map(|(path, info)| (path.clone(), Observation { modified: info.modified + Duration::from_secs(3600), size: info.size }))
+        .collect();
+    record.observations.insert("laptop".to_owned(), laptop.clone());
+    save_sync_data(&record, &metadata_path(&fixture.remote())).unwrap();
+
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+    assert!(!observer.logs().iter().any(|line| line.contains("扫描本地: 重新校验")), "{:#?}", observer.logs());
+
+    let record = fixture.metadata();
+    assert_eq!(record.observations["laptop"], laptop);
+    let reference = record.local_reference("laptop").unwrap();
+    for (path, seen) in &laptop {
+        assert_eq!(reference.files[path].modified, seen.modified);
+        assert_eq!(reference.files[path].hash, record.files[path].hash);
+    }
+}
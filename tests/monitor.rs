@@ -0,0 +1,58 @@
+//! The loopback endpoint that streams the progress of a run to external programs.
+
+use crossbeam_channel::unbounded;
+use std::io::{BufRead, BufReader};
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+use syncu::models::{ActivityKind, RunOutcome, SyncMessage, SyncStats};
+use syncu::monitor::{Monitor, MonitorEvent};
+use syncu::observer::{ChannelObserver, SyncObserver};
+
+fn connect(monitor: &Monitor) -> BufReader<TcpStream> {
+    let stream = TcpStream::connect(monitor.local_addr()).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    // Give the monitor thread a moment to accept the client before events are sent
+    thread::sleep(Duration::from_millis(200));
+    BufReader::new(stream)
+}
+
+fn next_event(reader: &mut BufReader<TcpStream>) -> MonitorEvent {
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    serde_json::from_str(&line).unwrap()
+}
+
+#[test]
+fn progress_and_outcome_reach_a_connected_client() {
+    let monitor = Monitor::start(0).unwrap();
+    let mut client = connect(&monitor);
+    let (tx, _rx_ui) = unbounded();
+    let (_tx_ui, rx) = unbounded();
+    let observer = ChannelObserver::new(tx, rx).with_monitor(Some(monitor.sender()));
+
+    observer.on_progress(0.5, "a.txt".to_string());
+    observer.on_log("错误: b.txt 无法读取".to_string());
+    observer.on_stats(SyncStats { completed: 1, failed: 1, remaining: 2, ..SyncStats::default() });
+    observer.on_finished(RunOutcome::Completed);
+
+    assert_eq!(
+        next_event(&mut client),
+        MonitorEvent::Progress { fraction: 0.5, current: "a.txt".to_string(), phase: "bookkeeping".to_string() }
+    );
+    assert_eq!(next_event(&mut client), MonitorEvent::Error { message: "错误: b.txt 无法读取".to_string() });
+    assert_eq!(next_event(&mut client), MonitorEvent::Stats { completed: 1, skipped: 0, failed: 1, remaining: 2 });
+    assert_eq!(next_event(&mut client), MonitorEvent::Completed);
+}
+
+#[test]
+fn sending_without_clients_or_with_a_full_backlog_never_blocks() {
+    let monitor = Monitor::start(0).unwrap();
+    let sender = monitor.sender();
+    for i in 0..10_000 {
+        sender.forward(&SyncMessage::Progress(0.0, format!("{}.txt", i), ActivityKind::Copy));
+    }
+    // Shuts down even though a sender is still around
+    drop(monitor);
+    sender.forward(&SyncMessage::Complete);
+}
@@ -0,0 +1,111 @@
+//! Compensating a constant whole-hour shift between the mtimes on disk and the sync record.
+
+mod common;
+
+use common::{write_file, Fixture, ScriptedObserver, TempDir};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use syncu::models::{FileInfo, SyncData};
+use syncu::utils::{infer_mtime_offset, shift_mtimes};
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+fn modified(path: &Path) -> SystemTime {
+    path.metadata().unwrap().modified().unwrap()
+}
+
+fn shift_modified(path: &Path, by: Duration) {
+    let time = modified(path) + by;
+    File::options().write(true).open(path).unwrap().set_modified(time).unwrap();
+}
+
+// Twenty files, and a record of them with their mtimes moved back by `recorded_behind`
+fn files_and_record(root: &Path, recorded_behind: Duration) -> SyncData {
+    let mut record = SyncData::default();
+    for i in 0..20 {
+        let name = format!("{}.txt", i);
+        write_file(root, &name, b"content");
+        let path = PathBuf::from(&name);
+        let info = FileInfo { path: path.clone(), hash: String::new(), modified: modified(&root.join(&name)) - recorded_behind, size: 7, verified: false };
+        record.files.insert(path, info);
+    }
+    record
+}
+
+#[test]
+fn a_whole_hour_offset_shared_by_the_files_is_found() {
+    let dir = TempDir::new();
+    let record = files_and_record(dir.path(), 8 * HOUR);
+
+    let offset = infer_mtime_offset(dir.path(), &record).expect("offset not found");
+    assert_eq!(offset.seconds, 8 * 3600);
+    assert_eq!(offset.label(), "+8:00");
+    assert_eq!((offset.matching, offset.sampled), (20, 20));
+
+    let shifted = shift_mtimes(&record, offset);
+    assert!(shifted.files.values().all(|info| info.modified == modified(&dir.path().join(&info.path))));
+}
+
+#[test]
+fn only_clear_whole_hour_offsets_count() {
+    let dir = TempDir::new();
+    // Matching mtimes need no compensation
+    assert_eq!(infer_mtime_offset(dir.path(), &files_and_record(dir.path(), Duration::ZERO)), None);
+    // Not a whole number of hours
+    assert_eq!(infer_mtime_offset(dir.path(), &files_and_record(dir.path(), HOUR + Duration::from_secs(1))), None);
+    // Further than any time zone
+    assert_eq!(infer_mtime_offset(dir.path(), &files_and_record(dir.path(), 15 * HOUR)), None);
+
+    // 17 of 20 is not more than 90%
+    let mut record = files_and_record(dir.path(), 8 * HOUR);
+    for i in 0..3 {
+        record.files.get_mut(Path::new(&format!("{}.txt", i))).unwrap().modified += 8 * HOUR;
+    }
+    assert_eq!(infer_mtime_offset(dir.path(), &record), None);
+
+    // Too few files to tell
+    let dir = TempDir::new();
+    let record = files_and_record(dir.path(), 8 * HOUR);
+    let record = SyncData { files: record.files.into_iter().take(9).collect(), ..Default::default() };
+    assert_eq!(infer_mtime_offset(dir.path(), &record), None);
+}
+
+fn synced_fixture() -> Fixture {
+    let files: Vec<(String, String)> = (0..20).map(|i| (format!("{}.txt", i), format!("file {}\n", i))).collect();
+    let tree: Vec<(&str, &[u8])> = files.iter().map(|(path, contents)| (path.as_str(), contents.as_bytes())).collect();
+    Fixture::synced(&tree)
+}
+
+#[test]
+fn a_time_zone_shift_keeps_the_mtime_shortcut_working() {
+    let fixture = synced_fixture();
+    for i in 0..20 {
+        shift_modified(&fixture.local.join(format!("{}.txt", i)), 8 * HOUR);
+    }
+
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+    let logs = observer.logs();
+    assert!(logs.iter().any(|line| line.contains("扫描本地: 检测到疑似时区偏移 +8:00，已自动补偿")), "{:#?}", logs);
+    assert!(!logs.iter().any(|line| line.contains("扫描本地: 重新校验")), "{:#?}", logs);
+
+    // The new record holds the shifted times, so the next run needs no compensation
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+    assert!(!observer.logs().iter().any(|line| line.contains("时区偏移")), "{:#?}", observer.logs());
+}
+
+#[test]
+fn other_shifts_are_hashed_again() {
+    let fixture = synced_fixture();
+    for i in 0..20 {
+        shift_modified(&fixture.local.join(format!("{}.txt", i)), 8 * HOUR + Duration::from_secs(5));
+    }
+
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+    let logs = observer.logs();
+    assert!(!logs.iter().any(|line| line.contains("时区偏移")), "{:#?}", logs);
+    assert!(logs.iter().any(|line| line.contains("扫描本地: 重新校验 20 个文件")), "{:#?}", logs);
+}
@@ -0,0 +1,87 @@
+//! Local names that differ only by trailing dots or spaces, which Windows and FAT drives store as one file.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+use syncu::utils::{collision_rename, name_collisions, strip_trailing_dots_and_spaces};
+
+#[test]
+fn trailing_dots_and_spaces_are_dropped_from_every_component() {
+    assert_eq!(strip_trailing_dots_and_spaces(Path::new("notes. /report.")), PathBuf::from("notes/report"));
+    assert_eq!(strip_trailing_dots_and_spaces(Path::new("a.txt. .")), PathBuf::from("a.txt"));
+    assert_eq!(strip_trailing_dots_and_spaces(Path::new("plain/name.md")), PathBuf::from("plain/name.md"));
+    assert_eq!(strip_trailing_dots_and_spaces(Path::new("...")), PathBuf::from("..."));
+}
+
+#[test]
+fn colliding_paths_are_grouped_with_the_plain_name_first() {
+    let paths: Vec<PathBuf> = ["report.", "report", "report ", "other.txt", "dir./a.txt", "dir/a.txt"].iter().map(PathBuf::from).collect();
+
+    let groups = name_collisions(paths.iter(), Path::to_path_buf);
+
+    assert_eq!(
+        groups,
+        vec![
+            vec![PathBuf::from("dir/a.txt"), PathBuf::from("dir./a.txt")],
+            vec![PathBuf::from("report"), PathBuf::from("report "), PathBuf::from("report.")],
+        ]
+    );
+}
+
+#[test]
+fn renamed_collisions_keep_their_extension() {
+    assert_eq!(collision_rename(Path::new("report."), 1), PathBuf::from("report (重名 1)"));
+    assert_eq!(collision_rename(Path::new("docs/a.txt "), 2), PathBuf::from("docs/a (重名 2).txt"));
+}
+
+// Only the extended-length syntax can create such names on Windows; the sync itself uses plain paths
+#[cfg(windows)]
+mod on_windows {
+    use super::common::{Fixture, ScriptedObserver};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use syncu::models::NameCollisionChoice;
+
+    fn write_verbatim(folder: &Path, name: &str, contents: &[u8]) {
+        fs::write(format!(r"\\?\{}\{}", folder.display(), name), contents).unwrap();
+    }
+
+    fn read_verbatim(folder: &Path, name: &str) -> Vec<u8> {
+        fs::read(format!(r"\\?\{}\{}", folder.display(), name)).unwrap()
+    }
+
+    #[test]
+    fn renamed_files_land_under_numbered_names_and_stay_there() {
+        let fixture = Fixture::new();
+        write_verbatim(&fixture.local, "report", b"plain\n");
+        write_verbatim(&fixture.local, "report.", b"trailing dot\n");
+        write_verbatim(&fixture.local, "report ", b"trailing space\n");
+
+        let observer = ScriptedObserver::new();
+        assert!(!fixture.sync(&observer));
+        assert!(observer.logs().iter().any(|line| line.contains("名称冲突: 2 个文件")), "{:#?}", observer.logs());
+        assert_eq!(fs::read(fixture.remote().join("report")).unwrap(), b"plain\n");
+        assert_eq!(fs::read(fixture.remote().join("report (重名 1)")).unwrap(), b"trailing space\n");
+        assert_eq!(fs::read(fixture.remote().join("report (重名 2)")).unwrap(), b"trailing dot\n");
+
+        // The numbered names are remembered: no question, no copies back to the local folder
+        let next = ScriptedObserver::new().with_name_collision_choice(NameCollisionChoice::Abort);
+        assert!(!fixture.sync(&next));
+        assert!(next.logs().iter().any(|line| line == "未检测到变化."), "{:#?}", next.logs());
+        assert!(!fixture.local.join("report (重名 1)").exists());
+        assert_eq!(read_verbatim(&fixture.local, "report."), b"trailing dot\n");
+    }
+
+    #[test]
+    fn skipped_files_are_left_out_until_renamed_locally() {
+        let fixture = Fixture::new();
+        write_verbatim(&fixture.local, "notes.txt", b"plain\n");
+        write_verbatim(&fixture.local, "notes.txt.", b"trailing dot\n");
+
+        let observer = ScriptedObserver::new().with_name_collision_choice(NameCollisionChoice::Skip);
+        assert!(!fixture.sync(&observer));
+        assert_eq!(fs::read(fixture.remote().join("notes.txt")).unwrap(), b"plain\n");
+        assert_eq!(fs::read_dir(fixture.remote()).unwrap().filter(|entry| !entry.as_ref().unwrap().file_name().to_string_lossy().starts_with(".syncu")).count(), 1);
+        assert!(!fixture.metadata().files.contains_key(&PathBuf::from("notes.txt.")));
+    }
+}
@@ -0,0 +1,89 @@
+//! A sync folder on the drive nested inside another one, as when `E:\` is chosen for a folder named `Backup` and
+//! `E:\Backup` later for another folder: each sync must leave the other's files and record alone.
+
+mod common;
+
+use common::{write_file, Fixture, ScriptedObserver, TempDir};
+use std::fs;
+use std::path::{Path, PathBuf};
+use syncu::settings::Profile;
+use syncu::sync::run_sync;
+use syncu::utils::metadata_path;
+
+// The inner pair: a local folder "Data" whose sync folder ends up inside the fixture's
+struct Inner {
+    _root: TempDir,
+    local: PathBuf,
+}
+
+impl Inner {
+    fn new() -> Self {
+        let root = TempDir::new();
+        let local = root.path().join("Data");
+        write_file(&local, "b.txt", b"inner\n");
+        Self { _root: root, local }
+    }
+
+    fn sync(&self, fixture: &Fixture) -> ScriptedObserver {
+        let observer = ScriptedObserver::new();
+        let profile = Profile { local_folder: self.local.clone(), ..Default::default() };
+        run_sync(Some(self.local.clone()), Some(fixture.remote()), profile, false, &observer);
+        assert!(!observer.logs().iter().any(|line| line.starts_with("错误")), "{:#?}", observer.logs());
+        observer
+    }
+}
+
+fn record(root: &Path) -> Vec<u8> {
+    fs::read(metadata_path(root)).unwrap()
+}
+
+fn assert_outer_untouched_by_inner(fixture: &Fixture) {
+    assert!(!fixture.local.join("Data").exists());
+    assert!(!fixture.metadata().files.keys().any(|path| path.starts_with("Data")), "{:#?}", fixture.metadata().files);
+    assert!(!fixture.metadata().directories.iter().any(|path| path.starts_with("Data")));
+}
+
+#[test]
+fn syncing_both_in_turn_keeps_each_record_to_its_own_files() {
+    let fixture = Fixture::new();
+    write_file(&fixture.local, "a.txt", b"outer\n");
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    let inner = Inner::new();
+    inner.sync(&fixture);
+    let inner_root = fixture.remote().join("Data");
+    let inner_record = record(&inner_root);
+
+    let outer = ScriptedObserver::new();
+    assert!(!fixture.sync(&outer));
+    assert!(outer.logs().iter().any(|line| line.contains("已跳过嵌套的 SyncU 同步目录")), "{:#?}", outer.logs());
+    assert_outer_untouched_by_inner(&fixture);
+    assert_eq!(record(&inner_root), inner_record);
+    assert_eq!(fs::read(inner_root.join("b.txt")).unwrap(), b"inner\n");
+
+    // And the other way round
+    let outer_record = record(&fixture.remote());
+    let again = inner.sync(&fixture);
+    assert!(again.logs().iter().any(|line| line == "未检测到变化."), "{:#?}", again.logs());
+    assert_eq!(record(&fixture.remote()), outer_record);
+    assert_eq!(fs::read(fixture.remote().join("a.txt")).unwrap(), b"outer\n");
+}
+
+#[test]
+fn an_outer_folder_synced_after_the_inner_one_leaves_it_alone() {
+    let fixture = Fixture::new();
+    // The inner pair's drive is the outer sync folder, which must be there for it to sync into
+    fs::create_dir_all(fixture.remote()).unwrap();
+    let inner = Inner::new();
+    inner.sync(&fixture);
+    let inner_root = fixture.remote().join("Data");
+    let inner_record = record(&inner_root);
+
+    write_file(&fixture.local, "a.txt", b"outer\n");
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    assert_outer_untouched_by_inner(&fixture);
+    assert_eq!(record(&inner_root), inner_record);
+
+    let again = inner.sync(&fixture);
+    assert!(again.logs().iter().any(|line| line == "未检测到变化."), "{:#?}", again.logs());
+    assert_eq!(fs::read(inner.local.join("b.txt")).unwrap(), b"inner\n");
+}
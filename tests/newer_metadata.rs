@@ -0,0 +1,63 @@
+//! Records written by another version of SyncU.
+
+mod common;
+
+use common::{write_tree, Fixture, ScriptedObserver};
+use std::fs;
+use syncu::models::{RunOutcome, SYNCU_VERSION};
+use syncu::utils::metadata_path;
+
+#[test]
+fn a_saved_record_names_the_version_that_wrote_it() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+
+    assert_eq!(fixture.metadata().written_by.as_deref(), Some(SYNCU_VERSION));
+}
+
+// A synced folder whose record then claims a future version, with a field of its own and one inside every file entry
+fn with_newer_record(fixture: &Fixture) -> Vec<u8> {
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+
+    let path = metadata_path(&fixture.remote());
+    let mut record: serde_json::Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+    record["written_by"] = "99.0.0".into();
+    record["future_field"] = serde_json::json!({ "kept": true });
+    for info in record["files"].as_object_mut().unwrap().values_mut() {
+        info["future_flag"] = true.into();
+    }
+    let newer = serde_json::to_vec_pretty(&record).unwrap();
+    fs::write(&path, &newer).unwrap();
+    newer
+}
+
+#[test]
+fn a_record_from_a_newer_version_is_left_untouched() {
+    let fixture = Fixture::new();
+    let newer = with_newer_record(&fixture);
+
+    // Loading still works, but the run refuses to save over what it doesn't understand
+    assert_eq!(fixture.metadata().written_by.as_deref(), Some("99.0.0"));
+    write_tree(&fixture.local, &[("b.txt", b"bravo\n")]);
+    let observer = ScriptedObserver::new();
+    assert_eq!(fixture.run(&observer), RunOutcome::Completed);
+
+    assert!(observer.logs().iter().any(|line| line.starts_with("错误") && line.contains("99.0.0")));
+    assert_eq!(fs::read(metadata_path(&fixture.remote())).unwrap(), newer);
+    assert!(!fixture.remote().join("b.txt").exists());
+}
+
+#[test]
+fn the_files_can_be_synced_without_updating_a_newer_record() {
+    let fixture = Fixture::new();
+    let newer = with_newer_record(&fixture);
+    write_tree(&fixture.local, &[("b.txt", b"bravo\n")]);
+
+    let observer = ScriptedObserver::new().syncing_past_newer_metadata();
+    assert!(!fixture.sync(&observer));
+    assert!(observer.logs().iter().any(|line| line.starts_with("警告") && line.contains("99.0.0")), "{:#?}", observer.logs());
+    assert_eq!(fs::read(fixture.remote().join("b.txt")).unwrap(), b"bravo\n");
+    assert_eq!(fs::read(metadata_path(&fixture.remote())).unwrap(), newer);
+}
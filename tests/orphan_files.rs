@@ -0,0 +1,40 @@
+//! The leftover-files report: files on the USB drive that syncing no longer updates or deletes.
+
+mod common;
+
+use common::{write_file, write_tree, Fixture, ScriptedObserver};
+use std::fs;
+use std::path::PathBuf;
+use syncu::observer::DeletionDecision;
+use syncu::settings::DeviceLogVerbosity;
+use syncu::sync::{find_orphan_files, move_orphans_to_trash, OrphanKind};
+use syncu::utils::{trash_path, TEMP_FILE_SUFFIX};
+
+#[test]
+fn kept_and_temporary_files_are_found_and_moved_to_the_trash() {
+    let fixture = Fixture::new();
+    write_tree(
+        &fixture.local,
+        &[("a.txt", b"alpha\n"), ("b.txt", b"bravo\n"), ("c.txt", b"charlie\n"), ("notes/d.md", b"# delta\n"), ("notes/e.md", b"# echo\n")],
+    );
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    fs::remove_file(fixture.local.join("b.txt")).unwrap();
+    assert!(!fixture.sync(&ScriptedObserver::new().with_deletion_decision(DeletionDecision::KeepPermanently)));
+    let temp_name = format!("notes/d.md{}", TEMP_FILE_SUFFIX);
+    write_file(&fixture.remote(), &temp_name, b"partial");
+
+    let orphans = find_orphan_files(&fixture.local, &fixture.usb).unwrap();
+    let found: Vec<(PathBuf, OrphanKind)> = orphans.iter().map(|orphan| (orphan.path.clone(), orphan.kind)).collect();
+    assert_eq!(found, [(PathBuf::from("b.txt"), OrphanKind::KeptAfterDeletion), (PathBuf::from(&temp_name), OrphanKind::LeftoverTemp)]);
+
+    let moved = move_orphans_to_trash(&fixture.local, &fixture.usb, &orphans, DeviceLogVerbosity::Full, &ScriptedObserver::new()).unwrap();
+    assert_eq!(moved, 2);
+    assert!(!fixture.remote().join("b.txt").exists());
+    assert!(!fixture.remote().join(&temp_name).exists());
+    assert!(fixture.remote().join("notes/d.md").is_file());
+    let batches: Vec<PathBuf> = fs::read_dir(trash_path(&fixture.remote())).unwrap().map(|entry| entry.unwrap().path()).collect();
+    assert_eq!(batches.len(), 1);
+    assert_eq!(fs::read(batches[0].join("b.txt")).unwrap(), b"bravo\n");
+    assert_eq!(fs::read(batches[0].join(&temp_name)).unwrap(), b"partial");
+    assert!(find_orphan_files(&fixture.local, &fixture.usb).unwrap().is_empty());
+}
@@ -0,0 +1,58 @@
+//! The quick check of a picked local folder for folders that can't be opened.
+
+mod common;
+
+use common::{write_tree, TempDir};
+use syncu::utils::{probe_folder_permissions, PermissionProbe};
+
+#[test]
+fn a_readable_folder_passes() {
+    let dir = TempDir::new();
+    write_tree(dir.path(), &[("a/1.txt", b"1"), ("b/2.txt", b"2"), ("c.txt", b"3")]);
+    let probe = probe_folder_permissions(dir.path());
+
+    assert_eq!(probe.probed, 3);
+    assert!(probe.inaccessible.is_empty());
+    assert!(!probe.is_significant());
+}
+
+#[test]
+fn a_missing_folder_is_inaccessible() {
+    let dir = TempDir::new();
+    let missing = dir.path().join("missing");
+    let probe = probe_folder_permissions(&missing);
+
+    assert_eq!(probe.inaccessible, vec![missing]);
+    assert!(probe.is_significant());
+}
+
+#[test]
+fn only_a_noticeable_share_of_unreadable_folders_is_significant() {
+    let few = PermissionProbe { probed: 51, inaccessible: vec!["a".into()] };
+    assert!(!few.is_significant());
+    let many = PermissionProbe { probed: 11, inaccessible: vec!["a".into(), "b".into()] };
+    assert!(many.is_significant());
+}
+
+#[cfg(unix)]
+#[test]
+fn unreadable_subfolders_are_listed() {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = TempDir::new();
+    write_tree(dir.path(), &[("open/1.txt", b"1"), ("locked/2.txt", b"2")]);
+    let locked = dir.path().join("locked");
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+    // Root ignores permissions, so there is nothing to observe
+    let readable_anyway = fs::read_dir(&locked).is_ok();
+    let probe = probe_folder_permissions(dir.path());
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+    if readable_anyway {
+        return;
+    }
+
+    assert_eq!(probe.probed, 3);
+    assert_eq!(probe.inaccessible, vec![locked]);
+    assert!(probe.is_significant());
+}
@@ -0,0 +1,97 @@
+//! Long plans kept on the drive while they run, and continuing them after an interruption.
+
+mod common;
+
+use common::{assert_in_sync, write_file, Fixture, ScriptedObserver};
+use std::fs;
+use syncu::utils::{load_plan_checkpoint, plan_path};
+
+// Enough files for the plan to be kept on the drive
+const FILE_COUNT: usize = 600;
+
+fn many_files(fixture: &Fixture) {
+    for n in 0..FILE_COUNT {
+        write_file(&fixture.local, &format!("f{:04}.txt", n), format!("file {}\n", n).as_bytes());
+    }
+}
+
+fn interrupted(fixture: &Fixture) -> usize {
+    assert!(fixture.sync(&ScriptedObserver::new().stopping_after(200)));
+    let checkpoint = load_plan_checkpoint(&plan_path(&fixture.remote())).unwrap().expect("plan kept on the drive");
+    checkpoint.remaining()
+}
+
+#[test]
+fn an_interrupted_plan_is_continued_and_removed_once_done() {
+    let fixture = Fixture::new();
+    many_files(&fixture);
+    let remaining = interrupted(&fixture);
+    assert!(remaining > 0 && remaining < FILE_COUNT, "{}", remaining);
+
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+    assert_eq!(observer.resumes_asked(), 1);
+    assert!(observer.logs().iter().any(|line| line.contains("继续上次未完成的同步")), "{:#?}", observer.logs());
+    assert!(!plan_path(&fixture.remote()).exists());
+    assert_in_sync(&fixture);
+}
+
+#[test]
+fn a_plan_whose_pending_sources_changed_is_planned_again() {
+    let fixture = Fixture::new();
+    many_files(&fixture);
+    interrupted(&fixture);
+    // Files are copied in name order, so the last one is still pending
+    write_file(&fixture.local, &format!("f{:04}.txt", FILE_COUNT - 1), b"changed since the plan was made\n");
+
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+    assert_eq!(observer.resumes_asked(), 0);
+    assert!(observer.logs().iter().any(|line| line.contains("上次未完成的同步计划已失效")), "{:#?}", observer.logs());
+    assert_in_sync(&fixture);
+    assert_eq!(fs::read(fixture.remote().join(format!("f{:04}.txt", FILE_COUNT - 1))).unwrap(), b"changed since the plan was made\n");
+}
+
+#[test]
+fn a_plan_whose_pending_targets_changed_is_planned_again() {
+    let fixture = Fixture::new();
+    many_files(&fixture);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    // Every file is edited locally, so the plan copies each one over its USB copy
+    for n in 0..FILE_COUNT {
+        write_file(&fixture.local, &format!("f{:04}.txt", n), format!("file {} edited\n", n).as_bytes());
+    }
+    interrupted(&fixture);
+    // The stick was taken to another computer and the copy of a still pending file edited there
+    let last = format!("f{:04}.txt", FILE_COUNT - 1);
+    write_file(&fixture.remote(), &last, b"edited on another computer\n");
+
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+    assert_eq!(observer.resumes_asked(), 0);
+    assert!(observer.logs().iter().any(|line| line.contains("上次未完成的同步计划已失效")), "{:#?}", observer.logs());
+    assert_eq!(observer.conflicts_asked(), 1);
+}
+
+#[test]
+fn starting_over_drops_the_plan() {
+    let fixture = Fixture::new();
+    many_files(&fixture);
+    interrupted(&fixture);
+
+    let observer = ScriptedObserver::new().with_resume_plan(false);
+    assert!(!fixture.sync(&observer));
+    assert_eq!(observer.resumes_asked(), 1);
+    assert!(observer.logs().iter().any(|line| line.contains("放弃上次未完成的同步计划")), "{:#?}", observer.logs());
+    assert!(!plan_path(&fixture.remote()).exists());
+    assert_in_sync(&fixture);
+}
+
+#[test]
+fn small_plans_are_not_kept() {
+    let fixture = Fixture::new();
+    write_file(&fixture.local, "a.txt", b"alpha\n");
+    write_file(&fixture.local, "b.txt", b"bravo\n");
+    assert!(fixture.sync(&ScriptedObserver::new().stopping_after(1)));
+    assert!(!plan_path(&fixture.remote()).exists());
+}
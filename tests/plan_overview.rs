@@ -0,0 +1,50 @@
+//! The plan a run reports before executing, and the per-action outcomes that refer back to it by index.
+
+mod common;
+
+use common::{write_tree, Fixture, ScriptedObserver};
+use std::collections::HashSet;
+use syncu::models::{ActionStatus, SyncAction};
+use syncu::observer::DeletionDecision;
+
+#[test]
+fn every_planned_action_is_reported_once_by_index() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n"), ("sub/b.txt", b"bravo\n"), ("sub/deep/c.txt", b"charlie\n")]);
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+
+    let plan = observer.plan().expect("plan reported");
+    assert!(plan.contains(&SyncAction::LocalToRemote("a.txt".into())), "{:#?}", plan);
+    let finished = observer.finished_actions();
+    assert_eq!(finished.len(), plan.len());
+    let indices: HashSet<usize> = finished.iter().map(|(index, _)| *index).collect();
+    assert_eq!(indices, (0..plan.len()).collect());
+    assert!(finished.iter().all(|(_, status)| *status == ActionStatus::Done), "{:#?}", finished);
+}
+
+#[test]
+fn an_unchanged_folder_reports_an_empty_plan() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+    assert_eq!(observer.plan(), Some(Vec::new()));
+    assert!(observer.finished_actions().is_empty());
+}
+
+#[test]
+fn declined_deletions_are_reported_as_skipped() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n"), ("b.txt", b"bravo\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    std::fs::remove_file(fixture.local.join("b.txt")).unwrap();
+
+    let observer = ScriptedObserver::new().with_deletion_decision(DeletionDecision::Keep);
+    fixture.run(&observer);
+    let plan = observer.plan().unwrap();
+    let index = plan.iter().position(|action| *action == SyncAction::DeleteRemote("b.txt".into())).unwrap();
+    assert_eq!(observer.finished_actions(), vec![(index, ActionStatus::Skipped)]);
+}
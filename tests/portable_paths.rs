@@ -0,0 +1,98 @@
+//! Sync records written on Windows (`\`) and on Linux or macOS (`/`) must read the same on either.
+
+mod common;
+
+use common::{write_tree, Fixture, ScriptedObserver};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+use syncu::models::{FileInfo, Observation, SkippedConflict, SyncData};
+use syncu::utils::{load_sync_data, metadata_path, save_sync_data};
+
+fn nested(parts: &[&str]) -> PathBuf {
+    parts.iter().collect()
+}
+
+fn sample_data() -> SyncData {
+    let file = |parts: &[&str]| {
+        let path = nested(parts);
+        (path.clone(), FileInfo { path, hash: "abc".to_owned(), modified: UNIX_EPOCH + Duration::from_secs(1_700_000_000), size: 3, verified: false })
+    };
+    let files: HashMap<PathBuf, FileInfo> = [file(&["a.txt"]), file(&["photos", "2024", "b.jpg"])].into_iter().collect();
+    SyncData {
+        directories: HashSet::from([nested(&["photos"]), nested(&["photos", "2024"])]),
+        routes: HashMap::from([(nested(&["photos", "2024", "b.jpg"]), nested(&["archive", "b.jpg"]))]),
+        tombstones: HashMap::from([(nested(&["old", "c.txt"]), "def".to_owned())]),
+        skipped_conflicts: HashMap::from([(
+            nested(&["notes", "d.md"]),
+            SkippedConflict { local_hash: "1".to_owned(), remote_hash: "2".to_owned() },
+        )]),
+        observations: HashMap::from([(
+            "laptop".to_owned(),
+            files.iter().map(|(path, info)| (path.clone(), Observation { modified: info.modified, size: info.size })).collect(),
+        )]),
+        files,
+        ..Default::default()
+    }
+}
+
+fn assert_same_paths(actual: &SyncData, expected: &SyncData) {
+    let mut actual_files: Vec<_> = actual.files.iter().map(|(key, info)| (key.clone(), info.path.clone())).collect();
+    let mut expected_files: Vec<_> = expected.files.iter().map(|(key, info)| (key.clone(), info.path.clone())).collect();
+    actual_files.sort();
+    expected_files.sort();
+    assert_eq!(actual_files, expected_files);
+    assert_eq!(actual.directories, expected.directories);
+    assert_eq!(actual.routes, expected.routes);
+    assert_eq!(actual.tombstones.keys().collect::<HashSet<_>>(), expected.tombstones.keys().collect::<HashSet<_>>());
+    assert_eq!(actual.skipped_conflicts, expected.skipped_conflicts);
+    assert_eq!(actual.observations["laptop"].keys().collect::<HashSet<_>>(), expected.observations["laptop"].keys().collect::<HashSet<_>>());
+}
+
+#[test]
+fn saved_records_use_forward_slashes_and_read_back() {
+    let fixture = Fixture::new();
+    let path = fixture.local.join("metadata.json");
+    let data = sample_data();
+    save_sync_data(&data, &path).unwrap();
+
+    let json = fs::read_to_string(&path).unwrap();
+    assert!(json.contains("\"photos/2024/b.jpg\""), "{}", json);
+    assert!(!json.contains("\\\\"), "{}", json);
+    assert_same_paths(&load_sync_data(&path).unwrap(), &data);
+}
+
+#[test]
+fn records_written_with_backslashes_read_as_native_paths() {
+    let fixture = Fixture::new();
+    let path = fixture.local.join("metadata.json");
+    let data = sample_data();
+    save_sync_data(&data, &path).unwrap();
+    // The same record as a Windows build before this change would have written it
+    let windows_json = fs::read_to_string(&path).unwrap().replace('/', "\\\\");
+    fs::write(&path, windows_json).unwrap();
+
+    let loaded = load_sync_data(&path).unwrap();
+    assert_same_paths(&loaded, &data);
+
+    // Saving again migrates the record to forward slashes
+    save_sync_data(&loaded, &path).unwrap();
+    assert!(!fs::read_to_string(&path).unwrap().contains("\\\\"));
+}
+
+#[test]
+fn stick_synced_on_windows_is_not_seen_as_all_new() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n"), ("notes/deep/b.md", b"# bravo\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    let metadata = metadata_path(&fixture.remote());
+    let windows_json = fs::read_to_string(&metadata).unwrap().replace("notes/deep/b.md", "notes\\\\deep\\\\b.md").replace("notes/deep", "notes\\\\deep");
+    fs::write(&metadata, windows_json).unwrap();
+
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+    assert_eq!(observer.conflicts_asked(), 0);
+    assert!(fixture.metadata().files.contains_key(&nested(&["notes", "deep", "b.md"])));
+    assert!(!fs::read_to_string(&metadata).unwrap().contains("\\\\"));
+}
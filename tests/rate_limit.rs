@@ -0,0 +1,56 @@
+//! Copy speed limits: runs take as long as the limit requires, and no limit costs nothing.
+
+mod common;
+
+use common::{assert_in_sync, content, write_file, Fixture, ScriptedObserver};
+use std::time::{Duration, Instant};
+use syncu::utils::RateLimiter;
+
+const MB: u64 = 1024 * 1024;
+
+#[test]
+fn limited_run_takes_as_long_as_the_limit_requires() {
+    let fixture = Fixture::new();
+    // One small file and one above the large file threshold, so both copy paths are paced
+    write_file(&fixture.local, "small.bin", &content(1, 2 * MB as usize));
+    write_file(&fixture.local, "large.bin", &content(2, 11 * MB as usize));
+
+    let started = Instant::now();
+    assert!(!fixture.sync(&ScriptedObserver::new().with_rate_limit(8 * MB)));
+    // 13 MB at 8 MB/s, less the half second burst allowed up front
+    assert!(started.elapsed() >= Duration::from_secs(1), "took {:?}", started.elapsed());
+    assert_in_sync(&fixture);
+}
+
+#[test]
+fn pacing_without_a_limit_never_waits() {
+    let limiter = RateLimiter::default();
+    let observer = ScriptedObserver::new();
+    let started = Instant::now();
+    for _ in 0..1000 {
+        assert!(!limiter.pace(64 * MB, &observer));
+    }
+    assert!(started.elapsed() < Duration::from_millis(100));
+}
+
+#[test]
+fn pacing_allows_a_short_burst_then_waits() {
+    let limiter = RateLimiter::default();
+    let observer = ScriptedObserver::new().with_rate_limit(4 * MB);
+    let started = Instant::now();
+    // Half a second's worth goes out at once
+    assert!(!limiter.pace(2 * MB, &observer));
+    assert!(started.elapsed() < Duration::from_millis(100));
+    // The next megabyte waits for a quarter of a second
+    assert!(!limiter.pace(MB, &observer));
+    assert!(started.elapsed() >= Duration::from_millis(200), "took {:?}", started.elapsed());
+}
+
+#[test]
+fn stopping_interrupts_the_wait() {
+    let limiter = RateLimiter::default();
+    let observer = ScriptedObserver::new().with_rate_limit(MB).stopping_after(0);
+    let started = Instant::now();
+    assert!(limiter.pace(10 * MB, &observer));
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
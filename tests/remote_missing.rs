@@ -0,0 +1,72 @@
+//! The safety check asking what to do when many synced files vanished from the USB drive outside SyncU.
+
+mod common;
+
+use common::{assert_in_sync, read_tree, write_file, Fixture, ScriptedObserver};
+use std::fs;
+use syncu::models::{RemoteMissingChoice, RunOutcome};
+
+const FILES: usize = 10;
+
+fn name(n: usize) -> String {
+    format!("f{}.txt", n)
+}
+
+// Synced files, of which `removed` are then deleted from the USB drive directly
+fn with_files_gone_from_usb(removed: usize) -> Fixture {
+    let fixture = Fixture::new();
+    for n in 0..FILES {
+        write_file(&fixture.local, &name(n), format!("file {}\n", n).as_bytes());
+    }
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    for n in 0..removed {
+        fs::remove_file(fixture.remote().join(name(n))).unwrap();
+    }
+    fixture
+}
+
+fn local_files(fixture: &Fixture) -> usize {
+    read_tree(&fixture.local).files.len()
+}
+
+#[test]
+fn a_few_missing_files_are_not_asked_about() {
+    // One in ten stays below the default threshold of 20%
+    let fixture = with_files_gone_from_usb(1);
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+    assert!(observer.remote_missing_asked().is_empty());
+}
+
+#[test]
+fn recopying_puts_the_missing_files_back_on_the_usb() {
+    let fixture = with_files_gone_from_usb(5);
+    let observer = ScriptedObserver::new().with_remote_missing_choice(RemoteMissingChoice::Recopy);
+    assert!(!fixture.sync(&observer));
+    assert_eq!(observer.remote_missing_asked(), vec![(5, FILES)]);
+    assert_eq!(local_files(&fixture), FILES);
+    assert_in_sync(&fixture);
+}
+
+#[test]
+fn deleting_locally_follows_the_usb() {
+    let fixture = with_files_gone_from_usb(5);
+    let observer = ScriptedObserver::new().with_remote_missing_choice(RemoteMissingChoice::DeleteLocal);
+    assert!(!fixture.sync(&observer));
+    assert_eq!(observer.remote_missing_asked(), vec![(5, FILES)]);
+    assert_eq!(local_files(&fixture), FILES - 5);
+    assert!(!fixture.local.join(name(0)).exists());
+    assert_in_sync(&fixture);
+}
+
+#[test]
+fn aborting_changes_nothing() {
+    let fixture = with_files_gone_from_usb(5);
+    let record = fixture.metadata();
+    let observer = ScriptedObserver::new().with_remote_missing_choice(RemoteMissingChoice::Abort);
+    assert_eq!(fixture.run(&observer), RunOutcome::Stopped);
+    assert_eq!(observer.remote_missing_asked(), vec![(5, FILES)]);
+    assert_eq!(local_files(&fixture), FILES);
+    assert_eq!(read_tree(&fixture.remote()).files.len(), FILES - 5);
+    assert!(fixture.metadata().same_state(&record));
+}
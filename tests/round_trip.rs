@@ -0,0 +1,234 @@
+//! End-to-end runs of `run_sync` between a temporary local folder and a fake USB drive.
+
+mod common;
+
+use common::{assert_in_sync, content, read_tree, write_file, write_tree, Fixture, ScriptedObserver};
+use std::fs;
+use std::path::PathBuf;
+use syncu::models::{Resolution, SyncData};
+use syncu::observer::DeletionDecision;
+use syncu::utils::{scan_directory_with_progress, HashStrategy};
+
+// Enough files that a single missing one stays below the default safety check threshold.
+const BASE_TREE: &[(&str, &[u8])] = &[
+    ("a.txt", b"alpha\n"),
+    ("b.txt", b"bravo\n"),
+    ("c.txt", b"charlie\n"),
+    ("notes/d.md", b"# delta\n"),
+    ("notes/e.md", b"# echo\n"),
+    ("notes/deep/f.bin", &[6; 4096]),
+];
+
+#[test]
+fn initial_sync_copies_everything_and_records_metadata() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, BASE_TREE);
+    // Above the large file threshold, so the chunked copy path is used
+    write_file(&fixture.local, "media/large.bin", &content(7, 11 * 1024 * 1024));
+
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    assert_in_sync(&fixture);
+
+    let tree = read_tree(&fixture.local);
+    let metadata = fixture.metadata();
+    assert_eq!(metadata.files.len(), tree.files.len());
+    for (path, contents) in &tree.files {
+        let info = metadata.files.get(path).unwrap_or_else(|| panic!("{} missing from metadata", path.display()));
+        assert_eq!(info.size, contents.len() as u64, "recorded size of {}", path.display());
+    }
+    for dir in &tree.directories {
+        assert!(metadata.directories.contains(dir), "{} missing from metadata", dir.display());
+    }
+    assert_eq!(metadata.source_folder.as_ref(), Some(&fixture.local));
+}
+
+#[test]
+fn second_run_without_changes_is_a_no_op() {
+    let fixture = Fixture::synced(BASE_TREE);
+    let before = fixture.metadata();
+
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    assert_in_sync(&fixture);
+    let after = fixture.metadata();
+    assert_eq!(before.files.len(), after.files.len());
+    for (path, info) in &before.files {
+        assert_eq!(after.files[path].hash, info.hash, "hash of {} changed", path.display());
+    }
+}
+
+#[test]
+fn modifications_propagate_in_both_directions() {
+    let fixture = Fixture::synced(BASE_TREE);
+
+    write_file(&fixture.local, "a.txt", b"alpha, edited locally\n");
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    assert_eq!(fs::read(fixture.remote().join("a.txt")).unwrap(), b"alpha, edited locally\n");
+
+    write_file(&fixture.remote(), "notes/d.md", b"# delta, edited on the stick\n");
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    assert_eq!(fs::read(fixture.local.join("notes/d.md")).unwrap(), b"# delta, edited on the stick\n");
+    assert_in_sync(&fixture);
+}
+
+#[test]
+fn recorded_hashes_match_a_fresh_scan() {
+    let fixture = Fixture::synced(BASE_TREE);
+    write_file(&fixture.local, "a.txt", b"alpha, edited locally\n");
+    write_file(&fixture.remote(), "b.txt", b"bravo, edited on the stick\n");
+    write_file(&fixture.remote(), "notes/g.md", b"# golf\n");
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+
+    let fresh = scan_directory_with_progress(&fixture.local, &ScriptedObserver::new(), None, "校验", &SyncData::default(), HashStrategy::Full)
+        .unwrap()
+        .unwrap();
+    let metadata = fixture.metadata();
+    assert_eq!(metadata.files.len(), fresh.files.len());
+    for (path, info) in &fresh.files {
+        assert_eq!(metadata.files[path].hash, info.hash, "recorded hash of {}", path.display());
+    }
+}
+
+#[test]
+fn deletions_propagate_in_both_directions() {
+    let fixture = Fixture::synced(BASE_TREE);
+
+    fs::remove_file(fixture.local.join("a.txt")).unwrap();
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    assert!(!fixture.remote().join("a.txt").exists());
+
+    fs::remove_file(fixture.remote().join("notes/e.md")).unwrap();
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    assert!(!fixture.local.join("notes/e.md").exists());
+
+    assert_in_sync(&fixture);
+    let metadata = fixture.metadata();
+    assert!(!metadata.files.contains_key(&PathBuf::from("a.txt")));
+    assert!(!metadata.files.contains_key(&PathBuf::from("notes/e.md")));
+}
+
+#[test]
+fn permanently_kept_file_is_not_asked_about_again() {
+    let fixture = Fixture::synced(BASE_TREE);
+
+    fs::remove_file(fixture.local.join("b.txt")).unwrap();
+    let observer = ScriptedObserver::new().with_deletion_decision(DeletionDecision::KeepPermanently);
+    assert!(!fixture.sync(&observer));
+    assert!(fixture.remote().join("b.txt").exists());
+    assert!(fixture.metadata().tombstones.contains_key(&PathBuf::from("b.txt")));
+
+    // A later run that would delete it leaves it alone, and doesn't copy it back either
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    assert!(fixture.remote().join("b.txt").exists());
+    assert!(!fixture.local.join("b.txt").exists());
+}
+
+#[test]
+fn conflicting_edits_follow_the_chosen_resolution() {
+    let fixture = Fixture::synced(BASE_TREE);
+
+    write_file(&fixture.local, "a.txt", b"alpha from the laptop\n");
+    write_file(&fixture.remote(), "a.txt", b"alpha from another computer\n");
+    assert!(!fixture.sync(&ScriptedObserver::new().with_conflict_resolution(Resolution::KeepLocal)));
+    assert_eq!(fs::read(fixture.remote().join("a.txt")).unwrap(), b"alpha from the laptop\n");
+
+    write_file(&fixture.local, "c.txt", b"charlie from the laptop\n");
+    write_file(&fixture.remote(), "c.txt", b"charlie from another computer\n");
+    assert!(!fixture.sync(&ScriptedObserver::new().with_conflict_resolution(Resolution::KeepRemote)));
+    assert_eq!(fs::read(fixture.local.join("c.txt")).unwrap(), b"charlie from another computer\n");
+    assert_in_sync(&fixture);
+
+    // A skipped conflict leaves both sides as they were
+    write_file(&fixture.local, "b.txt", b"bravo from the laptop\n");
+    write_file(&fixture.remote(), "b.txt", b"bravo from another computer\n");
+    assert!(!fixture.sync(&ScriptedObserver::new().with_conflict_resolution(Resolution::Skip)));
+    assert_eq!(fs::read(fixture.local.join("b.txt")).unwrap(), b"bravo from the laptop\n");
+    assert_eq!(fs::read(fixture.remote().join("b.txt")).unwrap(), b"bravo from another computer\n");
+}
+
+#[test]
+fn identical_edits_on_both_sides_are_not_a_conflict() {
+    let fixture = Fixture::synced(BASE_TREE);
+
+    write_file(&fixture.local, "a.txt", b"alpha, same edit everywhere\n");
+    write_file(&fixture.remote(), "a.txt", b"alpha, same edit everywhere\n");
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+
+    assert_eq!(observer.conflicts_asked(), 0);
+    assert!(observer.logs().iter().any(|line| line.contains("双方修改一致")));
+    assert_in_sync(&fixture);
+    let recorded = fixture.metadata().files[&PathBuf::from("a.txt")].hash.clone();
+    let next = ScriptedObserver::new();
+    assert!(!fixture.sync(&next));
+    assert_eq!(next.conflicts_asked(), 0);
+    assert_eq!(fixture.metadata().files[&PathBuf::from("a.txt")].hash, recorded);
+}
+
+#[test]
+fn directories_are_created_and_deleted() {
+    let fixture = Fixture::synced(BASE_TREE);
+
+    fs::create_dir_all(fixture.local.join("empty/nested")).unwrap();
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    assert!(fixture.remote().join("empty/nested").is_dir());
+
+    fs::remove_dir_all(fixture.remote().join("empty")).unwrap();
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    assert!(!fixture.local.join("empty").exists());
+
+    fs::remove_dir_all(fixture.local.join("notes")).unwrap();
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    assert!(!fixture.remote().join("notes").exists());
+    assert_in_sync(&fixture);
+}
+
+#[test]
+fn stopped_run_converges_on_the_next_run() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, BASE_TREE);
+    for index in 0..10 {
+        write_file(&fixture.local, &format!("batch/{}.txt", index), &content(index, 100 + index as usize));
+    }
+    write_file(&fixture.local, "media/large.bin", &content(9, 11 * 1024 * 1024));
+
+    assert!(fixture.sync(&ScriptedObserver::new().stopping_after(3)));
+    // A stopped run never writes partial copies under their real names
+    for (path, contents) in read_tree(&fixture.remote()).files {
+        assert_eq!(fs::read(fixture.local.join(&path)).unwrap(), contents, "partial copy of {}", path.display());
+    }
+
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+    assert!(!observer.logs().is_empty());
+    assert_in_sync(&fixture);
+    assert_eq!(fixture.metadata().files.len(), read_tree(&fixture.local).files.len());
+}
+
+#[test]
+fn skipped_conflict_is_not_raised_again_until_a_side_changes() {
+    let fixture = Fixture::synced(BASE_TREE);
+    write_file(&fixture.local, "a.txt", b"alpha from the laptop\n");
+    write_file(&fixture.remote(), "a.txt", b"alpha from another computer\n");
+    let skipping = ScriptedObserver::new().with_conflict_resolution(Resolution::Skip);
+    assert!(!fixture.sync(&skipping));
+    assert_eq!(skipping.conflicts_asked(), 1);
+    assert!(fixture.metadata().skipped_conflicts.contains_key(&PathBuf::from("a.txt")));
+
+    // Nothing changed since the skip
+    let rerun = ScriptedObserver::new();
+    assert!(!fixture.sync(&rerun));
+    assert_eq!(rerun.conflicts_asked(), 0);
+    assert!(rerun.logs().iter().any(|line| line.contains("冲突先前已跳过，双方均未再修改: a.txt")));
+    assert_eq!(fs::read(fixture.local.join("a.txt")).unwrap(), b"alpha from the laptop\n");
+    assert_eq!(fs::read(fixture.remote().join("a.txt")).unwrap(), b"alpha from another computer\n");
+    assert!(!fixture.metadata().files.contains_key(&PathBuf::from("a.txt")));
+
+    // One side changes: the conflict comes back and can be resolved
+    write_file(&fixture.remote(), "a.txt", b"alpha from another computer, edited again\n");
+    let changed = ScriptedObserver::new().with_conflict_resolution(Resolution::KeepRemote);
+    assert!(!fixture.sync(&changed));
+    assert_eq!(changed.conflicts_asked(), 1);
+    assert_eq!(fs::read(fixture.local.join("a.txt")).unwrap(), b"alpha from another computer, edited again\n");
+    assert!(fixture.metadata().skipped_conflicts.is_empty());
+    assert_in_sync(&fixture);
+}
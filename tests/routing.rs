@@ -0,0 +1,73 @@
+//! Routing rules placing files in another folder on the USB drive than locally.
+
+mod common;
+
+use common::{write_file, Fixture, ScriptedObserver};
+use std::path::PathBuf;
+use syncu::models::SyncAction;
+use syncu::settings::{Profile, RoutingRule};
+
+fn photos_profile(fixture: &Fixture) -> Profile {
+    Profile {
+        routing_rules: vec![RoutingRule { pattern: "*.jpg".to_owned(), destination: PathBuf::from("Photos") }],
+        ..fixture.profile()
+    }
+}
+
+fn sync_with(fixture: &Fixture, profile: Profile) -> ScriptedObserver {
+    let observer = ScriptedObserver::new();
+    fixture.run_with_profile(&observer, profile);
+    assert!(!observer.logs().iter().any(|line| line.starts_with("错误")), "{:#?}", observer.logs());
+    observer
+}
+
+#[test]
+fn matching_files_are_placed_under_the_destination() {
+    let fixture = Fixture::new();
+    write_file(&fixture.local, "x.jpg", b"picture\n");
+    sync_with(&fixture, photos_profile(&fixture));
+
+    assert!(fixture.remote().join("Photos").join("x.jpg").is_file());
+    assert!(!fixture.remote().join("x.jpg").exists());
+    assert_eq!(fixture.metadata().routes.get(&PathBuf::from("x.jpg")), Some(&PathBuf::from("Photos").join("x.jpg")));
+}
+
+#[test]
+fn a_file_made_under_the_destination_on_the_usb_stays_there() {
+    let fixture = Fixture::new();
+    write_file(&fixture.remote(), "Photos/x.jpg", b"taken on the road\n");
+    sync_with(&fixture, photos_profile(&fixture));
+
+    let routed = PathBuf::from("Photos").join("x.jpg");
+    assert!(fixture.local.join(&routed).is_file());
+    assert!(fixture.remote().join(&routed).is_file());
+    assert!(!fixture.remote().join("Photos").join("Photos").exists());
+
+    // The next run finds both sides where routing wants them
+    let observer = sync_with(&fixture, photos_profile(&fixture));
+    let plan = observer.plan().unwrap_or_default();
+    assert!(!plan.iter().any(|action| matches!(action, SyncAction::MoveRemote { .. })), "{:#?}", plan);
+    assert!(!fixture.remote().join("Photos").join("Photos").exists());
+}
+
+#[test]
+fn a_file_routed_onto_another_is_left_out_instead_of_overwriting_it() {
+    let fixture = Fixture::new();
+    write_file(&fixture.local, "x.jpg", b"routed\n");
+    write_file(&fixture.local, "Photos/x.jpg", b"already there\n");
+    let observer = sync_with(&fixture, photos_profile(&fixture));
+    assert!(observer.logs().iter().any(|line| line.contains("已跳过: x.jpg")), "{:#?}", observer.logs());
+
+    let routed = PathBuf::from("Photos").join("x.jpg");
+    assert_eq!(std::fs::read(fixture.remote().join(&routed)).unwrap(), b"already there\n");
+    assert!(!fixture.metadata().files.contains_key(&PathBuf::from("x.jpg")));
+
+    // Nothing is proposed for deletion, and both local files survive the next run
+    let observer = sync_with(&fixture, photos_profile(&fixture));
+    assert_eq!(observer.deletions_asked(), 0);
+    let plan = observer.plan().unwrap_or_default();
+    assert!(!plan.iter().any(|action| matches!(action, SyncAction::DeleteLocal(_) | SyncAction::DeleteRemote(_))), "{:#?}", plan);
+    assert_eq!(std::fs::read(fixture.local.join("x.jpg")).unwrap(), b"routed\n");
+    assert_eq!(std::fs::read(fixture.local.join(&routed)).unwrap(), b"already there\n");
+    assert_eq!(std::fs::read(fixture.remote().join(&routed)).unwrap(), b"already there\n");
+}
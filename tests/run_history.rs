@@ -0,0 +1,113 @@
+//! The history of past runs kept in the app data directory, and what the history window reads back from it.
+
+mod common;
+
+use common::{write_tree, Fixture, ScriptedObserver, TempDir};
+use std::fs;
+use std::path::PathBuf;
+use syncu::history::{actions_csv, list_runs, load_run, prune_history, save_run};
+use syncu::models::{ActionStatus, SyncAction};
+use syncu::report::RunReport;
+use syncu::settings::HistoryRetention;
+
+fn report_started_at(started: &str, files: usize) -> RunReport {
+    let mut report = RunReport::start();
+    report.started = started.to_owned();
+    report.set_plan((0..files).map(|i| (SyncAction::LocalToRemote(PathBuf::from(format!("file {}.txt", i))), Some(10))));
+    report.finish("完成");
+    report
+}
+
+const KEEP_ALL: HistoryRetention = HistoryRetention { max_runs: 100, max_megabytes: 100 };
+
+#[test]
+fn a_run_hands_over_its_actions_for_the_history() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"a"), ("Photos/b.jpg", b"b")]);
+    let observer = ScriptedObserver::new();
+    fixture.sync(&observer);
+
+    let report = observer.recorded_run().expect("the run was not recorded");
+    assert_eq!(report.outcome, "完成");
+    assert!(report.actions.iter().any(|action| action.action == SyncAction::LocalToRemote(PathBuf::from("Photos/b.jpg"))));
+    assert!(report.actions.iter().all(|action| action.status == Some(ActionStatus::Done)));
+
+    // A run with nothing to do leaves no entry
+    let observer = ScriptedObserver::new();
+    fixture.sync(&observer);
+    assert!(observer.recorded_run().is_none());
+}
+
+#[test]
+fn saved_runs_are_listed_newest_first_and_read_back_whole() {
+    let dir = TempDir::new();
+    let mut older = report_started_at("2024-05-01 14:03:12", 2);
+    older.finish_action(0, ActionStatus::Done, None, None);
+    older.finish_action(1, ActionStatus::Failed, None, Some("拒绝访问".to_owned()));
+    let older_path = save_run(dir.path(), &older, KEEP_ALL).unwrap();
+    save_run(dir.path(), &report_started_at("2024-05-02 09:00:00", 1), KEEP_ALL).unwrap();
+    // Runs started within the same second don't overwrite each other
+    save_run(dir.path(), &report_started_at("2024-05-02 09:00:00", 3), KEEP_ALL).unwrap();
+
+    let runs = list_runs(dir.path());
+    let started: Vec<&str> = runs.iter().map(|entry| entry.summary.started.as_str()).collect();
+    assert_eq!(started, ["2024-05-02 09:00:00", "2024-05-02 09:00:00", "2024-05-01 14:03:12"]);
+    assert_eq!(runs[2].path, older_path);
+    assert_eq!((runs[2].summary.done, runs[2].summary.failed), (1, 1));
+    assert_eq!(runs[2].summary.counts_text(), "完成 1 · 失败 1");
+
+    let run = load_run(&older_path).unwrap();
+    assert_eq!(run.actions, older.actions);
+    // Unset fields are left out, which keeps large runs small
+    assert!(!fs::read_to_string(&older_path).unwrap().contains("null"));
+}
+
+#[test]
+fn search_matches_paths_kinds_and_results() {
+    let dir = TempDir::new();
+    let mut report = report_started_at("2024-05-01 14:03:12", 0);
+    report.set_plan([
+        (SyncAction::LocalToRemote(PathBuf::from("Photos/Beach.jpg")), Some(10)),
+        (SyncAction::DeleteRemote(PathBuf::from("notes.txt")), Some(5)),
+        (SyncAction::RemoteToLocal(PathBuf::from("report.docx")), Some(20)),
+    ]);
+    report.finish_action(0, ActionStatus::Done, None, None);
+    report.finish_action(1, ActionStatus::Done, None, None);
+    report.finish_action(2, ActionStatus::Failed, None, Some("设备错误".to_owned()));
+    let run = load_run(&save_run(dir.path(), &report, KEEP_ALL).unwrap()).unwrap();
+
+    assert_eq!(run.search(""), [0, 1, 2]);
+    assert_eq!(run.search("beach"), [0]);
+    assert_eq!(run.search("删除U盘"), [1]);
+    assert_eq!(run.search("u盘"), [0, 1]);
+    assert_eq!(run.search("失败"), [2]);
+}
+
+#[test]
+fn old_runs_are_removed_by_count_and_by_size() {
+    let dir = TempDir::new();
+    for day in 1..=5 {
+        save_run(dir.path(), &report_started_at(&format!("2024-05-0{} 12:00:00", day), 1), KEEP_ALL).unwrap();
+    }
+    assert_eq!(prune_history(dir.path(), HistoryRetention { max_runs: 3, max_megabytes: 100 }), 2);
+    let started: Vec<String> = list_runs(dir.path()).into_iter().map(|entry| entry.summary.started).collect();
+    assert_eq!(started, ["2024-05-05 12:00:00", "2024-05-04 12:00:00", "2024-05-03 12:00:00"]);
+
+    // A run larger than the whole allowance still keeps the newest one
+    save_run(dir.path(), &report_started_at("2024-05-06 12:00:00", 20_000), KEEP_ALL).unwrap();
+    assert_eq!(prune_history(dir.path(), HistoryRetention { max_runs: 100, max_megabytes: 0 }), 3);
+    let runs = list_runs(dir.path());
+    assert_eq!(runs.len(), 1);
+    assert_eq!(runs[0].summary.started, "2024-05-06 12:00:00");
+}
+
+#[test]
+fn the_export_quotes_fields_that_need_it() {
+    let mut report = report_started_at("2024-05-01 14:03:12", 0);
+    report.set_plan([(SyncAction::LocalToRemote(PathBuf::from("a, \"b\".txt")), Some(3))]);
+    report.finish_action(0, ActionStatus::Failed, None, Some("拒绝访问".to_owned()));
+
+    let csv = actions_csv(&report.actions);
+    let lines: Vec<&str> = csv.trim_start_matches('\u{feff}').lines().collect();
+    assert_eq!(lines, ["操作,路径,大小,结果,错误", "→U盘,\"a, \"\"b\"\".txt\",3,失败,拒绝访问"]);
+}
@@ -0,0 +1,102 @@
+//! The HTML page a run can leave in the bookkeeping folder.
+
+mod common;
+
+use common::{write_tree, Fixture, ScriptedObserver, TempDir};
+use std::fs;
+use std::path::{Path, PathBuf};
+use syncu::models::{ActionStatus, Resolution, SyncAction};
+use syncu::report::{escape_html, file_url, latest_report, list_reports, render_html, write_html_report, RunReport, REPORT_KEEP};
+use syncu::settings::Profile;
+use syncu::utils::BOOKKEEPING_DIR_NAME;
+
+fn reporting(fixture: &Fixture) -> Profile {
+    Profile { bookkeeping_subfolder: true, html_report: true, ..fixture.profile() }
+}
+
+#[test]
+fn a_run_writes_a_report_of_its_actions() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n"), ("sub/b.txt", b"bravo\n")]);
+    let observer = ScriptedObserver::new();
+    fixture.run_with_profile(&observer, reporting(&fixture));
+
+    let path = latest_report(&fixture.remote()).expect("no report written");
+    assert_eq!(path.parent().unwrap(), fixture.remote().join(BOOKKEEPING_DIR_NAME));
+    let html = String::from_utf8(fs::read(&path).unwrap()).unwrap();
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<td>a.txt</td>"), "{}", html);
+    assert!(html.contains("完成"));
+    assert!(html.contains("执行计划"));
+    // Self-contained: nothing is loaded from elsewhere
+    assert!(!html.contains("src=") && !html.contains("href="));
+    assert!(observer.logs().iter().any(|line| line.contains("同步报告已生成")), "{:#?}", observer.logs());
+}
+
+#[test]
+fn conflicts_and_their_resolution_are_listed() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    write_tree(&fixture.local, &[("a.txt", b"local\n")]);
+    write_tree(&fixture.remote(), &[("a.txt", b"remote!\n")]);
+
+    fixture.run_with_profile(&ScriptedObserver::new().with_conflict_resolution(Resolution::KeepRemote), reporting(&fixture));
+
+    let html = fs::read_to_string(latest_report(&fixture.remote()).unwrap()).unwrap();
+    assert!(html.contains("<h2>冲突</h2>"));
+    assert!(html.contains("采用U盘"));
+}
+
+#[test]
+fn no_report_without_the_bookkeeping_folder() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n")]);
+    let observer = ScriptedObserver::new();
+    let profile = Profile { html_report: true, ..fixture.profile() };
+    fixture.run_with_profile(&observer, profile);
+
+    assert!(!fixture.remote().join(BOOKKEEPING_DIR_NAME).exists());
+    assert_eq!(latest_report(&fixture.remote()), None);
+    assert!(observer.logs().iter().any(|line| line.starts_with("警告: 同步报告")), "{:#?}", observer.logs());
+}
+
+#[test]
+fn paths_are_escaped() {
+    assert_eq!(escape_html("a<b>&\"c'.txt"), "a&lt;b&gt;&amp;&quot;c&#39;.txt");
+
+    let mut report = RunReport::start();
+    report.local_folder = Some(PathBuf::from("/home/<me>"));
+    report.set_plan([(SyncAction::LocalToRemote(PathBuf::from("x<script>alert(1)</script>.txt")), Some(3))]);
+    report.finish_action(0, ActionStatus::Failed, None, Some("错误: x<script>: denied & gone".to_string()));
+    report.finish("完成");
+    let html = render_html(&report);
+
+    assert!(!html.contains("<script>alert"));
+    assert!(html.contains("x&lt;script&gt;alert(1)&lt;/script&gt;.txt"));
+    assert!(html.contains("/home/&lt;me&gt;"));
+    assert!(html.contains("denied &amp; gone"));
+    assert!(html.contains("class=\"failed\""));
+}
+
+#[test]
+fn only_the_newest_reports_are_kept() {
+    let dir = TempDir::new();
+    let folder = dir.path().join(BOOKKEEPING_DIR_NAME);
+    fs::create_dir_all(&folder).unwrap();
+    for i in 0..REPORT_KEEP {
+        fs::write(folder.join(format!("report_2000-01-01_{:06}.html", i)), "old").unwrap();
+    }
+    let written = write_html_report(&RunReport::start(), dir.path()).unwrap();
+
+    let reports = list_reports(dir.path());
+    assert_eq!(reports.len(), REPORT_KEEP);
+    assert_eq!(reports.last(), Some(&written));
+    assert!(!folder.join("report_2000-01-01_000000.html").exists());
+}
+
+#[test]
+fn file_urls_encode_characters_that_would_cut_them_short() {
+    assert_eq!(file_url(Path::new("/media/u盘/my docs/#1.html")), "file:///media/u盘/my%20docs/%231.html");
+    assert_eq!(file_url(Path::new(r"E:\docs\report.html")), "file:///E:/docs/report.html");
+}
@@ -0,0 +1,59 @@
+//! Peak memory of scanning a large tree, which the streaming scan keeps to the results themselves.
+
+mod common;
+
+#[cfg(target_os = "linux")]
+mod on_linux {
+    use super::common::{Fixture, ScriptedObserver};
+    use std::fs;
+    use std::time::Instant;
+    use syncu::models::SyncData;
+    use syncu::utils::{scan_directory_with_progress, HashStrategy};
+
+    const FOLDERS: usize = 2_000;
+    const FILES_PER_FOLDER: usize = 100;
+
+    // The process's peak resident set in KiB
+    fn peak_rss_kib() -> u64 {
+        let status = fs::read_to_string("/proc/self/status").unwrap();
+        let line = status.lines().find(|line| line.starts_with("VmHWM:")).unwrap();
+        line.split_whitespace().nth(1).unwrap().parse().unwrap()
+    }
+
+    /// Scans a synthetic tree of 200,000 small files in 2,000 folders and reports the peak RSS it took.
+    /// Run with `cargo test --release --test scan_memory -- --ignored --nocapture` to see the numbers; the test
+    /// binary holds nothing else, so the peak is the scan's.
+    #[test]
+    #[ignore = "benchmark"]
+    fn benchmark_scan_peak_memory() {
+        let fixture = Fixture::new();
+        for folder in 0..FOLDERS {
+            let folder_path = fixture.local.join(format!("d{:04}", folder));
+            fs::create_dir(&folder_path).unwrap();
+            for file in 0..FILES_PER_FOLDER {
+                fs::write(folder_path.join(format!("f{:03}.txt", file)), format!("{} {}\n", folder, file)).unwrap();
+            }
+        }
+        // Start the peak over, so building the tree doesn't count
+        fs::write("/proc/self/clear_refs", "5").unwrap();
+        let before = peak_rss_kib();
+
+        let started = Instant::now();
+        let scanned =
+            scan_directory_with_progress(&fixture.local, &ScriptedObserver::new(), None, "扫描本地", &SyncData::default(), HashStrategy::Full)
+                .unwrap()
+                .unwrap();
+        let elapsed = started.elapsed();
+        let peak = peak_rss_kib();
+
+        assert_eq!(scanned.files.len(), FOLDERS * FILES_PER_FOLDER);
+        eprintln!(
+            "{} files: peak RSS {:.1} MiB, {:.1} MiB above the {:.1} MiB before scanning, {:?}",
+            scanned.files.len(),
+            peak as f64 / 1024.0,
+            peak.saturating_sub(before) as f64 / 1024.0,
+            before as f64 / 1024.0,
+            elapsed
+        );
+    }
+}
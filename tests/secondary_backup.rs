@@ -0,0 +1,79 @@
+//! The secondary backup destination, which receives a one-way copy of the local folder after each sync.
+
+mod common;
+
+use common::{write_file, Fixture, ScriptedObserver, TempDir, FOLDER_NAME};
+use std::fs::{self, File};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use syncu::models::RunOutcome;
+use syncu::observer::DeletionDecision;
+use syncu::settings::{NewerDestinationPolicy, Profile};
+
+fn set_modified(path: &Path, time: SystemTime) {
+    File::options().write(true).open(path).unwrap().set_modified(time).unwrap();
+}
+
+fn backup_profile(fixture: &Fixture, backup: &TempDir, policy: NewerDestinationPolicy) -> Profile {
+    Profile { secondary_destination: Some(backup.path().to_path_buf()), newer_destination_policy: policy, ..fixture.profile() }
+}
+
+// Syncs once, mirrors to a backup, then edits a.txt locally and, later, in the backup.
+fn fixture_with_newer_backup_file(backup: &TempDir) -> Fixture {
+    let fixture = Fixture::synced(&[("a.txt", b"alpha\n"), ("b.txt", b"bravo\n")]);
+    let profile = backup_profile(&fixture, backup, NewerDestinationPolicy::SkipAndWarn);
+    assert_eq!(fixture.run_with_profile(&ScriptedObserver::new(), profile), RunOutcome::Completed);
+    assert_eq!(fs::read(backup.path().join(FOLDER_NAME).join("a.txt")).unwrap(), b"alpha\n");
+
+    let now = SystemTime::now();
+    write_file(&fixture.local, "a.txt", b"alpha, edited locally\n");
+    set_modified(&fixture.local.join("a.txt"), now - Duration::from_secs(60));
+    let backup_file = backup.path().join(FOLDER_NAME).join("a.txt");
+    fs::write(&backup_file, b"alpha, edited in the backup\n").unwrap();
+    set_modified(&backup_file, now);
+    fixture
+}
+
+#[test]
+fn newer_backup_file_is_kept_and_reported() {
+    let backup = TempDir::new();
+    let fixture = fixture_with_newer_backup_file(&backup);
+
+    let observer = ScriptedObserver::new();
+    let profile = backup_profile(&fixture, &backup, NewerDestinationPolicy::SkipAndWarn);
+    assert_eq!(fixture.run_with_profile(&observer, profile), RunOutcome::Completed);
+
+    assert_eq!(fs::read(backup.path().join(FOLDER_NAME).join("a.txt")).unwrap(), b"alpha, edited in the backup\n");
+    assert!(observer.logs().iter().any(|line| line.contains("目标文件比本地文件新，已跳过: a.txt")));
+    assert!(observer.logs().iter().any(|line| line.contains("1 个目标文件较新 (跳过 1, 覆盖 0)")));
+}
+
+#[test]
+fn overwrite_policy_replaces_newer_backup_file() {
+    let backup = TempDir::new();
+    let fixture = fixture_with_newer_backup_file(&backup);
+
+    let observer = ScriptedObserver::new();
+    let profile = backup_profile(&fixture, &backup, NewerDestinationPolicy::Overwrite);
+    assert_eq!(fixture.run_with_profile(&observer, profile), RunOutcome::Completed);
+
+    assert_eq!(fs::read(backup.path().join(FOLDER_NAME).join("a.txt")).unwrap(), b"alpha, edited locally\n");
+    assert!(observer.logs().iter().any(|line| line.contains("目标文件比本地文件新，已覆盖: a.txt")));
+}
+
+#[test]
+fn files_kept_only_on_the_usb_drive_are_not_mirrored() {
+    let fixture = Fixture::synced(&[("a.txt", b"alpha\n"), ("b.txt", b"bravo\n"), ("c.txt", b"charlie\n"), ("d.txt", b"delta\n")]);
+    fs::remove_file(fixture.local.join("b.txt")).unwrap();
+
+    // The record keeps b.txt for the copy left on the USB drive, but this machine no longer has it
+    let backup = TempDir::new();
+    let observer = ScriptedObserver::new().with_deletion_decision(DeletionDecision::KeepPermanently);
+    let profile = backup_profile(&fixture, &backup, NewerDestinationPolicy::SkipAndWarn);
+    assert_eq!(fixture.run_with_profile(&observer, profile), RunOutcome::Completed);
+
+    assert!(fixture.metadata().files.contains_key(Path::new("b.txt")));
+    assert!(!observer.logs().iter().any(|line| line.starts_with("警告: [备份]")), "{:#?}", observer.logs());
+    assert!(backup.path().join(FOLDER_NAME).join("a.txt").is_file());
+    assert!(!backup.path().join(FOLDER_NAME).join("b.txt").exists());
+}
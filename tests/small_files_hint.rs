@@ -0,0 +1,43 @@
+//! The hint that a plan copies so many small files to the USB drive that the run will be slow.
+
+mod common;
+
+use common::{write_file, write_tree, Fixture, ScriptedObserver};
+use syncu::settings::Profile;
+use syncu::sync::{SMALL_FILE_HINT_MIN_COUNT, SMALL_FILE_MAX_BYTES};
+
+#[test]
+fn many_small_copies_to_the_drive_are_pointed_out() {
+    let fixture = Fixture::new();
+    for i in 0..SMALL_FILE_HINT_MIN_COUNT {
+        write_file(&fixture.local, &format!("notes/{}.txt", i), b"small\n");
+    }
+    write_file(&fixture.local, "large.bin", &vec![0; SMALL_FILE_MAX_BYTES as usize]);
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+
+    assert_eq!(observer.small_files(), Some(SMALL_FILE_HINT_MIN_COUNT));
+}
+
+#[test]
+fn a_few_small_files_go_without_a_hint() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n"), ("b.txt", b"bravo\n")]);
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+
+    assert_eq!(observer.small_files(), None);
+}
+
+#[test]
+fn the_hint_can_be_turned_off() {
+    let fixture = Fixture::new();
+    for i in 0..SMALL_FILE_HINT_MIN_COUNT {
+        write_file(&fixture.local, &format!("notes/{}.txt", i), b"small\n");
+    }
+    let profile = Profile { small_files_hint: false, ..fixture.profile() };
+    let observer = ScriptedObserver::new();
+    fixture.run_with_profile(&observer, profile);
+
+    assert_eq!(observer.small_files(), None);
+}
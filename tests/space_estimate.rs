@@ -0,0 +1,30 @@
+//! The plan's estimated effect on the free space of the USB drive.
+
+mod common;
+
+use common::{write_file, Fixture, ScriptedObserver};
+use std::fs;
+
+#[test]
+fn estimate_counts_copies_overwrites_and_deletions() {
+    let fixture = Fixture::synced(&[
+        ("a.txt", &[b'a'; 10]),
+        ("b.txt", &[b'b'; 20]),
+        ("c.txt", b"charlie\n"),
+        ("d.txt", b"delta\n"),
+        ("e.txt", b"echo\n"),
+        ("f.txt", b"foxtrot\n"),
+    ]);
+    write_file(&fixture.local, "a.txt", &[b'A'; 30]);
+    fs::remove_file(fixture.local.join("b.txt")).unwrap();
+    write_file(&fixture.local, "g.txt", &[b'g'; 5]);
+
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+
+    // Fake drives live on whatever disk holds the temporary directory, whose free space may not be readable
+    let Some(estimate) = observer.space_estimate() else { return };
+    // 20 more bytes for a.txt, 20 freed by b.txt, 5 for g.txt
+    assert_eq!(estimate.available as i64 - estimate.after, 5);
+    assert!(observer.logs().contains(&estimate.summary()));
+}
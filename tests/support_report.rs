@@ -0,0 +1,55 @@
+//! The diagnostics text users copy for whoever helps them, with paths optionally redacted.
+
+mod common;
+
+use common::TempDir;
+use std::path::Component;
+use syncu::diagnostics::{redact_path, support_report, DriveSummary, SupportInfo, SUPPORT_LOG_LINES};
+use syncu::settings::{Profile, Settings};
+
+#[test]
+fn redaction_keeps_the_root_and_hashes_the_names() {
+    let dir = TempDir::new();
+    let path = dir.path().join("Family Photos").join("2024");
+    let redacted = redact_path(&path);
+
+    assert!(!redacted.to_string_lossy().contains("Family Photos"));
+    assert_eq!(redacted.components().count(), path.components().count());
+    assert!(redacted.has_root());
+    // Equal names hash alike, so the same folder is recognizable across the report
+    assert_eq!(redact_path(&path), redacted);
+    assert_eq!(redact_path(&dir.path().join("Family Photos")), redacted.parent().unwrap());
+    assert!(redacted.components().all(|component| !matches!(component, Component::Normal(name) if name.len() != 8)));
+}
+
+#[test]
+fn the_report_lists_settings_drives_errors_and_the_end_of_the_log() {
+    let dir = TempDir::new();
+    let local = dir.path().join("Family Photos");
+    let settings = Settings { profiles: vec![Profile { local_folder: local.clone(), ..Default::default() }], ..Default::default() };
+    let drives = [DriveSummary { mount_point: dir.path().join("stick"), file_system: Some("exFAT".to_owned()), available_space: Some(2048), unavailable: None }];
+    let log: Vec<String> = (0..SUPPORT_LOG_LINES + 10).map(|i| format!("第 {} 行", i)).collect();
+    let error = format!("错误: 无法读取 {}", local.join("a.jpg").display());
+    let info = SupportInfo { settings: &settings, drives: &drives, log: &log, last_error: Some(&error), redact_paths: false };
+
+    let report = support_report(&info);
+    assert!(report.contains(&format!("版本: SyncU {}", env!("CARGO_PKG_VERSION"))));
+    assert!(report.contains("Family Photos"));
+    assert!(report.contains("exFAT, 可用 2.0 KB"), "{}", report);
+    assert!(report.contains(&error));
+    assert!(report.contains(&format!("第 {} 行", SUPPORT_LOG_LINES + 9)));
+    assert!(!report.contains("第 9 行"));
+
+    let redacted = support_report(&SupportInfo { redact_paths: true, ..info });
+    assert!(!redacted.contains("Family Photos"), "{}", redacted);
+    assert!(redacted.contains(&redact_path(&local).display().to_string()));
+    assert!(!redacted.contains(&dir.path().display().to_string()));
+}
+
+#[test]
+fn a_report_without_drives_or_errors_says_so() {
+    let settings = Settings::default();
+    let report = support_report(&SupportInfo { settings: &settings, drives: &[], log: &[], last_error: None, redact_paths: true });
+    assert!(report.contains("(未检测到U盘)"));
+    assert!(report.contains("== 最近的错误 ==\n(无)"));
+}
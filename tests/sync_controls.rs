@@ -0,0 +1,110 @@
+//! The controls offered while a run goes on: skipping the copy in progress, pausing, and stopping after it.
+
+mod common;
+
+use common::{content, write_tree, Fixture, ScriptedObserver};
+use crossbeam_channel::unbounded;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+use syncu::models::{ActionStatus, ActivityKind, SyncAction, SyncMessage};
+use syncu::observer::{ChannelObserver, SyncObserver};
+
+// Above the size that is copied in chunks, so the copy checks for a skip request
+const LARGE: usize = 11 * 1024 * 1024;
+
+#[test]
+fn a_skipped_copy_is_left_out_and_copied_next_time() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("big.bin", &content(3, LARGE))]);
+    let observer = ScriptedObserver::new().skipping_action(0);
+    assert!(!fixture.sync(&observer));
+
+    assert_eq!(observer.finished_actions(), vec![(0, ActionStatus::Skipped)]);
+    assert!(observer.logs().iter().any(|line| line.contains("已按要求跳过")));
+    let leftovers: Vec<_> = std::fs::read_dir(fixture.remote()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    assert!(!fixture.remote().join("big.bin").exists(), "{:?}", leftovers);
+    assert!(!fixture.metadata().files.contains_key(&PathBuf::from("big.bin")));
+
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    assert_eq!(std::fs::read(fixture.remote().join("big.bin")).unwrap(), content(3, LARGE));
+}
+
+fn channel_observer() -> (ChannelObserver, crossbeam_channel::Sender<SyncMessage>, crossbeam_channel::Receiver<SyncMessage>) {
+    let (to_sync, from_ui) = unbounded();
+    let (to_ui, from_sync) = unbounded();
+    (ChannelObserver::new(to_ui, from_ui), to_sync, from_sync)
+}
+
+#[test]
+fn progress_tells_whether_the_current_step_can_be_skipped() {
+    let (observer, _to_sync, from_sync) = channel_observer();
+    let activity = |observer: &ChannelObserver| {
+        observer.on_progress(0.5, String::new());
+        match from_sync.try_recv() {
+            Ok(SyncMessage::Progress(_, _, activity)) => activity,
+            other => panic!("expected progress, got {:?}", other.map(|_| ())),
+        }
+    };
+    assert_eq!(activity(&observer), ActivityKind::Bookkeeping);
+    observer.on_action_started(0, &SyncAction::LocalToRemote(PathBuf::from("a.txt")));
+    assert!(activity(&observer).skippable());
+    observer.on_action_finished(0, ActionStatus::Done);
+    let _ = from_sync.try_recv();
+    observer.on_action_started(1, &SyncAction::DeleteRemote(PathBuf::from("b.txt")));
+    assert_eq!(activity(&observer), ActivityKind::Other);
+}
+
+#[test]
+fn a_skip_request_only_applies_to_a_copy_in_progress() {
+    let (observer, to_sync, _from_sync) = channel_observer();
+    observer.on_action_started(0, &SyncAction::DeleteRemote(PathBuf::from("a.txt")));
+    to_sync.send(SyncMessage::SkipCurrent).unwrap();
+    assert!(!observer.should_stop());
+    assert!(!observer.should_skip_current());
+
+    observer.on_action_started(1, &SyncAction::LocalToRemote(PathBuf::from("b.txt")));
+    to_sync.send(SyncMessage::SkipCurrent).unwrap();
+    assert!(!observer.should_stop());
+    assert!(observer.should_skip_current());
+    // The request is taken once
+    assert!(!observer.should_skip_current());
+}
+
+#[test]
+fn a_soft_stop_waits_for_the_action_in_progress() {
+    let (observer, to_sync, _from_sync) = channel_observer();
+    observer.on_action_started(0, &SyncAction::LocalToRemote(PathBuf::from("a.txt")));
+    to_sync.send(SyncMessage::StopAfterAction).unwrap();
+    assert!(!observer.should_stop());
+    observer.on_action_finished(0, ActionStatus::Done);
+    assert!(observer.should_stop());
+}
+
+#[test]
+fn a_paused_run_waits_until_resumed_or_stopped() {
+    let (observer, to_sync, _from_sync) = channel_observer();
+    to_sync.send(SyncMessage::Pause).unwrap();
+    let resume = to_sync.clone();
+    let resumer = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        resume.send(SyncMessage::Resume).unwrap();
+    });
+    assert!(!observer.should_stop());
+    resumer.join().unwrap();
+
+    to_sync.send(SyncMessage::Pause).unwrap();
+    to_sync.send(SyncMessage::Stop).unwrap();
+    assert!(observer.should_stop());
+}
+
+#[test]
+fn the_counts_carry_the_bytes_the_plan_copies() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha"), ("notes/b.md", b"bravo!")]);
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+
+    let stats = observer.initial_stats().expect("no counts were sent");
+    assert_eq!(stats.total_bytes, 11);
+}
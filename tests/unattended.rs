@@ -0,0 +1,89 @@
+//! Runs nobody is watching must never wait for an answer.
+
+mod common;
+
+use common::{write_file, Fixture, ScriptedObserver};
+use crossbeam_channel::unbounded;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use syncu::models::{ConflictSuggestion, Resolution, RunOutcome, SyncMessage};
+use syncu::observer::{ChannelObserver, DeletionDecision, SyncObserver};
+use syncu::settings::Profile;
+
+const FILES: &[(&str, &[u8])] =
+    &[("a.txt", b"alpha
+"), ("b.txt", b"bravo
+"), ("c.txt", b"charlie
+"), ("d.txt", b"delta
+"), ("e.txt", b"echo
+")];
+
+#[test]
+fn unattended_run_skips_questions_the_profile_leaves_open() {
+    let fixture = Fixture::synced(FILES);
+    fs::remove_file(fixture.local.join("a.txt")).unwrap();
+    write_file(&fixture.local, "b.txt", b"bravo from the laptop\n");
+    write_file(&fixture.remote(), "b.txt", b"bravo from another computer\n");
+
+    let observer = ScriptedObserver::new();
+    assert_eq!(fixture.run_unattended(&observer, fixture.profile()), RunOutcome::Completed);
+
+    assert_eq!(observer.deletions_asked(), 0);
+    assert_eq!(observer.conflicts_asked(), 0);
+    assert!(fixture.remote().join("a.txt").exists());
+    assert_eq!(fs::read(fixture.local.join("b.txt")).unwrap(), b"bravo from the laptop\n");
+    assert_eq!(fs::read(fixture.remote().join("b.txt")).unwrap(), b"bravo from another computer\n");
+    assert!(observer.logs().iter().any(|line| line.contains("无人值守: 未确认删除，已跳过")));
+    assert!(observer.logs().iter().any(|line| line.contains("无人值守: 冲突未解决，已跳过")));
+}
+
+#[test]
+fn unattended_run_follows_remembered_answers() {
+    let fixture = Fixture::synced(FILES);
+    fs::remove_file(fixture.local.join("a.txt")).unwrap();
+    write_file(&fixture.local, "b.txt", b"bravo from the laptop\n");
+    write_file(&fixture.remote(), "b.txt", b"bravo from another computer\n");
+
+    let observer = ScriptedObserver::new();
+    let profile = Profile {
+        default_deletion_choice: Some(true),
+        default_conflict_resolution: Some(Resolution::KeepRemote),
+        ..fixture.profile()
+    };
+    assert_eq!(fixture.run_unattended(&observer, profile), RunOutcome::Completed);
+
+    assert_eq!(observer.deletions_asked() + observer.conflicts_asked(), 0);
+    assert!(!fixture.remote().join("a.txt").exists());
+    assert_eq!(fs::read(fixture.local.join("b.txt")).unwrap(), b"bravo from another computer\n");
+}
+
+#[test]
+fn unanswered_prompts_are_skipped_after_the_timeout() {
+    let (tx_to_ui, rx_from_sync) = unbounded();
+    let (_tx_to_sync, rx_from_ui) = unbounded();
+    let observer = ChannelObserver::new(tx_to_ui, rx_from_ui).with_prompt_timeout(Some(Duration::from_millis(200)));
+
+    let started = Instant::now();
+    assert_eq!(observer.confirm_deletion(Path::new("a.txt"), 1, 1).unwrap(), DeletionDecision::Keep);
+    assert_eq!(observer.resolve_conflict(Path::new("b.txt"), None, ConflictSuggestion::Undecided).unwrap(), Resolution::Skip);
+    assert!(started.elapsed() >= Duration::from_millis(400));
+
+    let messages: Vec<SyncMessage> = rx_from_sync.try_iter().collect();
+    let expired: Vec<u64> = messages
+        .iter()
+        .filter_map(|message| match message {
+            SyncMessage::PromptExpired { id } => Some(*id),
+            _ => None,
+        })
+        .collect();
+    let asked: Vec<u64> = messages
+        .iter()
+        .filter_map(|message| match message {
+            SyncMessage::ConfirmDeletion { id, .. } | SyncMessage::AskForConflictResolution { id, .. } => Some(*id),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(expired, asked);
+    assert!(messages.iter().any(|message| matches!(message, SyncMessage::Log(line) if line.contains("等待回答超时，已跳过: a.txt"))));
+}
@@ -0,0 +1,49 @@
+//! Runs that change nothing leave the sync record on the drive untouched.
+
+mod common;
+
+use common::{write_file, write_tree, Fixture, ScriptedObserver};
+use std::fs;
+use std::thread;
+use std::time::{Duration, SystemTime};
+use syncu::utils::{log_path, metadata_path};
+
+fn modified(fixture: &Fixture) -> SystemTime {
+    fs::metadata(metadata_path(&fixture.remote())).unwrap().modified().unwrap()
+}
+
+#[test]
+fn a_run_without_changes_does_not_rewrite_the_record() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n"), ("notes/b.md", b"# bravo\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    let before = modified(&fixture);
+    let log_before = fs::read_to_string(log_path(&fixture.remote())).unwrap();
+
+    // Long enough for a rewrite to show up even on file systems with coarse timestamps
+    thread::sleep(Duration::from_millis(1100));
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+
+    assert_eq!(modified(&fixture), before);
+    assert_eq!(fs::read_to_string(log_path(&fixture.remote())).unwrap(), log_before);
+    assert!(observer.logs().iter().any(|line| line.contains("同步记录未变化，跳过写入")));
+}
+
+#[test]
+fn a_run_with_changes_still_writes_the_record() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    let before = modified(&fixture);
+
+    thread::sleep(Duration::from_millis(1100));
+    write_file(&fixture.local, "b.txt", b"bravo\n");
+    let observer = ScriptedObserver::new();
+    assert!(!fixture.sync(&observer));
+
+    assert_ne!(modified(&fixture), before);
+    assert!(fixture.metadata().files.contains_key(std::path::Path::new("b.txt")));
+    assert!(!observer.logs().iter().any(|line| line.contains("同步记录未变化")));
+}
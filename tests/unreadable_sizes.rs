@@ -0,0 +1,51 @@
+//! Files whose size can't be read while planning are reported together instead of ending the run,
+//! and are tried once more when their turn comes.
+
+mod common;
+
+// A file standing where a folder was makes reading the size below it fail with something other than "not found"
+#[cfg(unix)]
+mod on_unix {
+    use super::common::{write_tree, Fixture, ScriptedObserver};
+    use std::fs;
+    use std::path::PathBuf;
+    use syncu::models::{ActionStatus, RunOutcome, SyncAction};
+
+    // Replaces the local "Photos" folder by a file once the scans are done, just before the plan is made.
+    fn observer_breaking_photos(fixture: &Fixture) -> ScriptedObserver {
+        let photos = fixture.local.join("Photos");
+        ScriptedObserver::new().doing_on_progress("正在分析文件差异", move || {
+            fs::remove_dir_all(&photos).unwrap();
+            fs::write(&photos, b"not a folder").unwrap();
+        })
+    }
+
+    #[test]
+    fn still_unreadable_files_are_skipped_and_the_rest_is_synced() {
+        let fixture = Fixture::new();
+        write_tree(&fixture.local, &[("a.txt", b"a"), ("Photos/b.jpg", b"b")]);
+
+        let observer = observer_breaking_photos(&fixture);
+        assert_eq!(fixture.run(&observer), RunOutcome::Completed);
+        assert_eq!(observer.unreadable_sizes(), Some(vec![PathBuf::from("Photos/b.jpg")]));
+        assert!(observer.logs().iter().any(|line| line.contains("无法读取 1 个文件的大小")), "{:#?}", observer.logs());
+
+        let plan = observer.plan().unwrap();
+        let index = plan.iter().position(|action| *action == SyncAction::LocalToRemote(PathBuf::from("Photos/b.jpg"))).unwrap();
+        assert!(observer.finished_actions().contains(&(index, ActionStatus::Skipped)));
+        assert!(observer.logs().iter().any(|line| line.contains("仍无法读取，已跳过: Photos/b.jpg")), "{:#?}", observer.logs());
+        assert_eq!(fs::read(fixture.remote().join("a.txt")).unwrap(), b"a");
+        assert!(!fixture.remote().join("Photos/b.jpg").exists());
+    }
+
+    #[test]
+    fn the_run_can_be_cancelled_before_anything_is_copied() {
+        let fixture = Fixture::new();
+        write_tree(&fixture.local, &[("a.txt", b"a"), ("Photos/b.jpg", b"b")]);
+
+        let observer = observer_breaking_photos(&fixture).cancelling_on_unreadable_sizes();
+        assert_eq!(fixture.run(&observer), RunOutcome::Stopped);
+        assert!(observer.logs().iter().any(|line| line.contains("用户取消同步")), "{:#?}", observer.logs());
+        assert!(!fixture.remote().join("a.txt").exists());
+    }
+}
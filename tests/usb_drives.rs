@@ -0,0 +1,23 @@
+//! Listing removable drives, including those that can't be used right now.
+
+use std::fs;
+use syncu::models::DriveUnavailable;
+use syncu::utils::find_usb_drives;
+
+#[test]
+fn drives_offered_as_usable_can_be_read() {
+    // Machines running the tests rarely have a stick plugged in; then there is nothing to check
+    for drive in find_usb_drives() {
+        if drive.unavailable.is_none() {
+            assert!(fs::read_dir(&drive.mount_point).is_ok(), "{}", drive.mount_point.display());
+        }
+    }
+}
+
+#[test]
+fn every_reason_has_a_tag_and_instructions() {
+    for reason in [DriveUnavailable::BitLockerLocked, DriveUnavailable::Inaccessible] {
+        assert!(!reason.label().is_empty());
+        assert!(reason.instructions().contains("重新检查"), "{:?}", reason);
+    }
+}
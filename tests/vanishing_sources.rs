@@ -0,0 +1,45 @@
+//! Files deleted between planning and copying are skipped without failing the rest of the run.
+
+mod common;
+
+use common::{write_tree, Fixture, ScriptedObserver};
+use std::fs;
+use std::path::PathBuf;
+
+#[test]
+fn file_deleted_after_planning_is_skipped_and_the_plan_completes() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n"), ("b.txt", b"bravo\n"), ("c.txt", b"charlie\n")]);
+    let doomed = fixture.local.join("c.txt");
+    let observer = ScriptedObserver::new().doing_on_progress("(1/", move || fs::remove_file(doomed).unwrap());
+
+    assert!(!fixture.sync(&observer));
+    assert!(observer.logs().iter().any(|line| line.ends_with("源文件已不存在，已跳过: c.txt")), "{:#?}", observer.logs());
+    assert_eq!(fs::read(fixture.remote().join("a.txt")).unwrap(), b"alpha\n");
+    assert_eq!(fs::read(fixture.remote().join("b.txt")).unwrap(), b"bravo\n");
+    assert!(!fixture.remote().join("c.txt").exists());
+    assert!(!fixture.metadata().files.contains_key(&PathBuf::from("c.txt")));
+
+    // Nothing is left over for the next run
+    let next = ScriptedObserver::new();
+    assert!(!fixture.sync(&next));
+    assert!(next.logs().iter().any(|line| line == "未检测到变化."), "{:#?}", next.logs());
+}
+
+#[test]
+fn file_deleted_before_sizes_are_totalled_only_warns() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("base.txt", b"base\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n"), ("b.txt", b"bravo\n")]);
+    // The local scan is done by the time the USB folder is scanned
+    let doomed = fixture.local.join("b.txt");
+    let observer = ScriptedObserver::new().doing_on_progress("扫描U盘", move || fs::remove_file(doomed).unwrap());
+
+    assert!(!fixture.sync(&observer));
+    assert!(observer.logs().iter().any(|line| line.starts_with("警告: 源文件在扫描后已不存在")), "{:#?}", observer.logs());
+    assert!(observer.logs().iter().any(|line| line.ends_with("源文件已不存在，已跳过: b.txt")));
+    assert_eq!(fs::read(fixture.remote().join("a.txt")).unwrap(), b"alpha\n");
+    assert!(!fixture.remote().join("b.txt").exists());
+}
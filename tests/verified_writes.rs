@@ -0,0 +1,40 @@
+//! Recording whether the last write of each file was read back and matched its source.
+
+mod common;
+
+use common::{write_tree, Fixture, ScriptedObserver};
+use std::path::Path;
+
+#[test]
+fn copies_to_the_local_folder_are_verified_by_the_final_scan() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.local, &[("a.txt", b"alpha\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    write_tree(&fixture.remote(), &[("b.txt", b"bravo\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+
+    let files = fixture.metadata().files;
+    assert!(files[Path::new("b.txt")].verified);
+    // Nothing reads the USB copy back
+    assert!(!files[Path::new("a.txt")].verified);
+}
+
+#[test]
+fn verification_survives_until_the_file_changes() {
+    let fixture = Fixture::new();
+    write_tree(&fixture.remote(), &[("b.txt", b"bravo\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    assert!(fixture.metadata().files[Path::new("b.txt")].verified);
+
+    write_tree(&fixture.local, &[("b.txt", b"bravo, edited\n")]);
+    assert!(!fixture.sync(&ScriptedObserver::new()));
+    assert!(!fixture.metadata().files[Path::new("b.txt")].verified);
+}
+
+#[test]
+fn records_without_the_flag_load_as_unverified() {
+    let json = r#"{"path":"a.txt","hash":"abc","modified":{"secs_since_epoch":1700000000,"nanos_since_epoch":0},"size":3}"#;
+    let info: syncu::models::FileInfo = serde_json::from_str(json).unwrap();
+    assert!(!info.verified);
+}